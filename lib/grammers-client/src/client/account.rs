@@ -0,0 +1,752 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Methods related to the settings of the logged-in account.
+
+use super::Client;
+use crate::types::{
+    profile::ProfileBuilderInner, Authorization, ChatMap, PrivacyKey, PrivacyRule, ProfileBuilder,
+    Takeout, TakeoutOptions, Theme, Uploaded, User, Wallpaper,
+};
+use crate::utils;
+use grammers_crypto::two_factor_auth::{calculate_password_hash, check_p_and_g, extend_new_salt};
+use grammers_mtproto::mtp::RpcError;
+pub use grammers_mtsender::InvocationError;
+use grammers_tl_types as tl;
+use std::fmt;
+use std::future::Future;
+
+/// The error type which is returned when changing the two-factor authentication settings fails.
+#[derive(Debug)]
+pub enum PasswordError {
+    /// The current password is wrong (or missing, if the account has a password).
+    InvalidPassword,
+    /// The recovery email is not valid.
+    InvalidEmail,
+    /// The change will only take effect once the recovery email is confirmed with the code of
+    /// the given length sent to it, using [`Client::confirm_password_email`].
+    EmailUnconfirmed {
+        code_length: u32,
+    },
+    Other(InvocationError),
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PasswordError::*;
+        match self {
+            InvalidPassword => write!(f, "password error: invalid current password"),
+            InvalidEmail => write!(f, "password error: invalid recovery email"),
+            EmailUnconfirmed { code_length } => write!(
+                f,
+                "password error: recovery email must be confirmed with a {}-digit code",
+                code_length
+            ),
+            Other(e) => write!(f, "password error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+impl From<InvocationError> for PasswordError {
+    fn from(error: InvocationError) -> Self {
+        match error {
+            InvocationError::Rpc(RpcError { ref name, .. }) if name == "PASSWORD_HASH_INVALID" => {
+                Self::InvalidPassword
+            }
+            InvocationError::Rpc(RpcError { ref name, .. }) if name == "EMAIL_INVALID" => {
+                Self::InvalidEmail
+            }
+            InvocationError::Rpc(RpcError {
+                ref name,
+                value: Some(code_length),
+                ..
+            }) if name == "EMAIL_UNCONFIRMED" => Self::EmailUnconfirmed { code_length },
+            error => Self::Other(error),
+        }
+    }
+}
+
+/// The error type which is returned when changing the username of the account fails.
+#[derive(Debug)]
+pub enum UsernameError {
+    /// The username is already taken by someone else.
+    Occupied,
+    /// The username is not valid (for example, it's too short or contains invalid characters).
+    Invalid,
+    Other(InvocationError),
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UsernameError::*;
+        match self {
+            Occupied => write!(f, "username error: username is occupied"),
+            Invalid => write!(f, "username error: username is invalid"),
+            Other(e) => write!(f, "username error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UsernameError {}
+
+impl From<InvocationError> for UsernameError {
+    fn from(error: InvocationError) -> Self {
+        match error {
+            InvocationError::Rpc(RpcError { ref name, .. }) if name == "USERNAME_OCCUPIED" => {
+                Self::Occupied
+            }
+            InvocationError::Rpc(RpcError { ref name, .. }) if name == "USERNAME_INVALID" => {
+                Self::Invalid
+            }
+            error => Self::Other(error),
+        }
+    }
+}
+
+/// Method implementations related to the appearance and other settings of the current account.
+impl Client {
+    /// Fetch all the cloud themes installed by the current account.
+    ///
+    /// The `format` is the name of the platform the themes should be fetched for (for example,
+    /// `"android"`, `"ios"`, `"macos"` or `"tdesktop"`), since themes are platform-specific.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// for theme in client.get_themes("android").await? {
+    ///     println!("{} has been installed {} times", theme.title(), theme.installs_count());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_themes(&self, format: &str) -> Result<Vec<Theme>, InvocationError> {
+        match self
+            .invoke(&tl::functions::account::GetThemes {
+                format: format.to_string(),
+                hash: 0,
+            })
+            .await?
+        {
+            tl::enums::account::Themes::Themes(t) => {
+                Ok(t.themes.into_iter().map(Theme::from_raw).collect())
+            }
+            tl::enums::account::Themes::NotModified => {
+                panic!("API returned Themes::NotModified even though hash = 0")
+            }
+        }
+    }
+
+    /// Install the given cloud theme for the current account, so that other applications logged
+    /// in to the same account can apply it too.
+    ///
+    /// Set `dark` to `true` if the theme should be used when applications are in night mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(theme) = client.get_themes("android").await?.pop() {
+    ///     client.install_theme(&theme, "android", false).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn install_theme(
+        &self,
+        theme: &Theme,
+        format: &str,
+        dark: bool,
+    ) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::InstallTheme {
+            dark,
+            format: Some(format.to_string()),
+            theme: Some(theme.to_input_theme()),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Fetch all the wallpapers saved by the current account, including the default ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let dark_wallpapers = client
+    ///     .get_wallpapers()
+    ///     .await?
+    ///     .into_iter()
+    ///     .filter(|w| w.is_dark())
+    ///     .count();
+    ///
+    /// println!("There are {} dark wallpapers", dark_wallpapers);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_wallpapers(&self) -> Result<Vec<Wallpaper>, InvocationError> {
+        match self
+            .invoke(&tl::functions::account::GetWallPapers { hash: 0 })
+            .await?
+        {
+            tl::enums::account::WallPapers::Papers(w) => {
+                Ok(w.wallpapers.into_iter().map(Wallpaper::from_raw).collect())
+            }
+            tl::enums::account::WallPapers::NotModified => {
+                panic!("API returned WallPapers::NotModified even though hash = 0")
+            }
+        }
+    }
+
+    /// Upload a new wallpaper from a previously-uploaded image file.
+    ///
+    /// The wallpaper will be saved to the current account, but not installed. Use
+    /// [`Client::install_wallpaper`] to do so.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let uploaded = client.upload_file("/home/username/photos/background.jpg").await?;
+    /// let wallpaper = client.upload_wallpaper(uploaded, "image/jpeg").await?;
+    /// client.install_wallpaper(&wallpaper).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn upload_wallpaper(
+        &self,
+        file: Uploaded,
+        mime_type: &str,
+    ) -> Result<Wallpaper, InvocationError> {
+        self.invoke(&tl::functions::account::UploadWallPaper {
+            file: file.input_file,
+            mime_type: mime_type.to_string(),
+            settings: tl::types::WallPaperSettings {
+                blur: false,
+                motion: false,
+                background_color: None,
+                second_background_color: None,
+                intensity: None,
+                rotation: None,
+            }
+            .into(),
+        })
+        .await
+        .map(Wallpaper::from_raw)
+    }
+
+    /// Install the given wallpaper, making it the chat background for the current account.
+    pub async fn install_wallpaper(&self, wallpaper: &Wallpaper) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::InstallWallPaper {
+            wallpaper: wallpaper.to_input_wallpaper(),
+            settings: wallpaper.settings(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Save the given wallpaper to the list of wallpapers of the current account.
+    pub async fn save_wallpaper(&self, wallpaper: &Wallpaper) -> Result<(), InvocationError> {
+        self.set_wallpaper_saved(wallpaper, true).await
+    }
+
+    /// Remove the given wallpaper from the list of wallpapers of the current account.
+    pub async fn unsave_wallpaper(&self, wallpaper: &Wallpaper) -> Result<(), InvocationError> {
+        self.set_wallpaper_saved(wallpaper, false).await
+    }
+
+    async fn set_wallpaper_saved(
+        &self,
+        wallpaper: &Wallpaper,
+        saved: bool,
+    ) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::SaveWallPaper {
+            wallpaper: wallpaper.to_input_wallpaper(),
+            unsave: !saved,
+            settings: wallpaper.settings(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Delete all the wallpapers saved by the current account, restoring the default ones.
+    pub async fn reset_wallpapers(&self) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::ResetWallPapers {})
+            .await
+            .map(drop)
+    }
+
+    /// Enable two-factor authentication for the current account, which must not have a password
+    /// yet. The password will then be required to sign in, along with the login code.
+    ///
+    /// If a recovery `email` is given, the password will only be enabled after the email is
+    /// confirmed (see [`PasswordError::EmailUnconfirmed`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::client::account::PasswordError;
+    ///
+    /// fn ask_code_to_user() -> String {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// match client.enable_password("secret", Some("hint"), Some("me@example.com")).await {
+    ///     Err(PasswordError::EmailUnconfirmed { .. }) => {
+    ///         client.confirm_password_email(&ask_code_to_user()).await?;
+    ///     }
+    ///     result => result?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn enable_password(
+        &self,
+        password: impl AsRef<[u8]>,
+        hint: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(), PasswordError> {
+        self.update_password_settings(None, |info| {
+            let (new_algo, new_password_hash) = new_password_algo(info, password.as_ref());
+            tl::types::account::PasswordInputSettings {
+                new_algo: Some(new_algo),
+                new_password_hash: Some(new_password_hash),
+                hint: Some(hint.unwrap_or_default().to_string()),
+                email: email.map(|email| email.to_string()),
+                new_secure_settings: None,
+            }
+        })
+        .await
+    }
+
+    /// Change the two-factor authentication password of the current account, along with its
+    /// hint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.change_password("old secret", "new secret", None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn change_password(
+        &self,
+        current_password: impl AsRef<[u8]>,
+        new_password: impl AsRef<[u8]>,
+        hint: Option<&str>,
+    ) -> Result<(), PasswordError> {
+        self.update_password_settings(Some(current_password.as_ref()), |info| {
+            let (new_algo, new_password_hash) = new_password_algo(info, new_password.as_ref());
+            tl::types::account::PasswordInputSettings {
+                new_algo: Some(new_algo),
+                new_password_hash: Some(new_password_hash),
+                hint: Some(hint.unwrap_or_default().to_string()),
+                email: None,
+                new_secure_settings: None,
+            }
+        })
+        .await
+    }
+
+    /// Change the recovery email of the current account, which can be used to reset the
+    /// two-factor authentication password if it's forgotten.
+    ///
+    /// The new email must be confirmed (see [`PasswordError::EmailUnconfirmed`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::client::account::PasswordError;
+    ///
+    /// match client.set_recovery_email("secret", "me@example.com").await {
+    ///     Err(PasswordError::EmailUnconfirmed { code_length }) => {
+    ///         println!("Check your inbox for a {}-digit code", code_length);
+    ///     }
+    ///     result => result?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_recovery_email(
+        &self,
+        current_password: impl AsRef<[u8]>,
+        email: &str,
+    ) -> Result<(), PasswordError> {
+        self.update_password_settings(Some(current_password.as_ref()), |_| {
+            tl::types::account::PasswordInputSettings {
+                new_algo: None,
+                new_password_hash: None,
+                hint: None,
+                email: Some(email.to_string()),
+                new_secure_settings: None,
+            }
+        })
+        .await
+    }
+
+    /// Disable two-factor authentication for the current account, removing its password.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.disable_password("secret").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn disable_password(
+        &self,
+        current_password: impl AsRef<[u8]>,
+    ) -> Result<(), PasswordError> {
+        self.update_password_settings(Some(current_password.as_ref()), |_| {
+            tl::types::account::PasswordInputSettings {
+                new_algo: Some(tl::enums::PasswordKdfAlgo::Unknown),
+                new_password_hash: Some(Vec::new()),
+                hint: Some(String::new()),
+                email: None,
+                new_secure_settings: None,
+            }
+        })
+        .await
+    }
+
+    /// Confirm the recovery email with the code that was sent to it, which completes the
+    /// change that returned [`PasswordError::EmailUnconfirmed`].
+    pub async fn confirm_password_email(&self, code: &str) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::ConfirmPasswordEmail {
+            code: code.to_string(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Send the code to confirm the recovery email again.
+    pub async fn resend_password_email(&self) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::ResendPasswordEmail {})
+            .await
+            .map(drop)
+    }
+
+    /// Cancel the change that is waiting for the recovery email to be confirmed.
+    pub async fn cancel_password_email(&self) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::CancelPasswordEmail {})
+            .await
+            .map(drop)
+    }
+
+    /// Fetch the sessions logged in to the current account, including the one in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// for auth in client.get_authorizations().await? {
+    ///     println!("{} ({}) last active from {}", auth.device_model(), auth.app_name(), auth.ip());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_authorizations(&self) -> Result<Vec<Authorization>, InvocationError> {
+        let tl::enums::account::Authorizations::Authorizations(auths) = self
+            .invoke(&tl::functions::account::GetAuthorizations {})
+            .await?;
+
+        Ok(auths
+            .authorizations
+            .into_iter()
+            .map(Authorization::from_raw)
+            .collect())
+    }
+
+    /// Terminate another session of the current account, identified by the hash of its
+    /// authorization. Once terminated, that session will need to sign in again.
+    ///
+    /// The hash can be obtained with [`Authorization::hash`] from [`Client::get_authorizations`].
+    ///
+    /// Returns `false` if no session had the given hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// # let hash = 0;
+    /// if client.reset_authorization(hash).await? {
+    ///     println!("The session was terminated");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reset_authorization(&self, hash: i64) -> Result<bool, InvocationError> {
+        self.invoke(&tl::functions::account::ResetAuthorization { hash })
+            .await
+    }
+
+    /// Fetch how many days of inactivity must pass before the account is deleted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let days = client.get_account_ttl().await?;
+    /// println!("The account will self-destruct after {} days of inactivity", days);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_account_ttl(&self) -> Result<i32, InvocationError> {
+        let tl::enums::AccountDaysTtl::Ttl(ttl) = self
+            .invoke(&tl::functions::account::GetAccountTtl {})
+            .await?;
+        Ok(ttl.days)
+    }
+
+    /// Change how many days of inactivity must pass before the account is deleted.
+    ///
+    /// Telegram only accepts some values (currently, between 30 and 366 days), and will fail
+    /// with `TTL_DAYS_INVALID` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.set_account_ttl(365).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_account_ttl(&self, days: i32) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::SetAccountTtl {
+            ttl: tl::types::AccountDaysTtl { days }.into(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Fetch the rules of the given privacy setting, in the order they are applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::PrivacyKey;
+    ///
+    /// for rule in client.get_privacy(PrivacyKey::LastSeen).await? {
+    ///     println!("{:?}", rule);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_privacy(&self, key: PrivacyKey) -> Result<Vec<PrivacyRule>, InvocationError> {
+        let rules = self
+            .invoke(&tl::functions::account::GetPrivacy { key: key.to_raw() })
+            .await?;
+        Ok(privacy_rules(rules))
+    }
+
+    /// Replace the rules of the given privacy setting, returning the rules now in effect.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client, friend: grammers_client::types::Chat) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::{PrivacyKey, PrivacyRule};
+    ///
+    /// // Only the friend and contacts can see the phone number.
+    /// client
+    ///     .set_privacy(
+    ///         PrivacyKey::PhoneNumber,
+    ///         &[
+    ///             PrivacyRule::allow_users(vec![&friend]),
+    ///             PrivacyRule::AllowContacts,
+    ///             PrivacyRule::DisallowAll,
+    ///         ],
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_privacy(
+        &self,
+        key: PrivacyKey,
+        rules: &[PrivacyRule],
+    ) -> Result<Vec<PrivacyRule>, InvocationError> {
+        let rules = self
+            .invoke(&tl::functions::account::SetPrivacy {
+                key: key.to_raw(),
+                rules: rules.iter().map(PrivacyRule::to_raw).collect(),
+            })
+            .await?;
+        Ok(privacy_rules(rules))
+    }
+
+    /// Edit the profile of the logged-in account.
+    ///
+    /// Returns a new [`ProfileBuilder`] instance. Only the details which are set in the builder
+    /// are changed. Nothing is done until it is awaited, at which point the updated user is
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let me = client
+    ///     .update_profile()
+    ///     .first_name("Grammers")
+    ///     .bio("Powered by Rust")
+    ///     .await?;
+    ///
+    /// println!("Profile updated: {}", me.full_name());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update_profile(
+        &self,
+    ) -> ProfileBuilder<impl Future<Output = Result<User, InvocationError>>> {
+        ProfileBuilder::new(self.clone(), ProfileBuilderInner::invoke)
+    }
+
+    /// Check whether the given username is available to be used by the account.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// if client.check_username("grammers").await? {
+    ///     client.set_username("grammers").await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_username(&self, username: &str) -> Result<bool, UsernameError> {
+        match self
+            .invoke(&tl::functions::account::CheckUsername {
+                username: username.to_string(),
+            })
+            .await
+            .map_err(UsernameError::from)
+        {
+            Err(UsernameError::Occupied) => Ok(false),
+            result => result,
+        }
+    }
+
+    /// Change the public username of the account, returning the updated user.
+    ///
+    /// An empty username removes the current one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::UsernameError;
+    ///
+    /// match client.set_username("grammers").await {
+    ///     Ok(me) => println!("Now known as @{}", me.username().unwrap_or_default()),
+    ///     Err(UsernameError::Occupied) => println!("Someone else is using that username"),
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_username(&self, username: &str) -> Result<User, UsernameError> {
+        let user = User::from_raw(
+            self.invoke(&tl::functions::account::UpdateUsername {
+                username: username.to_string(),
+            })
+            .await?,
+        );
+        *self.0.self_user.lock("client.set_username") = Some(user.clone());
+        Ok(user)
+    }
+
+    /// Start a takeout session, used to export the data of the account.
+    ///
+    /// Requests sent through the returned [`Takeout`] are subject to much higher rate limits.
+    /// Telegram may require waiting some time before allowing the session to be started, and
+    /// fail with `TAKEOUT_INIT_DELAY` until then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::TakeoutOptions;
+    /// use grammers_tl_types as tl;
+    ///
+    /// let takeout = client
+    ///     .takeout(TakeoutOptions {
+    ///         contacts: true,
+    ///         ..Default::default()
+    ///     })
+    ///     .await?;
+    ///
+    /// let contacts = takeout
+    ///     .invoke(&tl::functions::contacts::GetSaved {})
+    ///     .await?;
+    /// println!("Exported {} contacts", contacts.len());
+    ///
+    /// takeout.finish(true).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn takeout(&self, options: TakeoutOptions) -> Result<Takeout, InvocationError> {
+        let tl::enums::account::Takeout::Takeout(takeout) = self.invoke(&options.to_raw()).await?;
+        Ok(Takeout::new(self.clone(), takeout.id))
+    }
+
+    async fn update_password_settings(
+        &self,
+        current_password: Option<&[u8]>,
+        settings: impl FnOnce(
+            &tl::types::account::Password,
+        ) -> tl::types::account::PasswordInputSettings,
+    ) -> Result<(), PasswordError> {
+        let tl::enums::account::Password::Password(info) =
+            self.invoke(&tl::functions::account::GetPassword {}).await?;
+
+        self.invoke(&tl::functions::account::UpdatePasswordSettings {
+            password: utils::password_srp(&info, current_password),
+            new_settings: settings(&info).into(),
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn privacy_rules(rules: tl::enums::account::PrivacyRules) -> Vec<PrivacyRule> {
+    let tl::enums::account::PrivacyRules::Rules(rules) = rules;
+    let chats = ChatMap::new(rules.users, rules.chats);
+    rules
+        .rules
+        .into_iter()
+        .map(|rule| PrivacyRule::from_raw(rule, &chats))
+        .collect()
+}
+
+/// Prepare the algorithm and hash used to store a new password, without sending the password.
+fn new_password_algo(
+    info: &tl::types::account::Password,
+    password: &[u8],
+) -> (tl::enums::PasswordKdfAlgo, Vec<u8>) {
+    let mut algo = match &info.new_algo {
+        tl::enums::PasswordKdfAlgo::Sha256Sha256Pbkdf2Hmacsha512iter100000Sha256ModPow(algo) => {
+            algo.clone()
+        }
+        tl::enums::PasswordKdfAlgo::Unknown => panic!("Unknown KDF (most likely, the client is outdated and does not support the specified KDF algorithm)"),
+    };
+    if !check_p_and_g(&algo.g, &algo.p) {
+        panic!("Failed to get correct password information from Telegram")
+    }
+
+    algo.salt1 = extend_new_salt(&algo.salt1);
+    let hash = calculate_password_hash(&algo.salt1, &algo.salt2, &algo.g, &algo.p, password);
+    (algo.into(), hash)
+}
@@ -5,14 +5,21 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::net::connect_sender;
+use super::net::{connect_sender, temp_auth_key_rotation};
 use super::Client;
 use crate::types::{LoginToken, PasswordToken, TermsOfService, User};
 use crate::utils;
 use grammers_crypto::two_factor_auth::{calculate_2fa, check_p_and_g};
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
+use grammers_session::MessageBox;
 use grammers_tl_types as tl;
+use log::warn;
 use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// How long to wait for the work in progress to finish when disconnecting after signing out.
+const SIGN_OUT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// The error type which is returned when signing in fails.
 #[derive(Debug)]
@@ -90,6 +97,7 @@ impl Client {
             .chat_hashes
             .lock("client.complete_login")
             .set_self_user(user.pack());
+        *self.0.self_user.lock("client.complete_login") = Some(user.clone());
 
         match self.invoke(&tl::functions::updates::GetState {}).await {
             Ok(state) => {
@@ -104,6 +112,7 @@ impl Client {
                 // `message_box` will try to correct its state as updates arrive.
             }
         }
+        self.autosave(true).await;
 
         Ok(user)
     }
@@ -116,6 +125,9 @@ impl Client {
     /// fails, it is recommended to [`Client::sign_out`]. If the session cannot be saved, then the
     /// authorization will be "lost" in the list of logged-in clients, since it is unaccessible.
     ///
+    /// The bot user is returned and cached, so that [`Client::get_me`] doesn't need to fetch it
+    /// again.
+    ///
     /// # Examples
     ///
     /// ```
@@ -158,12 +170,15 @@ impl Client {
 
         let result = match self.invoke(&request).await {
             Ok(x) => x,
-            Err(InvocationError::Rpc(err)) if err.is("USER_MIGRATE") => {
-                let dc_id = err.value.unwrap() as i32;
+            Err(InvocationError::Rpc(err)) if err.is("USER_MIGRATE_X") => {
+                let dc_id = err.value().unwrap() as i32;
                 let (sender, request_tx) = connect_sender(dc_id, &self.0.config).await?;
                 *self.0.sender.lock("client.bot_sign_in").await = sender;
                 *self.0.request_tx.lock("client.bot_sign_in") = request_tx;
                 *self.0.dc_id.lock("client.bot_sign_in") = dc_id;
+                *self.0.temp_auth_key_rotation.lock("client.bot_sign_in") =
+                    temp_auth_key_rotation(&self.0.config);
+                self.autosave(true).await;
                 self.invoke(&request).await?
             }
             Err(e) => return Err(e.into()),
@@ -228,18 +243,23 @@ impl Client {
 
         let sent_code: tl::types::auth::SentCode = match self.invoke(&request).await {
             Ok(x) => x.into(),
-            Err(InvocationError::Rpc(err)) if err.is("PHONE_MIGRATE") => {
+            Err(InvocationError::Rpc(err)) if err.is("PHONE_MIGRATE_X") => {
                 // Since we are not logged in (we're literally requesting for
                 // the code to login now), there's no need to export the current
                 // authorization and re-import it at a different datacenter.
                 //
                 // Just connect and generate a new authorization key with it
                 // before trying again.
-                let dc_id = err.value.unwrap() as i32;
+                let dc_id = err.value().unwrap() as i32;
                 let (sender, request_tx) = connect_sender(dc_id, &self.0.config).await?;
                 *self.0.sender.lock("client.request_login_code").await = sender;
                 *self.0.request_tx.lock("client.request_login_code") = request_tx;
                 *self.0.dc_id.lock("client.request_login_code") = dc_id;
+                *self
+                    .0
+                    .temp_auth_key_rotation
+                    .lock("client.request_login_code") = temp_auth_key_rotation(&self.0.config);
+                self.autosave(true).await;
                 self.invoke(&request).await?.into()
             }
             Err(e) => return Err(e.into()),
@@ -353,17 +373,15 @@ impl Client {
     /// // ... enter phone number, request login code ...
     ///
     /// let user = match client.sign_in(&token, &code).await {
-    ///     Err(SignInError::PasswordRequired(password_token) ) => {
-    ///         let mut password = get_user_password(password_token.hint().unwrap());
+    ///     Err(SignInError::PasswordRequired(password_token)) => {
+    ///         let hint = password_token.hint().cloned().unwrap_or_default();
+    ///         let password = get_user_password(&hint);
     ///
-    ///         client
-    ///             .check_password(password_token, password)
-    ///             .await.unwrap()
+    ///         client.check_password(password_token, password).await?
     ///     }
     ///     Ok(user) => user,
-    ///     Ok(_) => panic!("Sign in required"),
     ///     Err(err) => {
-    ///         panic!("Failed to sign in as a user :(\n{}");
+    ///         panic!("Failed to sign in as a user :(\n{}", err);
     ///     }
     /// };
     /// # Ok(())
@@ -374,45 +392,123 @@ impl Client {
         password_token: PasswordToken,
         password: impl AsRef<[u8]>,
     ) -> Result<User, SignInError> {
+        let password = password.as_ref();
         let mut password_info = password_token.password;
-        let current_algo = password_info.current_algo.unwrap();
-        let mut params = utils::extract_password_parameters(&current_algo);
-
-        // Telegram sent us incorrect parameters, trying to get them again
-        if !check_p_and_g(params.2, params.3) {
-            password_info = self
-                .get_password_information()
-                .await
-                .map_err(SignInError::Other)?
-                .password;
-            params =
-                utils::extract_password_parameters(password_info.current_algo.as_ref().unwrap());
+        let mut refreshed = false;
+
+        loop {
+            let current_algo = password_info.current_algo.clone().unwrap();
+            let mut params = utils::extract_password_parameters(&current_algo);
+
+            // Telegram sent us incorrect parameters, trying to get them again
             if !check_p_and_g(params.2, params.3) {
-                panic!("Failed to get correct password information from Telegram")
+                password_info = self
+                    .get_password_information()
+                    .await
+                    .map_err(SignInError::Other)?
+                    .password;
+                params = utils::extract_password_parameters(
+                    password_info.current_algo.as_ref().unwrap(),
+                );
+                if !check_p_and_g(params.2, params.3) {
+                    panic!("Failed to get correct password information from Telegram")
+                }
             }
-        }
 
-        let (salt1, salt2, g, p) = params;
+            let (salt1, salt2, g, p) = params;
 
-        let g_b = password_info.srp_b.unwrap();
-        let a: Vec<u8> = password_info.secure_random;
+            let g_b = password_info.srp_b.clone().unwrap();
+            let a: Vec<u8> = password_info.secure_random.clone();
 
-        let (m1, g_a) = calculate_2fa(salt1, salt2, g, p, g_b, a, password);
+            let (m1, g_a) = calculate_2fa(salt1, salt2, g, p, g_b, a, password);
 
-        let check_password = tl::functions::auth::CheckPassword {
-            password: tl::enums::InputCheckPasswordSrp::Srp(tl::types::InputCheckPasswordSrp {
-                srp_id: password_info.srp_id.unwrap(),
-                a: g_a,
-                m1,
-            }),
-        };
+            let check_password = tl::functions::auth::CheckPassword {
+                password: tl::enums::InputCheckPasswordSrp::Srp(tl::types::InputCheckPasswordSrp {
+                    srp_id: password_info.srp_id.unwrap(),
+                    a: g_a,
+                    m1,
+                }),
+            };
+
+            match self.invoke(&check_password).await {
+                Ok(tl::enums::auth::Authorization::Authorization(x)) => {
+                    break self.complete_login(x).await.map_err(SignInError::Other);
+                }
+                Ok(tl::enums::auth::Authorization::SignUpRequired(_x)) => {
+                    panic!("Unexpected result")
+                }
+                Err(err) if err.is("PASSWORD_HASH_INVALID") => {
+                    break Err(SignInError::InvalidPassword)
+                }
+                // The SRP parameters expire after a while, so they are fetched again (only once,
+                // to avoid looping forever if something else is wrong).
+                Err(err) if err.is("SRP_ID_INVALID") && !refreshed => {
+                    refreshed = true;
+                    password_info = self
+                        .get_password_information()
+                        .await
+                        .map_err(SignInError::Other)?
+                        .password;
+                }
+                Err(error) => break Err(SignInError::Other(error)),
+            }
+        }
+    }
+
+    /// Request a code to recover access to an account whose two-factor authentication password
+    /// was forgotten. The code is sent to the recovery email, and a pattern of the email address
+    /// is returned (for example, `"m**@e******.com"`).
+    ///
+    /// This only works if a recovery email was set, which can be checked with
+    /// [`PasswordToken::has_recovery`]. Use the code with [`Client::recover_password`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let email_pattern = client.request_password_recovery().await?;
+    /// println!("Enter the code sent to {}", email_pattern);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn request_password_recovery(&mut self) -> Result<String, InvocationError> {
+        let tl::enums::auth::PasswordRecovery::Recovery(recovery) = self
+            .invoke(&tl::functions::auth::RequestPasswordRecovery {})
+            .await?;
+        Ok(recovery.email_pattern)
+    }
 
-        match self.invoke(&check_password).await {
+    /// Sign in using the code sent to the recovery email by [`Client::request_password_recovery`],
+    /// instead of the two-factor authentication password.
+    ///
+    /// Note that doing so will disable the two-factor authentication of the account.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// fn ask_code_to_user() -> String {
+    ///     unimplemented!()
+    /// }
+    ///
+    /// client.request_password_recovery().await?;
+    /// let user = client.recover_password(&ask_code_to_user()).await?;
+    /// println!("Signed in as {}", user.first_name());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn recover_password(&mut self, code: &str) -> Result<User, SignInError> {
+        match self
+            .invoke(&tl::functions::auth::RecoverPassword {
+                code: code.to_string(),
+            })
+            .await
+        {
             Ok(tl::enums::auth::Authorization::Authorization(x)) => {
                 self.complete_login(x).await.map_err(SignInError::Other)
             }
             Ok(tl::enums::auth::Authorization::SignUpRequired(_x)) => panic!("Unexpected result"),
-            Err(err) if err.is("PASSWORD_HASH_INVALID") => Err(SignInError::InvalidPassword),
+            Err(err) if err.is("CODE_*") => Err(SignInError::InvalidCode),
             Err(error) => Err(SignInError::Other(error)),
         }
     }
@@ -423,6 +519,9 @@ impl Client {
     /// [`SignInError::SignUpRequired`]. This is also the only way to know if a certain phone
     /// number is already reigstered on Telegram or not, by trying and failing to login.
     ///
+    /// If [`SignInError::SignUpRequired`] included Terms of Service, they should be accepted with
+    /// [`Client::accept_terms_of_service`] after signing up.
+    ///
     /// It is recommended to save the [`Client::session()`] on successful sign up, and if saving
     /// fails, it is recommended to [`Client::sign_out`]. If the session cannot be saved, then the
     /// authorization will be "lost" in the list of logged-in clients, since it is unaccessible.
@@ -447,7 +546,11 @@ impl Client {
     ///     }
     ///     Err(SignInError::SignUpRequired { terms_of_service }) => {
     ///         println!("Signing up! You must agree to these TOS: {:?}", terms_of_service);
-    ///         client.sign_up(&token, "My first name", "(optional last name)").await?
+    ///         let user = client.sign_up(&token, "My first name", "(optional last name)").await?;
+    ///         if let Some(tos) = terms_of_service {
+    ///             client.accept_terms_of_service(&tos).await?;
+    ///         }
+    ///         user
     ///     }
     ///     Err(err) => {
     ///         println!("Something else went wrong... {}", err);
@@ -465,7 +568,6 @@ impl Client {
         first_name: &str,
         last_name: &str,
     ) -> Result<User, AuthorizationError> {
-        // TODO accept tos? maybe accept method in the tos object?
         match self
             .invoke(&tl::functions::auth::SignUp {
                 phone_number: token.phone.clone(),
@@ -485,11 +587,40 @@ impl Client {
         }
     }
 
+    /// Accept the given Terms of Service, which should be done right after signing up if
+    /// [`SignInError::SignUpRequired`] included them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(tos: grammers_client::types::TermsOfService, mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// println!("{}", tos.text());
+    /// client.accept_terms_of_service(&tos).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn accept_terms_of_service(
+        &mut self,
+        terms_of_service: &TermsOfService,
+    ) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::help::AcceptTermsOfService {
+            id: terms_of_service.id(),
+        })
+        .await
+        .map(drop)
+    }
+
     /// Signs out of the account authorized by this client's session.
     ///
     /// If the client was not logged in, this method returns false.
     ///
-    /// The client is not disconnected after signing out.
+    /// The client is not disconnected after signing out, but its authorization key is no longer
+    /// valid, so it's also removed from the session (along with the user and update state). The
+    /// session should be saved afterwards so that the key is wiped from storage as well, and a
+    /// new client must be connected to sign in again.
+    ///
+    /// If a [`crate::InitParams::session_storage`] is configured, the session data is deleted from
+    /// it instead.
     ///
     /// Note that after using this method you will have to sign in again. If all you want to do
     /// is disconnect, simply [`drop`] the [`Client`] instance.
@@ -507,7 +638,30 @@ impl Client {
     /// # }
     /// ```
     pub async fn sign_out(&mut self) -> Result<bool, InvocationError> {
-        self.invoke(&tl::functions::auth::LogOut {}).await
+        let signed_out = self.invoke(&tl::functions::auth::LogOut {}).await?;
+        self.forget_authorization().await;
+        Ok(signed_out)
+    }
+
+    /// Remove the authorization from the session and the configured storage, as needed once it's
+    /// no longer valid.
+    async fn forget_authorization(&self) {
+        self.0.config.session.clear_authorization();
+        *self.0.message_box.lock("client.forget_authorization") = MessageBox::new();
+        *self.0.self_user.lock("client.forget_authorization") = None;
+        // The authorization imported in other datacenters is no longer valid either.
+        self.0
+            .dc_senders
+            .lock("client.forget_authorization")
+            .clear();
+        if let Some(storage) = &self.0.config.params.session_storage {
+            if let Err(e) = storage.delete().await {
+                warn!(
+                    "failed to delete session from storage after signing out: {}",
+                    e
+                );
+            }
+        }
     }
 
     /// Synchronize all state to the session file and provide mutable access to it.
@@ -520,11 +674,90 @@ impl Client {
         &self.0.config.session
     }
 
-    /// Calls [`Client::sign_out`] and disconnects.
+    /// Synchronize all state to the session and save it to the configured
+    /// [`crate::InitParams::session_storage`].
+    ///
+    /// Fails if no storage was configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.save_session().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn save_session(&self) -> io::Result<()> {
+        match &self.0.config.params.session_storage {
+            Some(storage) => self.session().save_to_async_storage(storage.as_ref()).await,
+            None => Err(io::Error::other("no session storage was configured")),
+        }
+    }
+
+    /// Calls [`Client::sign_out`] and disconnects by using [`Client::shutdown`].
     ///
     /// The client will be disconnected even if signing out fails.
     pub async fn sign_out_disconnect(&mut self) -> Result<(), InvocationError> {
-        let _res = self.invoke(&tl::functions::auth::LogOut {}).await;
-        panic!("disconnect now only works via dropping");
+        let result = self.sign_out().await;
+        self.shutdown(SIGN_OUT_SHUTDOWN_TIMEOUT).await;
+        result.map(drop)
+    }
+
+    /// Asks Telegram to destroy the authorization key used by this client, which also signs out
+    /// of the account authorized with it, and disconnects by using [`Client::shutdown`].
+    ///
+    /// This fully revokes the credentials stored in the session, as needed when decommissioning
+    /// a deployment. If the key is destroyed, the session is cleared as with [`Client::sign_out`].
+    /// When using [`crate::InitParams::perfect_forward_secrecy`], only the temporary key in use
+    /// is destroyed, so [`Client::sign_out`] should be used first.
+    ///
+    /// Returns `false` if Telegram failed to destroy the key, in which case the session is kept
+    /// and the client is not disconnected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// if !client.destroy_auth_key().await? {
+    ///     println!("The authorization key could not be destroyed");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn destroy_auth_key(&mut self) -> Result<bool, InvocationError> {
+        use tl::enums::DestroyAuthKeyRes;
+
+        match self.invoke(&tl::functions::DestroyAuthKey {}).await? {
+            DestroyAuthKeyRes::DestroyAuthKeyOk | DestroyAuthKeyRes::DestroyAuthKeyNone => {}
+            DestroyAuthKeyRes::DestroyAuthKeyFail => return Ok(false),
+        }
+        self.forget_authorization().await;
+        self.shutdown(SIGN_OUT_SHUTDOWN_TIMEOUT).await;
+        Ok(true)
+    }
+
+    /// Asks Telegram to forget the data of a different session using the same authorization
+    /// key, such as one left behind by a previous connection which is no longer used.
+    ///
+    /// Returns `false` if there was no such session. The result of destroying the session in use
+    /// by this client is undefined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let stale_session_id = 1234;
+    /// client.destroy_session(stale_session_id).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn destroy_session(&self, session_id: i64) -> Result<bool, InvocationError> {
+        match self
+            .invoke(&tl::functions::DestroySession { session_id })
+            .await?
+        {
+            tl::enums::DestroySessionRes::DestroySessionOk(_) => Ok(true),
+            tl::enums::DestroySessionRes::DestroySessionNone(_) => Ok(false),
+        }
     }
 }
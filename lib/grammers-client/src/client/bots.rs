@@ -5,7 +5,9 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use crate::types::IterBuffer;
+use crate::types::{
+    BotCommand, ChatMap, GameHighScore, InlineMessageId, InputMessage, Invoice, IterBuffer, Message,
+};
 use crate::utils::generate_random_id;
 use crate::Client;
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
@@ -149,4 +151,240 @@ impl Client {
     pub fn inline_query<C: Into<PackedChat>>(&self, bot: C, query: &str) -> InlineResultIter {
         InlineResultIter::new(self, bot.into(), query)
     }
+
+    /// Set the list of commands supported by the logged-in bot, replacing the previous list.
+    ///
+    /// The commands are shown to users when they type "/" in the chat with the bot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::BotCommand;
+    ///
+    /// client.set_bot_commands(&[
+    ///     BotCommand::new("start", "Start talking with the bot"),
+    ///     BotCommand::new("help", "Show what the bot can do"),
+    /// ]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_bot_commands(&self, commands: &[BotCommand]) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::bots::SetBotCommands {
+            commands: commands.iter().map(BotCommand::to_raw).collect(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Get the list of commands supported by the logged-in bot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// for command in client.get_bot_commands().await? {
+    ///     println!("/{} - {}", command.command, command.description);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_bot_commands(&self) -> Result<Vec<BotCommand>, InvocationError> {
+        let tl::enums::UserFull::Full(full) = self
+            .invoke(&tl::functions::users::GetFullUser {
+                id: tl::enums::InputUser::UserSelf,
+            })
+            .await?;
+
+        Ok(match full.bot_info {
+            Some(tl::enums::BotInfo::Info(info)) => info
+                .commands
+                .into_iter()
+                .map(BotCommand::from_raw)
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// Edit a message sent via inline mode by the logged-in bot.
+    ///
+    /// These messages don't belong to any chat the bot is in, so they're identified by the
+    /// [`InlineMessageId`] obtained from [`crate::types::InlineSend::message_id`] instead.
+    ///
+    /// The edit must be made in the datacenter where the message is stored, so if it's not the
+    /// same one the client is connected to, a temporary connection will be made to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(inline_send: grammers_client::types::InlineSend, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(message_id) = inline_send.message_id() {
+    ///     client.edit_inline_message(&message_id, "New text message").await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn edit_inline_message<M: Into<InputMessage>>(
+        &self,
+        message_id: &InlineMessageId,
+        new_message: M,
+    ) -> Result<(), InvocationError> {
+        let new_message = new_message.into();
+        self.invoke_in_dc(
+            message_id.dc_id(),
+            &tl::functions::messages::EditInlineBotMessage {
+                no_webpage: !new_message.link_preview,
+                id: message_id.0.clone().into(),
+                message: Some(new_message.text),
+                media: new_message.media,
+                reply_markup: new_message.reply_markup,
+                entities: Some(new_message.entities),
+            },
+        )
+        .await
+        .map(drop)
+    }
+
+    /// Send the game with the given short name, as registered with @BotFather, to a chat.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.send_game(&chat, "tetris").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_game<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        short_name: &str,
+    ) -> Result<Message, InvocationError> {
+        let mut message = InputMessage::default();
+        message.media = Some(
+            tl::types::InputMediaGame {
+                id: tl::types::InputGameShortName {
+                    bot_id: tl::enums::InputUser::UserSelf,
+                    short_name: short_name.to_string(),
+                }
+                .into(),
+            }
+            .into(),
+        );
+        self.send_message(chat, message).await
+    }
+
+    /// Send an invoice to a chat, which users can pay to the bot.
+    ///
+    /// Once a user confirms the payment, an [`crate::Update::PreCheckoutQuery`] will be received,
+    /// which must be answered for the payment to complete. A message with a
+    /// [`crate::types::Message::successful_payment`] will be received after that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::Invoice;
+    ///
+    /// let invoice = Invoice::new("Coffee", "A cup of coffee", "order-1", "provider-token", "USD")
+    ///     .price("Coffee", 250)
+    ///     .price("Tax", 25);
+    ///
+    /// client.send_invoice(&chat, &invoice).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_invoice<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        invoice: &Invoice,
+    ) -> Result<Message, InvocationError> {
+        let mut message = InputMessage::default();
+        message.media = Some(invoice.to_input_media());
+        self.send_message(chat, message).await
+    }
+
+    /// Set the score of a user in the game sent in the given message.
+    ///
+    /// The message is edited to show the new score. Telegram will refuse to lower the score of
+    /// a user unless `force` is `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, user: grammers_client::types::User, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let game_message_id = 123;
+    /// client.set_game_score(&chat, game_message_id, &user, 9000, false).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_game_score<C: Into<PackedChat>, U: Into<PackedChat>>(
+        &self,
+        chat: C,
+        message_id: i32,
+        user: U,
+        score: i32,
+        force: bool,
+    ) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::messages::SetGameScore {
+            edit_message: true,
+            force,
+            peer: chat.into().to_input_peer(),
+            id: message_id,
+            user_id: user.into().to_input_user_lossy(),
+            score,
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Get the high scores of the game sent in the given message, around the score of the
+    /// given user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, user: grammers_client::types::User, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let game_message_id = 123;
+    /// for score in client.get_game_high_scores(&chat, game_message_id, &user).await? {
+    ///     println!("{}. {} - {}", score.position(), score.user().first_name(), score.score());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_game_high_scores<C: Into<PackedChat>, U: Into<PackedChat>>(
+        &self,
+        chat: C,
+        message_id: i32,
+        user: U,
+    ) -> Result<Vec<GameHighScore>, InvocationError> {
+        let tl::enums::messages::HighScores::Scores(scores) = self
+            .invoke(&tl::functions::messages::GetGameHighScores {
+                peer: chat.into().to_input_peer(),
+                id: message_id,
+                user_id: user.into().to_input_user_lossy(),
+            })
+            .await?;
+
+        let chats = ChatMap::new(scores.users, Vec::new());
+        Ok(scores
+            .scores
+            .into_iter()
+            .filter_map(|score| GameHighScore::from_raw(score, &chats))
+            .collect())
+    }
+
+    /// Remove all the commands of the logged-in bot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.reset_bot_commands().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reset_bot_commands(&self) -> Result<(), InvocationError> {
+        self.set_bot_commands(&[]).await
+    }
 }
@@ -10,20 +10,22 @@
 
 use super::Client;
 use crate::types::{
-    chats::AdminRightsBuilderInner, chats::BannedRightsBuilderInner, AdminRightsBuilder,
-    BannedRightsBuilder, Chat, ChatMap, IterBuffer, Message, Participant, Photo, User,
+    chats::AdminRightsBuilderInner, chats::BannedRightsBuilderInner, AdminLogEntry,
+    AdminRightsBuilder, BannedRightsBuilder, Chat, ChatMap, IterBuffer, Message, Participant,
+    Photo, Presence, Uploaded, User,
 };
 use grammers_mtproto::mtp::RpcError;
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
 use grammers_session::{PackedChat, PackedType};
 use grammers_tl_types as tl;
+use log::warn;
 use std::collections::VecDeque;
 use std::future::Future;
-use std::sync::Arc;
 use std::time::Duration;
 
 const MAX_PARTICIPANT_LIMIT: usize = 200;
 const MAX_PHOTO_LIMIT: usize = 100;
+const MAX_ADMIN_LOG_LIMIT: usize = 100;
 const KICK_BAN_DURATION: i32 = 60; // in seconds, in case the second request fails
 
 pub enum ParticipantIter {
@@ -117,13 +119,12 @@ impl ParticipantIter {
                 };
 
                 // Don't actually care for the chats, just the users.
-                let mut chats = ChatMap::new(full.users, Vec::new());
-                let chats = Arc::get_mut(&mut chats).unwrap();
+                let chats = ChatMap::new(full.users, Vec::new());
 
                 buffer.extend(
                     participants
                         .into_iter()
-                        .map(|p| Participant::from_raw_chat(chats, p)),
+                        .map(|p| Participant::from_raw_chat(&chats, p)),
                 );
 
                 *total = Some(buffer.len());
@@ -146,13 +147,12 @@ impl ParticipantIter {
                 }
 
                 // Don't actually care for the chats, just the users.
-                let mut chats = ChatMap::new(users, Vec::new());
-                let chats = Arc::get_mut(&mut chats).unwrap();
+                let chats = ChatMap::new(users, Vec::new());
 
                 iter.buffer.extend(
                     participants
                         .into_iter()
-                        .map(|p| Participant::from_raw_channel(chats, p)),
+                        .map(|p| Participant::from_raw_channel(&chats, p)),
                 );
 
                 iter.total = Some(count as usize);
@@ -311,12 +311,202 @@ impl ProfilePhotoIter {
     }
 }
 
+pub type AdminLogIter = IterBuffer<tl::functions::channels::GetAdminLog, AdminLogEntry>;
+
+impl AdminLogIter {
+    fn new(client: &Client, chat: PackedChat) -> Self {
+        Self::from_request(
+            client,
+            MAX_ADMIN_LOG_LIMIT,
+            tl::functions::channels::GetAdminLog {
+                channel: chat
+                    .try_to_input_channel()
+                    .unwrap_or(tl::enums::InputChannel::Empty),
+                q: String::new(),
+                events_filter: None,
+                admins: None,
+                max_id: 0,
+                min_id: 0,
+                limit: 0,
+            },
+        )
+    }
+
+    /// Changes the query of the search. Only events containing this text will be returned.
+    pub fn query(mut self, query: &str) -> Self {
+        self.request.q = query.to_string();
+        self
+    }
+
+    /// Only return the events caused by the given administrators.
+    pub fn admins<C: Into<PackedChat>, I: IntoIterator<Item = C>>(mut self, admins: I) -> Self {
+        self.request.admins = Some(
+            admins
+                .into_iter()
+                .filter_map(|admin| admin.into().try_to_input_user())
+                .collect(),
+        );
+        self
+    }
+
+    /// Changes the raw event filter. Only events matching this filter will be returned.
+    ///
+    /// Prefer using the presets such as [`AdminLogIter::only_bans`] when possible.
+    pub fn filter(mut self, filter: tl::enums::ChannelAdminLogEventsFilter) -> Self {
+        self.request.events_filter = Some(filter);
+        self
+    }
+
+    /// Only return events where participants were banned, unbanned, kicked or unkicked.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_bans(self) -> Self {
+        self.extend_filter(|f| {
+            f.ban = true;
+            f.unban = true;
+            f.kick = true;
+            f.unkick = true;
+        })
+    }
+
+    /// Only return events where participants joined or were invited.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_joins(self) -> Self {
+        self.extend_filter(|f| {
+            f.join = true;
+            f.invite = true;
+        })
+    }
+
+    /// Only return events where participants left.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_leaves(self) -> Self {
+        self.extend_filter(|f| f.leave = true)
+    }
+
+    /// Only return events where participants were promoted or demoted.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_promotions(self) -> Self {
+        self.extend_filter(|f| {
+            f.promote = true;
+            f.demote = true;
+        })
+    }
+
+    /// Only return events where messages were edited or deleted.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_edits_and_deletes(self) -> Self {
+        self.extend_filter(|f| {
+            f.edit = true;
+            f.delete = true;
+        })
+    }
+
+    /// Only return events where messages were pinned.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_pins(self) -> Self {
+        self.extend_filter(|f| f.pinned = true)
+    }
+
+    /// Only return events where the information (such as the title or photo) or the settings of
+    /// the channel were changed.
+    ///
+    /// The presets can be combined, in which case events matching any of them are returned.
+    pub fn only_info_changes(self) -> Self {
+        self.extend_filter(|f| {
+            f.info = true;
+            f.settings = true;
+        })
+    }
+
+    fn extend_filter<F: FnOnce(&mut tl::types::ChannelAdminLogEventsFilter)>(
+        mut self,
+        f: F,
+    ) -> Self {
+        let mut filter = match self.request.events_filter.take() {
+            Some(tl::enums::ChannelAdminLogEventsFilter::Filter(filter)) => filter,
+            None => tl::types::ChannelAdminLogEventsFilter {
+                join: false,
+                leave: false,
+                invite: false,
+                ban: false,
+                unban: false,
+                kick: false,
+                unkick: false,
+                promote: false,
+                demote: false,
+                info: false,
+                settings: false,
+                pinned: false,
+                edit: false,
+                delete: false,
+                group_call: false,
+                invites: false,
+            },
+        };
+        f(&mut filter);
+        self.request.events_filter = Some(filter.into());
+        self
+    }
+
+    /// Return the next `AdminLogEntry` from the internal buffer, filling the buffer previously if
+    /// it's empty.
+    ///
+    /// Returns `None` if the `limit` is reached or there are no events left.
+    pub async fn next(&mut self) -> Result<Option<AdminLogEntry>, InvocationError> {
+        if let Some(result) = self.next_raw() {
+            return result;
+        }
+
+        // Only channels have an administrator log, so don't bother asking for anything else.
+        if let tl::enums::InputChannel::Empty = self.request.channel {
+            return Err(InvocationError::Rpc(RpcError {
+                code: 400,
+                name: "CHANNEL_INVALID".to_string(),
+                value: None,
+                caused_by: None,
+            }));
+        }
+
+        self.request.limit = self.determine_limit(MAX_ADMIN_LOG_LIMIT);
+        let tl::enums::channels::AdminLogResults::Results(results) =
+            self.client.invoke(&self.request).await?;
+
+        self.last_chunk = results.events.len() < self.request.limit as usize;
+
+        let chats = ChatMap::new(results.users, results.chats);
+        let client = self.client.clone();
+        self.buffer.extend(
+            results
+                .events
+                .into_iter()
+                .map(|event| AdminLogEntry::from_raw(&client, event, &chats)),
+        );
+
+        // Don't bother updating offsets if this is the last time stuff has to be fetched.
+        if !self.last_chunk && !self.buffer.is_empty() {
+            self.request.max_id = self.buffer[self.buffer.len() - 1].id();
+        }
+
+        Ok(self.pop_item())
+    }
+}
+
 /// Method implementations related to dealing with chats or other users.
 impl Client {
     /// Resolves a username into the chat that owns it, if any.
     ///
     /// Note that this method is expensive to call, and can quickly cause long flood waits.
     ///
+    /// If the username belongs to a chat the client has already seen, the cheaper
+    /// [`Client::unpack_chat`] is used instead of resolving it again. Should the chat no longer
+    /// own that username, the stale cache entry is dropped and the username is resolved.
+    ///
     /// # Examples
     ///
     /// ```
@@ -331,6 +521,23 @@ impl Client {
         &mut self,
         username: &str,
     ) -> Result<Option<Chat>, InvocationError> {
+        let cached = self
+            .0
+            .chat_hashes
+            .lock("client.resolve_username")
+            .get_by_username(username);
+        if let Some(packed) = cached {
+            match self.unpack_chat(packed).await {
+                Ok(chat) if username_matches(&chat, username) => return Ok(Some(chat)),
+                Ok(_) => {}
+                Err(e) => warn!("failed to unpack cached chat for {}: {}", username, e),
+            }
+            self.0
+                .chat_hashes
+                .lock("client.resolve_username")
+                .forget_username(username);
+        }
+
         let tl::types::contacts::ResolvedPeer { peer, users, chats } = match self
             .invoke(&tl::functions::contacts::ResolveUsername {
                 username: username.into(),
@@ -341,6 +548,10 @@ impl Client {
             Err(err) if err.is("USERNAME_NOT_OCCUPIED") => return Ok(None),
             Err(err) => return Err(err),
         };
+        self.0
+            .chat_hashes
+            .lock("client.resolve_username")
+            .extend(&users, &chats);
 
         Ok(match peer {
             tl::enums::Peer::User(tl::types::PeerUser { user_id }) => users
@@ -359,7 +570,8 @@ impl Client {
 
     /// Fetch full information about the currently logged-in user.
     ///
-    /// Although this method is cheap to call, you might want to cache the results somewhere.
+    /// The user is cached once known (after signing in, or after the first call), in which case
+    /// no request is made.
     ///
     /// # Examples
     ///
@@ -371,6 +583,10 @@ impl Client {
     /// # }
     /// ```
     pub async fn get_me(&mut self) -> Result<User, InvocationError> {
+        if let Some(user) = self.0.self_user.lock("client.get_me").clone() {
+            return Ok(user);
+        }
+
         let mut res = self
             .invoke(&tl::functions::users::GetUsers {
                 id: vec![tl::enums::InputUser::UserSelf],
@@ -381,7 +597,81 @@ impl Client {
             panic!("fetching only one user should exactly return one user");
         }
 
-        Ok(User::from_raw(res.pop().unwrap()))
+        let user = User::from_raw(res.pop().unwrap());
+        *self.0.self_user.lock("client.get_me") = Some(user.clone());
+        Ok(user)
+    }
+
+    /// Get the logged-in user, if it's already known, without making any request.
+    ///
+    /// The user is known after signing in, or after calling [`Client::get_me`] once, and it's
+    /// kept up-to-date with the changes received as updates. Use [`Client::get_me`] instead to
+    /// fetch the user when it's not known yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// if let Some(me) = client.me() {
+    ///     println!("Logged in as {}", me.first_name());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn me(&self) -> Option<User> {
+        self.0.self_user.lock("client.me").clone()
+    }
+
+    /// Whether the logged-in account is a bot account.
+    ///
+    /// This is known from the session, so no request is made. It will be `false` if the account
+    /// is not signed in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn f(client: grammers_client::Client) {
+    /// if client.is_bot() {
+    ///     println!("Running as a bot");
+    /// }
+    /// # }
+    /// ```
+    pub fn is_bot(&self) -> bool {
+        self.0.chat_hashes.lock("client.is_bot").is_self_bot()
+    }
+
+    /// Fetch the current presence of a user, that is, whether they're online or when they were
+    /// last seen.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(user: grammers_client::types::User, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::Presence;
+    ///
+    /// match client.get_status(&user).await? {
+    ///     Presence::Online { .. } => println!("{} is online", user.full_name()),
+    ///     Presence::Offline { last_seen } => println!("{} was last seen at {}", user.full_name(), last_seen),
+    ///     _ => println!("{} hides their last seen time", user.full_name()),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_status<C: Into<PackedChat>>(
+        &self,
+        user: C,
+    ) -> Result<Presence, InvocationError> {
+        let mut res = self
+            .invoke(&tl::functions::users::GetUsers {
+                id: vec![user.into().to_input_user_lossy()],
+            })
+            .await?;
+
+        if res.len() != 1 {
+            panic!("fetching only one user should exactly return one user");
+        }
+
+        Ok(User::from_raw(res.pop().unwrap()).presence())
     }
 
     /// Iterate over the participants of a chat.
@@ -614,6 +904,40 @@ impl Client {
         )
     }
 
+    /// Iterate over the administrator log of the given channel, from most recent to oldest.
+    ///
+    /// Only administrators can fetch the log, which contains the events of the last 48 hours.
+    /// The iterator offers presets to narrow down which events are returned.
+    ///
+    /// Small group chats have no log, so iterating over theirs (or that of a user) fails with
+    /// `CHANNEL_INVALID` without contacting Telegram.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::AdminLogEvent;
+    ///
+    /// let mut log = client.iter_admin_log(&chat).only_edits_and_deletes();
+    ///
+    /// while let Some(entry) = log.next().await? {
+    ///     match entry.event() {
+    ///         AdminLogEvent::EditMessage { old, new } => {
+    ///             println!("{} edited {:?} to {:?}", entry.user().name(), old.text(), new.text());
+    ///         }
+    ///         AdminLogEvent::DeleteMessage(message) => {
+    ///             println!("{} deleted {:?}", entry.user().name(), message.text());
+    ///         }
+    ///         _ => {}
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn iter_admin_log<C: Into<PackedChat>>(&self, chat: C) -> AdminLogIter {
+        AdminLogIter::new(self, chat.into())
+    }
+
     /// Iterate over the history of profile photos for the given user or chat.
     ///
     /// Note that the current photo might not be present in the history, and to avoid doing more
@@ -765,6 +1089,232 @@ impl Client {
         let permissions = ParticipantPermissions::Channel(participant.participant);
         Ok(permissions)
     }
+
+    /// Set a new profile photo for the currently logged-in user.
+    ///
+    /// The photo must have been previously uploaded with [`Client::upload_file`] or
+    /// [`Client::upload_stream`].
+    ///
+    /// The new photo is returned on success.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let uploaded = client.upload_file("/home/username/photos/me.jpg").await?;
+    /// let photo = client.set_profile_photo(uploaded).await?;
+    /// println!("New profile photo has ID {}", photo.id());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_profile_photo(&self, photo: Uploaded) -> Result<Photo, InvocationError> {
+        self.upload_profile_photo(tl::types::InputChatUploadedPhoto {
+            file: Some(photo.input_file),
+            video: None,
+            video_start_ts: None,
+        })
+        .await
+    }
+
+    /// Set a new animated profile photo (a "video avatar") for the currently logged-in user.
+    ///
+    /// The video must have been previously uploaded, and should be a short, square MPEG4 video
+    /// without sound. `video_start` determines which frame of the video will be used as the
+    /// static preview (the start of the video is used if `None`).
+    ///
+    /// The new photo is returned on success.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// let uploaded = client.upload_file("/home/username/videos/me.mp4").await?;
+    /// client.set_profile_video(uploaded, Some(Duration::from_millis(1500))).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_profile_video(
+        &self,
+        video: Uploaded,
+        video_start: Option<Duration>,
+    ) -> Result<Photo, InvocationError> {
+        self.upload_profile_photo(tl::types::InputChatUploadedPhoto {
+            file: None,
+            video: Some(video.input_file),
+            video_start_ts: video_start.map(|d| d.as_secs_f64()),
+        })
+        .await
+    }
+
+    /// Make one of the photos from the history of the currently logged-in user the profile photo.
+    ///
+    /// This is the way to reorder the profile photos, since the chosen photo is moved to the
+    /// front of the history.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let me = client.get_me().await?;
+    /// let mut photos = client.iter_profile_photos(&me);
+    /// let _current = photos.next().await?;
+    /// if let Some(previous) = photos.next().await? {
+    ///     client.use_profile_photo(&previous).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn use_profile_photo(&self, photo: &Photo) -> Result<Photo, InvocationError> {
+        let tl::enums::photos::Photo::Photo(photo) = self
+            .invoke(&tl::functions::photos::UpdateProfilePhoto {
+                id: photo.to_input_media().id,
+            })
+            .await?;
+        Ok(Photo::from_raw(photo.photo, self.clone()))
+    }
+
+    /// Delete the given photos from the profile of the currently logged-in user, returning the identifiers of
+    /// the photos that were actually deleted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let me = client.get_me().await?;
+    /// let mut photos = client.iter_profile_photos(&me);
+    /// if let Some(current) = photos.next().await? {
+    ///     client.delete_profile_photos(&[current]).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn delete_profile_photos(
+        &self,
+        photos: &[Photo],
+    ) -> Result<Vec<i64>, InvocationError> {
+        self.invoke(&tl::functions::photos::DeletePhotos {
+            id: photos
+                .iter()
+                .map(|photo| photo.to_input_media().id)
+                .collect(),
+        })
+        .await
+    }
+
+    /// Set a new photo for the given chat.
+    ///
+    /// This works for small group chats, megagroups and broadcast channels, as long as you have
+    /// sufficient permissions to change the chat information. If the chat is the currently
+    /// logged-in user, their profile photo will be changed instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let uploaded = client.upload_file("/home/username/photos/group.jpg").await?;
+    /// client.set_chat_photo(&chat, uploaded).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_chat_photo<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        photo: Uploaded,
+    ) -> Result<(), InvocationError> {
+        self.edit_chat_photo(
+            chat.into(),
+            tl::types::InputChatUploadedPhoto {
+                file: Some(photo.input_file),
+                video: None,
+                video_start_ts: None,
+            },
+        )
+        .await
+    }
+
+    /// Set a new animated photo for the given chat.
+    ///
+    /// Refer to [`Client::set_chat_photo`] and [`Client::set_profile_video`] for details on
+    /// which chats are supported and the meaning of `video_start`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let uploaded = client.upload_file("/home/username/videos/group.mp4").await?;
+    /// client.set_chat_video(&chat, uploaded, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_chat_video<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        video: Uploaded,
+        video_start: Option<Duration>,
+    ) -> Result<(), InvocationError> {
+        self.edit_chat_photo(
+            chat.into(),
+            tl::types::InputChatUploadedPhoto {
+                file: None,
+                video: Some(video.input_file),
+                video_start_ts: video_start.map(|d| d.as_secs_f64()),
+            },
+        )
+        .await
+    }
+
+    async fn upload_profile_photo(
+        &self,
+        photo: tl::types::InputChatUploadedPhoto,
+    ) -> Result<Photo, InvocationError> {
+        let tl::enums::photos::Photo::Photo(tl::types::photos::Photo { photo, .. }) = self
+            .invoke(&tl::functions::photos::UploadProfilePhoto {
+                file: photo.file,
+                video: photo.video,
+                video_start_ts: photo.video_start_ts,
+            })
+            .await?;
+
+        Ok(Photo::from_raw(photo, self.clone()))
+    }
+
+    async fn edit_chat_photo(
+        &self,
+        chat: PackedChat,
+        photo: tl::types::InputChatUploadedPhoto,
+    ) -> Result<(), InvocationError> {
+        // Users have no `InputChatPhoto`, but uploading a profile photo takes the same values.
+        if chat.is_user() {
+            // The logged-in user may not be known yet, in which case it's fetched.
+            let me = self.clone().get_me().await?;
+            return if chat.id == me.id() {
+                self.upload_profile_photo(photo).await.map(drop)
+            } else {
+                Err(InvocationError::Rpc(RpcError {
+                    code: 400,
+                    name: "PEER_ID_INVALID".to_string(),
+                    value: None,
+                    caused_by: None,
+                }))
+            };
+        }
+
+        let photo = tl::enums::InputChatPhoto::InputChatUploadedPhoto(photo);
+        if let Some(channel) = chat.try_to_input_channel() {
+            self.invoke(&tl::functions::channels::EditPhoto { channel, photo })
+                .await
+                .map(drop)
+        } else {
+            self.invoke(&tl::functions::messages::EditChatPhoto {
+                chat_id: chat.id,
+                photo,
+            })
+            .await
+            .map(drop)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -832,3 +1382,11 @@ impl ParticipantPermissions {
         }
     }
 }
+
+/// Whether the chat (still) owns the given username, ignoring case and the leading `@`.
+fn username_matches(chat: &Chat, username: &str) -> bool {
+    match chat.username() {
+        Some(owned) => owned.eq_ignore_ascii_case(username.trim_start_matches('@')),
+        None => false,
+    }
+}
@@ -24,6 +24,7 @@ pub const MIN_CHUNK_SIZE: i32 = 4 * 1024;
 pub const MAX_CHUNK_SIZE: i32 = 512 * 1024;
 const BIG_FILE_SIZE: usize = 10 * 1024 * 1024;
 const WORKER_COUNT: usize = 4;
+// The least time to wait before retrying after a flood wait, even if it was zero.
 const RATE_LIMIT_DELAY: Duration = Duration::from_millis(250);
 const RATE_LIMIT_RETRIES: usize = 3;
 
@@ -90,7 +91,7 @@ impl DownloadIter {
         // TODO handle FILE_MIGRATE and maybe FILEREF_UPGRADE_NEEDED
         let mut retries = 0;
         loop {
-            break match self.client.invoke(&self.request).await {
+            break match self.client.invoke_media(&self.request).await {
                 Ok(File::File(f)) => {
                     if f.bytes.len() < self.request.limit as usize {
                         self.done = true;
@@ -105,15 +106,15 @@ impl DownloadIter {
                 Ok(File::CdnRedirect(_)) => {
                     panic!("API returned File::CdnRedirect even though cdn_supported = false");
                 }
-                // Rate limit hit
-                Err(InvocationError::Rpc(err))
-                    if err.code == 420 && retries < RATE_LIMIT_RETRIES =>
-                {
-                    tokio::time::sleep(RATE_LIMIT_DELAY).await;
-                    retries += 1;
-                    continue;
-                }
-                Err(e) => Err(e),
+                Err(e) => match e.flood_wait() {
+                    // Rate limit hit
+                    Some(delay) if retries < RATE_LIMIT_RETRIES => {
+                        tokio::time::sleep(delay.max(RATE_LIMIT_DELAY)).await;
+                        retries += 1;
+                        continue;
+                    }
+                    _ => Err(e),
+                },
             };
         }
     }
@@ -246,7 +247,7 @@ impl Client {
                     }
                     // Fetch from telegram
                     let res = client
-                        .invoke(&tl::functions::upload::GetFile {
+                        .invoke_media(&tl::functions::upload::GetFile {
                             precise: true,
                             cdn_supported: false,
                             location: location.clone(),
@@ -263,19 +264,16 @@ impl Client {
                                 "API returned File::CdnRedirect even though cdn_supported = false"
                             );
                         }
-                        Err(InvocationError::Rpc(err)) => {
+                        Err(e) => match e.flood_wait() {
                             // Retry on rate limit
-                            if err.code == 420 {
-                                tokio::time::sleep(RATE_LIMIT_DELAY).await;
+                            Some(delay) if retry_counter < RATE_LIMIT_RETRIES => {
+                                tokio::time::sleep(delay.max(RATE_LIMIT_DELAY)).await;
                                 retry_offset = Some(offset);
                                 retry_counter += 1;
-                                if retry_counter <= RATE_LIMIT_RETRIES {
-                                    continue;
-                                }
+                                continue;
                             }
-                            return Err(InvocationError::Rpc(err));
-                        }
-                        Err(e) => return Err(e),
+                            _ => return Err(e),
+                        },
                     }
                 }
                 Ok::<(), InvocationError>(())
@@ -366,7 +364,7 @@ impl Client {
                 let task = async move {
                     while let Some((part, bytes)) = parts.next_part().await? {
                         let ok = handle
-                            .invoke(&tl::functions::upload::SaveBigFilePart {
+                            .invoke_media(&tl::functions::upload::SaveBigFilePart {
                                 file_id,
                                 file_part: part,
                                 file_total_parts: total_parts,
@@ -402,7 +400,7 @@ impl Client {
             while let Some((part, bytes)) = parts.next_part().await? {
                 md5.consume(&bytes);
                 let ok = self
-                    .invoke(&tl::functions::upload::SaveFilePart {
+                    .invoke_media(&tl::functions::upload::SaveFilePart {
                         file_id,
                         file_part: part,
                         bytes,
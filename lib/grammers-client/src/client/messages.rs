@@ -7,13 +7,14 @@
 // except according to those terms.
 
 //! Methods related to sending messages.
-use crate::types::{IterBuffer, Message};
+use crate::types::{Conversation, IterBuffer, Message};
 use crate::utils::{generate_random_id, generate_random_ids};
 use crate::{types, ChatMap, Client};
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
 use grammers_session::PackedChat;
 use grammers_tl_types as tl;
 use std::collections::HashMap;
+use tokio::sync::mpsc;
 
 fn map_random_ids_to_messages(
     client: &Client,
@@ -746,6 +747,44 @@ impl Client {
         Ok(message_ids.iter().map(|id| map.remove(id)).collect())
     }
 
+    /// Start a conversation with the given chat, which can be used to send messages and wait
+    /// for the responses to them.
+    ///
+    /// Note that [`Client::next_update`] must keep being called (for example, by the
+    /// [`crate::dispatcher::Dispatcher`]) for the conversation to receive incoming messages.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// let mut conv = client.conversation(&chat);
+    /// conv.send("What's your name?").await?;
+    /// match conv.wait_for_reply(Duration::from_secs(60)).await {
+    ///     Some(reply) => {
+    ///         conv.send(format!("Nice to meet you, {}!", reply.text())).await?;
+    ///     }
+    ///     None => {
+    ///         conv.send("Took too long to answer!").await?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn conversation<C: Into<PackedChat>>(&self, chat: C) -> Conversation {
+        let chat = chat.into();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.0
+            .conversations
+            .lock("client.conversation")
+            .entry((&chat.to_peer()).into())
+            .or_default()
+            .push(sender);
+
+        Conversation::new(self, chat, receiver)
+    }
+
     /// Get the latest pin from a chat.
     ///
     /// # Examples
@@ -5,6 +5,7 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+pub mod account;
 pub mod auth;
 pub mod bots;
 pub mod chats;
@@ -13,8 +14,14 @@ pub mod dialogs;
 pub mod files;
 pub mod messages;
 pub mod net;
+pub mod notifications;
 pub mod updates;
 
+pub use account::{PasswordError, UsernameError};
 pub use auth::SignInError;
 pub(crate) use client::ClientInner;
-pub use client::{Client, Config, InitParams};
+pub use client::{
+    Client, Config, ConnectionEvent, InitParams, InvokeAction, InvokeHook, InvokeInfo, Metrics,
+    RateLimit, ReconnectionPolicy, RequestOrder, RequestPriority, TransportKind,
+    UpdateOverflowPolicy,
+};
@@ -5,20 +5,30 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{Client, ClientInner, Config};
-use crate::utils::{self, AsyncMutex, Mutex};
+use super::client::{ClientTransport, InFlight, LazySender};
+use super::{
+    Client, ClientInner, Config, ConnectionEvent, InvokeAction, InvokeInfo, RequestOrder,
+    RequestPriority, UpdateOverflowPolicy,
+};
+use crate::utils::{self, AsyncMutex, Mutex, RequestLimiter, RequestPermit, TokenBucket};
 use grammers_mtproto::mtp::{self};
-use grammers_mtproto::transport;
-use grammers_mtsender::{self as sender, AuthorizationError, InvocationError, Sender};
-use grammers_session::{ChatHashCache, MessageBox};
-use grammers_tl_types::{self as tl, Deserializable};
-use log::info;
+use grammers_mtsender::{
+    self as sender, AuthorizationError, Connector, InvocationError, ProxyConnector, Sender,
+    SystemResolver,
+};
+use grammers_session::{AsyncSessionStorage, ChatHashCache, MessageBox};
+use grammers_tl_types::{self as tl, Deserializable, Identifiable, Serializable};
+use log::{debug, info, warn};
 use sender::Enqueuer;
-use std::collections::VecDeque;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::oneshot::error::TryRecvError;
 use tokio::sync::Notify;
+use tokio::time::{timeout_at, Instant};
 
 /// Socket addresses to Telegram datacenters, where the index into this array
 /// represents the data center ID.
@@ -34,37 +44,266 @@ const DC_ADDRESSES: [(Ipv4Addr, u16); 6] = [
     (Ipv4Addr::new(91, 108, 56, 190), 443),
 ];
 
+/// Like [`DC_ADDRESSES`], but with the IPv6 addresses of the datacenters.
+const DC_ADDRESSES_V6: [(Ipv6Addr, u16); 6] = [
+    (Ipv6Addr::UNSPECIFIED, 0),
+    (
+        Ipv6Addr::new(0x2001, 0xb28, 0xf23d, 0xf001, 0, 0, 0, 0xa),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x67c, 0x4e8, 0xf002, 0, 0, 0, 0xa),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0xb28, 0xf23d, 0xf003, 0, 0, 0, 0xa),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x67c, 0x4e8, 0xf004, 0, 0, 0, 0xa),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0xb28, 0xf23f, 0xf005, 0, 0, 0, 0xa),
+        443,
+    ),
+];
+
+/// Socket addresses to Telegram's test datacenters, which are used like [`DC_ADDRESSES`].
+const TEST_DC_ADDRESSES: [(Ipv4Addr, u16); 4] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 0),
+    (Ipv4Addr::new(149, 154, 175, 10), 443),
+    (Ipv4Addr::new(149, 154, 167, 40), 443),
+    (Ipv4Addr::new(149, 154, 175, 117), 443),
+];
+
+/// Like [`TEST_DC_ADDRESSES`], but with the IPv6 addresses of the datacenters.
+const TEST_DC_ADDRESSES_V6: [(Ipv6Addr, u16); 4] = [
+    (Ipv6Addr::UNSPECIFIED, 0),
+    (
+        Ipv6Addr::new(0x2001, 0xb28, 0xf23d, 0xf001, 0, 0, 0, 0xe),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0x67c, 0x4e8, 0xf002, 0, 0, 0, 0xe),
+        443,
+    ),
+    (
+        Ipv6Addr::new(0x2001, 0xb28, 0xf23d, 0xf003, 0, 0, 0, 0xe),
+        443,
+    ),
+];
+
+/// Test datacenters are told apart from production ones by adding this to their ID, when it
+/// needs to be sent to someone other than Telegram's servers (such as an MTProxy).
+const TEST_DC_ID_OFFSET: i32 = 10_000;
+
 const DEFAULT_DC: i32 = 2;
 
+/// How many times a request is sent at most when [`crate::InvokeHook`]s keep asking to retry it.
+const MAX_HOOK_ATTEMPTS: usize = 5;
+
+/// How long temporary authorization keys are valid for when using perfect forward secrecy.
+const TEMP_AUTH_KEY_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long before expiring should temporary authorization keys be replaced.
+const TEMP_AUTH_KEY_ROTATION_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+/// How long to wait before trying to replace the temporary authorization key again on failure.
+const TEMP_AUTH_KEY_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// When should the temporary authorization key of a sender that just connected be replaced.
+pub(crate) fn temp_auth_key_rotation(config: &Config) -> Option<Instant> {
+    if config.params.perfect_forward_secrecy {
+        Some(Instant::now() + TEMP_AUTH_KEY_EXPIRY - TEMP_AUTH_KEY_ROTATION_MARGIN)
+    } else {
+        None
+    }
+}
+
+/// The address to connect to in order to reach the given datacenter, taking the configured
+/// overrides into account.
+fn dc_addr(dc_id: i32, config: &Config) -> Result<SocketAddr, AuthorizationError> {
+    let params = &config.params;
+    if let Some(addr) = params.server_addr {
+        return Ok(addr);
+    }
+    if let Some(addr) = params.dc_addresses.get(&dc_id) {
+        return Ok(*addr);
+    }
+
+    let index = dc_id as usize;
+    let addr = match (params.test_dc, params.prefer_ipv6) {
+        (false, false) => DC_ADDRESSES.get(index).map(|&addr| addr.into()),
+        (false, true) => DC_ADDRESSES_V6.get(index).map(|&addr| addr.into()),
+        (true, false) => TEST_DC_ADDRESSES.get(index).map(|&addr| addr.into()),
+        (true, true) => TEST_DC_ADDRESSES_V6.get(index).map(|&addr| addr.into()),
+    };
+    match addr {
+        Some(addr) if index != 0 => Ok(addr),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            format!("no address known for dc {}", dc_id),
+        )
+        .into()),
+    }
+}
+
+/// A request wrapped in `invokeWithoutUpdates`, without having to take ownership of it.
+struct WithoutUpdates<'a, R>(&'a R);
+
+impl<R: tl::RemoteCall> Identifiable for WithoutUpdates<'_, R> {
+    const CONSTRUCTOR_ID: u32 = tl::functions::InvokeWithoutUpdates::<R>::CONSTRUCTOR_ID;
+}
+
+impl<R: tl::RemoteCall> Serializable for WithoutUpdates<'_, R> {
+    fn serialize(&self, buf: tl::serialize::Buffer) {
+        Self::CONSTRUCTOR_ID.serialize(buf);
+        self.0.serialize(buf);
+    }
+}
+
+impl<R: tl::RemoteCall> tl::RemoteCall for WithoutUpdates<'_, R> {
+    type Return = R::Return;
+}
+
+/// The span in which a request is made, whose message identifier is recorded once it's known.
+#[cfg(feature = "tracing")]
+fn invoke_span<R: tl::RemoteCall>() -> tracing::Span {
+    tracing::debug_span!(
+        "invoke",
+        method = tl::name_for_id(R::CONSTRUCTOR_ID),
+        msg_id = tracing::field::Empty,
+    )
+}
+
+/// Whether the request failed because the connection it was sent through was lost.
+fn is_connection_error<T>(result: &Result<T, InvocationError>) -> bool {
+    matches!(
+        result,
+        Err(InvocationError::Read(
+            sender::ReadError::Io(_) | sender::ReadError::Transport(_)
+        ))
+    )
+}
+
+/// Convert the error that occured while connecting a secondary sender into the error of the
+/// request which needed it.
+fn connect_error(error: AuthorizationError) -> InvocationError {
+    match error {
+        AuthorizationError::Invoke(e) => e,
+        // There is no better fit for a failure to generate the key in a different datacenter,
+        // since the request is never sent.
+        AuthorizationError::Gen(e) => {
+            InvocationError::Read(sender::ReadError::Io(std::io::Error::other(e)))
+        }
+    }
+}
+
+/// Save the session data to the storage, unless it's the same as the data saved the last time,
+/// and remember when (and what) was saved.
+///
+/// Failing to save is only logged, as there's nobody to report the error to.
+async fn save_changed(
+    storage: &dyn AsyncSessionStorage,
+    last_autosave: &mut Option<(Instant, Vec<u8>)>,
+    data: Vec<u8>,
+) {
+    if let Some((last, saved)) = last_autosave {
+        if *saved == data {
+            *last = Instant::now();
+            return;
+        }
+    }
+
+    if let Err(e) = storage.save(&data).await {
+        warn!("failed to autosave session: {}", e);
+    }
+    // Even on failure, wait until the next interval before trying again.
+    *last_autosave = Some((Instant::now(), data));
+}
+
 pub(crate) async fn connect_sender(
     dc_id: i32,
     config: &Config,
-) -> Result<(Sender<transport::Full, mtp::Encrypted>, Enqueuer), AuthorizationError> {
-    let transport = transport::Full::new();
-
-    let addr: SocketAddr = if let Some(ip) = config.params.server_addr {
-        ip
+) -> Result<(Sender<ClientTransport, mtp::Encrypted>, Enqueuer), AuthorizationError> {
+    let transport_dc_id = if config.params.test_dc {
+        dc_id + TEST_DC_ID_OFFSET
     } else {
-        DC_ADDRESSES[dc_id as usize].into()
+        dc_id
+    };
+    let transport = config
+        .params
+        .proxy
+        .as_ref()
+        .and_then(|proxy| proxy.mtproxy_transport(transport_dc_id as i16))
+        .unwrap_or_else(|| config.params.transport.transport());
+
+    let addr = dc_addr(dc_id, config)?;
+    let proxy_connector;
+    let connector: &dyn Connector = match (&config.params.connector, &config.params.proxy) {
+        (Some(connector), _) => connector.as_ref(),
+        (None, Some(proxy)) => {
+            proxy_connector = ProxyConnector {
+                proxy,
+                resolver: config.params.resolver.as_deref().unwrap_or(&SystemResolver),
+                socket_options: &config.params.socket_options,
+            };
+            &proxy_connector
+        }
+        (None, None) => &config.params.socket_options,
     };
 
-    let (mut sender, request_tx) = if let Some(auth_key) = config.session.dc_auth_key(dc_id) {
+    let (mut sender, request_tx) = if config.params.perfect_forward_secrecy {
+        info!(
+            "creating a new sender with temporary auth key to dc {} {:?}",
+            dc_id, addr
+        );
+        let perm_auth_key = config.session.dc_auth_key(dc_id);
+        let had_perm_auth_key = perm_auth_key.is_some();
+        let expires_in = TEMP_AUTH_KEY_EXPIRY.as_secs() as i32;
+        let (sender, tx, perm_auth_key) = sender::connect_with_temp_auth_via_proxy(
+            transport,
+            addr,
+            connector,
+            &config.params.rsa_keys,
+            perm_auth_key,
+            dc_id,
+            expires_in,
+        )
+        .await?;
+
+        // Only the permanent key is persisted. The temporary key must never be saved.
+        if !had_perm_auth_key {
+            config.session.insert_dc(dc_id, addr, perm_auth_key);
+        }
+        (sender, tx)
+    } else if let Some(auth_key) = config.session.dc_auth_key(dc_id) {
         info!(
             "creating a new sender with existing auth key to dc {} {:?}",
             dc_id, addr
         );
-        sender::connect_with_auth(transport, addr, auth_key).await?
+        sender::connect_with_auth_via_proxy(transport, addr, connector, auth_key).await?
     } else {
         info!(
             "creating a new sender and auth key in dc {} {:?}",
             dc_id, addr
         );
-        let (sender, tx) = sender::connect(transport, addr).await?;
+        let (sender, tx) =
+            sender::connect_via_proxy(transport, addr, connector, &config.params.rsa_keys).await?;
 
         config.session.insert_dc(dc_id, addr, sender.auth_key());
         (sender, tx)
     };
 
+    sender.set_keepalive(
+        config.params.ping_interval,
+        config.params.ping_disconnect_delay,
+    );
+    sender.set_compression_threshold(config.params.compression_threshold);
+    sender.set_ack_batching(config.params.max_pending_acks, config.params.ack_delay);
+    sender.set_message_dump(config.params.message_dump.clone());
+
     // TODO handle -404 (we had a previously-valid authkey, but server no longer knows about it)
     // TODO all up-to-date server addresses should be stored in the session for future initial connections
     let _remote_config = sender
@@ -88,6 +327,17 @@ pub(crate) async fn connect_sender(
     Ok((sender, request_tx))
 }
 
+/// How a request should be enqueued by `Client::invoke_once`.
+#[derive(Clone, Copy)]
+enum InvokeMode<'a> {
+    /// The request may be executed in any order.
+    Default,
+    /// The request must be executed after the previous one with the same order.
+    InOrder(&'a RequestOrder),
+    /// The server is asked to quickly acknowledge the request, which calls the function.
+    QuickAck(&'a (dyn Fn() + Send + Sync)),
+}
+
 /// Method implementations directly related with network connectivity.
 impl Client {
     /// Creates and returns a new client instance upon successful connection to Telegram.
@@ -123,10 +373,18 @@ impl Client {
             .session
             .get_user()
             .map(|u| u.dc)
+            .or_else(|| config.session.authorized_dc())
             .unwrap_or(DEFAULT_DC);
         let (sender, request_tx) = connect_sender(dc_id, &config).await?;
+        let self_user = config.session.get_user();
+        let mut chat_hashes = ChatHashCache::new(self_user.map(|u| (u.id, u.bot)));
         let message_box = if config.params.catch_up {
             if let Some(state) = config.session.get_state() {
+                // The channels need their access hash to catch up on them.
+                state
+                    .channel_chats()
+                    .values()
+                    .for_each(|&chat| chat_hashes.insert(chat));
                 MessageBox::load(state)
             } else {
                 MessageBox::new()
@@ -149,23 +407,40 @@ impl Client {
             config.params.update_queue_limit = None;
         }
 
-        let self_user = config.session.get_user();
+        let request_limiter = config.params.max_pending_requests.map(RequestLimiter::new);
+
+        let time_offset = sender.time_offset();
 
-        // TODO Sender doesn't have a way to handle backpressure yet
-        let client = Self(Arc::new(ClientInner {
+        let mut client = Self(Arc::new(ClientInner {
             id: utils::generate_random_id(),
             sender: AsyncMutex::new("client.sender", sender),
             stepping_done: Notify::new(),
             dc_id: Mutex::new("client.dc_id", dc_id),
+            time_offset: Mutex::new("client.time_offset", time_offset),
+            temp_auth_key_rotation: Mutex::new(
+                "client.temp_auth_key_rotation",
+                temp_auth_key_rotation(&config),
+            ),
+            last_autosave: AsyncMutex::new("client.last_autosave", None),
             config,
             message_box: Mutex::new("client.message_box", message_box),
-            chat_hashes: Mutex::new(
-                "client.chat_hashes",
-                ChatHashCache::new(self_user.map(|u| (u.id, u.bot))),
-            ),
+            chat_hashes: Mutex::new("client.chat_hashes", chat_hashes),
             last_update_limit_warn: Mutex::new("client.last_update_limit_warn", None),
             updates: Mutex::new("client.updates", updates),
+            update_queue_space: Notify::new(),
+            conversations: Mutex::new("client.conversations", HashMap::new()),
+            raw_updates: Mutex::new("client.raw_updates", Vec::new()),
+            self_user: Mutex::new("client.self_user", None),
             request_tx: Mutex::new("client.request_tx", request_tx),
+            media_pool: Mutex::new("client.media_pool", HashMap::new()),
+            media_pool_next: AtomicUsize::new(0),
+            dc_senders: Mutex::new("client.dc_senders", HashMap::new()),
+            rate_limiters: Mutex::new("client.rate_limiters", HashMap::new()),
+            request_limiter,
+            shutting_down: AtomicBool::new(false),
+            shutdown_requested: Notify::new(),
+            in_flight: AtomicUsize::new(0),
+            in_flight_done: Notify::new(),
         }));
 
         // Don't bother getting pristine state if we're not logged in.
@@ -192,6 +467,19 @@ impl Client {
             }
         }
 
+        // Bots need to know their own username to tell which commands are addressed to them.
+        if client.is_bot() {
+            if let Err(e) = client.get_me().await {
+                warn!("failed to fetch the logged-in bot: {}", e);
+            }
+        }
+
+        // Connecting may have generated a new authorization key, which should not be lost.
+        client.autosave(true).await;
+
+        if let Some(on_connection_event) = &client.0.config.params.on_connection_event {
+            on_connection_event(ConnectionEvent::Connected { dc_id });
+        }
         Ok(client)
     }
 
@@ -200,6 +488,9 @@ impl Client {
     /// Using function definitions corresponding to a different layer is likely to cause the
     /// responses to the request to not be understood.
     ///
+    /// If the returned future is dropped before completing, the request is cancelled, and the
+    /// server is told that its result is no longer needed.
+    ///
     /// <div class="stab unstable">
     ///
     /// **Warning**: this method is **not** part of the stability guarantees of semantic
@@ -222,17 +513,335 @@ impl Client {
         &self,
         request: &R,
     ) -> Result<R::Return, InvocationError> {
-        let mut rx = self.0.request_tx.lock("invoke").enqueue(request);
+        self.invoke_with_priority(request, RequestPriority::Interactive)
+            .await
+    }
+
+    /// Like [`Client::invoke`], but with the given priority, which is used to decide which
+    /// requests go first when the [`crate::InitParams::max_pending_requests`] are reached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::RequestPriority;
+    /// use grammers_tl_types as tl;
+    ///
+    /// let request = tl::functions::Ping { ping_id: 0 };
+    /// dbg!(client.invoke_with_priority(&request, RequestPriority::Bulk).await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn invoke_with_priority<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+        priority: RequestPriority,
+    ) -> Result<R::Return, InvocationError> {
+        let call = self.with_flood_policy(|| {
+            self.with_hooks(request, || {
+                self.with_metrics::<R, _>(self.invoke_once(request, priority, InvokeMode::Default))
+            })
+        });
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, invoke_span::<R>());
+        let body = call.await?;
+        R::Return::from_bytes(&body).map_err(|e| e.into())
+    }
+
+    /// Like [`Client::invoke`], but Telegram is told to execute the request only after the
+    /// previous one made with the same [`RequestOrder`], if it's still pending, rather than in
+    /// any order.
+    ///
+    /// This guarantees the order of dependent operations without having to wait for the result
+    /// of each of them before sending the next.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::RequestOrder;
+    /// use grammers_tl_types as tl;
+    ///
+    /// let order = RequestOrder::new();
+    /// let (first, second) = tokio::join!(
+    ///     client.invoke_in_order(&tl::functions::Ping { ping_id: 1 }, &order),
+    ///     client.invoke_in_order(&tl::functions::Ping { ping_id: 2 }, &order),
+    /// );
+    /// dbg!(first?, second?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn invoke_in_order<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+        order: &RequestOrder,
+    ) -> Result<R::Return, InvocationError> {
+        let call = self.with_flood_policy(|| {
+            self.with_hooks(request, || {
+                self.with_metrics::<R, _>(self.invoke_once(
+                    request,
+                    RequestPriority::Interactive,
+                    InvokeMode::InOrder(order),
+                ))
+            })
+        });
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, invoke_span::<R>());
+        let body = call.await?;
+        R::Return::from_bytes(&body).map_err(|e| e.into())
+    }
+
+    /// Like [`Client::invoke`], but Telegram is also asked to quickly acknowledge the request as
+    /// soon as it receives it, at which point `on_quick_ack` is called.
+    ///
+    /// This lets you know that a request which takes a while to execute reached Telegram, long
+    /// before its result arrives. The callback may be called more than once if the request needs
+    /// to be sent again, and not at all if the transport in use does not support quick
+    /// acknowledgements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_tl_types as tl;
+    ///
+    /// let request = tl::functions::Ping { ping_id: 0 };
+    /// let pong = client
+    ///     .invoke_with_quick_ack(&request, || println!("ping received by Telegram"))
+    ///     .await?;
+    /// dbg!(pong);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn invoke_with_quick_ack<R, F>(
+        &self,
+        request: &R,
+        on_quick_ack: F,
+    ) -> Result<R::Return, InvocationError>
+    where
+        R: tl::RemoteCall,
+        F: Fn() + Send + Sync,
+    {
+        let call = self.with_flood_policy(|| {
+            self.with_hooks(request, || {
+                self.with_metrics::<R, _>(self.invoke_once(
+                    request,
+                    RequestPriority::Interactive,
+                    InvokeMode::QuickAck(&on_quick_ack),
+                ))
+            })
+        });
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, invoke_span::<R>());
+        let body = call.await?;
+        R::Return::from_bytes(&body).map_err(|e| e.into())
+    }
+
+    /// Report the outcome of the call to the configured [`crate::InitParams::metrics`].
+    async fn with_metrics<R, Fut>(&self, call: Fut) -> Result<Vec<u8>, InvocationError>
+    where
+        R: tl::RemoteCall,
+        Fut: Future<Output = Result<Vec<u8>, InvocationError>>,
+    {
+        let metrics = match &self.0.config.params.metrics {
+            Some(metrics) => metrics,
+            None => return call.await,
+        };
+
+        let start = Instant::now();
+        let result = call.await;
+        let name = tl::name_for_id(R::CONSTRUCTOR_ID);
+        metrics.request_finished(name, start.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Report the data transferred through the sender, and the requests the server ignored, to
+    /// the configured [`crate::InitParams::metrics`].
+    fn record_sender_metrics(&self, sender: &mut Sender<ClientTransport, mtp::Encrypted>) {
+        let (sent, received) = sender.take_traffic();
+        let bad_messages = sender.take_bad_messages();
+        if let Some(metrics) = &self.0.config.params.metrics {
+            metrics.bytes_sent(sent);
+            metrics.bytes_received(received);
+            for bad_message in bad_messages {
+                metrics.bad_message(&bad_message);
+            }
+        }
+    }
+
+    /// Wait for a free slot if the amount of pending requests is limited.
+    async fn acquire_request_slot(&self, priority: RequestPriority) -> Option<RequestPermit> {
+        match &self.0.request_limiter {
+            Some(limiter) => Some(limiter.acquire(priority).await),
+            None => None,
+        }
+    }
+
+    /// Run the configured [`crate::InitParams::invoke_hooks`] around the call.
+    async fn with_hooks<R, F, Fut>(
+        &self,
+        request: &R,
+        mut call: F,
+    ) -> Result<Vec<u8>, InvocationError>
+    where
+        R: tl::RemoteCall,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, InvocationError>>,
+    {
+        let hooks = &self.0.config.params.invoke_hooks;
+        if hooks.is_empty() {
+            return call().await;
+        }
+
+        let body = request.to_bytes();
+        let info = InvokeInfo {
+            name: tl::name_for_id(R::CONSTRUCTOR_ID),
+            constructor_id: R::CONSTRUCTOR_ID,
+            body: &body,
+        };
+        let mut attempts = 0;
         loop {
-            match rx.try_recv() {
-                Ok(response) => {
-                    break match response {
-                        Ok(body) => R::Return::from_bytes(&body).map_err(|e| e.into()),
-                        Err(err) => Err(err),
+            if let Some(response) = hooks.iter().find_map(|hook| hook.before_send(&info)) {
+                return Ok(response);
+            }
+
+            let start = Instant::now();
+            let result = call().await;
+            let latency = start.elapsed();
+            attempts += 1;
+            let mut retry = false;
+            for hook in hooks {
+                let action = hook.after_receive(&info, latency, result.as_deref());
+                retry |= action == InvokeAction::Retry;
+            }
+            if !retry {
+                break result;
+            }
+            if attempts >= MAX_HOOK_ATTEMPTS {
+                warn!(
+                    "giving up on {} after {} attempts requested by hooks",
+                    info.name, attempts
+                );
+                break result;
+            }
+        }
+    }
+
+    /// Retry the call after sleeping if it fails with a flood wait below the configured
+    /// [`crate::InitParams::flood_sleep_threshold`] (only once), or turn the error into
+    /// [`InvocationError::FloodWait`] otherwise. Without a threshold, errors are left as-is.
+    async fn with_flood_policy<T, F, Fut>(&self, mut call: F) -> Result<T, InvocationError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, InvocationError>>,
+    {
+        let mut slept = false;
+        loop {
+            let result = call().await;
+            let delay = match result.as_ref().err().and_then(InvocationError::flood_wait) {
+                Some(delay) => delay,
+                None => return result,
+            };
+
+            if let Some(metrics) = &self.0.config.params.metrics {
+                metrics.flood_wait(delay);
+            }
+            match self.0.config.params.flood_sleep_threshold {
+                None => return result,
+                Some(threshold) if !slept && delay.as_secs() <= threshold as u64 => {
+                    if let Err(InvocationError::Rpc(err)) = &result {
+                        info!("sleeping on {} for {:?} before retrying", err.name, delay);
                     }
+                    tokio::time::sleep(delay).await;
+                    slept = true;
                 }
+                Some(_) => return Err(InvocationError::FloodWait(delay)),
+            }
+        }
+    }
+
+    /// Wait until the request can be sent without exceeding the configured rate limits.
+    async fn throttle<R: tl::RemoteCall>(&self) {
+        let params = &self.0.config.params;
+        if params.rate_limit.is_none() && params.method_rate_limits.is_empty() {
+            return;
+        }
+        let method_limit = if params.method_rate_limits.is_empty() {
+            None
+        } else {
+            let id = R::CONSTRUCTOR_ID;
+            params.method_rate_limits.get(&id).map(|limit| (id, limit))
+        };
+
+        let delay = {
+            let now = Instant::now();
+            let mut limiters = self.0.rate_limiters.lock("client.throttle");
+            let mut delay = Duration::ZERO;
+            if let Some(limit) = &params.rate_limit {
+                let bucket = limiters
+                    .entry(None)
+                    .or_insert_with(|| TokenBucket::new(limit));
+                delay = delay.max(bucket.take(now));
+            }
+            if let Some((id, limit)) = method_limit {
+                let bucket = limiters
+                    .entry(Some(id))
+                    .or_insert_with(|| TokenBucket::new(limit));
+                delay = delay.max(bucket.take(now));
+            }
+            delay
+        };
+        if !delay.is_zero() {
+            debug!("throttling request for {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn invoke_once<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+        priority: RequestPriority,
+        mode: InvokeMode<'_>,
+    ) -> Result<Vec<u8>, InvocationError> {
+        let _in_flight = InFlight::new(self);
+        let _permit = self.acquire_request_slot(priority).await;
+        self.throttle::<R>().await;
+        let (mut rx, mut quick_ack) = {
+            let request_tx = self.0.request_tx.lock("invoke");
+            match (mode, self.0.config.params.no_updates) {
+                (InvokeMode::Default, true) => (request_tx.enqueue(&WithoutUpdates(request)), None),
+                (InvokeMode::Default, false) => (request_tx.enqueue(request), None),
+                (InvokeMode::InOrder(order), true) => {
+                    (order.enqueue(&request_tx, &WithoutUpdates(request)), None)
+                }
+                (InvokeMode::InOrder(order), false) => (order.enqueue(&request_tx, request), None),
+                (InvokeMode::QuickAck(on_quick_ack), no_updates) => {
+                    let (quick_ack, rx) = if no_updates {
+                        request_tx.enqueue_with_quick_ack(&WithoutUpdates(request))
+                    } else {
+                        request_tx.enqueue_with_quick_ack(request)
+                    };
+                    (rx, Some((quick_ack, on_quick_ack)))
+                }
+            }
+        };
+        loop {
+            if let Some((rx, on_quick_ack)) = quick_ack.as_mut() {
+                match rx.try_recv() {
+                    Ok(()) => {
+                        on_quick_ack();
+                        quick_ack = None;
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    Err(TryRecvError::Closed) => quick_ack = None,
+                }
+            }
+            match rx.try_recv() {
+                Ok(response) => break response,
                 Err(TryRecvError::Empty) => {
-                    self.step().await?;
+                    // Don't wait for room in the update queue, or the request could never
+                    // complete if the caller is the one supposed to consume the updates.
+                    self.step_network().await?;
                 }
                 Err(TryRecvError::Closed) => {
                     panic!("request channel dropped before receiving a result")
@@ -241,11 +850,247 @@ impl Client {
         }
     }
 
+    /// Like [`Client::invoke`], but give up on the request if its result doesn't arrive within
+    /// the given duration, in which case [`InvocationError::Timeout`] is returned.
+    ///
+    /// The request may have still been executed by the server, but its result is discarded.
+    ///
+    /// Dropping the future returned by [`Client::invoke`] cancels the request in the same way,
+    /// so this is equivalent to using a timeout around it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_tl_types as tl;
+    /// use std::time::Duration;
+    ///
+    /// let request = tl::functions::Ping { ping_id: 0 };
+    /// dbg!(client.invoke_with_timeout(&request, Duration::from_secs(5)).await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn invoke_with_timeout<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+        timeout: Duration,
+    ) -> Result<R::Return, InvocationError> {
+        match tokio::time::timeout(timeout, self.invoke(request)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("request timed out after {:?}", timeout);
+                Err(InvocationError::Timeout(timeout))
+            }
+        }
+    }
+
+    /// Invoke a raw API call in the given datacenter, which may be different from the one the
+    /// client is connected to.
+    ///
+    /// This is needed for some requests, such as those editing messages sent via inline queries,
+    /// which must be made in the datacenter the message belongs to.
+    ///
+    /// If the datacenter is different, a connection is made to it the first time, and the current
+    /// authorization is exported there before invoking the request. The connection is kept for
+    /// future requests to the same datacenter. Updates received through it are ignored.
+    ///
+    /// <div class="stab unstable">
+    ///
+    /// **Warning**: this method is **not** part of the stability guarantees of semantic
+    /// versioning. It **may** break during *minor* version changes (but not on patch version
+    /// changes). Use with care.
+    ///
+    /// </div>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(mut client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_tl_types as tl;
+    ///
+    /// dbg!(client.invoke_in_dc(4, &tl::functions::Ping { ping_id: 0 }).await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn invoke_in_dc<R: tl::RemoteCall>(
+        &self,
+        dc_id: i32,
+        request: &R,
+    ) -> Result<R::Return, InvocationError> {
+        if dc_id == *self.0.dc_id.lock("client.invoke_in_dc") {
+            return self.invoke(request).await;
+        }
+
+        let call = self.with_flood_policy(|| {
+            self.with_hooks(request, || {
+                self.with_metrics::<R, _>(self.invoke_in_dc_once(dc_id, request))
+            })
+        });
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, invoke_span::<R>());
+        let body = call.await?;
+        R::Return::from_bytes(&body).map_err(|e| e.into())
+    }
+
+    async fn invoke_in_dc_once<R: tl::RemoteCall>(
+        &self,
+        dc_id: i32,
+        request: &R,
+    ) -> Result<Vec<u8>, InvocationError> {
+        let _permit = self
+            .acquire_request_slot(RequestPriority::Interactive)
+            .await;
+        self.throttle::<R>().await;
+
+        let _in_flight = InFlight::new(self);
+        let slot: LazySender = Arc::clone(
+            self.0
+                .dc_senders
+                .lock("client.invoke_in_dc")
+                .entry(dc_id)
+                .or_insert_with(|| Arc::new(AsyncMutex::new("client.dc_sender", None))),
+        );
+
+        let mut sender = slot.lock("client.invoke_in_dc").await;
+        // Idle connections are not kept alive, so the server may have closed them in the
+        // meantime. If reusing one fails, the request is retried once through a new connection.
+        let mut can_retry = sender.is_some();
+        loop {
+            let result = self.invoke_in_dc_sender(dc_id, &mut sender, request).await;
+            if can_retry && is_connection_error(&result) {
+                info!("connection to dc {} was lost; reconnecting", dc_id);
+                can_retry = false;
+                continue;
+            }
+            break result;
+        }
+    }
+
+    /// Invoke the request through the given connection to a datacenter, connecting to it (and
+    /// importing the authorization) first if needed.
+    async fn invoke_in_dc_sender<R: tl::RemoteCall>(
+        &self,
+        dc_id: i32,
+        sender: &mut Option<Sender<ClientTransport, mtp::Encrypted>>,
+        request: &R,
+    ) -> Result<Vec<u8>, InvocationError> {
+        let dc_sender = match sender.as_mut() {
+            Some(dc_sender) => dc_sender,
+            None => {
+                info!("connecting to dc {} to import the authorization", dc_id);
+                let tl::enums::auth::ExportedAuthorization::Authorization(exported) = self
+                    .invoke(&tl::functions::auth::ExportAuthorization { dc_id })
+                    .await?;
+
+                let (mut new_sender, _) = self.connect_dc(dc_id).await.map_err(connect_error)?;
+                let result = new_sender
+                    .invoke(&WithoutUpdates(&tl::functions::auth::ImportAuthorization {
+                        id: exported.id,
+                        bytes: exported.bytes,
+                    }))
+                    .await;
+                self.record_sender_metrics(&mut new_sender);
+                result?;
+                sender.insert(new_sender)
+            }
+        };
+
+        let result = dc_sender.invoke(&WithoutUpdates(request)).await;
+        self.record_sender_metrics(dc_sender);
+        // A connection that failed is discarded, and a new one will be made the next time.
+        if let Err(InvocationError::Read(_)) = result {
+            *sender = None;
+        }
+        result
+    }
+
+    /// Invoke a raw API call related to file transfers.
+    ///
+    /// If the [`crate::InitParams::media_connections`] is not zero, the request is sent through
+    /// one of the connections of the media pool (in turns), instead of the main connection, so
+    /// that heavy transfers don't delay other requests. Updates received through the pool are
+    /// ignored.
+    pub(crate) async fn invoke_media<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+    ) -> Result<R::Return, InvocationError> {
+        if self.0.config.params.media_connections == 0 {
+            return self
+                .invoke_with_priority(request, RequestPriority::Bulk)
+                .await;
+        }
+        let call = self.with_flood_policy(|| {
+            self.with_hooks(request, || {
+                self.with_metrics::<R, _>(self.invoke_media_once(request))
+            })
+        });
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, invoke_span::<R>());
+        let body = call.await?;
+        R::Return::from_bytes(&body).map_err(|e| e.into())
+    }
+
+    async fn invoke_media_once<R: tl::RemoteCall>(
+        &self,
+        request: &R,
+    ) -> Result<Vec<u8>, InvocationError> {
+        let pool_size = self.0.config.params.media_connections;
+        let _permit = self.acquire_request_slot(RequestPriority::Bulk).await;
+        self.throttle::<R>().await;
+
+        let _in_flight = InFlight::new(self);
+        let dc_id = *self.0.dc_id.lock("client.invoke_media");
+        let slot: LazySender = {
+            let mut pool = self.0.media_pool.lock("client.invoke_media");
+            let senders = pool.entry(dc_id).or_insert_with(|| {
+                (0..pool_size)
+                    .map(|_| Arc::new(AsyncMutex::new("client.media_sender", None)))
+                    .collect()
+            });
+            let index = self.0.media_pool_next.fetch_add(1, Ordering::Relaxed);
+            Arc::clone(&senders[index % senders.len()])
+        };
+
+        let mut sender = slot.lock("client.invoke_media").await;
+        // Updates received through the pool would be ignored anyway, so don't ask for them.
+        let request = WithoutUpdates(request);
+        // Idle connections are not kept alive, so the server may have closed them in the
+        // meantime. If reusing one fails, the request is retried once through a new connection.
+        let mut can_retry = sender.is_some();
+        loop {
+            let result = match sender.as_mut() {
+                Some(sender) => sender.invoke(&request).await,
+                None => {
+                    info!("opening media connection to dc {}", dc_id);
+                    let (new_sender, _) = self.connect_dc(dc_id).await.map_err(connect_error)?;
+                    sender.insert(new_sender).invoke(&request).await
+                }
+            };
+
+            if let Some(sender) = sender.as_mut() {
+                self.record_sender_metrics(sender);
+            }
+            // A connection that failed is discarded, and a new one will be made the next time.
+            if let Err(InvocationError::Read(_)) = result {
+                *sender = None;
+            }
+            if can_retry && is_connection_error(&result) {
+                info!("media connection to dc {} was lost; reconnecting", dc_id);
+                can_retry = false;
+                continue;
+            }
+            break result;
+        }
+    }
+
     /// Perform a single network step.
     ///
     /// Most commonly, you will want to use the higher-level abstraction [`Client::next_update`]
     /// instead.
     ///
+    /// If the update queue is full and the [`crate::UpdateOverflowPolicy::Backpressure`] policy
+    /// is used, this method will wait until there is room in the queue before stepping.
+    ///
     /// # Examples
     ///
     /// ```
@@ -258,12 +1103,52 @@ impl Client {
     /// # }
     /// ```
     pub async fn step(&self) -> Result<(), sender::ReadError> {
+        self.wait_update_queue_space().await;
+        self.step_network().await
+    }
+
+    async fn wait_update_queue_space(&self) {
+        let params = &self.0.config.params;
+        let limit = match (params.update_overflow_policy, params.update_queue_limit) {
+            (UpdateOverflowPolicy::Backpressure, Some(limit)) => limit,
+            _ => return,
+        };
+
+        loop {
+            // Create the future before checking so that no notification can be missed.
+            let space = self.0.update_queue_space.notified();
+            if self.0.updates.lock("client.wait_update_queue_space").len() < limit {
+                break;
+            }
+            space.await;
+        }
+    }
+
+    async fn step_network(&self) -> Result<(), sender::ReadError> {
         match self.0.sender.try_lock("client.step") {
             Ok(mut sender) => {
                 // Sender was unlocked, we're the ones that will perform the network step.
-                let updates = sender.step().await?;
+                self.rotate_temp_auth_key(&mut sender).await;
+                let result = sender.step().await;
+                self.record_sender_metrics(&mut sender);
+                *self.0.time_offset.lock("client.step") = sender.time_offset();
+                let updates = match result {
+                    Ok(updates) => updates,
+                    Err(e @ (sender::ReadError::Io(_) | sender::ReadError::Transport(_)))
+                        if !self.is_shutting_down() =>
+                    {
+                        let result = self.reconnect(&mut sender, e).await;
+                        // Whoever was waiting for the step should check their request again.
+                        self.0.stepping_done.notify_waiters();
+                        return result;
+                    }
+                    Err(e) => return Err(e),
+                };
+                // Saving the session may take a while, and shouldn't hold up other requests.
+                drop(sender);
                 self.0.stepping_done.notify_waiters();
                 self.process_socket_updates(updates);
+                self.autosave(false).await;
 
                 // TODO request cancellation if this is Err
                 // (perhaps a method on the sender to cancel_all)
@@ -279,6 +1164,141 @@ impl Client {
         }
     }
 
+    /// Save the session to the configured storage if autosave is enabled, and either `force` is
+    /// set or the autosave interval has elapsed. Nothing is saved if the session is unchanged.
+    pub(crate) async fn autosave(&self, force: bool) {
+        let params = &self.0.config.params;
+        let (storage, interval) = match (&params.session_storage, params.session_autosave) {
+            (Some(storage), Some(interval)) => (storage, interval),
+            _ => return,
+        };
+        let mut last_autosave = self.0.last_autosave.lock("client.autosave").await;
+        if !force {
+            if let Some((last, _)) = &*last_autosave {
+                if last.elapsed() < interval {
+                    return;
+                }
+            }
+        }
+
+        self.sync_update_state();
+        save_changed(
+            storage.as_ref(),
+            &mut last_autosave,
+            self.0.config.session.save(),
+        )
+        .await;
+    }
+
+    /// Connect a new sender to the datacenter, saving the session right away if a new
+    /// authorization key had to be generated for it, so that it's not lost.
+    async fn connect_dc(
+        &self,
+        dc_id: i32,
+    ) -> Result<(Sender<ClientTransport, mtp::Encrypted>, Enqueuer), AuthorizationError> {
+        let had_auth_key = self.0.config.session.dc_auth_key(dc_id).is_some();
+        let result = connect_sender(dc_id, &self.0.config).await;
+        if result.is_ok() && !had_auth_key {
+            self.autosave(true).await;
+        }
+        result
+    }
+
+    /// Replace the temporary authorization key in use, if any, when it's about to expire.
+    ///
+    /// A new connection is made with a new key, and the requests which were still pending in the
+    /// old connection are sent through the new one instead. On failure, the old connection keeps
+    /// being used, and the replacement is attempted again later.
+    async fn rotate_temp_auth_key(&self, sender: &mut Sender<ClientTransport, mtp::Encrypted>) {
+        let due = match *self
+            .0
+            .temp_auth_key_rotation
+            .lock("client.rotate_temp_auth_key")
+        {
+            Some(rotation) => rotation <= Instant::now(),
+            None => false,
+        };
+        if !due {
+            return;
+        }
+
+        let dc_id = *self.0.dc_id.lock("client.rotate_temp_auth_key");
+        info!("replacing temporary auth key in dc {}", dc_id);
+        match self.connect_dc(dc_id).await {
+            Ok((mut new_sender, request_tx)) => {
+                // Swap the enqueuer first so that no new request can be sent to the old sender
+                // after its pending requests have been transferred.
+                *self.0.request_tx.lock("client.rotate_temp_auth_key") = request_tx;
+                sender.transfer_requests(&mut new_sender);
+                *sender = new_sender;
+                *self
+                    .0
+                    .temp_auth_key_rotation
+                    .lock("client.rotate_temp_auth_key") = temp_auth_key_rotation(&self.0.config);
+            }
+            Err(e) => {
+                warn!("failed to replace temporary auth key: {}", e);
+                *self
+                    .0
+                    .temp_auth_key_rotation
+                    .lock("client.rotate_temp_auth_key") =
+                    Some(Instant::now() + TEMP_AUTH_KEY_RETRY_DELAY);
+            }
+        }
+    }
+
+    /// Replace the sender after its connection was lost, following the reconnection policy.
+    ///
+    /// The requests which were still pending are sent through the new connection. If it can't
+    /// be established, the error that caused the disconnection is returned.
+    async fn reconnect(
+        &self,
+        sender: &mut Sender<ClientTransport, mtp::Encrypted>,
+        error: sender::ReadError,
+    ) -> Result<(), sender::ReadError> {
+        let params = &self.0.config.params;
+        let policy = &params.reconnection_policy;
+        let emit = |event| {
+            if let Some(on_connection_event) = &params.on_connection_event {
+                on_connection_event(event);
+            }
+        };
+
+        warn!("connection lost: {}", error);
+        let dc_id = *self.0.dc_id.lock("client.reconnect");
+        let mut attempt = 0;
+        loop {
+            if policy.max_retries.is_some_and(|max| attempt >= max) {
+                emit(ConnectionEvent::GaveUp);
+                return Err(error);
+            }
+            attempt += 1;
+            let delay = policy.delay(attempt);
+            info!(
+                "reconnecting to dc {} in {:?} (attempt {})",
+                dc_id, delay, attempt
+            );
+            emit(ConnectionEvent::Reconnecting { attempt, delay });
+            tokio::time::sleep(delay).await;
+
+            match self.connect_dc(dc_id).await {
+                Ok((mut new_sender, request_tx)) => {
+                    *self.0.request_tx.lock("client.reconnect") = request_tx;
+                    sender.transfer_requests(&mut new_sender);
+                    *sender = new_sender;
+                    *self.0.temp_auth_key_rotation.lock("client.reconnect") =
+                        temp_auth_key_rotation(&self.0.config);
+                    emit(ConnectionEvent::Connected { dc_id });
+                    if let Some(metrics) = &params.metrics {
+                        metrics.reconnected();
+                    }
+                    return Ok(());
+                }
+                Err(e) => warn!("failed to reconnect: {}", e),
+            }
+        }
+    }
+
     /// Run the client by repeatedly calling [`Client::step`] until a graceful disconnection
     /// occurs, or a network error occurs. Incoming updates are ignored and simply dropped.
     /// instead.
@@ -294,7 +1314,161 @@ impl Client {
     pub async fn run_until_disconnected(self) -> Result<(), sender::ReadError> {
         loop {
             // TODO review doc comments regarding disconnects
-            self.step().await?;
+            match self.step().await {
+                Ok(()) => {}
+                // The connection is expected to be closed when shutting down.
+                Err(_) if self.is_shutting_down() => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        }
+    }
+
+    /// Gracefully shut down the client.
+    ///
+    /// [`Client::next_update`] will stop returning updates (so the
+    /// [`crate::dispatcher::Dispatcher`] will stop running), and then this method will wait for
+    /// the requests and update handlers still in progress to finish, for up to `timeout`. After
+    /// that, the update state is synchronized to the session, and the connection is closed.
+    ///
+    /// If some updates were still queued and not returned by [`Client::next_update`], the update
+    /// state is left as it was the last time the queue was empty (see
+    /// [`Client::sync_update_state`]), so that those updates are fetched again on the next run
+    /// with [`crate::InitParams::catch_up`] enabled.
+    ///
+    /// Returns `true` if everything completed before the `timeout` expired.
+    ///
+    /// The session is saved to the [`crate::InitParams::session_storage`] if autosave is enabled.
+    /// Otherwise, it is not saved automatically, so it should be saved after this method
+    /// returns. Calling this method from within an update handler will cause it to wait for the
+    /// full `timeout`, since the handler itself is still in progress.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// tokio::signal::ctrl_c().await?;
+    /// client.shutdown(Duration::from_secs(10)).await;
+    /// client.session().save_to_file("bot.session")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.0.shutting_down.store(true, Ordering::SeqCst);
+        self.0.shutdown_requested.notify_waiters();
+        let deadline = Instant::now() + timeout;
+
+        let drained = timeout_at(deadline, async {
+            loop {
+                // Create the future before checking so that no notification can be missed.
+                let done = self.0.in_flight_done.notified();
+                if self.0.in_flight.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                done.await;
+            }
+        })
+        .await
+        .is_ok();
+        if !drained {
+            warn!("timed out waiting for requests and handlers to finish during shutdown");
+        }
+
+        let queued = self.0.updates.lock("client.shutdown").len();
+        if queued > 0 {
+            info!(
+                "{} queued updates were not handled before shutdown; the update state is not \
+                 synchronized so that they can be fetched again with catch_up",
+                queued
+            );
         }
+        self.sync_update_state();
+        self.autosave(true).await;
+
+        let disconnected = match timeout_at(deadline, self.0.sender.lock("client.shutdown")).await {
+            Ok(mut sender) => match sender.disconnect().await {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("failed to disconnect gracefully during shutdown: {}", e);
+                    false
+                }
+            },
+            Err(_) => {
+                warn!("timed out waiting for the connection to be idle during shutdown");
+                false
+            }
+        };
+
+        drained && disconnected
+    }
+
+    /// Whether [`Client::shutdown`] has been called.
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// The difference, in seconds, between Telegram's clock and the local system clock.
+    ///
+    /// This is derived from the messages received from Telegram, so it is kept up to date as
+    /// long as the client is being [stepped](Client::step).
+    pub fn time_offset(&self) -> i32 {
+        *self.0.time_offset.lock("client.time_offset")
+    }
+
+    /// The current time according to Telegram, which may differ from the local system clock.
+    ///
+    /// Prefer this over [`SystemTime::now`] when computing absolute dates that Telegram should
+    /// act upon, such as when a restriction expires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) {
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let in_a_day = client.server_time() + Duration::from_secs(24 * 60 * 60);
+    /// assert!(in_a_day > SystemTime::UNIX_EPOCH);
+    /// # }
+    /// ```
+    pub fn server_time(&self) -> SystemTime {
+        let offset = self.time_offset();
+        let now = SystemTime::now();
+        if offset >= 0 {
+            now + Duration::from_secs(offset as u64)
+        } else {
+            now - Duration::from_secs(offset.unsigned_abs() as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_session::{MemoryStorage, Session, SessionStorage};
+
+    #[tokio::test]
+    async fn autosave_keeps_the_latest_session() {
+        let storage = MemoryStorage::new();
+        let session = Session::new();
+        let mut last_autosave = None;
+
+        save_changed(&storage, &mut last_autosave, session.save()).await;
+        assert_eq!(
+            SessionStorage::load(&storage).unwrap(),
+            Some(session.save())
+        );
+
+        // A new authorization key (or update state) must survive a clean shutdown, which forces
+        // a save even if the interval hasn't elapsed yet.
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 443));
+        session.insert_dc(2, addr, [1; 256]);
+        save_changed(&storage, &mut last_autosave, session.save()).await;
+
+        let saved = SessionStorage::load(&storage).unwrap().unwrap();
+        assert_eq!(
+            Session::load(&saved).unwrap().dc_auth_key(2),
+            Some([1; 256])
+        );
     }
 }
@@ -0,0 +1,229 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Methods related to the notification settings.
+
+use super::Client;
+use crate::types::{Chat, ChatMap, NotifyScope, NotifySettings};
+pub use grammers_mtsender::InvocationError;
+use grammers_session::PackedChat;
+use grammers_tl_types as tl;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Method implementations related to the notification settings of chats.
+impl Client {
+    /// Fetch the notification settings of the given chat.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// let settings = client.get_notify_settings(&chat).await?;
+    /// if let Some(until) = settings.mute_until() {
+    ///     println!("{} is muted until {}", chat.name(), until);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_notify_settings<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+    ) -> Result<NotifySettings, InvocationError> {
+        self.fetch_notify_settings(notify_peer(chat.into())).await
+    }
+
+    /// Mute the notifications of the given chat for the given duration.
+    ///
+    /// Durations too large to be represented mute the chat forever. The other notification
+    /// settings of the chat are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// client.mute(&chat, Duration::from_secs(8 * 60 * 60)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn mute<C: Into<PackedChat>>(
+        &self,
+        chat: C,
+        duration: Duration,
+    ) -> Result<(), InvocationError> {
+        self.set_mute_until(notify_peer(chat.into()), mute_until(duration))
+            .await
+    }
+
+    /// Unmute the notifications of the given chat. The other notification settings of the chat
+    /// are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(chat: grammers_client::types::Chat, client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// client.unmute(&chat).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn unmute<C: Into<PackedChat>>(&self, chat: C) -> Result<(), InvocationError> {
+        self.set_mute_until(notify_peer(chat.into()), 0).await
+    }
+
+    /// Fetch the default notification settings for the given kind of chats.
+    ///
+    /// These apply to every chat of that kind which has no settings of its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::NotifyScope;
+    ///
+    /// let settings = client.get_default_notify_settings(NotifyScope::Groups).await?;
+    /// println!("Groups are muted by default: {}", settings.is_muted());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_default_notify_settings(
+        &self,
+        scope: NotifyScope,
+    ) -> Result<NotifySettings, InvocationError> {
+        self.fetch_notify_settings(scope.to_raw()).await
+    }
+
+    /// Replace the default notification settings for the given kind of chats.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::NotifyScope;
+    ///
+    /// let mut settings = client.get_default_notify_settings(NotifyScope::Channels).await?;
+    /// settings.set_show_previews(Some(false));
+    /// client.set_default_notify_settings(NotifyScope::Channels, &settings).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_default_notify_settings(
+        &self,
+        scope: NotifyScope,
+        settings: &NotifySettings,
+    ) -> Result<(), InvocationError> {
+        self.invoke(&tl::functions::account::UpdateNotifySettings {
+            peer: scope.to_raw(),
+            settings: settings.to_raw(),
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Mute the default notifications for the given kind of chats for the given duration.
+    ///
+    /// Refer to [`Client::mute`] for details.
+    pub async fn mute_default(
+        &self,
+        scope: NotifyScope,
+        duration: Duration,
+    ) -> Result<(), InvocationError> {
+        self.set_mute_until(scope.to_raw(), mute_until(duration))
+            .await
+    }
+
+    /// Unmute the default notifications for the given kind of chats.
+    pub async fn unmute_default(&self, scope: NotifyScope) -> Result<(), InvocationError> {
+        self.set_mute_until(scope.to_raw(), 0).await
+    }
+
+    /// Fetch the chats whose notification settings differ from the defaults, optionally only
+    /// those of the given kind, along with their settings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// for (chat, settings) in client.get_notify_exceptions(None).await? {
+    ///     println!("{} is muted: {}", chat.name(), settings.is_muted());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_notify_exceptions(
+        &self,
+        scope: Option<NotifyScope>,
+    ) -> Result<Vec<(Chat, NotifySettings)>, InvocationError> {
+        let (updates, users, chats) = match self
+            .invoke(&tl::functions::account::GetNotifyExceptions {
+                compare_sound: false,
+                peer: scope.map(NotifyScope::to_raw),
+            })
+            .await?
+        {
+            tl::enums::Updates::Updates(u) => (u.updates, u.users, u.chats),
+            tl::enums::Updates::Combined(u) => (u.updates, u.users, u.chats),
+            _ => return Ok(Vec::new()),
+        };
+
+        let chats = ChatMap::new(users, chats);
+        Ok(updates
+            .into_iter()
+            .filter_map(|update| match update {
+                tl::enums::Update::NotifySettings(tl::types::UpdateNotifySettings {
+                    peer: tl::enums::NotifyPeer::Peer(tl::types::NotifyPeer { peer }),
+                    notify_settings,
+                }) => chats
+                    .get(&peer)
+                    .map(|chat| (chat.clone(), NotifySettings::from_raw(notify_settings))),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn fetch_notify_settings(
+        &self,
+        peer: tl::enums::InputNotifyPeer,
+    ) -> Result<NotifySettings, InvocationError> {
+        self.invoke(&tl::functions::account::GetNotifySettings { peer })
+            .await
+            .map(NotifySettings::from_raw)
+    }
+
+    async fn set_mute_until(
+        &self,
+        peer: tl::enums::InputNotifyPeer,
+        until: i32,
+    ) -> Result<(), InvocationError> {
+        // Settings which are not sent are reset, so the current ones need to be fetched first.
+        let mut settings = self.fetch_notify_settings(peer.clone()).await?;
+        settings.set_mute_until(until);
+        self.invoke(&tl::functions::account::UpdateNotifySettings {
+            peer,
+            settings: settings.to_raw(),
+        })
+        .await
+        .map(drop)
+    }
+}
+
+fn notify_peer(chat: PackedChat) -> tl::enums::InputNotifyPeer {
+    tl::types::InputNotifyPeer {
+        peer: chat.to_input_peer(),
+    }
+    .into()
+}
+
+/// The date until which notifications should be muted to mute them for the given duration.
+fn mute_until(duration: Duration) -> i32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    i32::try_from(now.saturating_add(duration).as_secs()).unwrap_or(i32::MAX)
+}
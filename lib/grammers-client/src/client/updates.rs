@@ -9,13 +9,16 @@
 //! Methods to deal with and offer access to updates.
 
 use super::Client;
-use crate::types::{ChatMap, Update};
+use crate::types::{ChatMap, RawUpdates, Update};
+use crate::UpdateOverflowPolicy;
 pub use grammers_mtsender::{AuthorizationError, InvocationError};
 pub use grammers_session::UpdateState;
 use grammers_tl_types as tl;
 use log::warn;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use tokio::time::sleep_until;
 
 /// How long to wait after warning the user that the updates limit was exceeded.
@@ -25,7 +28,7 @@ impl Client {
     /// Returns the next update from the buffer where they are queued until used.
     ///
     /// Similar using an iterator manually, this method will return `Some` until no more updates
-    /// are available (e.g. a graceful disconnection occurred).
+    /// are available (e.g. a graceful disconnection occurred, or [`Client::shutdown`] was called).
     ///
     /// # Examples
     ///
@@ -47,18 +50,39 @@ impl Client {
     /// ```
     pub async fn next_update(&self) -> Result<Option<Update>, InvocationError> {
         loop {
-            if let Some(updates) = self.0.updates.lock("client.next_update").pop_front() {
-                return Ok(Some(updates));
+            // Create the future before checking so that no notification can be missed.
+            let shutdown = self.0.shutdown_requested.notified();
+            if self.is_shutting_down() {
+                return Ok(None);
+            }
+
+            let (update, drained) = {
+                let mut updates = self.0.updates.lock("client.next_update");
+                (updates.pop_front(), updates.is_empty())
+            };
+            if update.is_some() {
+                self.0.update_queue_space.notify_waiters();
+            }
+            if let Some(update) = update {
+                if drained {
+                    // Every update processed so far has now been handed out, so it's safe to
+                    // persist the state without risking to lose any of them after a restart.
+                    self.sync_update_state();
+                }
+                return Ok(Some(update));
             }
 
             let mut message_box = self.0.message_box.lock("client.next_update");
             if let Some(request) = message_box.get_difference() {
                 drop(message_box);
                 let response = self.invoke(&request).await?;
-                let mut message_box = self.0.message_box.lock("client.next_update/get_difference");
-                let mut chat_hashes = self.0.chat_hashes.lock("client.next_update/get_difference");
-                let (updates, users, chats) =
-                    message_box.apply_difference(response, &mut chat_hashes);
+                let (updates, users, chats) = {
+                    let mut message_box =
+                        self.0.message_box.lock("client.next_update/get_difference");
+                    let mut chat_hashes =
+                        self.0.chat_hashes.lock("client.next_update/get_difference");
+                    message_box.apply_difference(response, &mut chat_hashes)
+                };
 
                 self.extend_update_queue(updates, ChatMap::new(users, chats));
                 continue;
@@ -98,6 +122,9 @@ impl Client {
                 _ = sleep_until(deadline.into()) => {
                     log::trace!("slept")
                 }
+                _ = shutdown => {
+                    log::trace!("shutdown requested")
+                }
             }
         }
     }
@@ -107,66 +134,331 @@ impl Client {
             return;
         }
 
-        let mut result = (Vec::new(), Vec::new(), Vec::new());
-        let mut message_box = self.0.message_box.lock("client.process_socket_updates");
-        let mut chat_hashes = self.0.chat_hashes.lock("client.process_socket_updates");
+        let (updates, users, chats) = {
+            let mut result = (Vec::new(), Vec::new(), Vec::new());
+            let mut message_box = self.0.message_box.lock("client.process_socket_updates");
+            let mut chat_hashes = self.0.chat_hashes.lock("client.process_socket_updates");
 
-        for updates in all_updates {
-            match message_box.process_updates(updates, &mut chat_hashes, &mut result.0) {
-                Ok((users, chats)) => {
-                    result.1.extend(users);
-                    result.2.extend(chats);
+            for updates in all_updates {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!("process_updates").entered();
+                match message_box.process_updates(updates, &mut chat_hashes, &mut result.0) {
+                    Ok((users, chats)) => {
+                        result.1.extend(users);
+                        result.2.extend(chats);
+                    }
+                    Err(_) => return,
                 }
-                Err(_) => return,
             }
-        }
 
-        let (updates, users, chats) = result;
+            result
+        };
+
         self.extend_update_queue(updates, ChatMap::new(users, chats));
     }
 
-    fn extend_update_queue(&self, mut updates: Vec<tl::enums::Update>, chat_map: Arc<ChatMap>) {
-        let mut guard = self.0.updates.lock("client.extend_update_queue");
-
-        if let Some(limit) = self.0.config.params.update_queue_limit {
-            if let Some(exceeds) = (guard.len() + updates.len()).checked_sub(limit + 1) {
-                let exceeds = exceeds + 1;
-                let now = Instant::now();
-                let mut warn_guard = self
-                    .0
-                    .last_update_limit_warn
-                    .lock("client.extend_update_queue");
-                let notify = match *warn_guard {
-                    None => true,
-                    Some(instant) => now - instant > UPDATE_LIMIT_EXCEEDED_LOG_COOLDOWN,
-                };
+    /// Keep the cached logged-in user up-to-date with the changes received as updates.
+    fn refresh_self_user(&self, updates: &[tl::enums::Update], chat_map: &ChatMap) {
+        let mut guard = self.0.self_user.lock("client.refresh_self_user");
+        let user = match guard.as_mut() {
+            Some(user) => user,
+            None => return,
+        };
+
+        // Min constructors lack information, so they must not replace the cached user.
+        if let Some(fresh) = chat_map.get_user(user.id()) {
+            if !fresh.0.min {
+                *user = fresh;
+            }
+        }
 
-                updates.truncate(updates.len() - exceeds);
-                if notify {
-                    warn!(
-                        "{} updates were dropped because the update_queue_limit was exceeded",
-                        exceeds
-                    );
+        for update in updates {
+            match update {
+                tl::enums::Update::UserName(u) if u.user_id == user.id() => {
+                    user.0.first_name = Some(u.first_name.clone());
+                    user.0.last_name = Some(u.last_name.clone());
+                    user.0.username = Some(u.username.clone()).filter(|s| !s.is_empty());
+                }
+                tl::enums::Update::UserPhone(u) if u.user_id == user.id() => {
+                    user.0.phone = Some(u.phone.clone());
                 }
+                tl::enums::Update::UserPhoto(u) if u.user_id == user.id() => {
+                    user.0.photo = Some(u.photo.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Convert the raw updates and queue them.
+    ///
+    /// Must not be called while the `message_box` or `chat_hashes` are locked, because building
+    /// an [`Update`] may need to look up chats in the cache.
+    fn extend_update_queue(&self, updates: Vec<tl::enums::Update>, chat_map: Arc<ChatMap>) {
+        if let Some(metrics) = &self.0.config.params.metrics {
+            metrics.updates_received(updates.len());
+        }
+        self.refresh_self_user(&updates, &chat_map);
+        self.forward_raw_updates(&updates, &chat_map);
+        let updates = updates
+            .into_iter()
+            .flat_map(|u| Update::new(self, u, &chat_map))
+            .collect::<Vec<_>>();
+
+        // Conversations may drop the messages they can no longer receive, which in turn drops
+        // their client and synchronizes the update state, so the queue must not be locked yet.
+        self.forward_to_conversations(&updates);
 
-                *warn_guard = Some(now);
+        let params = &self.0.config.params;
+        let overflow = {
+            let mut guard = self.0.updates.lock("client.extend_update_queue");
+            queue_with_limit(
+                &mut guard,
+                updates,
+                params.update_queue_limit,
+                params.update_overflow_policy,
+            )
+        };
+        let dropped = overflow.len();
+        // Dropped messages drop their client, which synchronizes the update state and locks the
+        // queue again, so they must only be dropped once it's unlocked.
+        drop(overflow);
+
+        if dropped > 0 {
+            let now = Instant::now();
+            let mut warn_guard = self
+                .0
+                .last_update_limit_warn
+                .lock("client.extend_update_queue");
+            let notify = match *warn_guard {
+                None => true,
+                Some(instant) => now - instant > UPDATE_LIMIT_EXCEEDED_LOG_COOLDOWN,
+            };
+
+            if notify {
+                warn!(
+                    "{} updates were dropped because the update_queue_limit was exceeded",
+                    dropped
+                );
+            }
+
+            *warn_guard = Some(now);
+            drop(warn_guard);
+
+            if let Some(on_updates_dropped) = &params.on_updates_dropped {
+                on_updates_dropped(dropped);
             }
         }
+    }
 
-        guard.extend(
-            updates
-                .into_iter()
-                .flat_map(|u| Update::new(self, u, &chat_map)),
-        );
+    /// Send a copy of the raw updates to the active raw update streams.
+    fn forward_raw_updates(&self, updates: &[tl::enums::Update], chat_map: &Arc<ChatMap>) {
+        let mut senders = self.0.raw_updates.lock("client.forward_raw_updates");
+        for update in updates {
+            // Streams which were dropped can no longer receive updates.
+            senders.retain(|sender| sender.send((update.clone(), Arc::clone(chat_map))).is_ok());
+        }
+    }
+
+    /// Send a copy of the incoming messages to the conversations active in their chat.
+    ///
+    /// Must not be called while the `updates` are locked, because dropping a message drops its
+    /// client, which synchronizes the update state.
+    fn forward_to_conversations(&self, updates: &[Update]) {
+        let mut conversations = self.0.conversations.lock("client.forward_to_conversations");
+        if conversations.is_empty() {
+            return;
+        }
+
+        for update in updates {
+            if let Update::NewMessage(message) = update {
+                if message.outgoing() {
+                    continue;
+                }
+                // The raw peer is used so that no chat has to be looked up in the cache here.
+                let peer = (&message.msg.peer_id).into();
+                if let Some(senders) = conversations.get_mut(&peer) {
+                    // Conversations which were dropped can no longer receive messages.
+                    senders.retain(|sender| sender.send(message.clone()).is_ok());
+                    if senders.is_empty() {
+                        conversations.remove(&peer);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start receiving a copy of every raw update, before it's converted into an [`Update`].
+    ///
+    /// This is an escape hatch to handle updates for which there is no high-level variant yet.
+    /// Note that [`Client::next_update`] must keep being called (for example, by the
+    /// [`crate::dispatcher::Dispatcher`]) for the stream to receive updates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_tl_types as tl;
+    ///
+    /// let mut raw_updates = client.raw_updates();
+    /// tokio::spawn(async move {
+    ///     while let Some((update, _chats)) = raw_updates.next().await {
+    ///         if let tl::enums::Update::PeerSettings(settings) = update {
+    ///             println!("Settings changed: {:?}", settings);
+    ///         }
+    ///     }
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn raw_updates(&self) -> RawUpdates {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.0.raw_updates.lock("client.raw_updates").push(sender);
+        RawUpdates::new(receiver)
     }
 
     /// Synchronize the updates state to the session.
+    ///
+    /// The state is only synchronized once all the updates received so far have been returned by
+    /// [`Client::next_update`]. While updates are still queued, this method does nothing, and the
+    /// session keeps the state from the last time the queue was empty. This way, if the client is
+    /// restarted with [`crate::InitParams::catch_up`] enabled, updates which were still queued are
+    /// fetched again instead of being lost (some updates may be returned twice in that case).
     pub fn sync_update_state(&self) {
-        self.0.config.session.set_state(
-            self.0
-                .message_box
-                .lock("client.sync_update_state")
-                .session_state(),
+        let message_box = self.0.message_box.lock("client.sync_update_state");
+        if !self.0.updates.lock("client.sync_update_state").is_empty() {
+            return;
+        }
+        let chat_hashes = self.0.chat_hashes.lock("client.sync_update_state");
+        self.0
+            .config
+            .session
+            .set_state(message_box.session_state(&chat_hashes));
+    }
+}
+
+/// Push the `updates` to the `queue`, removing the updates that don't fit within the `limit`
+/// according to the `policy`.
+///
+/// The removed updates are returned instead of being dropped, so that the caller can drop them
+/// once the queue is unlocked.
+fn queue_with_limit<T>(
+    queue: &mut VecDeque<T>,
+    mut updates: Vec<T>,
+    limit: Option<usize>,
+    policy: UpdateOverflowPolicy,
+) -> Vec<T> {
+    let mut overflow = Vec::new();
+    if let Some(limit) = limit {
+        let exceeds = (queue.len() + updates.len()).saturating_sub(limit);
+        if exceeds > 0 {
+            match policy {
+                UpdateOverflowPolicy::DropNewest => {
+                    let keep = updates.len() - exceeds.min(updates.len());
+                    overflow = updates.split_off(keep);
+                }
+                UpdateOverflowPolicy::DropOldest => {
+                    let from_queue = exceeds.min(queue.len());
+                    overflow.extend(queue.drain(..from_queue));
+                    overflow.extend(updates.drain(..exceeds - from_queue));
+                }
+                // `Client::step` waits for room in the queue before reading from the network, so
+                // the updates which were read anyway (for example, while waiting for the result
+                // of a request) are all kept.
+                UpdateOverflowPolicy::Backpressure => {}
+            }
+        }
+    }
+    queue.extend(updates);
+    overflow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Locks the queue it belongs to when dropped, like messages do through their client.
+    struct Message(Arc<Mutex<VecDeque<Message>>>);
+
+    impl Drop for Message {
+        fn drop(&mut self) {
+            assert!(
+                self.0.try_lock().is_ok(),
+                "message dropped while the queue was locked"
+            );
+        }
+    }
+
+    /// Overflow a queue of 5 messages with 6, returning how many remain queued and were dropped.
+    fn overflow(policy: UpdateOverflowPolicy) -> (usize, usize) {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let messages = |n| (0..n).map(|_| Message(Arc::clone(&queue))).collect();
+
+        let mut dropped = 0;
+        for _ in 0..3 {
+            let overflow = {
+                let mut guard = queue.lock().unwrap();
+                queue_with_limit(&mut guard, messages(2), Some(5), policy)
+            };
+            dropped += overflow.len();
+            drop(overflow);
+        }
+
+        let len = queue.lock().unwrap().len();
+        // Break the reference cycle between the queue and its messages.
+        let remaining = std::mem::take(&mut *queue.lock().unwrap());
+        drop(remaining);
+        (len, dropped)
+    }
+
+    #[test]
+    fn drop_newest_overflow() {
+        assert_eq!(overflow(UpdateOverflowPolicy::DropNewest), (5, 1));
+    }
+
+    #[test]
+    fn drop_oldest_overflow() {
+        assert_eq!(overflow(UpdateOverflowPolicy::DropOldest), (5, 1));
+    }
+
+    #[test]
+    fn backpressure_keeps_everything() {
+        assert_eq!(overflow(UpdateOverflowPolicy::Backpressure), (6, 0));
+    }
+
+    #[test]
+    fn drop_oldest_keeps_newest() {
+        let mut queue = VecDeque::from(vec![1, 2, 3]);
+        let overflow = queue_with_limit(
+            &mut queue,
+            vec![4, 5, 6],
+            Some(4),
+            UpdateOverflowPolicy::DropOldest,
+        );
+        assert_eq!(overflow, vec![1, 2]);
+        assert_eq!(queue, vec![3, 4, 5, 6]);
+
+        let overflow = queue_with_limit(
+            &mut queue,
+            vec![7, 8, 9, 10, 11],
+            Some(4),
+            UpdateOverflowPolicy::DropOldest,
+        );
+        assert_eq!(overflow, vec![3, 4, 5, 6, 7]);
+        assert_eq!(queue, vec![8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_oldest() {
+        let mut queue = VecDeque::from(vec![1, 2, 3]);
+        let overflow = queue_with_limit(
+            &mut queue,
+            vec![4, 5, 6],
+            Some(4),
+            UpdateOverflowPolicy::DropNewest,
         );
+        assert_eq!(overflow, vec![5, 6]);
+        assert_eq!(queue, vec![1, 2, 3, 4]);
     }
 }
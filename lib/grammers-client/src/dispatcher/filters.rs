@@ -0,0 +1,182 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Filters to decide which updates a handler should receive.
+//!
+//! The filters provided by this module can be combined using the `&` (and), `|` (or) and `!`
+//! (not) operators. Any closure taking an `&Update` and returning a `bool` can also be used as
+//! a filter, and can be turned into a combinable filter with [`custom`].
+//!
+//! Filters are not tied to the dispatcher, and can also be used as plain predicates over
+//! updates with [`Filter::check`].
+//!
+//! # Examples
+//!
+//! ```
+//! # fn f(update: grammers_client::Update) {
+//! use grammers_client::dispatcher::filters::{self, Filter};
+//!
+//! let filter = filters::command("start") & filters::private() | filters::command("help");
+//! if filter.check(&update) {
+//!     println!("Someone needs help");
+//! }
+//! # }
+//! ```
+use super::chat_of;
+use crate::types::{Chat, PackedChat};
+use crate::Update;
+use std::ops::{BitAnd, BitOr, Not};
+
+/// A predicate over updates, used by the [`super::Dispatcher`] to find which handler should
+/// handle an update.
+pub trait Filter: Send + Sync + 'static {
+    /// Returns `true` if the update should be handled.
+    fn check(&self, update: &Update) -> bool;
+}
+
+impl<F: Fn(&Update) -> bool + Send + Sync + 'static> Filter for F {
+    fn check(&self, update: &Update) -> bool {
+        self(update)
+    }
+}
+
+/// A filter which can be combined with other filters by using the `&`, `|` and `!` operators.
+pub struct Predicate(Box<dyn Filter>);
+
+impl Filter for Predicate {
+    fn check(&self, update: &Update) -> bool {
+        self.0.check(update)
+    }
+}
+
+impl<F: Filter> BitAnd<F> for Predicate {
+    type Output = Predicate;
+
+    fn bitand(self, rhs: F) -> Self::Output {
+        custom(move |update: &Update| self.check(update) && rhs.check(update))
+    }
+}
+
+impl<F: Filter> BitOr<F> for Predicate {
+    type Output = Predicate;
+
+    fn bitor(self, rhs: F) -> Self::Output {
+        custom(move |update: &Update| self.check(update) || rhs.check(update))
+    }
+}
+
+impl Not for Predicate {
+    type Output = Predicate;
+
+    fn not(self) -> Self::Output {
+        custom(move |update: &Update| !self.check(update))
+    }
+}
+
+/// Turn any filter (such as a closure) into one which can be combined with others.
+pub fn custom<F: Filter>(filter: F) -> Predicate {
+    Predicate(Box::new(filter))
+}
+
+/// Matches every update.
+pub fn all() -> Predicate {
+    custom(|_: &Update| true)
+}
+
+/// Matches new messages.
+pub fn new_message() -> Predicate {
+    custom(|update: &Update| matches!(update, Update::NewMessage(_)))
+}
+
+/// Matches edited messages.
+pub fn message_edited() -> Predicate {
+    custom(|update: &Update| matches!(update, Update::MessageEdited(_)))
+}
+
+/// Matches new messages sent by someone else.
+pub fn incoming() -> Predicate {
+    custom(|update: &Update| match update {
+        Update::NewMessage(message) => !message.outgoing(),
+        _ => false,
+    })
+}
+
+/// Matches new messages sent by the logged-in account.
+pub fn outgoing() -> Predicate {
+    custom(|update: &Update| match update {
+        Update::NewMessage(message) => message.outgoing(),
+        _ => false,
+    })
+}
+
+/// Matches callback queries.
+pub fn callback_query() -> Predicate {
+    custom(|update: &Update| matches!(update, Update::CallbackQuery(_)))
+}
+
+/// Matches callback queries whose data starts with the given prefix.
+pub fn callback_data(prefix: &[u8]) -> Predicate {
+    let prefix = prefix.to_vec();
+    custom(move |update: &Update| match update {
+        Update::CallbackQuery(query) => query.data().starts_with(&prefix),
+        _ => false,
+    })
+}
+
+/// Matches inline queries.
+pub fn inline_query() -> Predicate {
+    custom(|update: &Update| matches!(update, Update::InlineQuery(_)))
+}
+
+/// Matches new messages containing the given bot command, such as `/start`, with or without
+/// arguments.
+///
+/// The `name` should not contain the leading slash. Commands explicitly addressed to a different
+/// bot, such as `/start@other_bot`, are not matched (see [`crate::types::Message::command`]).
+pub fn command(name: &str) -> Predicate {
+    let name = name.to_string();
+    custom(move |update: &Update| match update {
+        Update::NewMessage(message) => message
+            .command()
+            .is_some_and(|command| command.name() == name),
+        _ => false,
+    })
+}
+
+/// Matches updates which occur in private conversations.
+pub fn private() -> Predicate {
+    custom(|update: &Update| matches!(chat_of(update), Some(Chat::User(_))))
+}
+
+/// Matches updates which occur in groups, including megagroups.
+pub fn group() -> Predicate {
+    custom(|update: &Update| matches!(chat_of(update), Some(Chat::Group(_))))
+}
+
+/// Matches updates which occur in broadcast channels.
+pub fn channel() -> Predicate {
+    custom(|update: &Update| matches!(chat_of(update), Some(Chat::Channel(_))))
+}
+
+/// Matches updates which occur in the given chat.
+pub fn chat<C: Into<PackedChat>>(chat: C) -> Predicate {
+    let peer = chat.into().to_peer();
+    custom(move |update: &Update| match chat_of(update) {
+        Some(c) => c.pack().to_peer() == peer,
+        None => false,
+    })
+}
+
+/// Matches new messages whose text matches the given regular expression.
+#[cfg(feature = "regex")]
+pub fn regex(regex: regex::Regex) -> Predicate {
+    custom(move |update: &Update| match update {
+        Update::NewMessage(message) => regex.is_match(message.text()),
+        _ => false,
+    })
+}
@@ -0,0 +1,534 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finite-state machine helpers, used to remember at which step of a multi-step dialog each user
+//! is.
+//!
+//! The state is kept per user and chat, so the same user can be at different steps in different
+//! chats. States are plain strings, which makes it easy to persist them with any storage, such as
+//! a [`FileStorage`] or any [`grammers_session::SessionStorage`] through a [`SessionBackedStorage`].
+//!
+//! # Examples
+//!
+//! ```
+//! # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+//! use grammers_client::dispatcher::fsm::{FileStorage, Fsm};
+//! use grammers_client::dispatcher::{filters, Dispatcher};
+//! use grammers_client::Update;
+//!
+//! let fsm = Fsm::new(FileStorage::open("states.txt")?);
+//! let (start_fsm, name_fsm) = (fsm.clone(), fsm.clone());
+//!
+//! Dispatcher::new()
+//!     .handler(filters::command("start"), move |_, update| {
+//!         let fsm = start_fsm.clone();
+//!         async move {
+//!             if let Update::NewMessage(message) = update {
+//!                 fsm.set_for(&message, "waiting_name")?;
+//!                 message.reply("What's your name?").await?;
+//!             }
+//!             Ok(())
+//!         }
+//!     })
+//!     .handler(fsm.filter("waiting_name"), move |_, update| {
+//!         let fsm = name_fsm.clone();
+//!         async move {
+//!             if let Update::NewMessage(message) = update {
+//!                 fsm.reset_for(&message)?;
+//!                 message.reply(format!("Hello, {}!", message.text())).await?;
+//!             }
+//!             Ok(())
+//!         }
+//!     })
+//!     .run(&client)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::chat_of;
+use super::filters::{custom, Predicate};
+use crate::types::{Chat, Message, Peer};
+use crate::utils::Mutex;
+use crate::Update;
+use grammers_session::SessionStorage;
+use log::warn;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// How many records a [`FileStorage`] may have before it's considered worth compacting, even if
+/// most of them are outdated.
+const MIN_RECORDS_TO_COMPACT: usize = 1024;
+
+/// Identifies the state of a user within a chat.
+///
+/// The kind of peer is part of the key, because users, small groups and channels may share the
+/// same identifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateKey {
+    pub chat: Peer,
+    pub user: Peer,
+}
+
+/// Storage for the states of the finite-state machine.
+pub trait StateStorage: Send + Sync + 'static {
+    /// Returns the current state for the key, if any.
+    fn get(&self, key: StateKey) -> Option<String>;
+
+    /// Changes the state for the key, or clears it if `state` is `None`.
+    fn set(&self, key: StateKey, state: Option<&str>) -> io::Result<()>;
+}
+
+/// Storage which keeps the states in memory, so they're lost when the program exits.
+pub struct MemoryStorage {
+    states: Mutex<HashMap<StateKey, String>>,
+}
+
+/// Storage which keeps the states in memory, and appends every change to a file, so they
+/// survive restarts.
+///
+/// The file is compacted when it's opened and once most of its records are outdated, so that it
+/// doesn't grow forever.
+///
+/// States stored in a file cannot contain newlines.
+pub struct FileStorage {
+    path: PathBuf,
+    log: Mutex<StateLog>,
+}
+
+struct StateLog {
+    states: HashMap<StateKey, String>,
+    file: File,
+    // How many records the file has, including those which were later overwritten.
+    records: usize,
+}
+
+/// Storage which keeps the states in memory, and saves them to a [`SessionStorage`] every time
+/// they change, so they survive restarts.
+///
+/// This allows keeping the states in any of the places where a session can be kept, such as a
+/// database or an encrypted file. The storage must not be the same one used by the session.
+/// Every change saves all of the states, so this is best suited for a modest amount of users.
+///
+/// States stored this way cannot contain newlines.
+pub struct SessionBackedStorage<S: SessionStorage> {
+    storage: S,
+    states: Mutex<HashMap<StateKey, String>>,
+}
+
+/// A finite-state machine, which can be cheaply cloned to share it between handlers.
+#[derive(Clone)]
+pub struct Fsm {
+    storage: Arc<dyn StateStorage>,
+}
+
+impl MemoryStorage {
+    /// Create a new, empty storage.
+    pub fn new() -> Self {
+        Self {
+            states: Mutex::new("memory_storage.states", HashMap::new()),
+        }
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateStorage for MemoryStorage {
+    fn get(&self, key: StateKey) -> Option<String> {
+        self.states.lock("memory_storage.get").get(&key).cloned()
+    }
+
+    fn set(&self, key: StateKey, state: Option<&str>) -> io::Result<()> {
+        let mut states = self.states.lock("memory_storage.set");
+        match state {
+            Some(state) => states.insert(key, state.to_string()),
+            None => states.remove(&key),
+        };
+        Ok(())
+    }
+}
+
+impl FileStorage {
+    /// Load the states stored in the given file, creating an empty storage if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (states, records) = match fs::read_to_string(&path) {
+            Ok(contents) => read_records(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (HashMap::new(), 0),
+            Err(e) => return Err(e),
+        };
+
+        let log = if records > states.len() {
+            compact(&path, states)?
+        } else {
+            StateLog {
+                states,
+                file: OpenOptions::new().create(true).append(true).open(&path)?,
+                records,
+            }
+        };
+
+        Ok(Self {
+            path,
+            log: Mutex::new("file_storage.log", log),
+        })
+    }
+}
+
+impl StateStorage for FileStorage {
+    fn get(&self, key: StateKey) -> Option<String> {
+        self.log.lock("file_storage.get").states.get(&key).cloned()
+    }
+
+    fn set(&self, key: StateKey, state: Option<&str>) -> io::Result<()> {
+        check_state(state)?;
+        let mut log = self.log.lock("file_storage.set");
+        log.file.write_all(write_record(key, state).as_bytes())?;
+        log.records += 1;
+        match state {
+            Some(state) => log.states.insert(key, state.to_string()),
+            None => log.states.remove(&key),
+        };
+
+        if log.records >= MIN_RECORDS_TO_COMPACT.max(log.states.len() * 2) {
+            let states = std::mem::take(&mut log.states);
+            *log = compact(&self.path, states)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: SessionStorage> SessionBackedStorage<S> {
+    /// Load the states saved in the given storage, starting with no states if it's empty.
+    pub fn open(storage: S) -> io::Result<Self> {
+        let states = match storage.load()? {
+            Some(data) => {
+                let contents = String::from_utf8(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                read_records(&contents)?.0
+            }
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            storage,
+            states: Mutex::new("session_backed_storage.states", states),
+        })
+    }
+}
+
+impl<S: SessionStorage + 'static> StateStorage for SessionBackedStorage<S> {
+    fn get(&self, key: StateKey) -> Option<String> {
+        self.states
+            .lock("session_backed_storage.get")
+            .get(&key)
+            .cloned()
+    }
+
+    fn set(&self, key: StateKey, state: Option<&str>) -> io::Result<()> {
+        check_state(state)?;
+        let mut states = self.states.lock("session_backed_storage.set");
+        match state {
+            Some(state) => states.insert(key, state.to_string()),
+            None => states.remove(&key),
+        };
+        self.storage.save(write_records(&states).as_bytes())
+    }
+}
+
+impl Fsm {
+    /// Create a new finite-state machine backed by the given storage.
+    pub fn new<S: StateStorage>(storage: S) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+
+    /// Create a new finite-state machine which keeps the states in memory.
+    pub fn in_memory() -> Self {
+        Self::new(MemoryStorage::new())
+    }
+
+    /// Returns the current state of the user in the chat, if any.
+    pub fn get(&self, key: StateKey) -> Option<String> {
+        self.storage.get(key)
+    }
+
+    /// Changes the state of the user in the chat.
+    pub fn set(&self, key: StateKey, state: &str) -> io::Result<()> {
+        self.storage.set(key, Some(state))
+    }
+
+    /// Clears the state of the user in the chat.
+    pub fn reset(&self, key: StateKey) -> io::Result<()> {
+        self.storage.set(key, None)
+    }
+
+    /// Returns the current state of the sender of the message in its chat, if any.
+    pub fn get_for(&self, message: &Message) -> Option<String> {
+        message_key(message).and_then(|key| self.get(key))
+    }
+
+    /// Changes the state of the sender of the message in its chat.
+    ///
+    /// Does nothing if the message has no sender.
+    pub fn set_for(&self, message: &Message, state: &str) -> io::Result<()> {
+        match message_key(message) {
+            Some(key) => self.set(key, state),
+            None => Ok(()),
+        }
+    }
+
+    /// Clears the state of the sender of the message in its chat.
+    ///
+    /// Does nothing if the message has no sender.
+    pub fn reset_for(&self, message: &Message) -> io::Result<()> {
+        match message_key(message) {
+            Some(key) => self.reset(key),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a filter matching the new messages and callback queries of the users which are
+    /// currently in the given state.
+    pub fn filter(&self, state: &str) -> Predicate {
+        let storage = Arc::clone(&self.storage);
+        let state = state.to_string();
+        custom(move |update: &Update| match update_key(update) {
+            Some(key) => storage.get(key).as_deref() == Some(state.as_str()),
+            None => false,
+        })
+    }
+}
+
+fn peer_of(chat: &Chat) -> Peer {
+    (&chat.pack().to_peer()).into()
+}
+
+fn message_key(message: &Message) -> Option<StateKey> {
+    Some(StateKey {
+        chat: peer_of(&message.chat()),
+        user: peer_of(&message.sender()?),
+    })
+}
+
+fn update_key(update: &Update) -> Option<StateKey> {
+    match update {
+        Update::NewMessage(message) => message_key(message),
+        Update::CallbackQuery(query) => Some(StateKey {
+            chat: peer_of(&chat_of(update)?),
+            user: peer_of(query.sender()),
+        }),
+        _ => None,
+    }
+}
+
+fn check_state(state: Option<&str>) -> io::Result<()> {
+    match state {
+        Some(state) if state.contains('\n') => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "stored states cannot contain newlines",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrite the file with only the current states, so that it no longer contains outdated
+/// records, and reopen it to append new records.
+fn compact(path: &Path, states: HashMap<StateKey, String>) -> io::Result<StateLog> {
+    // Write to a temporary file first so that a crash can't leave a half-written file.
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, write_records(&states))?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(StateLog {
+        records: states.len(),
+        states,
+        file: OpenOptions::new().append(true).open(path)?,
+    })
+}
+
+fn write_peer(peer: Peer) -> String {
+    match peer {
+        Peer::User(id) => format!("u{}", id),
+        Peer::Chat(id) => format!("g{}", id),
+        Peer::Channel(id) => format!("c{}", id),
+    }
+}
+
+fn read_peer(peer: &str) -> Option<Peer> {
+    let (kind, id) = peer.split_at(peer.char_indices().nth(1)?.0);
+    let id = id.parse().ok()?;
+    match kind {
+        "u" => Some(Peer::User(id)),
+        "g" => Some(Peer::Chat(id)),
+        "c" => Some(Peer::Channel(id)),
+        _ => None,
+    }
+}
+
+/// A record which sets the state with `+`, or clears it with `-`.
+fn write_record(key: StateKey, state: Option<&str>) -> String {
+    let (chat, user) = (write_peer(key.chat), write_peer(key.user));
+    match state {
+        Some(state) => format!("+ {} {} {}\n", chat, user, state),
+        None => format!("- {} {}\n", chat, user),
+    }
+}
+
+fn write_records(states: &HashMap<StateKey, String>) -> String {
+    states
+        .iter()
+        .map(|(&key, state)| write_record(key, Some(state)))
+        .collect()
+}
+
+/// Replay the records, returning the resulting states and how many records there were.
+///
+/// A crash while appending can leave the last record incomplete, in which case it's treated as
+/// never written. It's still counted, so that the file is compacted (dropping it) before new
+/// records are appended after it.
+fn read_records(contents: &str) -> io::Result<(HashMap<StateKey, String>, usize)> {
+    let mut states = HashMap::new();
+    let mut records = 0;
+    let mut lines = contents.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        records += 1;
+        let record = line.strip_suffix('\n').and_then(read_record);
+        match record {
+            Some((key, Some(state))) => {
+                states.insert(key, state.to_string());
+            }
+            Some((key, None)) => {
+                states.remove(&key);
+            }
+            None if lines.peek().is_none() => {
+                warn!("ignoring incomplete state record at the end of the file");
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "malformed state record",
+                ))
+            }
+        }
+    }
+    Ok((states, records))
+}
+
+/// Parse a record, returning the key it refers to and the state it sets, if any.
+fn read_record(line: &str) -> Option<(StateKey, Option<&str>)> {
+    let mut parts = line.splitn(4, ' ');
+    let op = parts.next();
+    let chat = parts.next().and_then(read_peer)?;
+    let user = parts.next().and_then(read_peer)?;
+    let key = StateKey { chat, user };
+    match (op, parts.next()) {
+        (Some("+"), Some(state)) => Some((key, Some(state))),
+        (Some("-"), None) => Some((key, None)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_session::MemoryStorage as SessionMemoryStorage;
+
+    fn key(chat: Peer, user: i32) -> StateKey {
+        StateKey {
+            chat,
+            user: Peer::User(user),
+        }
+    }
+
+    #[test]
+    fn peers_with_the_same_id_are_different_keys() {
+        let storage = MemoryStorage::new();
+        storage.set(key(Peer::User(1), 2), Some("a")).unwrap();
+        storage.set(key(Peer::Chat(1), 2), Some("b")).unwrap();
+        storage.set(key(Peer::Channel(1), 2), Some("c")).unwrap();
+
+        assert_eq!(storage.get(key(Peer::User(1), 2)).as_deref(), Some("a"));
+        assert_eq!(storage.get(key(Peer::Chat(1), 2)).as_deref(), Some("b"));
+        assert_eq!(storage.get(key(Peer::Channel(1), 2)).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn file_storage_appends_and_compacts() {
+        let path = std::env::temp_dir().join(format!("grammers-{}.states", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let storage = FileStorage::open(&path).unwrap();
+        storage.set(key(Peer::Chat(1), 2), Some("first")).unwrap();
+        storage
+            .set(key(Peer::Chat(1), 2), Some("second step"))
+            .unwrap();
+        storage
+            .set(key(Peer::Channel(1), 2), Some("other"))
+            .unwrap();
+        storage.set(key(Peer::Channel(1), 2), None).unwrap();
+        drop(storage);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(
+            storage.get(key(Peer::Chat(1), 2)).as_deref(),
+            Some("second step")
+        );
+        assert_eq!(storage.get(key(Peer::Channel(1), 2)), None);
+        assert!(storage.set(key(Peer::Chat(1), 2), Some("a\nb")).is_err());
+        drop(storage);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_storage_ignores_incomplete_last_record() {
+        let path = std::env::temp_dir().join(format!("grammers-{}.torn", std::process::id()));
+        fs::write(&path, "+ g1 u2 first\n+ g3 u4 sec").unwrap();
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get(key(Peer::Chat(1), 2)).as_deref(), Some("first"));
+        assert_eq!(storage.get(key(Peer::Chat(3), 4)), None);
+        storage.set(key(Peer::Chat(3), 4), Some("second")).unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get(key(Peer::Chat(1), 2)).as_deref(), Some("first"));
+        assert_eq!(
+            storage.get(key(Peer::Chat(3), 4)).as_deref(),
+            Some("second")
+        );
+        drop(storage);
+
+        fs::write(&path, "+ g1 u2 first\n+ g3\n+ g3 u4 second\n").unwrap();
+        assert!(FileStorage::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn session_backed_storage_round_trip() {
+        let storage = SessionBackedStorage::open(SessionMemoryStorage::new()).unwrap();
+        storage.set(key(Peer::User(1), 2), Some("step")).unwrap();
+        storage.set(key(Peer::Chat(3), 4), Some("other")).unwrap();
+        storage.set(key(Peer::Chat(3), 4), None).unwrap();
+
+        let reopened = SessionBackedStorage::open(storage.storage).unwrap();
+        assert_eq!(reopened.get(key(Peer::User(1), 2)).as_deref(), Some("step"));
+        assert_eq!(reopened.get(key(Peer::Chat(3), 4)), None);
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Middleware which runs around the handlers of the dispatcher.
+//!
+//! Middleware can be used to implement cross-cutting concerns such as logging, rate limiting or
+//! ignoring banned users, without repeating the same logic in every handler.
+//!
+//! # Examples
+//!
+//! ```
+//! use grammers_client::dispatcher::middleware::{BoxFuture, Flow, Middleware};
+//! use grammers_client::{Client, Update};
+//!
+//! struct IgnoreEdits;
+//!
+//! impl Middleware for IgnoreEdits {
+//!     fn before<'a>(&'a self, _client: &'a Client, update: &'a Update) -> BoxFuture<'a, Flow> {
+//!         Box::pin(async move {
+//!             match update {
+//!                 Update::MessageEdited(_) => Flow::Stop,
+//!                 _ => Flow::Continue,
+//!             }
+//!         })
+//!     }
+//! }
+//! ```
+use super::HandlerError;
+use crate::{Client, Update};
+pub use futures_util::future::BoxFuture;
+
+/// Whether the processing of an update should continue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Carry on with the next middleware, and eventually, the handler.
+    Continue,
+    /// Stop processing the update. No further middleware nor handlers will be called with it.
+    Stop,
+}
+
+/// Code which runs before and after the handlers of the dispatcher.
+///
+/// The `before` methods of all middleware are called in the order the middleware was added,
+/// and the `after` methods in the reverse order.
+pub trait Middleware: Send + Sync + 'static {
+    /// Called before the update reaches the handlers.
+    ///
+    /// Returning [`Flow::Stop`] short-circuits the processing of the update, and neither the
+    /// handlers nor the `after` methods will be called.
+    fn before<'a>(&'a self, client: &'a Client, update: &'a Update) -> BoxFuture<'a, Flow> {
+        let _ = (client, update);
+        Box::pin(async { Flow::Continue })
+    }
+
+    /// Called after the handler finished with the update.
+    ///
+    /// The `result` is `None` if no handler matched the update.
+    fn after<'a>(
+        &'a self,
+        client: &'a Client,
+        update: &'a Update,
+        result: Option<&'a Result<(), HandlerError>>,
+    ) -> BoxFuture<'a, ()> {
+        let _ = (client, update, result);
+        Box::pin(async {})
+    }
+}
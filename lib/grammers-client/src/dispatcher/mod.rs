@@ -0,0 +1,395 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An optional way to handle updates by registering handlers, instead of manually writing the
+//! loop that calls [`Client::next_update`].
+//!
+//! Handlers are tried in the order they were added, and the first one whose [`Filter`] matches
+//! an update will handle it. Updates from the same chat are handled one after another, in the
+//! order they arrived, while updates from different chats are handled concurrently.
+//!
+//! # Examples
+//!
+//! ```
+//! # async fn f(client: grammers_client::Client) -> Result<(), Box<dyn std::error::Error>> {
+//! use grammers_client::dispatcher::{filters, Dispatcher};
+//! use grammers_client::Update;
+//!
+//! Dispatcher::new()
+//!     .handler(filters::command("start"), |_client, update| async move {
+//!         if let Update::NewMessage(message) = update {
+//!             message.reply("Hello!").await?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .handler(filters::new_message(), |_client, update| async move {
+//!         if let Update::NewMessage(message) = update {
+//!             message.respond(message.text()).await?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .run(&client)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+pub mod filters;
+pub mod fsm;
+pub mod middleware;
+
+use crate::client::client::InFlight;
+use crate::types::{Chat, Peer};
+use crate::utils::Mutex;
+use crate::{Client, Update, UpdateOverflowPolicy};
+pub use filters::Filter;
+use futures_util::FutureExt;
+use grammers_mtsender::InvocationError;
+use log::warn;
+pub use middleware::{Flow, Middleware};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// The error a handler may fail with. It is logged by the dispatcher, which then carries on.
+pub type HandlerError = Box<dyn Error + Send + Sync>;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), HandlerError>> + Send>>;
+
+struct Handler {
+    filter: Box<dyn Filter>,
+    callback: Box<dyn Fn(Client, Update) -> HandlerFuture + Send + Sync>,
+}
+
+/// Routes incoming updates to the first handler with a matching filter.
+pub struct Dispatcher {
+    handlers: Vec<Handler>,
+    middleware: Vec<Box<dyn Middleware>>,
+    chat_queue_limit: Option<usize>,
+    chat_queue_overflow_policy: UpdateOverflowPolicy,
+}
+
+/// Updates waiting to be handled, for every chat which has a handler running.
+struct ChatQueues {
+    queues: Mutex<HashMap<Peer, VecDeque<Update>>>,
+    // Notified every time an update is taken out of a queue, to wait until there's room in it.
+    space: Notify,
+}
+
+/// What to do with an update after trying to queue it.
+enum Enqueued {
+    /// There was no handler running for its chat, so one should be spawned to handle it.
+    Spawn(Update),
+    /// The update was queued, possibly dropping the given update to make room.
+    Queued(Option<Update>),
+    /// The queue is full, so the update should be queued once there's room.
+    Wait(Update),
+}
+
+impl Dispatcher {
+    /// Create a new dispatcher without any handlers.
+    pub fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+            middleware: Vec::new(),
+            chat_queue_limit: Some(100),
+            chat_queue_overflow_policy: UpdateOverflowPolicy::DropNewest,
+        }
+    }
+
+    /// Add a new handler, which will be called with the updates matching the `filter` unless a
+    /// previously-added handler matched them first.
+    pub fn handler<F, H, Fut>(mut self, filter: F, handler: H) -> Self
+    where
+        F: Filter,
+        H: Fn(Client, Update) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), HandlerError>> + Send + 'static,
+    {
+        self.handlers.push(Handler {
+            filter: Box::new(filter),
+            callback: Box::new(move |client, update| Box::pin(handler(client, update))),
+        });
+        self
+    }
+
+    /// Add a new middleware, which will run before and after the handlers for every update.
+    pub fn middleware<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Limit how many updates from the same chat can wait while a handler for that chat is still
+    /// running, and what to do with the updates which arrive once the limit is reached.
+    ///
+    /// With [`UpdateOverflowPolicy::Backpressure`], the dispatcher stops fetching updates until
+    /// there is room, which in turn fills the client's own update queue.
+    ///
+    /// By default, up to 100 updates are queued per chat, and newer updates are dropped. Setting
+    /// the limit to `None` lets the queues grow without bound.
+    pub fn chat_queue_limit(mut self, limit: Option<usize>, policy: UpdateOverflowPolicy) -> Self {
+        self.chat_queue_limit = limit;
+        self.chat_queue_overflow_policy = policy;
+        self
+    }
+
+    /// Fetch updates from the client and dispatch them to the handlers until the client is
+    /// disconnected or an error occurs while fetching updates.
+    ///
+    /// Errors returned by the handlers are logged, but they do not stop the dispatcher. Neither do
+    /// handlers which panic.
+    pub async fn run(self, client: &Client) -> Result<(), InvocationError> {
+        let dispatcher = Arc::new(self);
+        let queues = Arc::new(ChatQueues {
+            queues: Mutex::new("dispatcher.queues", HashMap::new()),
+            space: Notify::new(),
+        });
+
+        while let Some(update) = client.next_update().await? {
+            let key = match key_of(&update) {
+                Some(key) => key,
+                None => {
+                    // Without a chat there is no order to preserve.
+                    let dispatcher = Arc::clone(&dispatcher);
+                    let client = client.clone();
+                    let in_flight = InFlight::new(&client);
+                    tokio::spawn(async move {
+                        dispatcher.dispatch(client, update).await;
+                        drop(in_flight);
+                    });
+                    continue;
+                }
+            };
+
+            let mut update = update;
+            let update = loop {
+                // Create the future before checking so that no notification can be missed.
+                let space = queues.space.notified();
+                match dispatcher.enqueue(&queues, key, update) {
+                    Enqueued::Spawn(update) => break Some(update),
+                    Enqueued::Queued(dropped) => {
+                        if dropped.is_some() {
+                            warn!(
+                                "an update was dropped because the chat_queue_limit was exceeded"
+                            );
+                        }
+                        // Dropped updates may hold a client, so they're only dropped once the
+                        // queues are unlocked.
+                        drop(dropped);
+                        break None;
+                    }
+                    Enqueued::Wait(waiting) => {
+                        update = waiting;
+                        space.await;
+                    }
+                }
+            };
+            let update = match update {
+                Some(update) => update,
+                None => continue,
+            };
+
+            tokio::spawn(drain(
+                InFlight::new(client),
+                Arc::clone(&dispatcher),
+                client.clone(),
+                Arc::clone(&queues),
+                key,
+                update,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Dispatcher {
+    /// Queue the update if a handler is already running for its chat.
+    fn enqueue(&self, queues: &ChatQueues, key: Peer, update: Update) -> Enqueued {
+        let mut queues = queues.queues.lock("dispatcher.enqueue");
+        let queue = match queues.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(VecDeque::new());
+                return Enqueued::Spawn(update);
+            }
+        };
+
+        // A handler is already running for this chat and will pick it up next.
+        match self.chat_queue_limit {
+            Some(limit) if queue.len() >= limit => match self.chat_queue_overflow_policy {
+                UpdateOverflowPolicy::DropNewest => Enqueued::Queued(Some(update)),
+                UpdateOverflowPolicy::DropOldest => {
+                    let dropped = queue.pop_front();
+                    queue.push_back(update);
+                    Enqueued::Queued(dropped)
+                }
+                UpdateOverflowPolicy::Backpressure => Enqueued::Wait(update),
+            },
+            _ => {
+                queue.push_back(update);
+                Enqueued::Queued(None)
+            }
+        }
+    }
+
+    async fn dispatch(&self, client: Client, update: Update) {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("update", kind = update_kind(&update));
+        let handle = self.handle(client, update);
+        #[cfg(feature = "tracing")]
+        let handle = tracing::Instrument::instrument(handle, span);
+        // A panicking handler must not take down the task draining the queue of its chat, or
+        // every later update from that chat would be queued forever.
+        if AssertUnwindSafe(handle).catch_unwind().await.is_err() {
+            warn!("update handler panicked");
+        }
+    }
+
+    async fn handle(&self, client: Client, update: Update) {
+        for middleware in self.middleware.iter() {
+            if middleware.before(&client, &update).await == Flow::Stop {
+                return;
+            }
+        }
+
+        let handler = self.handlers.iter().find(|h| h.filter.check(&update));
+        let result = if self.middleware.is_empty() {
+            match handler {
+                Some(handler) => Some((handler.callback)(client, update).await),
+                None => None,
+            }
+        } else {
+            // The middleware needs the update after the handler is done with it.
+            let result = match handler {
+                Some(handler) => Some((handler.callback)(client.clone(), update.clone()).await),
+                None => None,
+            };
+            for middleware in self.middleware.iter().rev() {
+                middleware.after(&client, &update, result.as_ref()).await;
+            }
+            result
+        };
+
+        if let Some(Err(e)) = result {
+            warn!("update handler failed: {}", e);
+        }
+    }
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle the given update, and then every other update queued for the same chat in order.
+async fn drain(
+    _in_flight: InFlight,
+    dispatcher: Arc<Dispatcher>,
+    client: Client,
+    queues: Arc<ChatQueues>,
+    key: Peer,
+    mut update: Update,
+) {
+    loop {
+        dispatcher.dispatch(client.clone(), update).await;
+
+        let next = {
+            let mut queues = queues.queues.lock("dispatcher.drain");
+            let next = queues.get_mut(&key).and_then(VecDeque::pop_front);
+            if next.is_none() {
+                queues.remove(&key);
+            }
+            next
+        };
+        queues.space.notify_waiters();
+
+        match next {
+            Some(next) => update = next,
+            None => break,
+        }
+    }
+}
+
+/// The name of the kind of update, to tell them apart in the traces.
+#[cfg(feature = "tracing")]
+fn update_kind(update: &Update) -> &'static str {
+    match update {
+        Update::NewMessage(_) => "new_message",
+        Update::MessageEdited(_) => "message_edited",
+        Update::MessageDeleted(_) => "message_deleted",
+        Update::CallbackQuery(_) => "callback_query",
+        Update::InlineQuery(_) => "inline_query",
+        Update::InlineSend(_) => "inline_send",
+        Update::ChatMemberUpdated(_) => "chat_member_updated",
+        Update::UserTyping(_) => "user_typing",
+        Update::UserStatus(_) => "user_status",
+        Update::PollUpdated(_) => "poll_updated",
+        Update::PollVote(_) => "poll_vote",
+        Update::PreCheckoutQuery(_) => "pre_checkout_query",
+        Update::ShippingQuery(_) => "shipping_query",
+    }
+}
+
+/// The peer of the chat where the update occurred, if it belongs to one, under which the updates
+/// from the same chat are ordered.
+///
+/// Inline updates are keyed by the identifier of their sender, so that they're ordered even if
+/// the sender itself is missing from the update.
+fn key_of(update: &Update) -> Option<Peer> {
+    match update {
+        Update::InlineQuery(query) => Some(Peer::User(query.sender_id())),
+        Update::InlineSend(send) => Some(Peer::User(send.sender_id())),
+        _ => chat_of(update).map(|chat| (&chat.pack().to_peer()).into()),
+    }
+}
+
+/// The chat where the update occurred, if it belongs to one (and is known).
+pub(crate) fn chat_of(update: &Update) -> Option<Chat> {
+    match update {
+        Update::NewMessage(message) | Update::MessageEdited(message) => Some(message.chat()),
+        Update::CallbackQuery(query) => Some(query.chat().clone()),
+        Update::InlineQuery(query) => query.try_sender().cloned().map(Chat::User),
+        Update::InlineSend(send) => send.try_sender().cloned().map(Chat::User),
+        Update::ChatMemberUpdated(update) => Some(update.chat().clone()),
+        Update::UserTyping(update) => Some(update.chat().clone()),
+        Update::UserStatus(update) => Some(update.user().clone()),
+        Update::PollVote(vote) => Some(vote.user().clone()),
+        Update::PreCheckoutQuery(query) => Some(query.sender().clone()),
+        Update::ShippingQuery(query) => Some(query.sender().clone()),
+        Update::MessageDeleted(_) | Update::PollUpdated(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ChatMap, InlineSend};
+    use grammers_tl_types as tl;
+
+    #[test]
+    fn inline_send_without_sender_is_keyed_by_its_id() {
+        let update = Update::InlineSend(InlineSend::new(
+            tl::types::UpdateBotInlineSend {
+                user_id: 123,
+                query: String::new(),
+                geo: None,
+                id: String::new(),
+                msg_id: None,
+            },
+            &ChatMap::empty(),
+        ));
+
+        assert!(chat_of(&update).is_none());
+        assert_eq!(key_of(&update), Some(Peer::User(123)));
+    }
+}
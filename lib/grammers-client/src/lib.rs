@@ -37,9 +37,16 @@
 //! [Telegram Bot API]: https://core.telegram.org/bots/api
 //! [obtain a developer API ID]: https://my.telegram.org/auth
 pub mod client;
+pub mod dispatcher;
 mod parsers;
 pub mod types;
 pub(crate) mod utils;
 
-pub use client::{Client, Config, InitParams, SignInError};
+pub use client::{
+    Client, Config, ConnectionEvent, InitParams, InvokeAction, InvokeHook, InvokeInfo, Metrics,
+    PasswordError, RateLimit, ReconnectionPolicy, RequestOrder, RequestPriority, SignInError,
+    TransportKind, UpdateOverflowPolicy, UsernameError,
+};
+pub use grammers_mtproto::authentication::RsaKey;
+pub use grammers_mtsender::Proxy;
 pub use types::{button, reply_markup, ChatMap, InputMessage, Update};
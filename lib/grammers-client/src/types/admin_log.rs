@@ -0,0 +1,177 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap, Message, Participant, Photo};
+use crate::utils;
+use crate::Client;
+use grammers_tl_types as tl;
+use std::sync::Arc;
+
+/// The action performed in a single entry of the administrator log of a channel.
+///
+/// Events which carry messages (such as edits or deletions) have those messages materialized,
+/// so their text, media and sender can be inspected like any other [`Message`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum AdminLogEvent {
+    /// The title of the channel was changed.
+    ChangeTitle { old: String, new: String },
+    /// The description of the channel was changed.
+    ChangeAbout { old: String, new: String },
+    /// The public username of the channel was changed.
+    ChangeUsername { old: String, new: String },
+    /// The photo of the channel was changed.
+    ChangePhoto { old: Photo, new: Photo },
+    /// A message was edited. Both the message before and after the edit are available.
+    EditMessage { old: Message, new: Message },
+    /// A message was deleted.
+    DeleteMessage(Message),
+    /// A message was pinned.
+    PinMessage(Message),
+    /// A poll was stopped.
+    StopPoll(Message),
+    /// A user joined the channel.
+    Join,
+    /// A user left the channel.
+    Leave,
+    /// A user was invited to the channel.
+    Invite(Participant),
+    /// The restrictions of a participant were changed (for example, they were banned or kicked).
+    ToggleBan { old: Participant, new: Participant },
+    /// The administrator rights of a participant were changed.
+    ToggleAdmin { old: Participant, new: Participant },
+    /// Any other event that does not have a dedicated variant yet.
+    Other(tl::enums::ChannelAdminLogEventAction),
+}
+
+/// A single entry in the administrator log of a channel.
+#[derive(Clone)]
+pub struct AdminLogEntry {
+    id: i64,
+    date: i32,
+    user: Chat,
+    event: AdminLogEvent,
+}
+
+impl AdminLogEvent {
+    fn from_raw(
+        client: &Client,
+        action: tl::enums::ChannelAdminLogEventAction,
+        chats: &Arc<ChatMap>,
+    ) -> Self {
+        use tl::enums::ChannelAdminLogEventAction as A;
+
+        let participant = |participant: &tl::enums::ChannelParticipant| {
+            Participant::try_from_raw_channel(chats, participant.clone())
+        };
+        let message = |message: &tl::enums::Message| Message::new(client, message.clone(), chats);
+
+        match action {
+            A::ChangeTitle(a) => Self::ChangeTitle {
+                old: a.prev_value,
+                new: a.new_value,
+            },
+            A::ChangeAbout(a) => Self::ChangeAbout {
+                old: a.prev_value,
+                new: a.new_value,
+            },
+            A::ChangeUsername(a) => Self::ChangeUsername {
+                old: a.prev_value,
+                new: a.new_value,
+            },
+            A::ChangePhoto(a) => Self::ChangePhoto {
+                old: Photo::from_raw(a.prev_photo, client.clone()),
+                new: Photo::from_raw(a.new_photo, client.clone()),
+            },
+            A::EditMessage(a) => match (message(&a.prev_message), message(&a.new_message)) {
+                (Some(old), Some(new)) => Self::EditMessage { old, new },
+                _ => Self::Other(A::EditMessage(a)),
+            },
+            A::DeleteMessage(a) => match message(&a.message) {
+                Some(msg) => Self::DeleteMessage(msg),
+                None => Self::Other(A::DeleteMessage(a)),
+            },
+            A::UpdatePinned(a) => match message(&a.message) {
+                Some(msg) => Self::PinMessage(msg),
+                None => Self::Other(A::UpdatePinned(a)),
+            },
+            A::StopPoll(a) => match message(&a.message) {
+                Some(msg) => Self::StopPoll(msg),
+                None => Self::Other(A::StopPoll(a)),
+            },
+            A::ParticipantJoin => Self::Join,
+            A::ParticipantLeave => Self::Leave,
+            A::ParticipantInvite(a) => match participant(&a.participant) {
+                Some(participant) => Self::Invite(participant),
+                None => Self::Other(A::ParticipantInvite(a)),
+            },
+            A::ParticipantToggleBan(a) => {
+                match (
+                    participant(&a.prev_participant),
+                    participant(&a.new_participant),
+                ) {
+                    (Some(old), Some(new)) => Self::ToggleBan { old, new },
+                    _ => Self::Other(A::ParticipantToggleBan(a)),
+                }
+            }
+            A::ParticipantToggleAdmin(a) => {
+                match (
+                    participant(&a.prev_participant),
+                    participant(&a.new_participant),
+                ) {
+                    (Some(old), Some(new)) => Self::ToggleAdmin { old, new },
+                    _ => Self::Other(A::ParticipantToggleAdmin(a)),
+                }
+            }
+            action => Self::Other(action),
+        }
+    }
+}
+
+impl AdminLogEntry {
+    pub(crate) fn from_raw(
+        client: &Client,
+        event: tl::enums::ChannelAdminLogEvent,
+        chats: &Arc<ChatMap>,
+    ) -> Self {
+        let tl::enums::ChannelAdminLogEvent::Event(event) = event;
+        Self {
+            id: event.id,
+            date: event.date,
+            user: utils::always_find_entity(
+                &tl::types::PeerUser {
+                    user_id: event.user_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            event: AdminLogEvent::from_raw(client, event.action, chats),
+        }
+    }
+
+    /// The unique identifier of this entry within the log of the channel.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// The date when the event took place.
+    pub fn date(&self) -> utils::Date {
+        utils::date(self.date)
+    }
+
+    /// The user who performed the action, most often an administrator.
+    pub fn user(&self) -> &Chat {
+        &self.user
+    }
+
+    /// The action that was performed.
+    pub fn event(&self) -> &AdminLogEvent {
+        &self.event
+    }
+}
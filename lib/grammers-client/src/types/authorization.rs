@@ -0,0 +1,97 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::utils;
+use grammers_tl_types as tl;
+
+/// A session logged in to the current account, such as another device or application.
+#[derive(Clone, Debug)]
+pub struct Authorization(tl::types::Authorization);
+
+impl Authorization {
+    pub(crate) fn from_raw(
+        tl::enums::Authorization::Authorization(auth): tl::enums::Authorization,
+    ) -> Self {
+        Self(auth)
+    }
+
+    /// The hash identifying this session, which can be used to terminate it with
+    /// [`crate::Client::reset_authorization`].
+    pub fn hash(&self) -> i64 {
+        self.0.hash
+    }
+
+    /// Whether this is the session currently in use by the client.
+    pub fn is_current(&self) -> bool {
+        self.0.current
+    }
+
+    /// Whether the session is from an official application.
+    pub fn is_official_app(&self) -> bool {
+        self.0.official_app
+    }
+
+    /// Whether the session has not yet entered the two-factor authentication password.
+    pub fn is_password_pending(&self) -> bool {
+        self.0.password_pending
+    }
+
+    /// The model of the device where the session was created.
+    pub fn device_model(&self) -> &str {
+        self.0.device_model.as_ref()
+    }
+
+    /// The platform (operating system) of the device.
+    pub fn platform(&self) -> &str {
+        self.0.platform.as_ref()
+    }
+
+    /// The version of the operating system of the device.
+    pub fn system_version(&self) -> &str {
+        self.0.system_version.as_ref()
+    }
+
+    /// The API ID of the application that created the session.
+    pub fn api_id(&self) -> i32 {
+        self.0.api_id
+    }
+
+    /// The name of the application that created the session.
+    pub fn app_name(&self) -> &str {
+        self.0.app_name.as_ref()
+    }
+
+    /// The version of the application that created the session.
+    pub fn app_version(&self) -> &str {
+        self.0.app_version.as_ref()
+    }
+
+    /// The date when the session was created.
+    pub fn date_created(&self) -> utils::Date {
+        utils::date(self.0.date_created)
+    }
+
+    /// The date when the session was last active.
+    pub fn date_active(&self) -> utils::Date {
+        utils::date(self.0.date_active)
+    }
+
+    /// The IP address last used by the session.
+    pub fn ip(&self) -> &str {
+        self.0.ip.as_ref()
+    }
+
+    /// The country the session was last active from, as determined from its IP address.
+    pub fn country(&self) -> &str {
+        self.0.country.as_ref()
+    }
+
+    /// The region the session was last active from, as determined from its IP address.
+    pub fn region(&self) -> &str {
+        self.0.region.as_ref()
+    }
+}
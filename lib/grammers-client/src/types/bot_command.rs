@@ -0,0 +1,43 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use grammers_tl_types as tl;
+
+/// A command supported by a bot, shown to users when they type "/" in the chat with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BotCommand {
+    /// The text of the command, without the leading slash (for example, `start`).
+    pub command: String,
+    /// A short description of what the command does.
+    pub description: String,
+}
+
+impl BotCommand {
+    /// Create a new command with the given text (without the leading slash) and description.
+    pub fn new(command: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            description: description.into(),
+        }
+    }
+
+    pub(crate) fn from_raw(command: tl::enums::BotCommand) -> Self {
+        let tl::enums::BotCommand::Command(command) = command;
+        Self {
+            command: command.command,
+            description: command.description,
+        }
+    }
+
+    pub(crate) fn to_raw(&self) -> tl::enums::BotCommand {
+        tl::types::BotCommand {
+            command: self.command.clone(),
+            description: self.description.clone(),
+        }
+        .into()
+    }
+}
@@ -19,6 +19,7 @@ use std::time::Duration;
 /// You should always [`CallbackQuery::answer`] these queries, even if you have no data to display
 /// to the user, because otherwise they will think the bot is non-responsive (the button spinner
 /// will timeout).
+#[derive(Clone)]
 pub struct CallbackQuery {
     pub(crate) query: tl::types::UpdateBotCallbackQuery,
     pub(crate) client: Client,
@@ -71,13 +72,35 @@ impl CallbackQuery {
     ///
     /// > Trivia: it used to be possible to fake the callback data, but a server-side check was
     /// > added circa 2018 to prevent malicious clients from doing so.
+    ///
+    /// Game buttons carry no data, so it will be empty if the [`CallbackQuery::game_short_name`]
+    /// is present instead.
     pub fn data(&self) -> &[u8] {
-        self.query.data.as_deref().unwrap()
+        self.query.data.as_deref().unwrap_or(&[])
+    }
+
+    /// The short name of the game to launch, if the pressed button was a game button.
+    ///
+    /// The query should be answered with the [`Answer::url`] to the game.
+    pub fn game_short_name(&self) -> Option<&str> {
+        self.query.game_short_name.as_deref()
+    }
+
+    /// The identifier of the message that contains the pressed inline button.
+    pub fn message_id(&self) -> i32 {
+        self.query.msg_id
     }
 
     /// Load the `Message` that contains the pressed inline button.
-    pub async fn load_message(&self) -> Result<types::Message, InvocationError> {
-        todo!()
+    ///
+    /// Returns `None` if the message was deleted in the meantime.
+    pub async fn load_message(&self) -> Result<Option<types::Message>, InvocationError> {
+        Ok(self
+            .client
+            .get_messages_by_id(self.chat(), &[self.query.msg_id])
+            .await?
+            .pop()
+            .flatten())
     }
 
     /// Answer the callback query.
@@ -124,6 +147,18 @@ impl<'a> Answer<'a> {
         self
     }
 
+    /// Configure the URL the user's client should open.
+    ///
+    /// Telegram only allows bots to open URLs for game buttons, or links to the bot such as
+    /// `t.me/your_bot?start=parameter`.
+    ///
+    /// When the pressed button was a game button, this should be the URL of the game, and the
+    /// `cache_time` can be used to avoid having to answer it again every time it's launched.
+    pub fn url<T: Into<String>>(mut self, url: T) -> Self {
+        self.request.url = Some(url.into());
+        self
+    }
+
     /// Send the answer back to Telegram, and then relayed to the user who pressed the inline
     /// button.
     pub async fn send(self) -> Result<(), InvocationError> {
@@ -108,6 +108,13 @@ impl Channel {
     pub fn title(&self) -> &str {
         self.0.title.as_str()
     }
+
+    /// Return the public @username of this channel, if any.
+    ///
+    /// The returned username does not contain the "@" prefix.
+    pub fn username(&self) -> Option<&str> {
+        self.0.username.as_deref()
+    }
 }
 
 impl From<Channel> for PackedChat {
@@ -108,6 +108,18 @@ impl Group {
         }
     }
 
+    /// Return the public @username of this group, if any.
+    ///
+    /// The returned username does not contain the "@" prefix. Only megagroups can have one.
+    pub fn username(&self) -> Option<&str> {
+        use tl::enums::Chat;
+
+        match &self.0 {
+            Chat::Channel(chat) => chat.username.as_deref(),
+            _ => None,
+        }
+    }
+
     /// Returns true if this group is a megagroup (also known as supergroups).
     ///
     /// In case inner type of group is Channel, that means it's a megagroup.
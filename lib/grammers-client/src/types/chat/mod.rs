@@ -102,6 +102,17 @@ impl Chat {
         }
     }
 
+    /// Return the public @username of this chat, if any.
+    ///
+    /// The returned username does not contain the "@" prefix.
+    pub fn username(&self) -> Option<&str> {
+        match self {
+            Self::User(user) => user.username(),
+            Self::Group(group) => group.username(),
+            Self::Channel(channel) => channel.username(),
+        }
+    }
+
     /// Pack this chat into a smaller representation that can be loaded later.
     pub fn pack(&self) -> PackedChat {
         match self {
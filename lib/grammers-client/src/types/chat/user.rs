@@ -5,6 +5,7 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use crate::types::Presence;
 use grammers_session::{PackedChat, PackedType};
 use grammers_tl_types as tl;
 use std::fmt;
@@ -69,7 +70,7 @@ impl fmt::Debug for User {
     }
 }
 
-// TODO: photo, status
+// TODO: photo
 impl User {
     pub(crate) fn from_raw(user: tl::enums::User) -> Self {
         Self(match user {
@@ -264,6 +265,13 @@ impl User {
     pub fn lang_code(&self) -> Option<&str> {
         self.0.lang_code.as_deref()
     }
+
+    /// The last time the user was seen online, as known when this user was fetched.
+    ///
+    /// Use [`crate::Client::get_status`] to fetch their current presence.
+    pub fn presence(&self) -> Presence {
+        Presence::from_raw(self.0.status.as_ref())
+    }
 }
 
 impl From<User> for PackedChat {
@@ -11,8 +11,11 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 /// Hashable `Peer`.
-#[derive(Hash, PartialEq, Eq)]
-pub(crate) enum Peer {
+///
+/// Identifiers are only unique among the same kind of peer, so a user, a small group and a
+/// channel may share the same identifier.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Peer {
     User(i32),
     Chat(i32),
     Channel(i32),
@@ -75,12 +78,10 @@ impl ChatMap {
         self.map.remove(&peer.into())
     }
 
-    pub(crate) fn remove_user(&mut self, user_id: i32) -> Option<User> {
-        self.map
-            .remove(&Peer::User(user_id))
-            .map(|chat| match chat {
-                Chat::User(user) => user,
-                _ => unreachable!(),
-            })
+    pub(crate) fn get_user(&self, user_id: i32) -> Option<User> {
+        self.map.get(&Peer::User(user_id)).map(|chat| match chat {
+            Chat::User(user) => user.clone(),
+            _ => unreachable!(),
+        })
     }
 }
@@ -0,0 +1,187 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap, Participant};
+use crate::{utils, Client};
+use grammers_tl_types as tl;
+
+/// The kind of change a chat member went through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChatMemberChange {
+    /// The user joined the chat, either on their own or because they were added by someone else.
+    Joined,
+    /// The user left the chat, either on their own or because they were removed by someone else.
+    Left,
+    /// The user was banned from the chat, or their restrictions in the chat changed.
+    Banned,
+    /// The user is still a member but something else changed, such as their administrator rights.
+    Updated,
+}
+
+/// Represents a chat member update, which occurs when a user joins, leaves or is banned from a
+/// chat, or when their rights in the chat change.
+///
+/// In small groups, these updates are sent when someone is added or removed (with no information
+/// about the member's rights). In channels and megagroups, Telegram sends them with the old and
+/// new participant states, but only to bots which are administrators in them.
+#[derive(Clone, Debug)]
+pub struct ChatMemberUpdate {
+    chat: Chat,
+    user: Chat,
+    actor: Option<Chat>,
+    change: ChatMemberChange,
+    old: Option<Participant>,
+    new: Option<Participant>,
+}
+
+impl ChatMemberUpdate {
+    pub(crate) fn from_chat_add(
+        client: &Client,
+        update: tl::types::UpdateChatParticipantAdd,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            chat: find_chat(client, update.chat_id, chats),
+            user: find_user(client, update.user_id, chats),
+            actor: if update.inviter_id != 0 {
+                Some(find_user(client, update.inviter_id, chats))
+            } else {
+                None
+            },
+            change: ChatMemberChange::Joined,
+            old: None,
+            new: chat_participant(
+                chats,
+                tl::types::ChatParticipant {
+                    user_id: update.user_id,
+                    inviter_id: update.inviter_id,
+                    date: update.date,
+                }
+                .into(),
+            ),
+        }
+    }
+
+    pub(crate) fn from_chat_delete(
+        client: &Client,
+        update: tl::types::UpdateChatParticipantDelete,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            chat: find_chat(client, update.chat_id, chats),
+            user: find_user(client, update.user_id, chats),
+            actor: None,
+            change: ChatMemberChange::Left,
+            old: None,
+            new: None,
+        }
+    }
+
+    pub(crate) fn from_channel(
+        client: &Client,
+        update: tl::types::UpdateChannelParticipant,
+        chats: &ChatMap,
+    ) -> Self {
+        use tl::enums::ChannelParticipant as P;
+
+        let was_member = match &update.prev_participant {
+            None | Some(P::Left(_)) | Some(P::Banned(_)) => false,
+            Some(_) => true,
+        };
+        let change = match &update.new_participant {
+            None | Some(P::Left(_)) => ChatMemberChange::Left,
+            Some(P::Banned(_)) => ChatMemberChange::Banned,
+            Some(_) if !was_member => ChatMemberChange::Joined,
+            Some(_) => ChatMemberChange::Updated,
+        };
+
+        Self {
+            chat: utils::always_find_entity(
+                &tl::types::PeerChannel {
+                    channel_id: update.channel_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            user: find_user(client, update.user_id, chats),
+            actor: Some(find_user(client, update.actor_id, chats)),
+            change,
+            old: update
+                .prev_participant
+                .and_then(|p| Participant::try_from_raw_channel(chats, p)),
+            new: update
+                .new_participant
+                .and_then(|p| Participant::try_from_raw_channel(chats, p)),
+        }
+    }
+
+    /// The chat where the change occurred.
+    pub fn chat(&self) -> &Chat {
+        &self.chat
+    }
+
+    /// The user whose membership changed.
+    pub fn user(&self) -> &Chat {
+        &self.user
+    }
+
+    /// The user who caused the change, if known.
+    ///
+    /// This will be the same as [`ChatMemberUpdate::user`] if the user joined or left on their
+    /// own.
+    pub fn actor(&self) -> Option<&Chat> {
+        self.actor.as_ref()
+    }
+
+    /// The kind of change the member went through.
+    pub fn change(&self) -> ChatMemberChange {
+        self.change
+    }
+
+    /// The state of the participant before the change.
+    ///
+    /// This is `None` if the user was not a member of the chat before, or if Telegram did not
+    /// include their previous state (as is the case for small group chats).
+    pub fn old_participant(&self) -> Option<&Participant> {
+        self.old.as_ref()
+    }
+
+    /// The state of the participant after the change.
+    ///
+    /// This is `None` if the user is no longer a member of the chat, or if Telegram did not
+    /// include their new state.
+    pub fn new_participant(&self) -> Option<&Participant> {
+        self.new.as_ref()
+    }
+}
+
+// Telegram should always include the participants' users along the update, but constructing
+// a `Participant` without them is not possible, and they're not worth a panic.
+fn chat_participant(
+    chats: &ChatMap,
+    participant: tl::enums::ChatParticipant,
+) -> Option<Participant> {
+    use tl::enums::ChatParticipant as P;
+
+    let user_id = match &participant {
+        P::Participant(p) => p.user_id,
+        P::Creator(p) => p.user_id,
+        P::Admin(p) => p.user_id,
+    };
+    chats.get_user(user_id)?;
+    Some(Participant::from_raw_chat(chats, participant))
+}
+
+fn find_user(client: &Client, user_id: i32, chats: &ChatMap) -> Chat {
+    utils::always_find_entity(&tl::types::PeerUser { user_id }.into(), chats, client)
+}
+
+fn find_chat(client: &Client, chat_id: i32, chats: &ChatMap) -> Chat {
+    utils::always_find_entity(&tl::types::PeerChat { chat_id }.into(), chats, client)
+}
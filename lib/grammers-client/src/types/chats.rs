@@ -18,7 +18,7 @@ use std::{
     mem::drop,
     pin::Pin,
     task::{Context, Poll},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, UNIX_EPOCH},
 };
 
 type BuilderRes = Result<(), InvocationError>;
@@ -529,13 +529,15 @@ impl<F: Future<Output = BuilderRes>> BannedRightsBuilder<F> {
     }
 
     /// Apply the restriction for a given duration.
+    ///
+    /// The duration starts counting from the server's time, as given by [`Client::server_time`],
+    /// so the restriction is lifted when intended even if the system clock is off.
     pub fn duration(mut self, val: Duration) -> Self {
-        // TODO this should account for the server time instead (via sender's offset)
-        self.inner_mut().rights.until_date = SystemTime::now()
+        let inner = self.inner_mut();
+        inner.rights.until_date = (inner.client.server_time() + val)
             .duration_since(UNIX_EPOCH)
             .expect("system time is before epoch")
-            .as_secs() as i32
-            + val.as_secs() as i32;
+            .as_secs() as i32;
 
         self
     }
@@ -0,0 +1,146 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// A bot command found at the start of a message, such as `/start@bot some arguments`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command<'a> {
+    name: &'a str,
+    username: Option<&'a str>,
+    args: &'a str,
+}
+
+impl<'a> Command<'a> {
+    /// Parse the command at the start of the text, if there is one.
+    ///
+    /// If `bot_username` is given and the command is addressed to a different bot (as in
+    /// `/start@other_bot`), it's not considered a command.
+    pub(crate) fn parse(text: &'a str, bot_username: Option<&str>) -> Option<Self> {
+        let text = text.strip_prefix('/')?;
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        let (head, args) = text.split_at(end);
+
+        let (name, username) = match head.find('@') {
+            Some(at) => (&head[..at], Some(&head[at + 1..])),
+            None => (head, None),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        if let (Some(username), Some(bot_username)) = (username, bot_username) {
+            if !username.eq_ignore_ascii_case(bot_username) {
+                return None;
+            }
+        }
+
+        Some(Self {
+            name,
+            username,
+            args: args.trim_start(),
+        })
+    }
+
+    /// The name of the command, without the leading slash nor the bot username.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The username of the bot the command was addressed to, if it was explicitly mentioned.
+    pub fn username(&self) -> Option<&'a str> {
+        self.username
+    }
+
+    /// The text after the command, as-is except for the leading whitespace.
+    pub fn raw_args(&self) -> &'a str {
+        self.args
+    }
+
+    /// The arguments after the command, separated by whitespace.
+    pub fn args(&self) -> impl Iterator<Item = &'a str> {
+        self.args.split_whitespace()
+    }
+
+    /// The arguments after the command, separated by whitespace, except for the text inside
+    /// double or single quotes, which is kept as a single argument.
+    ///
+    /// A backslash can be used to escape a quote, a whitespace or another backslash. Unclosed
+    /// quotes extend until the end of the text.
+    pub fn quoted_args(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        let mut current = None::<String>;
+        let mut quote = None;
+        let mut chars = self.args.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    let escaped = chars.next().unwrap_or('\\');
+                    current.get_or_insert_with(String::new).push(escaped);
+                }
+                c if Some(c) == quote => quote = None,
+                '"' | '\'' if quote.is_none() => {
+                    quote = Some(c);
+                    current.get_or_insert_with(String::new);
+                }
+                c if c.is_whitespace() && quote.is_none() => {
+                    result.extend(current.take());
+                }
+                c => current.get_or_insert_with(String::new).push(c),
+            }
+        }
+
+        result.extend(current);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_without_args() {
+        let command = Command::parse("/start", None).unwrap();
+        assert_eq!(command.name(), "start");
+        assert_eq!(command.username(), None);
+        assert_eq!(command.raw_args(), "");
+        assert_eq!(command.args().count(), 0);
+    }
+
+    #[test]
+    fn parse_command_with_username_and_args() {
+        let command = Command::parse("/echo@MyBot  hello   world ", Some("mybot")).unwrap();
+        assert_eq!(command.name(), "echo");
+        assert_eq!(command.username(), Some("MyBot"));
+        assert_eq!(command.raw_args(), "hello   world ");
+        assert_eq!(command.args().collect::<Vec<_>>(), vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn ignore_commands_for_other_bots() {
+        assert_eq!(Command::parse("/start@other_bot", Some("my_bot")), None);
+        assert!(Command::parse("/start@other_bot", None).is_some());
+    }
+
+    #[test]
+    fn ignore_non_commands() {
+        assert_eq!(Command::parse("start", None), None);
+        assert_eq!(Command::parse("/", None), None);
+        assert_eq!(Command::parse("/ start", None), None);
+        assert_eq!(Command::parse("/@my_bot", None), None);
+    }
+
+    #[test]
+    fn split_quoted_args() {
+        let command =
+            Command::parse(r#"/say "hello world" 'it\'s' me \"x\" "" "open"#, None).unwrap();
+        assert_eq!(
+            command.quoted_args(),
+            vec!["hello world", "it's", "me", "\"x\"", "", "open"]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{InputMessage, Message};
+use crate::Client;
+use grammers_mtsender::InvocationError;
+use grammers_session::PackedChat;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+/// A conversation with a single chat, used to write request and response flows linearly.
+///
+/// Incoming messages from the chat are buffered since the moment the conversation is created,
+/// so none are missed between sending a message and waiting for the reply. These messages are
+/// still returned by [`Client::next_update`] as usual, which must keep being called for the
+/// conversation to receive anything.
+///
+/// The conversation ends when it is dropped.
+pub struct Conversation {
+    client: Client,
+    chat: PackedChat,
+    incoming: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Conversation {
+    pub(crate) fn new(
+        client: &Client,
+        chat: PackedChat,
+        incoming: mpsc::UnboundedReceiver<Message>,
+    ) -> Self {
+        Self {
+            client: client.clone(),
+            chat,
+            incoming,
+        }
+    }
+
+    /// The chat this conversation takes place in.
+    pub fn chat(&self) -> PackedChat {
+        self.chat
+    }
+
+    /// Send a message to the chat of the conversation.
+    pub async fn send<M: Into<InputMessage>>(
+        &self,
+        message: M,
+    ) -> Result<Message, InvocationError> {
+        self.client.send_message(self.chat, message).await
+    }
+
+    /// Wait for the next incoming message in the chat of the conversation, returning the oldest
+    /// one that has not been returned yet if there are any buffered.
+    ///
+    /// Returns `None` if no message arrives before the `timeout` expires.
+    pub async fn wait_for_reply(&mut self, duration: Duration) -> Option<Message> {
+        timeout(duration, self.incoming.recv()).await.ok().flatten()
+    }
+}
+
+impl Drop for Conversation {
+    fn drop(&mut self) {
+        // Forget about the conversations in the chat which have ended (including this one), so
+        // that they don't linger until the next message from the chat arrives.
+        self.incoming.close();
+        let peer = (&self.chat.to_peer()).into();
+        let mut conversations = self.client.0.conversations.lock("conversation.drop");
+        if let Some(senders) = conversations.get_mut(&peer) {
+            senders.retain(|sender| !sender.is_closed());
+            if senders.is_empty() {
+                conversations.remove(&peer);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{ChatMap, User};
+use grammers_tl_types as tl;
+
+/// A score in the high score table of a game.
+#[derive(Clone, Debug)]
+pub struct GameHighScore {
+    position: i32,
+    user: User,
+    score: i32,
+}
+
+impl GameHighScore {
+    pub(crate) fn from_raw(score: tl::enums::HighScore, chats: &ChatMap) -> Option<Self> {
+        let tl::enums::HighScore::Score(score) = score;
+        Some(Self {
+            position: score.pos,
+            user: chats.get_user(score.user_id)?,
+            score: score.score,
+        })
+    }
+
+    /// The position of this score in the table, starting at 1.
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// The user who achieved this score.
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// The score achieved.
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+}
@@ -5,7 +5,8 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{Chat, ChatMap, User};
+use super::media::Document;
+use super::{Chat, ChatMap, Photo, ReplyMarkup, User};
 use crate::{client::Client, utils::generate_random_id, InputMessage};
 use grammers_mtsender::InvocationError;
 use grammers_tl_types as tl;
@@ -14,6 +15,7 @@ use std::sync::Arc;
 
 /// Represents an inline query update, which occurs when you sign in as a bot and a user sends an
 /// inline query such as `@bot query`.
+#[derive(Clone)]
 pub struct InlineQuery {
     query: tl::types::UpdateBotInlineQuery,
     client: Client,
@@ -30,6 +32,15 @@ pub struct Answer {
 ///
 /// The following types implement [`Into<InlineResult>`]:
 /// - [`Article`]
+/// - [`WebPhoto`]
+/// - [`WebDocument`]
+/// - [`CachedPhoto`]
+/// - [`CachedDocument`]
+/// - [`WebGif`]
+/// - [`WebVideo`]
+/// - [`WebAudio`]
+/// - [`Location`]
+/// - [`Contact`]
 pub struct InlineResult(tl::enums::InputBotInlineResult);
 
 impl From<InlineResult> for tl::enums::InputBotInlineResult {
@@ -53,28 +64,52 @@ impl InlineQuery {
 
     // User that sent the query.
     pub fn sender(&self) -> &User {
-        match self
-            .chats
-            .get(
-                &tl::types::PeerUser {
-                    user_id: self.query.user_id,
-                }
-                .into(),
-            )
-            .unwrap()
-        {
-            Chat::User(user) => user,
-            _ => unreachable!(),
+        self.try_sender().unwrap()
+    }
+
+    /// The user that sent the query, unless it was missing from the update.
+    pub(crate) fn try_sender(&self) -> Option<&User> {
+        let peer = tl::types::PeerUser {
+            user_id: self.sender_id(),
+        };
+        match self.chats.get(&peer.into()) {
+            Some(Chat::User(user)) => Some(user),
+            _ => None,
         }
     }
 
+    /// The identifier of the user that sent the query, known even if the user itself is missing.
+    pub(crate) fn sender_id(&self) -> i32 {
+        self.query.user_id
+    }
+
     // The text of the inline query.
     pub fn text(&self) -> &str {
         self.query.query.as_str()
     }
 
     /// Answer the inline query.
-    // TODO: add example
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f(query: grammers_client::types::InlineQuery) -> Result<(), Box<dyn std::error::Error>> {
+    /// use grammers_client::types::inline_query::{Article, WebPhoto};
+    ///
+    /// query
+    ///     .answer(vec![
+    ///         Article::new("Say hello", "Hello!").into(),
+    ///         WebPhoto::new("https://example.com/cat.jpg")
+    ///             .caption("A cat")
+    ///             .into(),
+    ///     ])
+    ///     .cache_time(60)
+    ///     .next_offset("1")
+    ///     .send()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn answer(&self, results: impl IntoIterator<Item = InlineResult>) -> Answer {
         Answer {
             request: tl::functions::messages::SetInlineBotResults {
@@ -208,6 +243,700 @@ impl From<Article> for InlineResult {
     }
 }
 
+/// A photo result which Telegram will fetch from the given URL.
+pub struct WebPhoto {
+    url: String,
+    title: Option<String>,
+    description: Option<String>,
+    thumb_url: Option<String>,
+    caption: InputMessage,
+}
+
+/// A document result (such as a PDF file, or a video) which Telegram will fetch from the given
+/// URL.
+pub struct WebDocument {
+    url: String,
+    mime_type: String,
+    title: String,
+    description: Option<String>,
+    thumb_url: Option<String>,
+    caption: InputMessage,
+}
+
+/// A photo result which has already been uploaded to Telegram, such as one from a message.
+pub struct CachedPhoto {
+    photo: Photo,
+    caption: InputMessage,
+}
+
+/// A document result which has already been uploaded to Telegram, such as one from a message.
+///
+/// The kind of result (such as a sticker, a voice note or a video) is determined from the
+/// attributes of the document, so that it's sent as what it is.
+pub struct CachedDocument {
+    document: Document,
+    title: Option<String>,
+    description: Option<String>,
+    caption: InputMessage,
+}
+
+/// The message to send along a media result, which will be used as its caption.
+fn media_caption(caption: InputMessage) -> tl::enums::InputBotInlineMessage {
+    tl::types::InputBotInlineMessageMediaAuto {
+        message: caption.text,
+        entities: Some(caption.entities),
+        reply_markup: caption.reply_markup,
+    }
+    .into()
+}
+
+fn web_document(url: String, mime_type: String) -> tl::enums::InputWebDocument {
+    web_document_with_attributes(url, mime_type, vec![])
+}
+
+fn web_document_with_attributes(
+    url: String,
+    mime_type: String,
+    attributes: Vec<tl::enums::DocumentAttribute>,
+) -> tl::enums::InputWebDocument {
+    tl::types::InputWebDocument {
+        url,
+        size: 0,
+        mime_type,
+        attributes,
+    }
+    .into()
+}
+
+impl WebPhoto {
+    /// Create a new photo result. The URL must point to a JPEG image.
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            url: url.into(),
+            title: None,
+            description: None,
+            thumb_url: None,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Title of the result.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Short description of the result.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// URL of the thumbnail for the result. Defaults to the URL of the photo itself.
+    pub fn thumb_url(mut self, thumb_url: impl Into<String>) -> Self {
+        self.thumb_url = Some(thumb_url.into());
+        self
+    }
+
+    /// Caption to send along the photo when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<WebPhoto> for InlineResult {
+    fn from(photo: WebPhoto) -> Self {
+        let url = photo.url;
+        let thumb_url = photo.thumb_url.unwrap_or_else(|| url.clone());
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: "photo".into(),
+                title: photo.title,
+                description: photo.description,
+                url: None,
+                thumb: Some(web_document(thumb_url, "image/jpeg".into())),
+                content: Some(web_document(url, "image/jpeg".into())),
+                send_message: media_caption(photo.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+impl WebDocument {
+    /// Create a new document result.
+    ///
+    /// Telegram only supports `application/pdf` and `application/zip` documents, as well as
+    /// `video/mp4` videos, when fetching them from an URL.
+    pub fn new<T: Into<String>, S: Into<String>, M: Into<String>>(
+        title: T,
+        url: S,
+        mime_type: M,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            mime_type: mime_type.into(),
+            title: title.into(),
+            description: None,
+            thumb_url: None,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Short description of the result.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// URL of the thumbnail for the result.
+    pub fn thumb_url(mut self, thumb_url: impl Into<String>) -> Self {
+        self.thumb_url = Some(thumb_url.into());
+        self
+    }
+
+    /// Caption to send along the document when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<WebDocument> for InlineResult {
+    fn from(document: WebDocument) -> Self {
+        let r#type = if document.mime_type.starts_with("video/") {
+            "video"
+        } else {
+            "file"
+        };
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: r#type.into(),
+                title: Some(document.title),
+                description: document.description,
+                url: None,
+                thumb: document
+                    .thumb_url
+                    .map(|url| web_document(url, "image/jpeg".into())),
+                content: Some(web_document(document.url, document.mime_type)),
+                send_message: media_caption(document.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+impl CachedPhoto {
+    /// Create a new result from a photo already stored in Telegram's servers.
+    pub fn new(photo: Photo) -> Self {
+        Self {
+            photo,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Caption to send along the photo when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<CachedPhoto> for InlineResult {
+    fn from(photo: CachedPhoto) -> Self {
+        Self(
+            tl::types::InputBotInlineResultPhoto {
+                id: generate_random_id().to_string(),
+                r#type: "photo".into(),
+                photo: photo.photo.to_input_media().id,
+                send_message: media_caption(photo.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+impl CachedDocument {
+    /// Create a new result from a document already stored in Telegram's servers.
+    pub fn new(document: Document) -> Self {
+        Self {
+            document,
+            title: None,
+            description: None,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Title of the result.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Short description of the result.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Caption to send along the document when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<CachedDocument> for InlineResult {
+    fn from(document: CachedDocument) -> Self {
+        Self(
+            tl::types::InputBotInlineResultDocument {
+                id: generate_random_id().to_string(),
+                r#type: document.document.inline_result_type().into(),
+                title: document.title,
+                description: document.description,
+                document: document.document.to_input_media().id,
+                send_message: media_caption(document.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+/// An animation result (either a GIF or a silent MP4 video) which Telegram will fetch from the
+/// given URL.
+pub struct WebGif {
+    url: String,
+    mime_type: String,
+    thumb_url: String,
+    title: Option<String>,
+    caption: InputMessage,
+}
+
+impl WebGif {
+    /// Create a new animation result, along with the URL of its (static) thumbnail, which
+    /// Telegram requires for animations.
+    ///
+    /// The animation is assumed to be a GIF, unless the URL ends with `.mp4`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either URL is empty.
+    pub fn new<S: Into<String>, T: Into<String>>(url: S, thumb_url: T) -> Self {
+        let url = url.into();
+        let thumb_url = thumb_url.into();
+        assert!(!url.is_empty(), "animation url cannot be empty");
+        assert!(!thumb_url.is_empty(), "animation thumb url cannot be empty");
+
+        let mime_type = if url.ends_with(".mp4") {
+            "video/mp4"
+        } else {
+            "image/gif"
+        };
+        Self {
+            url,
+            mime_type: mime_type.into(),
+            thumb_url,
+            title: None,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Title of the result.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Caption to send along the animation when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<WebGif> for InlineResult {
+    fn from(gif: WebGif) -> Self {
+        let r#type = if gif.mime_type == "video/mp4" {
+            "mpeg4_gif"
+        } else {
+            "gif"
+        };
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: r#type.into(),
+                title: gif.title,
+                description: None,
+                url: None,
+                thumb: Some(web_document(gif.thumb_url, "image/jpeg".into())),
+                content: Some(web_document(gif.url, gif.mime_type)),
+                send_message: media_caption(gif.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+/// A video result which Telegram will fetch from the given URL.
+pub struct WebVideo {
+    url: String,
+    title: String,
+    thumb_url: String,
+    description: Option<String>,
+    duration: i32,
+    width: i32,
+    height: i32,
+    caption: InputMessage,
+}
+
+impl WebVideo {
+    /// Create a new video result, along with the URL of its thumbnail, which Telegram requires
+    /// for videos. The URL must point to an MP4 video.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the title or either URL is empty.
+    pub fn new<T: Into<String>, S: Into<String>, U: Into<String>>(
+        title: T,
+        url: S,
+        thumb_url: U,
+    ) -> Self {
+        let title = title.into();
+        let url = url.into();
+        let thumb_url = thumb_url.into();
+        assert!(!title.is_empty(), "video title cannot be empty");
+        assert!(!url.is_empty(), "video url cannot be empty");
+        assert!(!thumb_url.is_empty(), "video thumb url cannot be empty");
+
+        Self {
+            url,
+            title,
+            thumb_url,
+            description: None,
+            duration: 0,
+            width: 0,
+            height: 0,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Short description of the result.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Duration of the video, in seconds.
+    pub fn duration(mut self, duration: i32) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Width and height of the video, in pixels.
+    pub fn size(mut self, width: i32, height: i32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Caption to send along the video when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<WebVideo> for InlineResult {
+    fn from(video: WebVideo) -> Self {
+        let attributes = vec![tl::types::DocumentAttributeVideo {
+            round_message: false,
+            supports_streaming: false,
+            duration: video.duration,
+            w: video.width,
+            h: video.height,
+        }
+        .into()];
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: "video".into(),
+                title: Some(video.title),
+                description: video.description,
+                url: None,
+                thumb: Some(web_document(video.thumb_url, "image/jpeg".into())),
+                content: Some(web_document_with_attributes(
+                    video.url,
+                    "video/mp4".into(),
+                    attributes,
+                )),
+                send_message: media_caption(video.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+/// An audio result which Telegram will fetch from the given URL.
+pub struct WebAudio {
+    url: String,
+    title: String,
+    performer: Option<String>,
+    duration: i32,
+    caption: InputMessage,
+}
+
+impl WebAudio {
+    /// Create a new audio result. The URL must point to an MP3 file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the title or the URL is empty.
+    pub fn new<T: Into<String>, S: Into<String>>(title: T, url: S) -> Self {
+        let title = title.into();
+        let url = url.into();
+        assert!(!title.is_empty(), "audio title cannot be empty");
+        assert!(!url.is_empty(), "audio url cannot be empty");
+
+        Self {
+            url,
+            title,
+            performer: None,
+            duration: 0,
+            caption: InputMessage::default(),
+        }
+    }
+
+    /// Performer of the audio.
+    pub fn performer(mut self, performer: impl Into<String>) -> Self {
+        self.performer = Some(performer.into());
+        self
+    }
+
+    /// Duration of the audio, in seconds.
+    pub fn duration(mut self, duration: i32) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Caption to send along the audio when the result is chosen.
+    pub fn caption<M: Into<InputMessage>>(mut self, caption: M) -> Self {
+        self.caption = caption.into();
+        self
+    }
+}
+
+impl From<WebAudio> for InlineResult {
+    fn from(audio: WebAudio) -> Self {
+        let attributes = vec![tl::types::DocumentAttributeAudio {
+            voice: false,
+            duration: audio.duration,
+            title: Some(audio.title.clone()),
+            performer: audio.performer.clone(),
+            waveform: None,
+        }
+        .into()];
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: "audio".into(),
+                title: Some(audio.title),
+                description: audio.performer,
+                url: None,
+                thumb: None,
+                content: Some(web_document_with_attributes(
+                    audio.url,
+                    "audio/mpeg".into(),
+                    attributes,
+                )),
+                send_message: media_caption(audio.caption),
+            }
+            .into(),
+        )
+    }
+}
+
+/// A location result, which will send a map with the given point when chosen.
+pub struct Location {
+    title: String,
+    latitude: f64,
+    longitude: f64,
+    accuracy_radius: Option<i32>,
+    thumb_url: Option<String>,
+    reply_markup: Option<tl::enums::ReplyMarkup>,
+}
+
+impl Location {
+    /// Create a new location result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the title is empty, or the coordinates are out of range (the latitude must be
+    /// within -90 and 90, and the longitude within -180 and 180).
+    pub fn new<T: Into<String>>(title: T, latitude: f64, longitude: f64) -> Self {
+        let title = title.into();
+        assert!(!title.is_empty(), "location title cannot be empty");
+        assert!(
+            (-90.0..=90.0).contains(&latitude),
+            "latitude must be within -90 and 90"
+        );
+        assert!(
+            (-180.0..=180.0).contains(&longitude),
+            "longitude must be within -180 and 180"
+        );
+
+        Self {
+            title,
+            latitude,
+            longitude,
+            accuracy_radius: None,
+            thumb_url: None,
+            reply_markup: None,
+        }
+    }
+
+    /// How accurate the location is, in meters.
+    pub fn accuracy_radius(mut self, accuracy_radius: i32) -> Self {
+        self.accuracy_radius = Some(accuracy_radius);
+        self
+    }
+
+    /// URL of the thumbnail for the result.
+    pub fn thumb_url(mut self, thumb_url: impl Into<String>) -> Self {
+        self.thumb_url = Some(thumb_url.into());
+        self
+    }
+
+    /// Reply markup to send along the location when the result is chosen.
+    pub fn reply_markup<RM: ReplyMarkup>(mut self, markup: &RM) -> Self {
+        self.reply_markup = Some(markup.to_reply_markup().0);
+        self
+    }
+}
+
+impl From<Location> for InlineResult {
+    fn from(location: Location) -> Self {
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: "geo".into(),
+                title: Some(location.title),
+                description: None,
+                url: None,
+                thumb: location
+                    .thumb_url
+                    .map(|url| web_document(url, "image/jpeg".into())),
+                content: None,
+                send_message: tl::types::InputBotInlineMessageMediaGeo {
+                    geo_point: tl::types::InputGeoPoint {
+                        lat: location.latitude,
+                        long: location.longitude,
+                        accuracy_radius: location.accuracy_radius,
+                    }
+                    .into(),
+                    heading: None,
+                    period: None,
+                    proximity_notification_radius: None,
+                    reply_markup: location.reply_markup,
+                }
+                .into(),
+            }
+            .into(),
+        )
+    }
+}
+
+/// A contact result, which will send the contact card when chosen.
+pub struct Contact {
+    phone_number: String,
+    first_name: String,
+    last_name: String,
+    vcard: String,
+    thumb_url: Option<String>,
+    reply_markup: Option<tl::enums::ReplyMarkup>,
+}
+
+impl Contact {
+    /// Create a new contact result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the phone number or the first name is empty.
+    pub fn new<P: Into<String>, F: Into<String>>(phone_number: P, first_name: F) -> Self {
+        let phone_number = phone_number.into();
+        let first_name = first_name.into();
+        assert!(!phone_number.is_empty(), "contact phone cannot be empty");
+        assert!(!first_name.is_empty(), "contact first name cannot be empty");
+
+        Self {
+            phone_number,
+            first_name,
+            last_name: String::new(),
+            vcard: String::new(),
+            thumb_url: None,
+            reply_markup: None,
+        }
+    }
+
+    /// Last name of the contact.
+    pub fn last_name(mut self, last_name: impl Into<String>) -> Self {
+        self.last_name = last_name.into();
+        self
+    }
+
+    /// Additional data about the contact, in the form of a vCard.
+    pub fn vcard(mut self, vcard: impl Into<String>) -> Self {
+        self.vcard = vcard.into();
+        self
+    }
+
+    /// URL of the thumbnail for the result.
+    pub fn thumb_url(mut self, thumb_url: impl Into<String>) -> Self {
+        self.thumb_url = Some(thumb_url.into());
+        self
+    }
+
+    /// Reply markup to send along the contact when the result is chosen.
+    pub fn reply_markup<RM: ReplyMarkup>(mut self, markup: &RM) -> Self {
+        self.reply_markup = Some(markup.to_reply_markup().0);
+        self
+    }
+}
+
+impl From<Contact> for InlineResult {
+    fn from(contact: Contact) -> Self {
+        let title = if contact.last_name.is_empty() {
+            contact.first_name.clone()
+        } else {
+            format!("{} {}", contact.first_name, contact.last_name)
+        };
+        Self(
+            tl::types::InputBotInlineResult {
+                id: generate_random_id().to_string(),
+                r#type: "contact".into(),
+                title: Some(title),
+                description: Some(contact.phone_number.clone()),
+                url: None,
+                thumb: contact
+                    .thumb_url
+                    .map(|url| web_document(url, "image/jpeg".into())),
+                content: None,
+                send_message: tl::types::InputBotInlineMessageMediaContact {
+                    phone_number: contact.phone_number,
+                    first_name: contact.first_name,
+                    last_name: contact.last_name,
+                    vcard: contact.vcard,
+                    reply_markup: contact.reply_markup,
+                }
+                .into(),
+            }
+            .into(),
+        )
+    }
+}
+
 impl fmt::Debug for InlineQuery {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("InlineQuery")
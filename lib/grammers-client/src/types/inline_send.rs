@@ -0,0 +1,96 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap, User};
+use grammers_tl_types as tl;
+use std::fmt;
+use std::sync::Arc;
+
+/// Represents a chosen inline result update, which occurs when a user picks one of the results
+/// the bot sent as the answer to an inline query.
+///
+/// Telegram only sends these updates if inline feedback is enabled for the bot via @BotFather.
+#[derive(Clone)]
+pub struct InlineSend {
+    update: tl::types::UpdateBotInlineSend,
+    chats: Arc<ChatMap>,
+}
+
+/// The identifier of a message sent via inline mode.
+///
+/// These messages don't belong to any chat the bot is in, so this identifier must be used in
+/// order to refer to them instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineMessageId(pub(crate) tl::types::InputBotInlineMessageId);
+
+impl InlineSend {
+    pub(crate) fn new(update: tl::types::UpdateBotInlineSend, chats: &Arc<ChatMap>) -> Self {
+        Self {
+            update,
+            chats: chats.clone(),
+        }
+    }
+
+    /// The user that chose the result.
+    pub fn sender(&self) -> &User {
+        self.try_sender().unwrap()
+    }
+
+    /// The user that chose the result, unless it was missing from the update.
+    pub(crate) fn try_sender(&self) -> Option<&User> {
+        let peer = tl::types::PeerUser {
+            user_id: self.sender_id(),
+        };
+        match self.chats.get(&peer.into()) {
+            Some(Chat::User(user)) => Some(user),
+            _ => None,
+        }
+    }
+
+    /// The identifier of the user that chose the result, known even if the user itself is missing.
+    pub(crate) fn sender_id(&self) -> i32 {
+        self.update.user_id
+    }
+
+    /// The text of the inline query that was used to obtain the result.
+    pub fn text(&self) -> &str {
+        self.update.query.as_str()
+    }
+
+    /// The identifier of the chosen result, as it was given when answering the inline query.
+    pub fn result_id(&self) -> &str {
+        self.update.id.as_str()
+    }
+
+    /// The identifier of the message sent with the chosen result.
+    ///
+    /// Telegram only includes it if the result had an inline keyboard attached, because only
+    /// then can the bot edit the message afterwards.
+    pub fn message_id(&self) -> Option<InlineMessageId> {
+        self.update
+            .msg_id
+            .clone()
+            .map(|tl::enums::InputBotInlineMessageId::Id(id)| InlineMessageId(id))
+    }
+}
+
+impl InlineMessageId {
+    /// The identifier of the datacenter where the message is stored.
+    pub fn dc_id(&self) -> i32 {
+        self.0.dc_id
+    }
+}
+
+impl fmt::Debug for InlineSend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InlineSend")
+            .field("text", &self.text())
+            .field("sender", &self.sender())
+            .field("result_id", &self.result_id())
+            .finish()
+    }
+}
@@ -10,3 +10,27 @@ pub struct LoginToken {
     pub(crate) phone: String,
     pub(crate) phone_code_hash: String,
 }
+
+impl LoginToken {
+    /// The login code which will be accepted for this phone number, if it's one of the numbers
+    /// reserved for testing (`99966XYYYY`, where `X` is the datacenter ID).
+    ///
+    /// Such numbers only work when connected to the test datacenters (see
+    /// [`InitParams::test_dc`]), and no message is sent to them. The code is always the
+    /// datacenter ID repeated five times.
+    ///
+    /// [`InitParams::test_dc`]: crate::InitParams::test_dc
+    pub fn test_code(&self) -> Option<String> {
+        let digits = self
+            .phone
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect::<String>();
+        let dc_id = digits.strip_prefix("99966")?.chars().next()?;
+        if digits.len() == 10 && ('1'..='3').contains(&dc_id) {
+            Some(dc_id.to_string().repeat(5))
+        } else {
+            None
+        }
+    }
+}
@@ -81,7 +81,7 @@ impl Photo {
         })
     }
 
-    fn to_input_media(&self) -> tl::types::InputMediaPhoto {
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaPhoto {
         use tl::{
             enums::{InputPhoto as eInputPhoto, Photo},
             types::InputPhoto,
@@ -161,7 +161,7 @@ impl Document {
         })
     }
 
-    fn to_input_media(&self) -> tl::types::InputMediaDocument {
+    pub(crate) fn to_input_media(&self) -> tl::types::InputMediaDocument {
         use tl::{
             enums::{Document, InputDocument as eInputDocument},
             types::InputDocument,
@@ -229,6 +229,36 @@ impl Document {
         }
     }
 
+    /// The type of inline result this document should be sent as, so that it's shown as what it
+    /// is (such as a sticker or a voice note) rather than as a generic file.
+    pub(crate) fn inline_result_type(&self) -> &'static str {
+        use tl::enums::DocumentAttribute as A;
+
+        let document = match self.document.document.as_ref() {
+            Some(tl::enums::Document::Document(d)) => d,
+            _ => return "file",
+        };
+
+        let mut kind = None;
+        for attr in document.attributes.iter() {
+            match attr {
+                A::Sticker(_) => return "sticker",
+                A::Animated => return "gif",
+                A::Audio(audio) if audio.voice => kind = Some("voice"),
+                A::Audio(_) => kind = Some("audio"),
+                A::Video(_) => kind = kind.or(Some("video")),
+                _ => {}
+            }
+        }
+
+        kind.unwrap_or_else(|| match document.mime_type.as_str() {
+            "image/gif" => "gif",
+            mime if mime.starts_with("video/") => "video",
+            mime if mime.starts_with("audio/") => "audio",
+            _ => "file",
+        })
+    }
+
     /// The size of the file.
     /// returns 0 if the document is empty.
     pub fn size(&self) -> i32 {
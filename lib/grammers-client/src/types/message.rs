@@ -287,6 +287,14 @@ impl Message {
         self.msg.reply_markup.clone()
     }
 
+    /// The reply markup (inline buttons or custom keyboard) attached to this message, if any.
+    pub fn markup(&self) -> Option<types::reply_markup::ReceivedMarkup> {
+        self.msg
+            .reply_markup
+            .clone()
+            .map(types::reply_markup::ReceivedMarkup::from_raw)
+    }
+
     /// The formatting entities used to format this message, such as bold, italic, with their
     /// offsets and lengths.
     pub fn fmt_entities(&self) -> Option<&Vec<tl::enums::MessageEntity>> {
@@ -345,6 +353,41 @@ impl Message {
         self.action.as_ref()
     }
 
+    /// The bot command at the start of the message text, if any, such as `/start@bot args`.
+    ///
+    /// Commands explicitly addressed to a different bot than the logged-in one are ignored. The
+    /// username of the logged-in bot is only known once [`Client::me`] returns it, which is the
+    /// case after signing in or connecting with a bot session (the bot is fetched on connect).
+    /// If fetching it failed, commands addressed to any bot are accepted until
+    /// [`Client::get_me`] succeeds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn f(message: grammers_client::types::Message) {
+    /// if let Some(command) = message.command() {
+    ///     if command.name() == "echo" {
+    ///         println!("{}", command.raw_args());
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn command(&self) -> Option<types::Command<'_>> {
+        let username = self
+            .client
+            .me()
+            .and_then(|me| me.username().map(|username| username.to_string()));
+        types::Command::parse(self.text(), username.as_deref())
+    }
+
+    /// If this message is the service message sent after a user paid an invoice sent by the
+    /// logged-in bot, return the details of the payment.
+    pub fn successful_payment(&self) -> Option<types::SuccessfulPayment> {
+        self.action
+            .as_ref()
+            .and_then(types::SuccessfulPayment::from_action)
+    }
+
     /// If this message is replying to another message, return the replied message ID.
     pub fn reply_to_message_id(&self) -> Option<i32> {
         if let Some(tl::enums::MessageReplyHeader::Header(m)) = &self.msg.reply_to {
@@ -0,0 +1,45 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use grammers_tl_types as tl;
+
+/// Represents a message deletion update, which occurs when one or more messages are deleted.
+///
+/// Telegram does not say in which chat the messages were deleted unless they belonged to a
+/// channel, because message identifiers are shared by all the private conversations and small
+/// group chats of an account.
+#[derive(Clone, Debug)]
+pub struct MessageDeletion {
+    channel_id: Option<i32>,
+    messages: Vec<i32>,
+}
+
+impl MessageDeletion {
+    pub(crate) fn new(update: tl::types::UpdateDeleteMessages) -> Self {
+        Self {
+            channel_id: None,
+            messages: update.messages,
+        }
+    }
+
+    pub(crate) fn new_channel(update: tl::types::UpdateDeleteChannelMessages) -> Self {
+        Self {
+            channel_id: Some(update.channel_id),
+            messages: update.messages,
+        }
+    }
+
+    /// The identifier of the channel where the messages were deleted, if they belonged to one.
+    pub fn channel_id(&self) -> Option<i32> {
+        self.channel_id
+    }
+
+    /// The identifiers of the messages that were deleted.
+    pub fn messages(&self) -> &[i32] {
+        &self.messages
+    }
+}
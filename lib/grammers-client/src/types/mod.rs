@@ -7,44 +7,84 @@
 // except according to those terms.
 
 //! Custom types extending those provided by Telegram.
+pub mod admin_log;
 pub mod attributes;
+pub mod authorization;
+pub mod bot_command;
 pub mod button;
 pub mod callback_query;
 pub mod chat;
 pub mod chat_map;
+pub mod chat_member;
 pub mod chats;
+pub mod command;
+pub mod conversation;
 pub mod dialog;
+pub mod game;
 pub mod inline_query;
+pub mod inline_send;
 pub mod input_message;
 pub mod iter_buffer;
 pub mod login_token;
 pub mod media;
 pub mod message;
+pub mod message_deletion;
+pub mod notify_settings;
 pub mod participant;
 pub mod password_token;
+pub mod payment;
 pub mod permissions;
 pub mod photo_sizes;
+pub mod poll;
+pub mod privacy;
+pub mod profile;
+pub mod raw_updates;
 pub mod reply_markup;
+pub mod takeout;
 pub mod terms_of_service;
+pub mod theme;
 pub mod update;
+pub mod user_status;
+pub mod user_typing;
 
+pub use admin_log::{AdminLogEntry, AdminLogEvent};
 pub use attributes::Attribute;
+pub use authorization::Authorization;
+pub use bot_command::BotCommand;
 pub use callback_query::CallbackQuery;
 pub use chat::{Channel, Chat, Group, PackedChat, Platform, RestrictionReason, User};
-pub use chat_map::ChatMap;
-pub(crate) use chat_map::Peer;
+pub use chat_map::{ChatMap, Peer};
+pub use chat_member::{ChatMemberChange, ChatMemberUpdate};
 pub use chats::{AdminRightsBuilder, BannedRightsBuilder};
+pub use command::Command;
+pub use conversation::Conversation;
 pub use dialog::Dialog;
+pub use game::GameHighScore;
 pub use inline_query::InlineQuery;
+pub use inline_send::{InlineMessageId, InlineSend};
 pub use input_message::InputMessage;
 pub use iter_buffer::IterBuffer;
 pub use login_token::LoginToken;
 pub(crate) use media::Uploaded;
 pub use media::{Media, Photo};
 pub use message::Message;
+pub use message_deletion::MessageDeletion;
+pub use notify_settings::{NotifyScope, NotifySettings};
 pub use participant::{Participant, Role};
 pub use password_token::PasswordToken;
+pub use payment::{
+    Invoice, PaymentInfo, PreCheckoutQuery, ShippingAddress, ShippingOption, ShippingQuery,
+    SuccessfulPayment,
+};
 pub use permissions::{Permissions, Restrictions};
+pub use poll::{PollUpdate, PollVote};
+pub use privacy::{PrivacyKey, PrivacyRule};
+pub use profile::ProfileBuilder;
+pub use raw_updates::{RawUpdate, RawUpdates};
 pub(crate) use reply_markup::ReplyMarkup;
+pub use takeout::{Takeout, TakeoutOptions};
 pub use terms_of_service::TermsOfService;
+pub use theme::{Theme, Wallpaper};
 pub use update::Update;
+pub use user_status::{Presence, UserStatus};
+pub use user_typing::{ChatAction, UserTyping};
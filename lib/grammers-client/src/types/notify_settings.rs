@@ -0,0 +1,110 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::utils;
+use grammers_tl_types as tl;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The kind of chats whose default notification settings are being read or changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyScope {
+    /// Private conversations with users.
+    Users,
+    /// Small group chats and megagroups.
+    Groups,
+    /// Broadcast channels.
+    Channels,
+}
+
+impl NotifyScope {
+    pub(crate) fn to_raw(self) -> tl::enums::InputNotifyPeer {
+        match self {
+            Self::Users => tl::enums::InputNotifyPeer::InputNotifyUsers,
+            Self::Groups => tl::enums::InputNotifyPeer::InputNotifyChats,
+            Self::Channels => tl::enums::InputNotifyPeer::InputNotifyBroadcasts,
+        }
+    }
+}
+
+/// The notification settings of a chat (or the default ones for a kind of chat).
+///
+/// Settings which are not present (`None`) use the value of the defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotifySettings(tl::types::PeerNotifySettings);
+
+impl NotifySettings {
+    pub(crate) fn from_raw(
+        tl::enums::PeerNotifySettings::Settings(settings): tl::enums::PeerNotifySettings,
+    ) -> Self {
+        Self(settings)
+    }
+
+    pub(crate) fn to_raw(&self) -> tl::enums::InputPeerNotifySettings {
+        tl::types::InputPeerNotifySettings {
+            show_previews: self.0.show_previews,
+            silent: self.0.silent,
+            mute_until: self.0.mute_until,
+            sound: self.0.sound.clone(),
+        }
+        .into()
+    }
+
+    pub(crate) fn set_mute_until(&mut self, mute_until: i32) {
+        self.0.mute_until = Some(mute_until);
+    }
+
+    /// The date until which notifications are muted, if any.
+    ///
+    /// Note that the date may be in the past, in which case the notifications are no longer
+    /// muted.
+    pub fn mute_until(&self) -> Option<utils::Date> {
+        self.0.mute_until.map(utils::date)
+    }
+
+    /// Whether notifications are currently muted.
+    pub fn is_muted(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        self.0
+            .mute_until
+            .map(|until| until as i64 > now)
+            .unwrap_or(false)
+    }
+
+    /// The name of the sound played for notifications.
+    pub fn sound(&self) -> Option<&str> {
+        self.0.sound.as_deref()
+    }
+
+    /// Whether the notifications show a preview of the message text.
+    pub fn show_previews(&self) -> Option<bool> {
+        self.0.show_previews
+    }
+
+    /// Whether the notifications are delivered silently, without sound.
+    pub fn is_silent(&self) -> Option<bool> {
+        self.0.silent
+    }
+
+    /// Change the name of the sound played for notifications.
+    pub fn set_sound(&mut self, sound: Option<&str>) {
+        self.0.sound = sound.map(|s| s.to_string());
+    }
+
+    /// Change whether the notifications show a preview of the message text.
+    pub fn set_show_previews(&mut self, show_previews: Option<bool>) {
+        self.0.show_previews = show_previews;
+    }
+
+    /// Change whether the notifications are delivered silently, without sound.
+    pub fn set_silent(&mut self, silent: Option<bool>) {
+        self.0.silent = silent;
+    }
+}
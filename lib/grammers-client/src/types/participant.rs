@@ -125,35 +125,50 @@ impl Banned {
 
 impl Participant {
     pub(crate) fn from_raw_channel(
-        chats: &mut ChatMap,
+        chats: &ChatMap,
         participant: tl::enums::ChannelParticipant,
     ) -> Self {
+        Self::try_from_raw_channel(chats, participant)
+            .expect("figure out how to deal with missing or non-user participants")
+    }
+
+    /// Like [`Participant::from_raw_channel`], but `None` is returned if the user is missing
+    /// from the map, or the participant is not a user (such as a banned channel).
+    pub(crate) fn try_from_raw_channel(
+        chats: &ChatMap,
+        participant: tl::enums::ChannelParticipant,
+    ) -> Option<Self> {
         use tl::enums::ChannelParticipant as P;
 
-        match participant {
+        let user = |peer: &tl::enums::Peer| match chats.get(peer) {
+            Some(Chat::User(user)) => Some(user.clone()),
+            _ => None,
+        };
+
+        Some(match participant {
             P::Participant(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id)?,
                 role: Role::User(Normal {
                     date: p.date,
                     inviter_id: None,
                 }),
             },
             P::ParticipantSelf(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id)?,
                 role: Role::User(Normal {
                     date: p.date,
                     inviter_id: Some(p.inviter_id),
                 }),
             },
             P::Creator(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id)?,
                 role: Role::Creator(Creator {
                     permissions: Permissions::from_raw(p.admin_rights.into()),
                     rank: p.rank,
                 }),
             },
             P::Admin(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id)?,
                 role: Role::Admin(Admin {
                     can_edit: p.can_edit,
                     inviter_id: p.inviter_id,
@@ -164,10 +179,7 @@ impl Participant {
                 }),
             },
             P::Banned(p) => Self {
-                user: match chats.remove(&p.peer).unwrap() {
-                    Chat::User(user) => user,
-                    _ => todo!("figure out how to deal with non-user being banned"),
-                },
+                user: user(&p.peer)?,
                 role: Role::Banned(Banned {
                     left: p.left,
                     kicked_by: p.kicked_by,
@@ -176,38 +188,32 @@ impl Participant {
                 }),
             },
             P::Left(p) => Self {
-                user: match chats.remove(&p.peer).unwrap() {
-                    Chat::User(user) => user,
-                    _ => todo!("figure out how to deal with non-user leaving"),
-                },
+                user: user(&p.peer)?,
                 role: Role::Left(Left {}),
             },
-        }
+        })
     }
 
-    pub(crate) fn from_raw_chat(
-        chats: &mut ChatMap,
-        participant: tl::enums::ChatParticipant,
-    ) -> Self {
+    pub(crate) fn from_raw_chat(chats: &ChatMap, participant: tl::enums::ChatParticipant) -> Self {
         use tl::enums::ChatParticipant as P;
 
         match participant {
             P::Participant(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id).unwrap(),
                 role: Role::User(Normal {
                     date: p.date,
                     inviter_id: Some(p.inviter_id),
                 }),
             },
             P::Creator(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id).unwrap(),
                 role: Role::Creator(Creator {
                     permissions: Permissions::new_full(),
                     rank: None,
                 }),
             },
             P::Admin(p) => Self {
-                user: chats.remove_user(p.user_id).unwrap(),
+                user: chats.get_user(p.user_id).unwrap(),
                 role: Role::Admin(Admin {
                     can_edit: true,
                     inviter_id: Some(p.inviter_id),
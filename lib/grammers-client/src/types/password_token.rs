@@ -7,6 +7,10 @@
 // except according to those terms.
 use grammers_tl_types as tl;
 
+/// Information needed to sign in to an account protected with two-factor authentication, as
+/// returned by [`crate::SignInError::PasswordRequired`].
+///
+/// Use it with [`crate::Client::check_password`].
 #[derive(Debug)]
 pub struct PasswordToken {
     pub(crate) password: tl::types::account::Password,
@@ -17,6 +21,13 @@ impl PasswordToken {
         PasswordToken { password }
     }
 
+    /// Whether a recovery email was set, in which case [`crate::Client::request_password_recovery`]
+    /// can be used if the password was forgotten.
+    pub fn has_recovery(&self) -> bool {
+        self.password.has_recovery
+    }
+
+    /// The hint the user configured to help them remember their password, if any.
     pub fn hint(&self) -> Option<&String> {
         self.password.hint.as_ref()
     }
@@ -0,0 +1,479 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap};
+use crate::{utils, Client};
+use grammers_mtsender::InvocationError;
+use grammers_tl_types as tl;
+use std::fmt;
+
+/// An invoice to be sent by a bot, which users can pay through the configured payment provider.
+///
+/// Amounts are always in the smallest units of the currency (for example, cents for `USD`).
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    raw: tl::types::InputMediaInvoice,
+}
+
+/// A shipping option offered to the user when answering a [`ShippingQuery`].
+#[derive(Clone, Debug)]
+pub struct ShippingOption(tl::types::ShippingOption);
+
+/// The information requested from the user who is paying an invoice.
+#[derive(Clone, Debug)]
+pub struct PaymentInfo(tl::types::PaymentRequestedInfo);
+
+/// A shipping address provided by the user who is paying an invoice.
+#[derive(Clone, Debug)]
+pub struct ShippingAddress(tl::types::PostAddress);
+
+/// Represents a pre-checkout query update, which occurs when a user confirms the payment of an
+/// invoice sent by the bot.
+///
+/// The query must be answered within 10 seconds, or the payment will be cancelled.
+#[derive(Clone)]
+pub struct PreCheckoutQuery {
+    query: tl::types::UpdateBotPrecheckoutQuery,
+    sender: Chat,
+    client: Client,
+}
+
+/// Represents a shipping query update, which occurs when a user paying an invoice sent by the
+/// bot with [`Invoice::flexible`] enters their shipping address.
+#[derive(Clone)]
+pub struct ShippingQuery {
+    query: tl::types::UpdateBotShippingQuery,
+    sender: Chat,
+    client: Client,
+}
+
+/// A successful payment received by the bot, found in the service message sent after the payment
+/// is completed.
+#[derive(Clone, Debug)]
+pub struct SuccessfulPayment(tl::types::MessageActionPaymentSentMe);
+
+fn labeled_price(label: String, amount: i64) -> tl::enums::LabeledPrice {
+    tl::types::LabeledPrice { label, amount }.into()
+}
+
+impl Invoice {
+    /// Create a new invoice.
+    ///
+    /// The `payload` is never shown to the user, and is returned along the payment updates so
+    /// that the bot can identify the invoice being paid. The `provider_token` is obtained from
+    /// @BotFather after connecting a payment provider to the bot.
+    pub fn new<T: Into<String>, D: Into<String>, P: Into<Vec<u8>>, S: Into<String>>(
+        title: T,
+        description: D,
+        payload: P,
+        provider_token: S,
+        currency: &str,
+    ) -> Self {
+        Self {
+            raw: tl::types::InputMediaInvoice {
+                multiple_allowed: false,
+                can_forward: false,
+                title: title.into(),
+                description: description.into(),
+                photo: None,
+                invoice: tl::types::Invoice {
+                    test: false,
+                    name_requested: false,
+                    phone_requested: false,
+                    email_requested: false,
+                    shipping_address_requested: false,
+                    flexible: false,
+                    phone_to_provider: false,
+                    email_to_provider: false,
+                    currency: currency.to_string(),
+                    prices: Vec::new(),
+                    max_tip_amount: None,
+                    suggested_tip_amounts: None,
+                }
+                .into(),
+                payload: payload.into(),
+                provider: provider_token.into(),
+                provider_data: tl::types::DataJson {
+                    data: "{}".to_string(),
+                }
+                .into(),
+                start_param: String::new(),
+            },
+        }
+    }
+
+    fn invoice(&mut self) -> &mut tl::types::Invoice {
+        let tl::enums::Invoice::Invoice(invoice) = &mut self.raw.invoice;
+        invoice
+    }
+
+    /// Add a price to the invoice, such as the product itself, taxes or a discount (with a
+    /// negative amount).
+    pub fn price<S: Into<String>>(mut self, label: S, amount: i64) -> Self {
+        self.invoice()
+            .prices
+            .push(labeled_price(label.into(), amount));
+        self
+    }
+
+    /// URL of the photo of the product. It must point to a JPEG image.
+    pub fn photo_url<S: Into<String>>(mut self, url: S) -> Self {
+        self.raw.photo = Some(
+            tl::types::InputWebDocument {
+                url: url.into(),
+                size: 0,
+                mime_type: "image/jpeg".into(),
+                attributes: vec![],
+            }
+            .into(),
+        );
+        self
+    }
+
+    /// Whether the invoice is meant to be paid with the test environment of the provider.
+    pub fn test(mut self, test: bool) -> Self {
+        self.invoice().test = test;
+        self
+    }
+
+    /// Whether the full name of the user is required to complete the payment.
+    pub fn need_name(mut self, need: bool) -> Self {
+        self.invoice().name_requested = need;
+        self
+    }
+
+    /// Whether the phone number of the user is required to complete the payment.
+    pub fn need_phone(mut self, need: bool) -> Self {
+        self.invoice().phone_requested = need;
+        self
+    }
+
+    /// Whether the email address of the user is required to complete the payment.
+    pub fn need_email(mut self, need: bool) -> Self {
+        self.invoice().email_requested = need;
+        self
+    }
+
+    /// Whether the shipping address of the user is required to complete the payment.
+    pub fn need_shipping_address(mut self, need: bool) -> Self {
+        self.invoice().shipping_address_requested = need;
+        self
+    }
+
+    /// Whether the final price depends on the shipping method, in which case a [`ShippingQuery`]
+    /// will be received once the user enters their shipping address.
+    pub fn flexible(mut self, flexible: bool) -> Self {
+        self.invoice().flexible = flexible;
+        self
+    }
+
+    /// Allow the user to add a tip of up to the given amount, suggesting some amounts.
+    pub fn tips(mut self, max_amount: i64, suggested_amounts: Vec<i64>) -> Self {
+        let invoice = self.invoice();
+        invoice.max_tip_amount = Some(max_amount);
+        invoice.suggested_tip_amounts = Some(suggested_amounts);
+        self
+    }
+
+    /// JSON-serialized data about the invoice to be shared with the payment provider.
+    pub fn provider_data<S: Into<String>>(mut self, data: S) -> Self {
+        self.raw.provider_data = tl::types::DataJson { data: data.into() }.into();
+        self
+    }
+
+    /// The deep-linking parameter to use for the "start" button of the invoice when it's
+    /// forwarded. If empty, forwarded copies of the invoice can be paid too.
+    pub fn start_param<S: Into<String>>(mut self, start_param: S) -> Self {
+        self.raw.start_param = start_param.into();
+        self
+    }
+
+    pub(crate) fn to_input_media(&self) -> tl::enums::InputMedia {
+        self.raw.clone().into()
+    }
+}
+
+impl ShippingOption {
+    /// Create a new shipping option with the given identifier, which is returned in
+    /// [`PreCheckoutQuery::shipping_option_id`] if it's chosen, and title.
+    pub fn new<I: Into<String>, T: Into<String>>(id: I, title: T) -> Self {
+        Self(tl::types::ShippingOption {
+            id: id.into(),
+            title: title.into(),
+            prices: Vec::new(),
+        })
+    }
+
+    /// Add a price to the shipping option.
+    pub fn price<S: Into<String>>(mut self, label: S, amount: i64) -> Self {
+        self.0.prices.push(labeled_price(label.into(), amount));
+        self
+    }
+}
+
+impl PaymentInfo {
+    /// The full name of the user, if it was requested.
+    pub fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    /// The phone number of the user, if it was requested.
+    pub fn phone(&self) -> Option<&str> {
+        self.0.phone.as_deref()
+    }
+
+    /// The email address of the user, if it was requested.
+    pub fn email(&self) -> Option<&str> {
+        self.0.email.as_deref()
+    }
+
+    /// The shipping address of the user, if it was requested.
+    pub fn shipping_address(&self) -> Option<ShippingAddress> {
+        self.0
+            .shipping_address
+            .clone()
+            .map(|tl::enums::PostAddress::Address(address)| ShippingAddress(address))
+    }
+}
+
+impl ShippingAddress {
+    /// The first line of the address.
+    pub fn street_line1(&self) -> &str {
+        &self.0.street_line1
+    }
+
+    /// The second line of the address.
+    pub fn street_line2(&self) -> &str {
+        &self.0.street_line2
+    }
+
+    /// The city of the address.
+    pub fn city(&self) -> &str {
+        &self.0.city
+    }
+
+    /// The state of the address.
+    pub fn state(&self) -> &str {
+        &self.0.state
+    }
+
+    /// The two-letter ISO 3166-1 code of the country of the address.
+    pub fn country_code(&self) -> &str {
+        &self.0.country_iso2
+    }
+
+    /// The post code of the address.
+    pub fn post_code(&self) -> &str {
+        &self.0.post_code
+    }
+}
+
+impl PreCheckoutQuery {
+    pub(crate) fn new(
+        client: &Client,
+        query: tl::types::UpdateBotPrecheckoutQuery,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            sender: utils::always_find_entity(
+                &tl::types::PeerUser {
+                    user_id: query.user_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            query,
+            client: client.clone(),
+        }
+    }
+
+    /// The user who is paying.
+    pub fn sender(&self) -> &Chat {
+        &self.sender
+    }
+
+    /// The payload of the invoice being paid.
+    pub fn payload(&self) -> &[u8] {
+        &self.query.payload
+    }
+
+    /// The three-letter ISO 4217 code of the currency used.
+    pub fn currency(&self) -> &str {
+        &self.query.currency
+    }
+
+    /// The total amount to be paid, in the smallest units of the currency.
+    pub fn total_amount(&self) -> i64 {
+        self.query.total_amount
+    }
+
+    /// The information provided by the user, if any was requested.
+    pub fn info(&self) -> Option<PaymentInfo> {
+        self.query
+            .info
+            .clone()
+            .map(|tl::enums::PaymentRequestedInfo::Info(info)| PaymentInfo(info))
+    }
+
+    /// The identifier of the shipping option chosen by the user, if any.
+    pub fn shipping_option_id(&self) -> Option<&str> {
+        self.query.shipping_option_id.as_deref()
+    }
+
+    /// Confirm that the bot is ready to proceed with the order.
+    pub async fn approve(&self) -> Result<(), InvocationError> {
+        self.answer(None).await
+    }
+
+    /// Refuse to proceed with the order, with an error message explaining why which will be
+    /// shown to the user.
+    pub async fn reject<S: Into<String>>(&self, error: S) -> Result<(), InvocationError> {
+        self.answer(Some(error.into())).await
+    }
+
+    async fn answer(&self, error: Option<String>) -> Result<(), InvocationError> {
+        self.client
+            .invoke(&tl::functions::messages::SetBotPrecheckoutResults {
+                success: error.is_none(),
+                query_id: self.query.query_id,
+                error,
+            })
+            .await
+            .map(drop)
+    }
+}
+
+impl ShippingQuery {
+    pub(crate) fn new(
+        client: &Client,
+        query: tl::types::UpdateBotShippingQuery,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            sender: utils::always_find_entity(
+                &tl::types::PeerUser {
+                    user_id: query.user_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            query,
+            client: client.clone(),
+        }
+    }
+
+    /// The user who is paying.
+    pub fn sender(&self) -> &Chat {
+        &self.sender
+    }
+
+    /// The payload of the invoice being paid.
+    pub fn payload(&self) -> &[u8] {
+        &self.query.payload
+    }
+
+    /// The shipping address entered by the user.
+    pub fn shipping_address(&self) -> ShippingAddress {
+        let tl::enums::PostAddress::Address(address) = self.query.shipping_address.clone();
+        ShippingAddress(address)
+    }
+
+    /// Offer the given shipping options to the user.
+    pub async fn answer(&self, options: Vec<ShippingOption>) -> Result<(), InvocationError> {
+        self.client
+            .invoke(&tl::functions::messages::SetBotShippingResults {
+                query_id: self.query.query_id,
+                error: None,
+                shipping_options: Some(options.into_iter().map(|o| o.0.into()).collect()),
+            })
+            .await
+            .map(drop)
+    }
+
+    /// Refuse to deliver to the given address, with an error message explaining why which will
+    /// be shown to the user.
+    pub async fn reject<S: Into<String>>(&self, error: S) -> Result<(), InvocationError> {
+        self.client
+            .invoke(&tl::functions::messages::SetBotShippingResults {
+                query_id: self.query.query_id,
+                error: Some(error.into()),
+                shipping_options: None,
+            })
+            .await
+            .map(drop)
+    }
+}
+
+impl SuccessfulPayment {
+    pub(crate) fn from_action(action: &tl::enums::MessageAction) -> Option<Self> {
+        match action {
+            tl::enums::MessageAction::PaymentSentMe(payment) => Some(Self(payment.clone())),
+            _ => None,
+        }
+    }
+
+    /// The payload of the invoice that was paid.
+    pub fn payload(&self) -> &[u8] {
+        &self.0.payload
+    }
+
+    /// The three-letter ISO 4217 code of the currency used.
+    pub fn currency(&self) -> &str {
+        &self.0.currency
+    }
+
+    /// The total amount paid, in the smallest units of the currency.
+    pub fn total_amount(&self) -> i64 {
+        self.0.total_amount
+    }
+
+    /// The information provided by the user, if any was requested.
+    pub fn info(&self) -> Option<PaymentInfo> {
+        self.0
+            .info
+            .clone()
+            .map(|tl::enums::PaymentRequestedInfo::Info(info)| PaymentInfo(info))
+    }
+
+    /// The identifier of the shipping option chosen by the user, if any.
+    pub fn shipping_option_id(&self) -> Option<&str> {
+        self.0.shipping_option_id.as_deref()
+    }
+
+    /// The identifier of the payment assigned by Telegram.
+    pub fn telegram_charge_id(&self) -> &str {
+        let tl::enums::PaymentCharge::Charge(charge) = &self.0.charge;
+        &charge.id
+    }
+
+    /// The identifier of the payment assigned by the payment provider.
+    pub fn provider_charge_id(&self) -> &str {
+        let tl::enums::PaymentCharge::Charge(charge) = &self.0.charge;
+        &charge.provider_charge_id
+    }
+}
+
+impl fmt::Debug for PreCheckoutQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PreCheckoutQuery")
+            .field("sender", &self.sender())
+            .field("currency", &self.currency())
+            .field("total_amount", &self.total_amount())
+            .finish()
+    }
+}
+
+impl fmt::Debug for ShippingQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShippingQuery")
+            .field("sender", &self.sender())
+            .field("shipping_address", &self.shipping_address())
+            .finish()
+    }
+}
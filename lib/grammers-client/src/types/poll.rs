@@ -0,0 +1,113 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap};
+use crate::{utils, Client};
+use grammers_tl_types as tl;
+
+/// Represents a poll update, which occurs when the results of a poll change or the poll is
+/// closed.
+///
+/// Bots only receive these updates for the polls they sent themselves.
+#[derive(Clone, Debug)]
+pub struct PollUpdate {
+    poll_id: i64,
+    poll: Option<tl::types::Poll>,
+    results: tl::types::PollResults,
+}
+
+impl PollUpdate {
+    pub(crate) fn new(update: tl::types::UpdateMessagePoll) -> Self {
+        let tl::enums::PollResults::Results(results) = update.results;
+        Self {
+            poll_id: update.poll_id,
+            poll: update.poll.map(|tl::enums::Poll::Poll(poll)| poll),
+            results,
+        }
+    }
+
+    /// The unique identifier of the poll.
+    pub fn poll_id(&self) -> i64 {
+        self.poll_id
+    }
+
+    /// The question of the poll, if Telegram included the poll itself in the update.
+    pub fn question(&self) -> Option<&str> {
+        self.poll.as_ref().map(|poll| poll.question.as_ref())
+    }
+
+    /// Whether the poll is closed and no longer accepts votes, if Telegram included the poll
+    /// itself in the update.
+    pub fn is_closed(&self) -> Option<bool> {
+        self.poll.as_ref().map(|poll| poll.closed)
+    }
+
+    /// How many users have voted in the poll, if known.
+    pub fn total_voters(&self) -> Option<i32> {
+        self.results.total_voters
+    }
+
+    /// The amount of voters each answer has, identified by its option data.
+    pub fn answer_voters(&self) -> impl Iterator<Item = (&[u8], i32)> {
+        self.results
+            .results
+            .iter()
+            .flatten()
+            .map(|tl::enums::PollAnswerVoters::Voters(v)| (v.option.as_ref(), v.voters))
+    }
+}
+
+/// Represents a poll vote update, which occurs when a user votes in a non-anonymous poll.
+///
+/// Bots only receive these updates for the polls they sent themselves.
+#[derive(Clone, Debug)]
+pub struct PollVote {
+    poll_id: i64,
+    user: Chat,
+    options: Vec<Vec<u8>>,
+}
+
+impl PollVote {
+    pub(crate) fn new(
+        client: &Client,
+        update: tl::types::UpdateMessagePollVote,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            poll_id: update.poll_id,
+            user: utils::always_find_entity(
+                &tl::types::PeerUser {
+                    user_id: update.user_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            options: update.options,
+        }
+    }
+
+    /// The unique identifier of the poll.
+    pub fn poll_id(&self) -> i64 {
+        self.poll_id
+    }
+
+    /// The user who voted.
+    pub fn user(&self) -> &Chat {
+        &self.user
+    }
+
+    /// The data of the answers chosen by the user, which is empty if they retracted their vote.
+    pub fn options(&self) -> impl Iterator<Item = &[u8]> {
+        self.options.iter().map(|option| option.as_ref())
+    }
+
+    /// Whether the user retracted their vote instead of voting.
+    pub fn is_retracted(&self) -> bool {
+        self.options.is_empty()
+    }
+}
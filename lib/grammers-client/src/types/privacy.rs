@@ -0,0 +1,177 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::ChatMap;
+use grammers_session::PackedChat;
+use grammers_tl_types as tl;
+
+/// The setting whose privacy rules are being read or changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PrivacyKey {
+    /// Who can see the last seen time and online status.
+    LastSeen,
+    /// Who can see the phone number.
+    PhoneNumber,
+    /// Who can find the account by its phone number.
+    AddedByPhone,
+    /// Who can see the profile photo.
+    ProfilePhoto,
+    /// Who can link to the account when forwarding its messages.
+    Forwards,
+    /// Who can call the account.
+    Calls,
+    /// Who can use peer-to-peer connections during calls.
+    PeerToPeerCalls,
+    /// Who can add the account to groups and channels.
+    Invites,
+}
+
+/// A single rule of a privacy setting.
+///
+/// Rules are applied in order, and the first one that matches a user decides whether they are
+/// allowed or not, so more specific rules (such as [`PrivacyRule::AllowUsers`]) should come
+/// before the general ones (such as [`PrivacyRule::DisallowAll`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PrivacyRule {
+    /// Allow everyone.
+    AllowAll,
+    /// Allow the contacts of the account.
+    AllowContacts,
+    /// Allow the given users.
+    AllowUsers(Vec<PackedChat>),
+    /// Allow the participants of the given groups.
+    AllowChatParticipants(Vec<PackedChat>),
+    /// Disallow everyone.
+    DisallowAll,
+    /// Disallow the contacts of the account.
+    DisallowContacts,
+    /// Disallow the given users.
+    DisallowUsers(Vec<PackedChat>),
+    /// Disallow the participants of the given groups.
+    DisallowChatParticipants(Vec<PackedChat>),
+}
+
+impl PrivacyKey {
+    pub(crate) fn to_raw(self) -> tl::enums::InputPrivacyKey {
+        use tl::enums::InputPrivacyKey as K;
+        match self {
+            Self::LastSeen => K::StatusTimestamp,
+            Self::PhoneNumber => K::PhoneNumber,
+            Self::AddedByPhone => K::AddedByPhone,
+            Self::ProfilePhoto => K::ProfilePhoto,
+            Self::Forwards => K::Forwards,
+            Self::Calls => K::PhoneCall,
+            Self::PeerToPeerCalls => K::PhoneP2P,
+            Self::Invites => K::ChatInvite,
+        }
+    }
+}
+
+impl PrivacyRule {
+    /// Allow the given users.
+    pub fn allow_users<C: Into<PackedChat>, I: IntoIterator<Item = C>>(users: I) -> Self {
+        Self::AllowUsers(users.into_iter().map(Into::into).collect())
+    }
+
+    /// Allow the participants of the given groups.
+    pub fn allow_chat_participants<C: Into<PackedChat>, I: IntoIterator<Item = C>>(
+        chats: I,
+    ) -> Self {
+        Self::AllowChatParticipants(chats.into_iter().map(Into::into).collect())
+    }
+
+    /// Disallow the given users.
+    pub fn disallow_users<C: Into<PackedChat>, I: IntoIterator<Item = C>>(users: I) -> Self {
+        Self::DisallowUsers(users.into_iter().map(Into::into).collect())
+    }
+
+    /// Disallow the participants of the given groups.
+    pub fn disallow_chat_participants<C: Into<PackedChat>, I: IntoIterator<Item = C>>(
+        chats: I,
+    ) -> Self {
+        Self::DisallowChatParticipants(chats.into_iter().map(Into::into).collect())
+    }
+
+    /// Convert the rule returned by Telegram, resolving its users and chats from the map.
+    ///
+    /// Users and chats which are not in the map are left out.
+    pub(crate) fn from_raw(rule: tl::enums::PrivacyRule, chats: &ChatMap) -> Self {
+        use tl::enums::PrivacyRule as R;
+
+        let users = |ids: Vec<i32>| {
+            ids.into_iter()
+                .filter_map(|user_id| {
+                    chats
+                        .get(&tl::types::PeerUser { user_id }.into())
+                        .map(|chat| chat.pack())
+                })
+                .collect()
+        };
+        // The identifiers may belong to either small group chats or channels.
+        let groups = |ids: Vec<i32>| {
+            ids.into_iter()
+                .filter_map(|id| {
+                    chats
+                        .get(&tl::types::PeerChat { chat_id: id }.into())
+                        .or_else(|| chats.get(&tl::types::PeerChannel { channel_id: id }.into()))
+                        .map(|chat| chat.pack())
+                })
+                .collect()
+        };
+
+        match rule {
+            R::PrivacyValueAllowAll => Self::AllowAll,
+            R::PrivacyValueAllowContacts => Self::AllowContacts,
+            R::PrivacyValueAllowUsers(r) => Self::AllowUsers(users(r.users)),
+            R::PrivacyValueAllowChatParticipants(r) => Self::AllowChatParticipants(groups(r.chats)),
+            R::PrivacyValueDisallowAll => Self::DisallowAll,
+            R::PrivacyValueDisallowContacts => Self::DisallowContacts,
+            R::PrivacyValueDisallowUsers(r) => Self::DisallowUsers(users(r.users)),
+            R::PrivacyValueDisallowChatParticipants(r) => {
+                Self::DisallowChatParticipants(groups(r.chats))
+            }
+        }
+    }
+
+    pub(crate) fn to_raw(&self) -> tl::enums::InputPrivacyRule {
+        use tl::enums::InputPrivacyRule as R;
+
+        let users = |users: &[PackedChat]| {
+            users
+                .iter()
+                .filter_map(PackedChat::try_to_input_user)
+                .collect()
+        };
+        let groups = |chats: &[PackedChat]| {
+            chats
+                .iter()
+                .filter(|chat| chat.try_to_input_user().is_none())
+                .map(|chat| chat.id)
+                .collect()
+        };
+
+        match self {
+            Self::AllowAll => R::InputPrivacyValueAllowAll,
+            Self::AllowContacts => R::InputPrivacyValueAllowContacts,
+            Self::AllowUsers(u) => {
+                tl::types::InputPrivacyValueAllowUsers { users: users(u) }.into()
+            }
+            Self::AllowChatParticipants(c) => {
+                tl::types::InputPrivacyValueAllowChatParticipants { chats: groups(c) }.into()
+            }
+            Self::DisallowAll => R::InputPrivacyValueDisallowAll,
+            Self::DisallowContacts => R::InputPrivacyValueDisallowContacts,
+            Self::DisallowUsers(u) => {
+                tl::types::InputPrivacyValueDisallowUsers { users: users(u) }.into()
+            }
+            Self::DisallowChatParticipants(c) => {
+                tl::types::InputPrivacyValueDisallowChatParticipants { chats: groups(c) }.into()
+            }
+        }
+    }
+}
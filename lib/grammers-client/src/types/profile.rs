@@ -0,0 +1,104 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::types::User;
+use crate::Client;
+use grammers_mtsender::InvocationError;
+use grammers_tl_types as tl;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    marker::PhantomPinned,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+type BuilderRes = Result<User, InvocationError>;
+type ProfileFutGen<F> = fn(ProfileBuilderInner) -> F;
+
+pub(crate) struct ProfileBuilderInner {
+    client: Client,
+    request: tl::functions::account::UpdateProfile,
+}
+
+impl ProfileBuilderInner {
+    // Perform the call.
+    pub(crate) async fn invoke(self) -> BuilderRes {
+        let user = User::from_raw(self.client.invoke(&self.request).await?);
+        *self.client.0.self_user.lock("client.update_profile") = Some(user.clone());
+        Ok(user)
+    }
+}
+
+pin_project! {
+    /// Builder for editing the profile of the logged-in account.
+    ///
+    /// Use [`Client::update_profile`] to retrieve an instance of this type.
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    pub struct ProfileBuilder<F: Future<Output = BuilderRes>> {
+        inner: Option<ProfileBuilderInner>,
+        gen: ProfileFutGen<F>,
+        #[pin]
+        fut: Option<F>,
+        _phantom: PhantomPinned
+    }
+}
+
+impl<F: Future<Output = BuilderRes>> Future for ProfileBuilder<F> {
+    type Output = BuilderRes;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BuilderRes> {
+        let mut s = self.project();
+        if s.fut.is_none() {
+            // unwrap safety: s.inner is None only when s.fut is some
+            // or s.fut is resolved
+            s.fut.set(Some((s.gen)(s.inner.take().unwrap())))
+        }
+
+        s.fut.as_pin_mut().unwrap().poll(cx)
+    }
+}
+
+impl<F: Future<Output = BuilderRes>> ProfileBuilder<F> {
+    pub(crate) fn new(client: Client, gen: ProfileFutGen<F>) -> Self {
+        Self {
+            inner: Some(ProfileBuilderInner {
+                client,
+                request: tl::functions::account::UpdateProfile {
+                    first_name: None,
+                    last_name: None,
+                    about: None,
+                },
+            }),
+            gen,
+            fut: None,
+            _phantom: PhantomPinned,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut ProfileBuilderInner {
+        // unwrap safety: inner is always Some while the builder is being configured
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Change the first name. It cannot be empty.
+    pub fn first_name(mut self, first_name: &str) -> Self {
+        self.inner_mut().request.first_name = Some(first_name.to_string());
+        self
+    }
+
+    /// Change the last name. An empty last name removes it.
+    pub fn last_name(mut self, last_name: &str) -> Self {
+        self.inner_mut().request.last_name = Some(last_name.to_string());
+        self
+    }
+
+    /// Change the bio (the "about" text of the profile). An empty bio removes it.
+    pub fn bio(mut self, bio: &str) -> Self {
+        self.inner_mut().request.about = Some(bio.to_string());
+        self
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::ChatMap;
+use grammers_tl_types as tl;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// A raw update, along with the users and chats that came with it.
+pub type RawUpdate = (tl::enums::Update, Arc<ChatMap>);
+
+/// A stream of the raw updates received by the client, before they're converted into the
+/// high-level [`crate::Update`].
+///
+/// This is useful to handle updates which don't have a high-level counterpart yet. The raw
+/// updates are received in addition to the usual updates, which are still returned by
+/// [`crate::Client::next_update`] (and it must keep being called for the stream to receive
+/// anything).
+///
+/// Raw updates are buffered since the moment the stream is created, and it ends when dropped.
+pub struct RawUpdates {
+    incoming: mpsc::UnboundedReceiver<RawUpdate>,
+}
+
+impl RawUpdates {
+    pub(crate) fn new(incoming: mpsc::UnboundedReceiver<RawUpdate>) -> Self {
+        Self { incoming }
+    }
+
+    /// Wait for the next raw update, along with the users and chats that came with it.
+    ///
+    /// Returns `None` if the client was dropped.
+    pub async fn next(&mut self) -> Option<RawUpdate> {
+        self.incoming.recv().await
+    }
+}
@@ -256,3 +256,271 @@ impl ForceReply {
         self
     }
 }
+
+/// Builder for inline reply markups, adding the buttons one by one.
+///
+/// Buttons are added to the current row, from left to right, until [`InlineKeyboard::row`] is
+/// used to start a new one.
+///
+/// # Examples
+///
+/// ```
+/// # async fn f(client: &mut grammers_client::Client, chat: &grammers_client::types::Chat) -> Result<(), Box<dyn std::error::Error>> {
+/// use grammers_client::{InputMessage, reply_markup::InlineKeyboard};
+///
+/// let markup = InlineKeyboard::new()
+///     .button_callback("Yes", "yes")
+///     .button_callback("No", "no")
+///     .row()
+///     .button_url("Read more", "https://example.com")
+///     .button_switch_inline("Share", "");
+///
+/// client.send_message(chat, InputMessage::text("Do you agree?").reply_markup(&markup)).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct InlineKeyboard {
+    rows: Vec<Vec<button::Inline>>,
+}
+
+/// Builder for keyboard reply markups, adding the buttons one by one.
+///
+/// Buttons are added to the current row, from left to right, until [`ReplyKeyboard::row`] is
+/// used to start a new one.
+///
+/// # Examples
+///
+/// ```
+/// # async fn f(client: &mut grammers_client::Client, chat: &grammers_client::types::Chat) -> Result<(), Box<dyn std::error::Error>> {
+/// use grammers_client::{InputMessage, reply_markup::ReplyKeyboard};
+///
+/// let markup = ReplyKeyboard::new()
+///     .button("Accept")
+///     .row()
+///     .button("Cancel")
+///     .button("Try something else")
+///     .resize()
+///     .one_time();
+///
+/// client.send_message(chat, InputMessage::text("What do you want to do?").reply_markup(&markup)).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ReplyKeyboard {
+    rows: Vec<Vec<button::Keyboard>>,
+    resize: bool,
+    single_use: bool,
+    selective: bool,
+}
+
+fn to_rows<B, F: Fn(&B) -> tl::enums::KeyboardButton>(
+    rows: &[Vec<B>],
+    to_button: F,
+) -> Vec<tl::enums::KeyboardButtonRow> {
+    rows.iter()
+        .filter(|row| !row.is_empty())
+        .map(|row| {
+            tl::types::KeyboardButtonRow {
+                buttons: row.iter().map(&to_button).collect(),
+            }
+            .into()
+        })
+        .collect()
+}
+
+impl InlineKeyboard {
+    /// Create a new, empty inline keyboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a button to the current row.
+    pub fn button(mut self, button: button::Inline) -> Self {
+        match self.rows.last_mut() {
+            Some(row) => row.push(button),
+            None => self.rows.push(vec![button]),
+        }
+        self
+    }
+
+    /// Add a button with a binary payload to the current row. See [`button::inline`].
+    pub fn button_callback<T: Into<String>, B: Into<Vec<u8>>>(self, text: T, bytes: B) -> Self {
+        self.button(button::inline(text, bytes))
+    }
+
+    /// Add a button that opens an URL to the current row. See [`button::url`].
+    pub fn button_url<T: Into<String>, U: Into<String>>(self, text: T, url: U) -> Self {
+        self.button(button::url(text, url))
+    }
+
+    /// Add a button that switches to inline mode in the same chat to the current row. See
+    /// [`button::switch_inline`].
+    pub fn button_switch_inline<T: Into<String>, Q: Into<String>>(self, text: T, query: Q) -> Self {
+        self.button(button::switch_inline(text, query))
+    }
+
+    /// Add a button that switches to inline mode in a different chat to the current row. See
+    /// [`button::switch_inline_elsewhere`].
+    pub fn button_switch_inline_elsewhere<T: Into<String>, Q: Into<String>>(
+        self,
+        text: T,
+        query: Q,
+    ) -> Self {
+        self.button(button::switch_inline_elsewhere(text, query))
+    }
+
+    /// Start a new row, so that the next buttons are added below the previous ones.
+    pub fn row(mut self) -> Self {
+        self.rows.push(Vec::new());
+        self
+    }
+}
+
+impl ReplyMarkup for InlineKeyboard {
+    fn to_reply_markup(&self) -> Markup {
+        Markup(
+            tl::types::ReplyInlineMarkup {
+                rows: to_rows(&self.rows, |button| button.0.clone()),
+            }
+            .into(),
+        )
+    }
+}
+
+impl ReplyKeyboard {
+    /// Create a new, empty keyboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a button to the current row.
+    pub fn button_with(mut self, button: button::Keyboard) -> Self {
+        match self.rows.last_mut() {
+            Some(row) => row.push(button),
+            None => self.rows.push(vec![button]),
+        }
+        self
+    }
+
+    /// Add a button which sends its text when pressed to the current row. See [`button::text`].
+    pub fn button<T: Into<String>>(self, text: T) -> Self {
+        self.button_with(button::text(text))
+    }
+
+    /// Start a new row, so that the next buttons are added below the previous ones.
+    pub fn row(mut self) -> Self {
+        self.rows.push(Vec::new());
+        self
+    }
+
+    /// Requests clients to resize the keyboard vertically for optimal fit. See
+    /// [`Keyboard::fit_size`].
+    pub fn resize(mut self) -> Self {
+        self.resize = true;
+        self
+    }
+
+    /// Requests clients to hide the keyboard as soon as it's been used. See
+    /// [`Keyboard::single_use`].
+    pub fn one_time(mut self) -> Self {
+        self.single_use = true;
+        self
+    }
+
+    /// Show the keyboard to specific users only. See [`Keyboard::selective`].
+    pub fn selective(mut self) -> Self {
+        self.selective = true;
+        self
+    }
+}
+
+impl ReplyMarkup for ReplyKeyboard {
+    fn to_reply_markup(&self) -> Markup {
+        Markup(
+            tl::types::ReplyKeyboardMarkup {
+                resize: self.resize,
+                single_use: self.single_use,
+                selective: self.selective,
+                rows: to_rows(&self.rows, |button| button.0.clone()),
+            }
+            .into(),
+        )
+    }
+}
+
+/// The reply markup attached to a received message.
+#[derive(Clone, Debug)]
+pub enum ReceivedMarkup {
+    /// Inline buttons, shown right under the message.
+    Inline(Vec<Vec<ReceivedButton>>),
+    /// A custom keyboard, replacing the user's own virtual keyboard.
+    Keyboard(Vec<Vec<ReceivedButton>>),
+    /// Hide a previously-sent keyboard.
+    Hide,
+    /// "Force" the user to send a reply.
+    ForceReply,
+}
+
+/// A button in the reply markup of a received message.
+#[derive(Clone, Debug)]
+pub struct ReceivedButton(tl::enums::KeyboardButton);
+
+impl ReceivedMarkup {
+    pub(crate) fn from_raw(markup: tl::enums::ReplyMarkup) -> Self {
+        fn rows(rows: Vec<tl::enums::KeyboardButtonRow>) -> Vec<Vec<ReceivedButton>> {
+            rows.into_iter()
+                .map(|tl::enums::KeyboardButtonRow::Row(row)| {
+                    row.buttons.into_iter().map(ReceivedButton).collect()
+                })
+                .collect()
+        }
+
+        match markup {
+            tl::enums::ReplyMarkup::ReplyInlineMarkup(markup) => Self::Inline(rows(markup.rows)),
+            tl::enums::ReplyMarkup::ReplyKeyboardMarkup(markup) => {
+                Self::Keyboard(rows(markup.rows))
+            }
+            tl::enums::ReplyMarkup::ReplyKeyboardHide(_) => Self::Hide,
+            tl::enums::ReplyMarkup::ReplyKeyboardForceReply(_) => Self::ForceReply,
+        }
+    }
+}
+
+impl ReceivedButton {
+    /// The text shown in the button.
+    pub fn text(&self) -> String {
+        self.0.text()
+    }
+
+    /// The binary payload sent when the button is pressed, if it's a callback button.
+    pub fn data(&self) -> Option<&[u8]> {
+        match &self.0 {
+            tl::enums::KeyboardButton::Callback(button) => Some(&button.data),
+            _ => None,
+        }
+    }
+
+    /// The URL opened when the button is pressed, if it's an URL button.
+    pub fn url(&self) -> Option<&str> {
+        match &self.0 {
+            tl::enums::KeyboardButton::Url(button) => Some(&button.url),
+            tl::enums::KeyboardButton::UrlAuth(button) => Some(&button.url),
+            _ => None,
+        }
+    }
+
+    /// The inline query inserted when the button is pressed, if it's a switch inline button.
+    pub fn switch_inline_query(&self) -> Option<&str> {
+        match &self.0 {
+            tl::enums::KeyboardButton::SwitchInline(button) => Some(&button.query),
+            _ => None,
+        }
+    }
+
+    /// The raw button, for the kinds of buttons without a dedicated accessor.
+    pub fn raw(&self) -> &tl::enums::KeyboardButton {
+        &self.0
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::Client;
+use grammers_mtsender::InvocationError;
+use grammers_tl_types as tl;
+use tokio::runtime::Handle;
+
+/// What data will be exported during a takeout session.
+///
+/// Telegram may refuse requests fetching data which was not declared when starting the session.
+#[derive(Clone, Debug, Default)]
+pub struct TakeoutOptions {
+    /// Export the contacts.
+    pub contacts: bool,
+    /// Export the messages in private conversations.
+    pub message_users: bool,
+    /// Export the messages in small group chats.
+    pub message_chats: bool,
+    /// Export the messages in megagroups.
+    pub message_megagroups: bool,
+    /// Export the messages in broadcast channels.
+    pub message_channels: bool,
+    /// Export the files, optionally limiting their size (in bytes).
+    pub files: bool,
+    pub file_max_size: Option<i32>,
+}
+
+/// A takeout session, used to export the data of the account at higher rate limits.
+///
+/// Requests are sent through the session with [`Takeout::invoke`]. The session should be
+/// finished with [`Takeout::finish`] once the export is done. If it's dropped without doing so,
+/// the session is finished and marked as unsuccessful in the background.
+///
+/// Use [`Client::takeout`] to retrieve an instance of this type.
+pub struct Takeout {
+    client: Client,
+    id: i64,
+    finished: bool,
+}
+
+impl TakeoutOptions {
+    pub(crate) fn to_raw(&self) -> tl::functions::account::InitTakeoutSession {
+        tl::functions::account::InitTakeoutSession {
+            contacts: self.contacts,
+            message_users: self.message_users,
+            message_chats: self.message_chats,
+            message_megagroups: self.message_megagroups,
+            message_channels: self.message_channels,
+            files: self.files,
+            file_max_size: if self.files { self.file_max_size } else { None },
+        }
+    }
+}
+
+impl Takeout {
+    pub(crate) fn new(client: Client, id: i64) -> Self {
+        Self {
+            client,
+            id,
+            finished: false,
+        }
+    }
+
+    /// The identifier of this takeout session.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Invoke a raw API call through this takeout session.
+    pub async fn invoke<R: tl::RemoteCall + Clone>(
+        &self,
+        request: &R,
+    ) -> Result<R::Return, InvocationError> {
+        self.client
+            .invoke(&tl::functions::InvokeWithTakeout {
+                takeout_id: self.id,
+                query: request.clone(),
+            })
+            .await
+    }
+
+    /// Finish the takeout session, indicating whether the export was successful.
+    pub async fn finish(mut self, success: bool) -> Result<(), InvocationError> {
+        self.finished = true;
+        self.invoke(&tl::functions::account::FinishTakeoutSession { success })
+            .await
+            .map(drop)
+    }
+}
+
+impl Drop for Takeout {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        // There's no way to finish the session without a runtime to drive the request.
+        if let Ok(handle) = Handle::try_current() {
+            let client = self.client.clone();
+            let takeout_id = self.id;
+            handle.spawn(async move {
+                let _ = client
+                    .invoke(&tl::functions::InvokeWithTakeout {
+                        takeout_id,
+                        query: tl::functions::account::FinishTakeoutSession { success: false },
+                    })
+                    .await;
+            });
+        }
+    }
+}
@@ -21,6 +21,10 @@ impl TermsOfService {
         Self(tos)
     }
 
+    pub(crate) fn id(&self) -> tl::enums::DataJson {
+        self.0.id.clone()
+    }
+
     /// Whether the terms should be shown as a popup dialog to the user.
     pub fn show_popup(&self) -> bool {
         self.0.popup
@@ -0,0 +1,143 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use grammers_tl_types as tl;
+
+/// A cloud theme, which can be installed to change the appearance of official applications.
+///
+/// Themes are platform-specific, so the same theme may look different (or not be available at
+/// all) depending on the format used when fetching them.
+#[derive(Clone, Debug)]
+pub struct Theme(tl::types::Theme);
+
+/// A chat background, which official applications display behind the messages.
+#[derive(Clone, Debug)]
+pub struct Wallpaper(tl::enums::WallPaper);
+
+impl Theme {
+    pub(crate) fn from_raw(tl::enums::Theme::Theme(theme): tl::enums::Theme) -> Self {
+        Self(theme)
+    }
+
+    pub(crate) fn to_input_theme(&self) -> tl::enums::InputTheme {
+        tl::types::InputTheme {
+            id: self.0.id,
+            access_hash: self.0.access_hash,
+        }
+        .into()
+    }
+
+    /// The unique identifier for this theme.
+    pub fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    /// The unique short name of this theme, used to share it via `t.me/addtheme/{slug}` links.
+    pub fn slug(&self) -> &str {
+        self.0.slug.as_ref()
+    }
+
+    /// The human-readable title of this theme.
+    pub fn title(&self) -> &str {
+        self.0.title.as_ref()
+    }
+
+    /// Whether the currently logged-in user created this theme.
+    pub fn is_creator(&self) -> bool {
+        self.0.creator
+    }
+
+    /// Whether this is one of the default themes.
+    pub fn is_default(&self) -> bool {
+        self.0.default
+    }
+
+    /// How many users have installed this theme.
+    pub fn installs_count(&self) -> i32 {
+        self.0.installs_count
+    }
+}
+
+impl Wallpaper {
+    pub(crate) fn from_raw(wallpaper: tl::enums::WallPaper) -> Self {
+        Self(wallpaper)
+    }
+
+    pub(crate) fn to_input_wallpaper(&self) -> tl::enums::InputWallPaper {
+        match &self.0 {
+            tl::enums::WallPaper::Paper(w) => tl::types::InputWallPaper {
+                id: w.id,
+                access_hash: w.access_hash,
+            }
+            .into(),
+            tl::enums::WallPaper::NoFile(_) => tl::enums::InputWallPaper::NoFile,
+        }
+    }
+
+    pub(crate) fn settings(&self) -> tl::enums::WallPaperSettings {
+        let settings = match &self.0 {
+            tl::enums::WallPaper::Paper(w) => w.settings.clone(),
+            tl::enums::WallPaper::NoFile(w) => w.settings.clone(),
+        };
+
+        settings.unwrap_or_else(|| {
+            tl::types::WallPaperSettings {
+                blur: false,
+                motion: false,
+                background_color: None,
+                second_background_color: None,
+                intensity: None,
+                rotation: None,
+            }
+            .into()
+        })
+    }
+
+    /// The unique identifier for this wallpaper.
+    ///
+    /// Wallpapers without a file (that is, a plain background color) have no identifier.
+    pub fn id(&self) -> Option<i64> {
+        match &self.0 {
+            tl::enums::WallPaper::Paper(w) => Some(w.id),
+            tl::enums::WallPaper::NoFile(_) => None,
+        }
+    }
+
+    /// The unique short name of this wallpaper, used to share it via `t.me/bg/{slug}` links.
+    pub fn slug(&self) -> Option<&str> {
+        match &self.0 {
+            tl::enums::WallPaper::Paper(w) => Some(w.slug.as_ref()),
+            tl::enums::WallPaper::NoFile(_) => None,
+        }
+    }
+
+    /// Whether the currently logged-in user uploaded this wallpaper.
+    pub fn is_creator(&self) -> bool {
+        match &self.0 {
+            tl::enums::WallPaper::Paper(w) => w.creator,
+            tl::enums::WallPaper::NoFile(_) => false,
+        }
+    }
+
+    /// Whether this is one of the default wallpapers.
+    pub fn is_default(&self) -> bool {
+        self.0.default()
+    }
+
+    /// Whether this wallpaper is a pattern to be drawn over a background color.
+    pub fn is_pattern(&self) -> bool {
+        match &self.0 {
+            tl::enums::WallPaper::Paper(w) => w.pattern,
+            tl::enums::WallPaper::NoFile(_) => false,
+        }
+    }
+
+    /// Whether this wallpaper is meant to be used with dark themes.
+    pub fn is_dark(&self) -> bool {
+        self.0.dark()
+    }
+}
@@ -5,22 +5,48 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{CallbackQuery, ChatMap, InlineQuery, Message};
+use super::{
+    CallbackQuery, ChatMap, ChatMemberUpdate, InlineQuery, InlineSend, Message, MessageDeletion,
+    PollUpdate, PollVote, PreCheckoutQuery, ShippingQuery, UserStatus, UserTyping,
+};
 use crate::Client;
 use grammers_tl_types as tl;
 use std::sync::Arc;
 
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Update {
     /// Occurs whenever a new text message or a message with media is produced.
     NewMessage(Message),
+    /// Occurs when a message is edited.
+    MessageEdited(Message),
+    /// Occurs when one or more messages are deleted.
+    MessageDeleted(MessageDeletion),
     /// Occurs when Telegram calls back into your bot because an inline callback button was
     /// pressed.
     CallbackQuery(CallbackQuery),
     /// Occurs whenever you sign in as a bot and a user sends an inline query such as
     /// `@bot query`.
     InlineQuery(InlineQuery),
+    /// Occurs whenever you sign in as a bot and a user chooses one of the results of an inline
+    /// query.
+    InlineSend(InlineSend),
+    /// Occurs when a user joins, leaves or is banned from a chat, or their rights change.
+    ChatMemberUpdated(ChatMemberUpdate),
+    /// Occurs when a user starts or stops typing (or performing some other action) in a chat.
+    UserTyping(UserTyping),
+    /// Occurs when a user goes online or offline.
+    UserStatus(UserStatus),
+    /// Occurs when the results of a poll change or the poll is closed.
+    PollUpdated(PollUpdate),
+    /// Occurs when a user votes in a non-anonymous poll, or retracts their vote.
+    PollVote(PollVote),
+    /// Occurs when a user confirms the payment of an invoice sent by the bot, which must be
+    /// answered before the payment can complete.
+    PreCheckoutQuery(PreCheckoutQuery),
+    /// Occurs when a user paying a flexible invoice sent by the bot enters their shipping
+    /// address.
+    ShippingQuery(ShippingQuery),
 }
 
 impl Update {
@@ -43,6 +69,81 @@ impl Update {
             tl::enums::Update::BotInlineQuery(query) => {
                 Some(Self::InlineQuery(InlineQuery::new(client, query, chats)))
             }
+            tl::enums::Update::BotInlineSend(update) => {
+                Some(Self::InlineSend(InlineSend::new(update, chats)))
+            }
+            tl::enums::Update::EditMessage(tl::types::UpdateEditMessage { message, .. }) => {
+                Message::new(client, message, chats).map(Self::MessageEdited)
+            }
+            tl::enums::Update::EditChannelMessage(tl::types::UpdateEditChannelMessage {
+                message,
+                ..
+            }) => Message::new(client, message, chats).map(Self::MessageEdited),
+            tl::enums::Update::DeleteMessages(update) => {
+                Some(Self::MessageDeleted(MessageDeletion::new(update)))
+            }
+            tl::enums::Update::DeleteChannelMessages(update) => {
+                Some(Self::MessageDeleted(MessageDeletion::new_channel(update)))
+            }
+            tl::enums::Update::ChatParticipantAdd(update) => Some(Self::ChatMemberUpdated(
+                ChatMemberUpdate::from_chat_add(client, update, chats),
+            )),
+            tl::enums::Update::ChatParticipantDelete(update) => Some(Self::ChatMemberUpdated(
+                ChatMemberUpdate::from_chat_delete(client, update, chats),
+            )),
+            tl::enums::Update::ChannelParticipant(update) => Some(Self::ChatMemberUpdated(
+                ChatMemberUpdate::from_channel(client, update, chats),
+            )),
+            tl::enums::Update::UserTyping(update) => {
+                let user = tl::types::PeerUser {
+                    user_id: update.user_id,
+                }
+                .into();
+                Some(Self::UserTyping(UserTyping::new(
+                    client,
+                    &user,
+                    &user,
+                    update.action,
+                    chats,
+                )))
+            }
+            tl::enums::Update::ChatUserTyping(update) => Some(Self::UserTyping(UserTyping::new(
+                client,
+                &tl::types::PeerChat {
+                    chat_id: update.chat_id,
+                }
+                .into(),
+                &update.from_id,
+                update.action,
+                chats,
+            ))),
+            tl::enums::Update::ChannelUserTyping(update) => {
+                Some(Self::UserTyping(UserTyping::new(
+                    client,
+                    &tl::types::PeerChannel {
+                        channel_id: update.channel_id,
+                    }
+                    .into(),
+                    &update.from_id,
+                    update.action,
+                    chats,
+                )))
+            }
+            tl::enums::Update::UserStatus(update) => {
+                Some(Self::UserStatus(UserStatus::new(client, update, chats)))
+            }
+            tl::enums::Update::MessagePoll(update) => {
+                Some(Self::PollUpdated(PollUpdate::new(update)))
+            }
+            tl::enums::Update::MessagePollVote(update) => {
+                Some(Self::PollVote(PollVote::new(client, update, chats)))
+            }
+            tl::enums::Update::BotPrecheckoutQuery(query) => Some(Self::PreCheckoutQuery(
+                PreCheckoutQuery::new(client, query, chats),
+            )),
+            tl::enums::Update::BotShippingQuery(query) => Some(Self::ShippingQuery(
+                ShippingQuery::new(client, query, chats),
+            )),
             _ => None,
         }
     }
@@ -0,0 +1,94 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap};
+use crate::{utils, Client};
+use grammers_tl_types as tl;
+
+/// The last time a user was seen online, as precise as their privacy settings allow.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Presence {
+    /// The user is currently online, and will be considered offline after the given date unless
+    /// their status is updated before.
+    Online { expires: utils::Date },
+    /// The user is offline, and was last seen online at the given date.
+    Offline { last_seen: utils::Date },
+    /// The user hides their exact last seen date, but was online recently.
+    Recently,
+    /// The user hides their exact last seen date, but was online within the last week.
+    LastWeek,
+    /// The user hides their exact last seen date, but was online within the last month.
+    LastMonth,
+    /// The user hides their last seen date completely, or has never been online.
+    Hidden,
+}
+
+impl Presence {
+    pub(crate) fn from_raw(status: Option<&tl::enums::UserStatus>) -> Self {
+        use tl::enums::UserStatus as S;
+
+        match status {
+            Some(S::Online(s)) => Self::Online {
+                expires: utils::date(s.expires),
+            },
+            Some(S::Offline(s)) => Self::Offline {
+                last_seen: utils::date(s.was_online),
+            },
+            Some(S::Recently) => Self::Recently,
+            Some(S::LastWeek) => Self::LastWeek,
+            Some(S::LastMonth) => Self::LastMonth,
+            Some(S::Empty) | None => Self::Hidden,
+        }
+    }
+
+    /// Whether the user is currently online.
+    pub fn is_online(&self) -> bool {
+        matches!(self, Self::Online { .. })
+    }
+}
+
+/// Represents a user status update, which occurs when a user goes online or offline.
+#[derive(Clone, Debug)]
+pub struct UserStatus {
+    user: Chat,
+    status: tl::enums::UserStatus,
+}
+
+impl UserStatus {
+    pub(crate) fn new(
+        client: &Client,
+        update: tl::types::UpdateUserStatus,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            user: utils::always_find_entity(
+                &tl::types::PeerUser {
+                    user_id: update.user_id,
+                }
+                .into(),
+                chats,
+                client,
+            ),
+            status: update.status,
+        }
+    }
+
+    /// The user whose status changed.
+    pub fn user(&self) -> &Chat {
+        &self.user
+    }
+
+    /// The new presence of the user.
+    pub fn presence(&self) -> Presence {
+        Presence::from_raw(Some(&self.status))
+    }
+
+    /// The new raw status of the user.
+    pub fn status(&self) -> &tl::enums::UserStatus {
+        &self.status
+    }
+}
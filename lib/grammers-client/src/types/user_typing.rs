@@ -0,0 +1,104 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Chat, ChatMap};
+use crate::{utils, Client};
+use grammers_tl_types as tl;
+
+/// The action a user is performing in a chat, such as typing or uploading a photo.
+///
+/// Actions which upload something may carry the progress of the upload, as a percentage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChatAction {
+    Typing,
+    Cancel,
+    RecordVideo,
+    UploadVideo(i32),
+    RecordAudio,
+    UploadAudio(i32),
+    UploadPhoto(i32),
+    UploadDocument(i32),
+    ChooseLocation,
+    ChooseContact,
+    PlayGame,
+    RecordRound,
+    UploadRound(i32),
+    SpeakInGroupCall,
+    ImportHistory(i32),
+}
+
+/// Represents a user typing update, which occurs when a user starts or stops performing some
+/// [`ChatAction`] in a chat.
+///
+/// Actions should be considered finished after a few seconds if no new update arrives.
+#[derive(Clone, Debug)]
+pub struct UserTyping {
+    chat: Chat,
+    user: Chat,
+    action: ChatAction,
+}
+
+impl ChatAction {
+    pub(crate) fn from_raw(action: tl::enums::SendMessageAction) -> Self {
+        use tl::enums::SendMessageAction as A;
+
+        match action {
+            A::SendMessageTypingAction => Self::Typing,
+            A::SendMessageCancelAction => Self::Cancel,
+            A::SendMessageRecordVideoAction => Self::RecordVideo,
+            A::SendMessageUploadVideoAction(a) => Self::UploadVideo(a.progress),
+            A::SendMessageRecordAudioAction => Self::RecordAudio,
+            A::SendMessageUploadAudioAction(a) => Self::UploadAudio(a.progress),
+            A::SendMessageUploadPhotoAction(a) => Self::UploadPhoto(a.progress),
+            A::SendMessageUploadDocumentAction(a) => Self::UploadDocument(a.progress),
+            A::SendMessageGeoLocationAction => Self::ChooseLocation,
+            A::SendMessageChooseContactAction => Self::ChooseContact,
+            A::SendMessageGamePlayAction => Self::PlayGame,
+            A::SendMessageRecordRoundAction => Self::RecordRound,
+            A::SendMessageUploadRoundAction(a) => Self::UploadRound(a.progress),
+            A::SpeakingInGroupCallAction => Self::SpeakInGroupCall,
+            A::SendMessageHistoryImportAction(a) => Self::ImportHistory(a.progress),
+        }
+    }
+}
+
+impl UserTyping {
+    pub(crate) fn new(
+        client: &Client,
+        chat: &tl::enums::Peer,
+        user: &tl::enums::Peer,
+        action: tl::enums::SendMessageAction,
+        chats: &ChatMap,
+    ) -> Self {
+        Self {
+            chat: utils::always_find_entity(chat, chats, client),
+            user: utils::always_find_entity(user, chats, client),
+            action: ChatAction::from_raw(action),
+        }
+    }
+
+    /// The chat where the action is being performed.
+    ///
+    /// For private conversations, this is the same as [`UserTyping::user`].
+    pub fn chat(&self) -> &Chat {
+        &self.chat
+    }
+
+    /// The user performing the action.
+    ///
+    /// In channels and groups, this may also be the chat itself if an anonymous administrator
+    /// is performing the action.
+    pub fn user(&self) -> &Chat {
+        &self.user
+    }
+
+    /// The action being performed.
+    pub fn action(&self) -> ChatAction {
+        self.action
+    }
+}
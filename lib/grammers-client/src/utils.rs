@@ -8,11 +8,14 @@
 
 use crate::types;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use grammers_crypto::two_factor_auth::{calculate_2fa, check_p_and_g};
 use grammers_session::{PackedChat, PackedType};
 use grammers_tl_types as tl;
 use log::trace;
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::SystemTime;
+use tokio::sync::oneshot;
 
 // This atomic isn't for anything critical, just to generate unique IDs without locks.
 // The worst that can happen if the load and store orderings are wrong is that the IDs
@@ -55,6 +58,40 @@ pub(crate) fn extract_password_parameters(
     (salt1, salt2, g, p)
 }
 
+/// Prove that the current password is known without sending it, or send an empty proof if the
+/// account has no password.
+pub(crate) fn password_srp(
+    password_info: &tl::types::account::Password,
+    password: Option<&[u8]>,
+) -> tl::enums::InputCheckPasswordSrp {
+    let (current_algo, password) = match (&password_info.current_algo, password) {
+        (Some(current_algo), Some(password)) => (current_algo, password),
+        _ => return tl::enums::InputCheckPasswordSrp::InputCheckPasswordEmpty,
+    };
+
+    let (salt1, salt2, g, p) = extract_password_parameters(current_algo);
+    if !check_p_and_g(g, p) {
+        panic!("Failed to get correct password information from Telegram")
+    }
+
+    let (m1, g_a) = calculate_2fa(
+        salt1,
+        salt2,
+        g,
+        p,
+        password_info.srp_b.clone().unwrap(),
+        password_info.secure_random.clone(),
+        password,
+    );
+
+    tl::types::InputCheckPasswordSrp {
+        srp_id: password_info.srp_id.unwrap(),
+        a: g_a,
+        m1,
+    }
+    .into()
+}
+
 /// Get a `Chat`, no matter what.
 ///
 /// If necessary, `access_hash` of `0` will be returned, but *something* will be returned.
@@ -204,3 +241,199 @@ impl<'a, T: ?Sized> Drop for AsyncMutexGuard<'a, T> {
         trace!("async-unlocking {} for {}", self.name, self.reason);
     }
 }
+
+/// A token bucket, used to throttle requests according to a [`crate::RateLimit`].
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    last_refill: tokio::time::Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: &crate::RateLimit) -> Self {
+        let capacity = limit.requests.max(1) as f64;
+        Self {
+            capacity,
+            per_second: capacity / limit.per.as_secs_f64().max(f64::EPSILON),
+            tokens: capacity,
+            last_refill: tokio::time::Instant::now(),
+        }
+    }
+
+    /// Take a token, returning how long to wait before it can be used.
+    ///
+    /// The tokens can go into debt, so that concurrent callers are queued one after another
+    /// instead of all waking up at the same time.
+    pub fn take(&mut self, now: tokio::time::Instant) -> std::time::Duration {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            std::time::Duration::ZERO
+        } else {
+            std::time::Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+/// How many requests of the interactive class can be let through in a row while requests of the
+/// bulk class are waiting.
+const INTERACTIVE_STREAK: u32 = 4;
+
+/// Limits how many requests can be pending at once, queueing the rest in order of arrival,
+/// separately for each [`crate::RequestPriority`].
+///
+/// Interactive requests go first, but bulk requests are still let through every few, so that
+/// neither class can starve the other.
+#[derive(Clone)]
+pub(crate) struct RequestLimiter(std::sync::Arc<Mutex<LimiterState>>);
+
+struct LimiterState {
+    available: usize,
+    interactive: VecDeque<oneshot::Sender<RequestPermit>>,
+    bulk: VecDeque<oneshot::Sender<RequestPermit>>,
+    streak: u32,
+}
+
+/// A pending request slot, released when dropped.
+pub(crate) struct RequestPermit(Option<RequestLimiter>);
+
+impl RequestLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self(std::sync::Arc::new(Mutex::new(
+            "client.request_limiter",
+            LimiterState {
+                available: limit.max(1),
+                interactive: VecDeque::new(),
+                bulk: VecDeque::new(),
+                streak: 0,
+            },
+        )))
+    }
+
+    /// Wait until the request can be sent.
+    pub async fn acquire(&self, priority: crate::RequestPriority) -> RequestPermit {
+        let rx = {
+            let mut state = self.0.lock("request_limiter.acquire");
+            if state.available > 0 && state.interactive.is_empty() && state.bulk.is_empty() {
+                state.available -= 1;
+                return RequestPermit(Some(self.clone()));
+            }
+            let (tx, rx) = oneshot::channel();
+            match priority {
+                crate::RequestPriority::Interactive => state.interactive.push_back(tx),
+                crate::RequestPriority::Bulk => state.bulk.push_back(tx),
+            }
+            rx
+        };
+        // The permit can only be dropped along with the sender, which lives in the limiter.
+        rx.await.expect("request limiter dropped while waiting")
+    }
+
+    /// Hand the slot over to the next waiting request, or make it available again.
+    fn release(&self) {
+        loop {
+            let tx = {
+                let mut state = self.0.lock("request_limiter.release");
+                let state = &mut *state;
+                let bulk_turn = state.streak >= INTERACTIVE_STREAK || state.interactive.is_empty();
+                let tx = if bulk_turn && !state.bulk.is_empty() {
+                    state.streak = 0;
+                    state.bulk.pop_front()
+                } else {
+                    state.streak += 1;
+                    state.interactive.pop_front()
+                };
+                match tx {
+                    Some(tx) => tx,
+                    None => {
+                        state.available += 1;
+                        return;
+                    }
+                }
+            };
+
+            match tx.send(RequestPermit(Some(self.clone()))) {
+                Ok(()) => return,
+                // The request was cancelled while waiting, so the slot goes to the next one.
+                // Releasing it here instead of on drop avoids recursing into this method.
+                Err(mut permit) => drop(permit.0.take()),
+            }
+        }
+    }
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.0.take() {
+            limiter.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn token_bucket_throttles() {
+        let mut bucket = TokenBucket::new(&crate::RateLimit {
+            requests: 2,
+            per: Duration::from_secs(1),
+        });
+        let start = bucket.last_refill;
+
+        assert_eq!(bucket.take(start), Duration::ZERO);
+        assert_eq!(bucket.take(start), Duration::ZERO);
+        assert_eq!(bucket.take(start), Duration::from_millis(500));
+        assert_eq!(bucket.take(start), Duration::from_secs(1));
+        // Enough time for the debt to be paid and a new token to be available.
+        assert_eq!(
+            bucket.take(start + Duration::from_millis(1500)),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn request_limiter_is_fair() {
+        use crate::RequestPriority::{Bulk, Interactive};
+        use std::sync::{Arc, Mutex};
+
+        let limiter = RequestLimiter::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let permit = limiter.acquire(Interactive).await;
+
+        let mut tasks = Vec::new();
+        let waiters = vec![
+            ("b1", Bulk),
+            ("i1", Interactive),
+            ("i2", Interactive),
+            ("i3", Interactive),
+            ("i4", Interactive),
+            ("i5", Interactive),
+        ];
+        for (name, priority) in waiters {
+            let (limiter, order) = (limiter.clone(), Arc::clone(&order));
+            tasks.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::task::yield_now().await;
+        }
+
+        drop(permit);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["i1", "i2", "i3", "i4", "b1", "i5"]
+        );
+    }
+}
@@ -5,8 +5,10 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use crate::constant_time_eq;
 use sha1::Sha1;
 use std::fmt;
+use zeroize::Zeroize;
 
 #[derive(Clone)]
 pub struct AuthKey {
@@ -17,7 +19,14 @@ pub struct AuthKey {
 
 impl PartialEq for AuthKey {
     fn eq(&self, other: &Self) -> bool {
-        self.key_id == other.key_id
+        constant_time_eq(&self.key_id, &other.key_id)
+    }
+}
+
+impl Drop for AuthKey {
+    fn drop(&mut self) {
+        self.data.zeroize();
+        self.aux_hash.zeroize();
     }
 }
 
@@ -56,6 +65,11 @@ impl AuthKey {
         self.data
     }
 
+    /// The identifier of this authorization key, as sent in the messages it encrypts.
+    pub fn key_id(&self) -> [u8; 8] {
+        self.key_id
+    }
+
     /// Calculates the new nonce hash based on the current attributes.
     pub fn calc_new_nonce_hash(&self, new_nonce: &[u8; 32], number: u8) -> [u8; 16] {
         let data = {
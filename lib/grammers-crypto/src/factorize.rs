@@ -6,7 +6,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-fn gcd(mut a: u128, mut b: u128) -> u128 {
+fn gcd(mut a: u64, mut b: u64) -> u64 {
     while b != 0 {
         let (na, nb) = (b, a % b);
         a = na;
@@ -15,84 +15,133 @@ fn gcd(mut a: u128, mut b: u128) -> u128 {
     a
 }
 
-fn modpow(mut n: u128, mut e: u128, m: u128) -> u128 {
-    if m == 1 {
-        return 0;
-    }
+/// Arithmetic modulo an odd `n` in [Montgomery form], which replaces the slow 128-bit divisions
+/// needed by every modular multiplication with a couple of multiplications and shifts.
+///
+/// Numbers are never converted in and out of Montgomery form, because the factorization only
+/// needs a pseudo-random sequence and the `gcd` of its differences with `n`, which is the same
+/// in both forms.
+///
+/// [Montgomery form]: https://en.wikipedia.org/wiki/Montgomery_modular_multiplication
+struct Montgomery {
+    n: u64,
+    /// The inverse of `n` modulo 2^64.
+    n_inv: u64,
+}
 
-    let mut result = 1;
-    n %= m;
-    while e > 0 {
-        if e % 2 == 1 {
-            result = (result * n) % m;
+impl Montgomery {
+    fn new(n: u64) -> Self {
+        debug_assert!(n % 2 == 1);
+        // Newton's method doubles the correct bits on each iteration, and `n` is already its own
+        // inverse modulo 2^3, so five iterations are enough for 64 bits.
+        let mut n_inv = n;
+        for _ in 0..5 {
+            n_inv = n_inv.wrapping_mul(2u64.wrapping_sub(n.wrapping_mul(n_inv)));
         }
-        e >>= 1;
-        n = (n * n) % m;
+        Self { n, n_inv }
     }
-    result
-}
 
-/// Factorize the given number into its two prime factors.
-///
-/// The algorithm here is a faster variant of [Pollard's rho algorithm],
-/// published by [Richard Brent], based on
-/// https://comeoncodeon.wordpress.com/2010/09/18/pollard-rho-brent-integer-factorization/.
-///
-/// Pollard's rho algorithm: https://en.wikipedia.org/wiki/Pollard%27s_rho_algorithm
-/// Richard Brent: https://maths-people.anu.edu.au/~brent/pd/rpb051i.pdf
-#[allow(clippy::many_single_char_names)]
-pub fn factorize(pq: u64) -> (u64, u64) {
-    if pq % 2 == 0 {
-        return (2, pq);
+    /// `a * b / 2^64 (mod n)`, for `a` and `b` less than `n`.
+    fn mul(&self, a: u64, b: u64) -> u64 {
+        let t = a as u128 * b as u128;
+        let m = (t as u64).wrapping_mul(self.n_inv);
+        let mn = m as u128 * self.n as u128;
+        let (t_hi, mn_hi) = ((t >> 64) as u64, (mn >> 64) as u64);
+        if t_hi >= mn_hi {
+            t_hi - mn_hi
+        } else {
+            t_hi.wrapping_sub(mn_hi).wrapping_add(self.n)
+        }
     }
 
-    let pq = pq as u128;
-    fn abs_sub(a: u128, b: u128) -> u128 {
-        a.max(b) - a.min(b)
+    /// `a + b (mod n)`, for `a` and `b` less than `n`.
+    fn add(&self, a: u64, b: u64) -> u64 {
+        let (sum, overflow) = a.overflowing_add(b);
+        if overflow || sum >= self.n {
+            sum.wrapping_sub(self.n)
+        } else {
+            sum
+        }
     }
+}
+
+/// Find a non-trivial factor of the odd `n` using the pseudo-random sequence `y² + c`, or `n`
+/// itself if this sequence fails to find one.
+fn brent(n: u64, c: u64) -> u64 {
+    // How many differences are multiplied together before taking their `gcd` with `n`.
+    const BATCH: u64 = 128;
 
-    // Random values in the range of 1..pq, chosen by fair dice roll.
-    let mut y = pq / 4;
-    let c = 2 * pq / 4;
-    let m = 3 * pq / 4;
-    let mut g = 1u128;
-    let mut r = 1u128;
-    let mut q = 1u128;
-    let mut x = 0u128;
-    let mut ys = 0u128;
+    let mont = Montgomery::new(n);
+    let f = |y| mont.add(mont.mul(y, y), c);
+    let abs_sub = |a: u64, b: u64| a.max(b) - a.min(b);
+
+    let mut y = n / 4;
+    let mut x = y;
+    let mut ys = y;
+    let mut g = 1;
+    let mut q = 1;
+    let mut r = 1;
 
     while g == 1 {
         x = y;
         for _ in 0..r {
-            y = (modpow(y, 2, pq) + c) % pq;
+            y = f(y);
         }
 
         let mut k = 0;
         while k < r && g == 1 {
             ys = y;
-            for _ in 0..m.min(r - k) {
-                y = (modpow(y, 2, pq) + c) % pq;
-                q = (q * abs_sub(x, y)) % pq;
+            for _ in 0..BATCH.min(r - k) {
+                y = f(y);
+                q = mont.mul(q, abs_sub(x, y));
             }
 
-            g = gcd(q, pq);
-            k += m;
+            g = gcd(q, n);
+            k += BATCH;
         }
 
         r *= 2;
     }
 
-    if g == pq {
+    // The factor was lost by multiplying too many differences together, so go back to the
+    // start of the last batch and check them one by one.
+    if g == n {
         loop {
-            ys = (modpow(ys, 2, pq) + c) % pq;
-            g = gcd(abs_sub(x, ys), pq);
+            ys = f(ys);
+            g = gcd(abs_sub(x, ys), n);
             if g > 1 {
                 break;
             }
         }
     }
 
-    let (p, q) = (g as u64, (pq / g) as u64);
+    g
+}
+
+/// Factorize the given number into its two prime factors.
+///
+/// The algorithm here is a faster variant of [Pollard's rho algorithm],
+/// published by [Richard Brent], based on
+/// https://comeoncodeon.wordpress.com/2010/09/18/pollard-rho-brent-integer-factorization/.
+///
+/// Pollard's rho algorithm: https://en.wikipedia.org/wiki/Pollard%27s_rho_algorithm
+/// Richard Brent: https://maths-people.anu.edu.au/~brent/pd/rpb051i.pdf
+pub fn factorize(pq: u64) -> (u64, u64) {
+    if pq % 2 == 0 {
+        return (2, pq / 2);
+    }
+
+    // A different sequence is tried in the rare case where one fails to find a factor.
+    let mut c = 1;
+    let p = loop {
+        let g = brent(pq, c % pq);
+        if g != pq {
+            break g;
+        }
+        c += 1;
+    };
+
+    let q = pq / p;
     (p.min(q), p.max(q))
 }
 
@@ -111,4 +160,16 @@ mod tests {
         let pq = factorize(2363612107535801713);
         assert_eq!(pq, (1518968219, 1556064227));
     }
+
+    #[test]
+    fn test_factorization_small() {
+        assert_eq!(factorize(15), (3, 5));
+        assert_eq!(factorize(22), (2, 11));
+    }
+
+    #[test]
+    fn test_factorization_large_primes() {
+        let pq = factorize(4294967291 * 4294967279);
+        assert_eq!(pq, (4294967279, 4294967291));
+    }
 }
@@ -9,6 +9,7 @@ pub mod aes;
 pub mod auth_key;
 pub mod factorize;
 pub mod rsa;
+pub mod srp;
 pub mod two_factor_auth;
 
 pub use auth_key::AuthKey;
@@ -16,6 +17,7 @@ use getrandom::getrandom;
 use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use std::fmt;
+use subtle::ConstantTimeEq;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
@@ -110,7 +112,11 @@ fn determine_padding_v2_length(len: usize) -> usize {
 }
 
 // Inner body of `encrypt_data_v2`, separated for testing purposes.
-fn do_encrypt_data_v2(plaintext: &[u8], auth_key: &AuthKey, random_padding: &[u8; 32]) -> Vec<u8> {
+fn do_encrypt_data_v2(
+    plaintext: &[u8],
+    auth_key: &AuthKey,
+    random_padding: &[u8; 32],
+) -> (Vec<u8>, u32) {
     let padded_plaintext = {
         // "Note that MTProto 2.0 requires from 12 to 1024 bytes of padding"
         // "[...] the resulting message length be divisible by 16 bytes"
@@ -131,6 +137,14 @@ fn do_encrypt_data_v2(plaintext: &[u8], auth_key: &AuthKey, random_padding: &[u8
         hasher.finalize()
     };
 
+    // The server responds with the first 32 bits of msg_key_large, with the most significant bit
+    // set, when a quick acknowledgement is requested.
+    let quick_ack = {
+        let mut buffer = [0; 4];
+        buffer.copy_from_slice(&msg_key_large[..4]);
+        u32::from_le_bytes(buffer) | 0x8000_0000
+    };
+
     // msg_key = substr (msg_key_large, 8, 16);
     let msg_key = {
         let mut buffer = [0; 16];
@@ -141,14 +155,14 @@ fn do_encrypt_data_v2(plaintext: &[u8], auth_key: &AuthKey, random_padding: &[u8
     // Calculate the key
     let (key, iv) = calc_key(&auth_key, &msg_key, side);
 
-    let ciphertext = { aes::ige_encrypt(&padded_plaintext, &key, &iv) };
-
-    let mut result = Vec::with_capacity(auth_key.key_id.len() + msg_key.len() + ciphertext.len());
+    let header_len = auth_key.key_id.len() + msg_key.len();
+    let mut result = Vec::with_capacity(header_len + padded_plaintext.len());
     result.extend(&auth_key.key_id);
     result.extend(&msg_key);
-    result.extend(&ciphertext);
+    result.extend(&padded_plaintext);
+    aes::ige_encrypt_in_place(&mut result[header_len..], &key, &iv);
 
-    result
+    (result, quick_ack)
 }
 
 /// This function implements the [MTProto 2.0 algorithm] for computing
@@ -157,6 +171,13 @@ fn do_encrypt_data_v2(plaintext: &[u8], auth_key: &AuthKey, random_padding: &[u8
 /// [MTProto 2.0 algorithm]: https://core.telegram.org/mtproto/description#defining-aes-key-and-initialization-vector
 #[must_use]
 pub fn encrypt_data_v2(plaintext: &[u8], auth_key: &AuthKey) -> Vec<u8> {
+    encrypt_data_v2_with_quick_ack(plaintext, auth_key).0
+}
+
+/// Like [`encrypt_data_v2`], but also returns the token the server will reply with if the
+/// transport asks it to quickly acknowledge the encrypted data.
+#[must_use]
+pub fn encrypt_data_v2_with_quick_ack(plaintext: &[u8], auth_key: &AuthKey) -> (Vec<u8>, u32) {
     let random_padding = {
         let mut buffer = [0; 32];
         getrandom(&mut buffer).expect("failed to generate a secure padding");
@@ -178,7 +199,7 @@ pub fn decrypt_data_v2(ciphertext: &[u8], auth_key: &AuthKey) -> Result<Vec<u8>,
 
     // TODO Check salt, session_id and sequence_number
     let key_id = &ciphertext[..8];
-    if auth_key.key_id != *key_id {
+    if !constant_time_eq(&auth_key.key_id, key_id) {
         return Err(Error::AuthKeyMismatch);
     }
 
@@ -199,13 +220,126 @@ pub fn decrypt_data_v2(ciphertext: &[u8], auth_key: &AuthKey) -> Result<Vec<u8>,
         hasher.finalize()
     };
 
-    if msg_key != our_key[8..8 + 16] {
+    if !constant_time_eq(&msg_key, &our_key[8..8 + 16]) {
         return Err(Error::MessageKeyMismatch);
     }
 
     Ok(plaintext)
 }
 
+/// Compare two byte slices in constant time.
+///
+/// This should be used to check keys, nonces and hashes received from the network, so that the
+/// time it takes to find a mismatch does not leak how many bytes matched. Slices of different
+/// length are never equal, but their length is not considered secret.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Calculate the key based on the [MTProto 1.0 algorithm], returning the pair `(key, iv)` for
+/// use in AES-IGE mode.
+///
+/// [MTProto 1.0 algorithm]: https://core.telegram.org/mtproto_v1#defining-aes-key-and-initialization-vector
+fn calc_key_v1(auth_key: &AuthKey, msg_key: &[u8; 16], side: Side) -> ([u8; 32], [u8; 32]) {
+    let x = side.x();
+    let mut hasher = Sha1::new();
+
+    // sha1_a = SHA1 (msg_key + substr (auth_key, x, 32));
+    let sha1_a = {
+        hasher.reset();
+        hasher.update(msg_key);
+        hasher.update(&auth_key.data[x..x + 32]);
+        hasher.digest().bytes()
+    };
+
+    // sha1_b = SHA1 (substr (auth_key, 32+x, 16) + msg_key + substr (auth_key, 48+x, 16));
+    let sha1_b = {
+        hasher.reset();
+        hasher.update(&auth_key.data[32 + x..32 + x + 16]);
+        hasher.update(msg_key);
+        hasher.update(&auth_key.data[48 + x..48 + x + 16]);
+        hasher.digest().bytes()
+    };
+
+    // sha1_c = SHA1 (substr (auth_key, 64+x, 32) + msg_key);
+    let sha1_c = {
+        hasher.reset();
+        hasher.update(&auth_key.data[64 + x..64 + x + 32]);
+        hasher.update(msg_key);
+        hasher.digest().bytes()
+    };
+
+    // sha1_d = SHA1 (msg_key + substr (auth_key, 96+x, 32));
+    let sha1_d = {
+        hasher.reset();
+        hasher.update(msg_key);
+        hasher.update(&auth_key.data[96 + x..96 + x + 32]);
+        hasher.digest().bytes()
+    };
+
+    // aes_key = substr (sha1_a, 0, 8) + substr (sha1_b, 8, 12) + substr (sha1_c, 4, 12);
+    let aes_key = {
+        let mut buffer = [0; 32];
+        buffer[..8].copy_from_slice(&sha1_a[..8]);
+        buffer[8..8 + 12].copy_from_slice(&sha1_b[8..8 + 12]);
+        buffer[20..20 + 12].copy_from_slice(&sha1_c[4..4 + 12]);
+        buffer
+    };
+
+    // aes_iv = substr (sha1_a, 8, 12) + substr (sha1_b, 0, 8) + substr (sha1_c, 16, 4) + substr (sha1_d, 0, 8);
+    let aes_iv = {
+        let mut buffer = [0; 32];
+        buffer[..12].copy_from_slice(&sha1_a[8..8 + 12]);
+        buffer[12..12 + 8].copy_from_slice(&sha1_b[..8]);
+        buffer[20..20 + 4].copy_from_slice(&sha1_c[16..16 + 4]);
+        buffer[24..24 + 8].copy_from_slice(&sha1_d[..8]);
+        buffer
+    };
+
+    (aes_key, aes_iv)
+}
+
+// Inner body of `encrypt_data_v1`, separated for testing purposes.
+fn do_encrypt_data_v1(plaintext: &[u8], auth_key: &AuthKey, random_padding: &[u8; 16]) -> Vec<u8> {
+    // msg_key = substr (SHA1 (plaintext), 4, 16);
+    let msg_key = {
+        let mut buffer = [0; 16];
+        buffer.copy_from_slice(&Sha1::from(plaintext).digest().bytes()[4..4 + 16]);
+        buffer
+    };
+
+    // "the resulting message length be divisible by 16 bytes"
+    let padding_len = (16 - (plaintext.len() % 16)) % 16;
+    let padded_plaintext = [plaintext, &random_padding[..padding_len]].concat();
+
+    let (key, iv) = calc_key_v1(auth_key, &msg_key, Side::Client);
+    let ciphertext = aes::ige_encrypt(&padded_plaintext, &key, &iv);
+
+    let mut result = Vec::with_capacity(auth_key.key_id.len() + msg_key.len() + ciphertext.len());
+    result.extend(&auth_key.key_id);
+    result.extend(&msg_key);
+    result.extend(&ciphertext);
+
+    result
+}
+
+/// This function implements the [MTProto 1.0 algorithm] for encrypting data.
+///
+/// It's only needed to encrypt the message used to bind a temporary authorization key to the
+/// permanent one, and should not be used for anything else.
+///
+/// [MTProto 1.0 algorithm]: https://core.telegram.org/mtproto_v1
+#[must_use]
+pub fn encrypt_data_v1(plaintext: &[u8], auth_key: &AuthKey) -> Vec<u8> {
+    let random_padding = {
+        let mut buffer = [0; 16];
+        getrandom(&mut buffer).expect("failed to generate a secure padding");
+        buffer
+    };
+
+    do_encrypt_data_v1(plaintext, auth_key, &random_padding)
+}
+
 /// Generate the AES key and initialization vector from the server nonce
 /// and the new client nonce. This is done after the DH exchange.
 pub fn generate_key_data_from_nonce(
@@ -363,10 +497,29 @@ mod tests {
             36, 61, 86, 62, 161, 128, 210, 24, 238, 117, 124, 154,
         ];
 
-        assert_eq!(
-            do_encrypt_data_v2(&plaintext, &auth_key, &random_padding),
-            expected
-        );
+        let (ciphertext, quick_ack) = do_encrypt_data_v2(&plaintext, &auth_key, &random_padding);
+        assert_eq!(ciphertext, expected);
+        assert_eq!(quick_ack & 0x8000_0000, 0x8000_0000);
+    }
+
+    #[test]
+    fn encrypt_client_data_v1() {
+        let plaintext = b"Hello, world! This data should remain secure!".to_vec();
+        let auth_key = get_test_auth_key();
+        let ciphertext = do_encrypt_data_v1(&plaintext, &auth_key, &[0; 16]);
+
+        assert_eq!(&ciphertext[..8], &auth_key.key_id);
+        let msg_key = {
+            let mut buffer = [0; 16];
+            buffer.copy_from_slice(&ciphertext[8..24]);
+            buffer
+        };
+        assert_eq!(msg_key, Sha1::from(&plaintext).digest().bytes()[4..20]);
+
+        let (key, iv) = calc_key_v1(&auth_key, &msg_key, Side::Client);
+        let decrypted = decrypt_ige(&ciphertext[24..], &key, &iv);
+        assert_eq!(&decrypted[..plaintext.len()], &plaintext[..]);
+        assert_eq!(decrypted.len() % 16, 0);
     }
 
     #[test]
@@ -459,6 +612,13 @@ mod tests {
         assert_eq!(encrypt_ige(&plaintext, &key, &iv), expected);
     }
 
+    #[test]
+    fn check_constant_time_eq() {
+        assert!(constant_time_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2]));
+    }
+
     #[test]
     fn verify_ige_decryption() {
         let ciphertext = get_test_aes_key_or_iv(); // Decrypting the key with itself
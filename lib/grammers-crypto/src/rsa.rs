@@ -9,18 +9,51 @@ use num_bigint::BigUint;
 use sha1::Sha1;
 
 /// RSA key.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Key {
     n: BigUint,
     e: BigUint,
 }
 
 impl Key {
+    /// Create a key from its modulus `n` and exponent `e`, both in decimal.
     pub fn new(n: &str, e: &str) -> Option<Self> {
         Some(Self {
             n: BigUint::parse_bytes(n.as_bytes(), 10)?,
             e: BigUint::parse_bytes(e.as_bytes(), 10)?,
         })
     }
+
+    /// The fingerprint the server uses to refer to this key, which is made from the lower 64
+    /// bits of the SHA1 hash of the key, serialized as a TL `rsa_public_key`.
+    pub fn fingerprint(&self) -> i64 {
+        let mut serialized = Vec::new();
+        serialize_bytes(&self.n.to_bytes_be(), &mut serialized);
+        serialize_bytes(&self.e.to_bytes_be(), &mut serialized);
+
+        let mut buffer = [0; 8];
+        buffer.copy_from_slice(&Sha1::from(&serialized).digest().bytes()[12..12 + 8]);
+        i64::from_le_bytes(buffer)
+    }
+}
+
+/// Serialize the data as TL `bytes`, which are prefixed with their length and padded to 4 bytes.
+fn serialize_bytes(data: &[u8], buffer: &mut Vec<u8>) {
+    let len = if data.len() <= 253 {
+        buffer.push(data.len() as u8);
+        data.len() + 1
+    } else {
+        buffer.extend(&[
+            254,
+            (data.len() & 0xff) as u8,
+            ((data.len() >> 8) & 0xff) as u8,
+            ((data.len() >> 16) & 0xff) as u8,
+        ]);
+        data.len()
+    };
+
+    buffer.extend(data);
+    buffer.extend(&[0; 3][..(4 - (len % 4)) % 4]);
 }
 
 /// Encrypt the given data, prefixing it with a hash before, using RSA.
@@ -57,6 +90,12 @@ pub fn encrypt_hashed(data: &[u8], key: &Key, random_bytes: &[u8; 256]) -> Vec<u
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rsa_fingerprint() {
+        let key = Key::new("22081946531037833540524260580660774032207476521197121128740358761486364763467087828766873972338019078976854986531076484772771735399701424566177039926855356719497736439289455286277202113900509554266057302466528985253648318314129246825219640197356165626774276930672688973278712614800066037531599375044750753580126415613086372604312320014358994394131667022861767539879232149461579922316489532682165746762569651763794500923643656753278887871955676253526661694459370047843286685859688756429293184148202379356802488805862746046071921830921840273062124571073336369210703400985851431491295910187179045081526826572515473914151", "65537").unwrap();
+        assert_eq!(key.fingerprint(), 847625836280919973);
+    }
+
     #[test]
     fn test_rsa_encryption() {
         let key = Key::new("22081946531037833540524260580660774032207476521197121128740358761486364763467087828766873972338019078976854986531076484772771735399701424566177039926855356719497736439289455286277202113900509554266057302466528985253648318314129246825219640197356165626774276930672688973278712614800066037531599375044750753580126415613086372604312320014358994394131667022861767539879232149461579922316489532682165746762569651763794500923643656753278887871955676253526661694459370047843286685859688756429293184148202379356802488805862746046071921830921840273062124571073336369210703400985851431491295910187179045081526826572515473914151", "65537").unwrap();
@@ -0,0 +1,189 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The primitives of the [Secure Remote Password] protocol used by Telegram's two-factor
+//! authentication, which proves that the password is known without ever sending it.
+//!
+//! The names follow the ones used in the algorithm description. Numbers are big-endian, and
+//! those modulo `p` are padded to 256 bytes.
+//!
+//! [Secure Remote Password]: https://core.telegram.org/api/srp
+use hmac::Hmac;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256, Sha512};
+
+/// `H(data) := sha256(data)`, with `data` being the concatenation of all `parts`.
+pub fn h(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// `SH(data, salt) := H(salt | data | salt)`
+pub fn sh(data: &[u8], salt: &[u8]) -> [u8; 32] {
+    h(&[salt, data, salt])
+}
+
+/// `PH1(password, salt1, salt2) := SH(SH(password, salt1), salt2)`
+pub fn ph1(password: &[u8], salt1: &[u8], salt2: &[u8]) -> [u8; 32] {
+    sh(&sh(password, salt1), salt2)
+}
+
+/// `PH2(password, salt1, salt2) := SH(pbkdf2(sha512, PH1(password, salt1, salt2), salt1,
+/// 100000), salt2)`
+pub fn ph2(password: &[u8], salt1: &[u8], salt2: &[u8]) -> [u8; 32] {
+    let hash1 = ph1(password, salt1, salt2);
+
+    // 512-bit derived key
+    let mut dk = [0u8; 64];
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(&hash1, salt1, 100000, &mut dk);
+
+    sh(&dk, salt2)
+}
+
+/// Pad the big-endian number with zeros on the left until it is 256 bytes long.
+pub fn pad_to_256(data: &[u8]) -> Vec<u8> {
+    let mut new_vec = vec![0; 256 - data.len()];
+    new_vec.extend(data);
+    new_vec
+}
+
+/// `v := pow(g, x) mod p`, with `x := PH2(password, salt1, salt2)`.
+///
+/// This verifier is what the server stores instead of the password.
+pub fn verifier(password: &[u8], salt1: &[u8], salt2: &[u8], g: i32, p: &[u8]) -> Vec<u8> {
+    let x = BigUint::from_bytes_be(&ph2(password, salt1, salt2));
+    let v = BigUint::from(g as u32).modpow(&x, &BigUint::from_bytes_be(p));
+    pad_to_256(&v.to_bytes_be())
+}
+
+/// Compute the proof that the password is known, given the server's `g_b` and the client's
+/// secret random `a`.
+///
+/// Returns `(M1, g_a)`, which must be sent to the server. The parameters `g` and `p` should
+/// be validated before using them.
+pub fn client_proof(
+    password: &[u8],
+    salt1: &[u8],
+    salt2: &[u8],
+    g: i32,
+    p: &[u8],
+    g_b: &[u8],
+    a: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+    let big_p = BigUint::from_bytes_be(p);
+    let big_g = BigUint::from(g as u32);
+    let g_for_hash = pad_to_256(&[g as u8]);
+
+    let g_b = pad_to_256(g_b);
+    let big_g_b = BigUint::from_bytes_be(&g_b);
+    let big_a = BigUint::from_bytes_be(a);
+
+    // k := H(p | g)
+    let k = BigUint::from_bytes_be(&h(&[p, &g_for_hash]));
+
+    // g_a := pow(g, a) mod p
+    let g_a = pad_to_256(&big_g.modpow(&big_a, &big_p).to_bytes_be());
+
+    // u := H(g_a | g_b)
+    let u = BigUint::from_bytes_be(&h(&[&g_a, &g_b]));
+
+    // x := PH2(password, salt1, salt2)
+    let x = BigUint::from_bytes_be(&ph2(password, salt1, salt2));
+
+    // v := pow(g, x) mod p
+    let v = big_g.modpow(&x, &big_p);
+
+    // k_v := (k * v) mod p
+    let k_v = (k * v) % &big_p;
+
+    // t := (g_b - k_v) mod p (positive modulo, if the result is negative increment by p)
+    let t = (big_g_b + &big_p - k_v) % &big_p;
+
+    // s_a := pow(t, a + u * x) mod p
+    let s_a = t.modpow(&(big_a + u * x), &big_p);
+
+    // k_a := H(s_a)
+    let k_a = h(&[&pad_to_256(&s_a.to_bytes_be())]);
+
+    // M1 := H(H(p) xor H(g) | H(salt1) | H(salt2) | g_a | g_b | k_a)
+    let p_xor_g: Vec<u8> = h(&[p])
+        .iter()
+        .zip(h(&[&g_for_hash]).iter())
+        .map(|(x1, x2)| x1 ^ x2)
+        .collect();
+
+    let m1 = h(&[&p_xor_g, &h(&[salt1]), &h(&[salt2]), &g_a, &g_b, &k_a]).to_vec();
+
+    (m1, g_a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_sh() {
+        assert_eq!(
+            sh(b"data", b"salt"),
+            [
+                124, 195, 19, 234, 197, 69, 128, 219, 176, 24, 215, 67, 53, 36, 110, 62, 200, 50,
+                20, 47, 227, 93, 197, 47, 54, 39, 98, 12, 68, 56, 235, 214
+            ]
+        );
+    }
+
+    #[test]
+    fn check_ph1() {
+        assert_eq!(
+            ph1(b"password", b"salt1", b"salt2"),
+            [
+                13, 59, 85, 18, 165, 49, 61, 17, 248, 117, 99, 11, 143, 179, 109, 234, 133, 246,
+                180, 10, 203, 19, 252, 195, 133, 147, 240, 146, 198, 0, 125, 108
+            ]
+        );
+    }
+
+    #[test]
+    fn check_ph2() {
+        assert_eq!(
+            ph2(b"password", b"salt1", b"salt2"),
+            [
+                149, 106, 195, 73, 117, 165, 184, 151, 90, 138, 110, 172, 177, 52, 156, 115, 106,
+                207, 140, 177, 71, 73, 1, 159, 51, 234, 145, 196, 195, 70, 24, 240
+            ]
+        );
+    }
+
+    #[test]
+    fn check_verifier() {
+        let x = BigUint::from_bytes_be(&ph2(&[7], &[1], &[2]));
+        let expected = BigUint::from(3u32).modpow(&x, &BigUint::from(47u32));
+        assert_eq!(
+            verifier(&[7], &[1], &[2], 3, &pad_to_256(&[47])),
+            pad_to_256(&expected.to_bytes_be())
+        );
+    }
+
+    // `k * v` is larger than `g_b` here, so `t` must wrap around `p`.
+    #[test]
+    fn check_client_proof_with_negative_t() {
+        let (m1, g_a) = client_proof(&[7], &[1], &[2], 3, &pad_to_256(&[47]), &[1], &[1]);
+
+        assert_eq!(
+            m1,
+            vec![
+                151, 192, 139, 202, 69, 57, 65, 228, 186, 194, 4, 245, 37, 70, 36, 237, 5, 56, 35,
+                72, 60, 222, 161, 67, 33, 204, 228, 240, 109, 218, 145, 202
+            ]
+        );
+        assert_eq!(g_a, pad_to_256(&[3]));
+    }
+}
@@ -5,24 +5,10 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use crate::srp;
+use getrandom::getrandom;
 use glass_pumpkin::safe_prime;
-use hmac::Hmac;
 use num_bigint::BigUint;
-use sha2::digest::Output;
-use sha2::{Digest, Sha256, Sha512};
-
-// H(data) := sha256(data)
-macro_rules! h {
-    ( $( $x:expr ),* ) => {
-        {
-            let mut hasher = Sha256::new();
-            $(
-                hasher.update($x);
-            )*
-            hasher.finalize()
-        }
-    };
-}
 
 /// Prepare the password for sending to telegram for verification.
 /// The method returns M1 and g_a parameters that should be sent to the telegram
@@ -38,67 +24,31 @@ pub fn calculate_2fa(
     a: Vec<u8>,
     password: impl AsRef<[u8]>,
 ) -> (Vec<u8>, Vec<u8>) {
-    // Prepare our parameters
-    let big_p = BigUint::from_bytes_be(&p);
-
-    let g_b = pad_to_256(&g_b);
-    let a = pad_to_256(&a);
-
-    let g_for_hash = vec![*g as u8];
-    let g_for_hash = pad_to_256(&g_for_hash);
-
-    let big_g_b = BigUint::from_bytes_be(&g_b);
-
-    let big_g = BigUint::from(*g as u32);
-    let big_a = BigUint::from_bytes_be(&a);
-
-    // k := H(p | g)
-    let k = h!(&p, &g_for_hash);
-    let big_k = BigUint::from_bytes_be(&k);
-
-    // g_a := pow(g, a) mod p
-    let g_a = big_g.modpow(&big_a, &big_p);
-    let g_a = pad_to_256(&g_a.to_bytes_be());
-
-    // u := H(g_a | g_b)
-    let u = h!(&g_a, &g_b);
-    let u = BigUint::from_bytes_be(&u);
-
-    // x := PH2(password, salt1, salt2)
-    let x = ph2(&password, &salt1, &salt2);
-    let x = BigUint::from_bytes_be(&x);
-
-    // v := pow(g, x) mod p
-    let big_v = big_g.modpow(&x, &big_p);
-
-    // k_v := (k * v) mod p
-    let k_v = (big_k * big_v) % &big_p;
-
-    // t := (g_b - k_v) mod p (positive modulo, if the result is negative increment by p)
-    let sub = if big_g_b > k_v {
-        big_g_b - k_v
-    } else {
-        k_v - big_g_b
-    };
-    let big_t = sub % &big_p;
-
-    // s_a := pow(t, a + u * x) mod p
-    let first = u * x;
-    let second = big_a + first;
-    let big_s_a = big_t.modpow(&(second), &big_p);
-
-    // k_a := H(s_a)
-    let k_a = h!(&pad_to_256(&big_s_a.to_bytes_be()));
-
-    // M1 := H(H(p) xor H(g) | H(salt1) | H(salt2) | g_a | g_b | k_a)
-    let h_p = h!(&p);
-    let h_g = h!(&g_for_hash);
-
-    let p_xor_g: Vec<u8> = xor(&h_p, &h_g);
+    srp::client_proof(password.as_ref(), salt1, salt2, *g, p, &g_b, &a)
+}
 
-    let m1 = h!(&p_xor_g, &h!(&salt1), &h!(&salt2), &g_a, &g_b, &k_a).to_vec();
+/// Append 32 random bytes to the `salt1` sent by Telegram, as required before calculating the
+/// hash of a new password.
+pub fn extend_new_salt(salt1: &[u8]) -> Vec<u8> {
+    let mut salt = vec![0; salt1.len() + 32];
+    salt[..salt1.len()].copy_from_slice(salt1);
+    getrandom(&mut salt[salt1.len()..]).expect("failed to generate a secure salt");
+    salt
+}
 
-    (m1, g_a)
+/// Calculate the verifier of a new password, which is sent to Telegram instead of the password
+/// itself when setting a new password. The `salt1` should first be extended with
+/// [`extend_new_salt`].
+///
+/// The algorithm is described here: https://core.telegram.org/api/srp#setting-a-new-2fa-password
+pub fn calculate_password_hash(
+    salt1: &[u8],
+    salt2: &[u8],
+    g: &i32,
+    p: &[u8],
+    password: impl AsRef<[u8]>,
+) -> Vec<u8> {
+    srp::verifier(password.as_ref(), salt1, salt2, *g, p)
 }
 
 /// Validation for parameters required for two-factor authentication
@@ -143,45 +93,10 @@ fn check_p_len(p: &[u8]) -> bool {
     p.len() == 256
 }
 
-// SH(data, salt) := H(salt | data | salt)
-fn sh(data: impl AsRef<[u8]>, salt: impl AsRef<[u8]>) -> Output<Sha256> {
-    return h!(&salt, &data, &salt);
-}
-
-// PH1(password, salt1, salt2) := SH(SH(password, salt1), salt2)
-fn ph1(password: impl AsRef<[u8]>, salt1: &[u8], salt2: &[u8]) -> Output<Sha256> {
-    sh(&sh(password, salt1), salt2)
-}
-
-// PH2(password, salt1, salt2)
-//                      := SH(pbkdf2(sha512, PH1(password, salt1, salt2), salt1, 100000), salt2)
-fn ph2(password: impl AsRef<[u8]>, salt1: &[u8], salt2: &[u8]) -> Output<Sha256> {
-    let hash1 = ph1(password, salt1, salt2);
-
-    // 512-bit derived key
-    let mut dk = [0u8; 64];
-    pbkdf2::pbkdf2::<Hmac<Sha512>>(&hash1, salt1, 100000, &mut dk);
-
-    sh(&dk, salt2)
-}
-
-fn xor(left: &Output<Sha256>, right: &Output<Sha256>) -> Vec<u8> {
-    return left
-        .iter()
-        .zip(right.iter())
-        .map(|(&x1, &x2)| x1 ^ x2)
-        .collect();
-}
-
-fn pad_to_256(data: &[u8]) -> Vec<u8> {
-    let mut new_vec = vec![0; 256 - data.len()];
-    new_vec.extend(data);
-    new_vec
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::srp::{pad_to_256, ph2};
 
     #[test]
     fn check_calculations_1() {
@@ -215,6 +130,31 @@ mod tests {
         assert_eq!(expected_g_a, g_a);
     }
 
+    #[test]
+    fn check_extend_new_salt() {
+        let salt1 = vec![1, 2, 3];
+        let salt = extend_new_salt(&salt1);
+
+        assert_eq!(salt.len(), salt1.len() + 32);
+        assert_eq!(&salt[..salt1.len()], &salt1[..]);
+        assert_ne!(salt, extend_new_salt(&salt1));
+    }
+
+    #[test]
+    fn check_password_hash() {
+        let salt1 = vec![1];
+        let salt2 = vec![2];
+        let g = 3;
+        let p = pad_to_256(&[47]);
+        let password = vec![7];
+
+        let hash = calculate_password_hash(&salt1, &salt2, &g, &p, &password);
+
+        let x = BigUint::from_bytes_be(&ph2(&password, &salt1, &salt2));
+        let expected = BigUint::from(3u32).modpow(&x, &BigUint::from(47u32));
+        assert_eq!(hash, pad_to_256(&expected.to_bytes_be()));
+    }
+
     #[test]
     fn check_calculations_2() {
         let salt1 = vec![
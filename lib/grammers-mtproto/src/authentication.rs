@@ -33,13 +33,15 @@
 //! }
 //! ```
 use getrandom::getrandom;
-use grammers_crypto::{factorize::factorize, rsa, AuthKey};
+use grammers_crypto::{constant_time_eq, factorize::factorize, rsa, AuthKey};
 use grammers_tl_types::{self as tl, Cursor, Deserializable, RemoteCall, Serializable};
 use num_bigint::{BigUint, ToBigUint};
 use sha1::Sha1;
 use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub use grammers_crypto::rsa::Key as RsaKey;
+
 /// Represents an error that occured during the generation of an
 /// authorization key.
 #[derive(Clone, Debug, PartialEq)]
@@ -234,19 +236,71 @@ fn do_step1(random_bytes: &[u8; 16]) -> Result<(Vec<u8>, Step1), Error> {
 
 /// The second step of the process to generate an authorization key.
 pub fn step2(data: Step1, response: &[u8]) -> Result<(Vec<u8>, Step2), Error> {
+    step2_with_keys(data, response, &[])
+}
+
+/// Like [`step2`], but the given RSA keys are also trusted, before the built-in keys of the
+/// official servers.
+///
+/// This is needed to authenticate against servers using different keys, such as self-hosted
+/// MTProto-compatible servers.
+pub fn step2_with_keys(
+    data: Step1,
+    response: &[u8],
+    keys: &[RsaKey],
+) -> Result<(Vec<u8>, Step2), Error> {
     let random_bytes = {
         let mut buffer = [0; 32 + 256];
         getrandom(&mut buffer).expect("failed to generate secure data for auth key");
         buffer
     };
 
-    do_step2(data, response, &random_bytes)
+    do_step2(data, response, &random_bytes, None, keys)
+}
+
+/// The second step of the process to generate a temporary authorization key, which will expire
+/// after `expires_in` seconds and be usable in the datacenter `dc_id` only.
+///
+/// The rest of the process is the same as for permanent keys. Temporary keys must be bound to a
+/// permanent key before they can be used to invoke most requests.
+pub fn step2_temp(
+    data: Step1,
+    response: &[u8],
+    dc_id: i32,
+    expires_in: i32,
+) -> Result<(Vec<u8>, Step2), Error> {
+    step2_temp_with_keys(data, response, dc_id, expires_in, &[])
+}
+
+/// Like [`step2_temp`], but the given RSA keys are also trusted, as in [`step2_with_keys`].
+pub fn step2_temp_with_keys(
+    data: Step1,
+    response: &[u8],
+    dc_id: i32,
+    expires_in: i32,
+    keys: &[RsaKey],
+) -> Result<(Vec<u8>, Step2), Error> {
+    let random_bytes = {
+        let mut buffer = [0; 32 + 256];
+        getrandom(&mut buffer).expect("failed to generate secure data for auth key");
+        buffer
+    };
+
+    do_step2(
+        data,
+        response,
+        &random_bytes,
+        Some((dc_id, expires_in)),
+        keys,
+    )
 }
 
 fn do_step2(
     data: Step1,
     response: &[u8],
     random_bytes: &[u8; 32 + 256],
+    temp: Option<(i32, i32)>,
+    keys: &[RsaKey],
 ) -> Result<(Vec<u8>, Step2), Error> {
     // Step 2. Validate the PQ response. Return `(p, q)` if it's valid.
     let Step1 { nonce } = data;
@@ -300,33 +354,48 @@ fn do_step2(
 
     // "pq is a representation of a natural number (in binary big endian format)"
     // https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation
-    let pq_inner_data = tl::enums::PQInnerData::Data(tl::types::PQInnerData {
-        pq: pq.to_be_bytes().to_vec(),
-        p: p_bytes.clone(),
-        q: q_bytes.clone(),
-        nonce,
-        server_nonce: res_pq.server_nonce,
-        new_nonce,
-    })
+    let pq_inner_data = match temp {
+        None => tl::enums::PQInnerData::Data(tl::types::PQInnerData {
+            pq: pq.to_be_bytes().to_vec(),
+            p: p_bytes.clone(),
+            q: q_bytes.clone(),
+            nonce,
+            server_nonce: res_pq.server_nonce,
+            new_nonce,
+        }),
+        Some((dc, expires_in)) => tl::enums::PQInnerData::TempDc(tl::types::PQInnerDataTempDc {
+            pq: pq.to_be_bytes().to_vec(),
+            p: p_bytes.clone(),
+            q: q_bytes.clone(),
+            nonce,
+            server_nonce: res_pq.server_nonce,
+            new_nonce,
+            dc,
+            expires_in,
+        }),
+    }
     .to_bytes();
 
     // sha_digest + data + random_bytes
-    let fingerprint = match res_pq
-        .server_public_key_fingerprints
-        .iter()
-        .cloned()
-        .find(|&fingerprint| key_for_fingerprint(fingerprint).is_some())
-    {
-        Some(x) => x,
-        None => {
-            return Err(Error::UnknownFingerprints {
-                fingerprints: res_pq.server_public_key_fingerprints.clone(),
-            })
-        }
-    };
+    let (fingerprint, key) =
+        match res_pq
+            .server_public_key_fingerprints
+            .iter()
+            .find_map(|&fingerprint| {
+                keys.iter()
+                    .find(|key| key.fingerprint() == fingerprint)
+                    .cloned()
+                    .or_else(|| key_for_fingerprint(fingerprint))
+                    .map(|key| (fingerprint, key))
+            }) {
+            Some(x) => x,
+            None => {
+                return Err(Error::UnknownFingerprints {
+                    fingerprints: res_pq.server_public_key_fingerprints.clone(),
+                })
+            }
+        };
 
-    // Safe to unwrap because we found it just above
-    let key = key_for_fingerprint(fingerprint).unwrap();
     let ciphertext = rsa::encrypt_hashed(&pq_inner_data, &key, &random_bytes);
 
     Ok((
@@ -432,7 +501,7 @@ fn do_step3(
             .bytes()
     };
 
-    if got_answer_hash != expected_answer_hash {
+    if !constant_time_eq(&got_answer_hash, &expected_answer_hash) {
         return Err(Error::InvalidAnswerHash {
             got: got_answer_hash,
             expected: expected_answer_hash,
@@ -605,7 +674,7 @@ pub fn create_key(data: Step3, response: &[u8]) -> Result<Finished, Error> {
 
 /// Helper function to avoid the boilerplate of checking for invalid nonce.
 fn check_nonce(got: &[u8; 16], expected: &[u8; 16]) -> Result<(), Error> {
-    if got == expected {
+    if constant_time_eq(got, expected) {
         Ok(())
     } else {
         Err(Error::InvalidNonce {
@@ -618,7 +687,7 @@ fn check_nonce(got: &[u8; 16], expected: &[u8; 16]) -> Result<(), Error> {
 /// Helper function to avoid the boilerplate of checking for invalid
 /// server nonce.
 fn check_server_nonce(got: &[u8; 16], expected: &[u8; 16]) -> Result<(), Error> {
-    if got == expected {
+    if constant_time_eq(got, expected) {
         Ok(())
     } else {
         Err(Error::InvalidServerNonce {
@@ -631,7 +700,7 @@ fn check_server_nonce(got: &[u8; 16], expected: &[u8; 16]) -> Result<(), Error>
 /// Helper function to avoid the boilerplate of checking for invalid
 /// new nonce hash.
 fn check_new_nonce_hash(got: &[u8; 16], expected: &[u8; 16]) -> Result<(), Error> {
-    if got == expected {
+    if constant_time_eq(got, expected) {
         Ok(())
     } else {
         Err(Error::InvalidNewNonceHash {
@@ -657,7 +726,7 @@ fn check_g_in_range(value: &BigUint, low: &BigUint, high: &BigUint) -> Result<()
 
 /// Find the RSA key's `(n, e)` pair for a certain fingerprint.
 #[allow(clippy::unreadable_literal)]
-fn key_for_fingerprint(fingerprint: i64) -> Option<rsa::Key> {
+fn key_for_fingerprint(fingerprint: i64) -> Option<RsaKey> {
     Some(match fingerprint {
         // New
         847625836280919973 => rsa::Key::new("22081946531037833540524260580660774032207476521197121128740358761486364763467087828766873972338019078976854986531076484772771735399701424566177039926855356719497736439289455286277202113900509554266057302466528985253648318314129246825219640197356165626774276930672688973278712614800066037531599375044750753580126415613086372604312320014358994394131667022861767539879232149461579922316489532682165746762569651763794500923643656753278887871955676253526661694459370047843286685859688756429293184148202379356802488805862746046071921830921840273062124571073336369210703400985851431491295910187179045081526826572515473914151", "65537").unwrap(),
@@ -833,7 +902,7 @@ mod tests {
         assert_eq!(request, step1_request.to_vec());
         let response = step1_response;
 
-        let (request, data) = do_step2(data, &response, &step2_random)?;
+        let (request, data) = do_step2(data, &response, &step2_random, None, &[])?;
         assert_eq!(request, step2_request.to_vec());
         let response = step2_response;
 
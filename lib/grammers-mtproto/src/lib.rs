@@ -19,9 +19,19 @@ pub mod mtp;
 pub mod transport;
 mod utils;
 
+use std::time::Duration;
+
 /// The default compression threshold to be used.
 pub const DEFAULT_COMPRESSION_THRESHOLD: Option<usize> = Some(512);
 
+/// The default amount of messages which can be waiting to be acknowledged before the
+/// acknowledgements are sent, even if there are no requests to send them with.
+pub const DEFAULT_MAX_PENDING_ACKS: usize = 16;
+
+/// The default time messages can wait to be acknowledged before the acknowledgements are sent,
+/// even if there are no requests to send them with.
+pub const DEFAULT_ACK_DELAY: Duration = Duration::from_secs(1);
+
 /// A Message Identifier.
 ///
 /// When requests are enqueued, a new associated message identifier is
@@ -31,3 +41,10 @@ pub const DEFAULT_COMPRESSION_THRESHOLD: Option<usize> = Some(512);
 /// you will know the response corresponds to it.
 #[derive(Copy, Clone, Debug, Hash, PartialEq, PartialOrd, Eq, Ord)]
 pub struct MsgId(i64);
+
+impl MsgId {
+    /// The raw value of the identifier, as used by requests which refer to other messages.
+    pub fn value(&self) -> i64 {
+        self.0
+    }
+}
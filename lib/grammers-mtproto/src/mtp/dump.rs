@@ -0,0 +1,180 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use crate::manual_tl;
+use grammers_tl_types::{self as tl, Identifiable};
+use log::warn;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Whether a message was sent to the server or received from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A plaintext message exchanged with the server, before encryption or after decryption.
+///
+/// Containers are recorded, and so are each of the messages inside them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DumpedMessage {
+    pub direction: Direction,
+    pub msg_id: i64,
+    pub seq_no: i32,
+    /// The constructor identifier of the body, or 0 if the body is too short to contain one.
+    pub constructor_id: u32,
+    pub body: Vec<u8>,
+}
+
+impl DumpedMessage {
+    pub(crate) fn new(direction: Direction, message: &manual_tl::Message) -> Self {
+        Self {
+            direction,
+            msg_id: message.msg_id,
+            seq_no: message.seq_no,
+            constructor_id: message.constructor_id().unwrap_or(0),
+            body: message.body.clone(),
+        }
+    }
+
+    /// The name of the TL constructor of the body.
+    pub fn name(&self) -> &'static str {
+        match self.constructor_id {
+            manual_tl::MessageContainer::CONSTRUCTOR_ID => "msg_container",
+            manual_tl::RpcResult::CONSTRUCTOR_ID => "rpc_result",
+            manual_tl::GzipPacked::CONSTRUCTOR_ID => "gzip_packed",
+            manual_tl::MessageCopy::CONSTRUCTOR_ID => "msg_copy",
+            id => tl::name_for_id(id),
+        }
+    }
+}
+
+impl fmt::Display for DumpedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Sent => "sent",
+            Direction::Received => "received",
+        };
+        write!(
+            f,
+            "{} msg_id={} seq_no={} {} ({} bytes):",
+            direction,
+            self.msg_id,
+            self.seq_no,
+            self.name(),
+            self.body.len()
+        )?;
+        for byte in self.body.iter() {
+            write!(f, " {:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the plaintext messages are recorded when the debug dump mode of [`super::Encrypted`]
+/// is enabled.
+///
+/// This is invaluable to diagnose deserialization errors or missing updates, but every message
+/// is recorded in full, so it should only be enabled while debugging.
+pub trait MessageDump: Send + Sync {
+    /// Record a message that was sent or received.
+    fn record(&self, message: &DumpedMessage);
+}
+
+/// A [`MessageDump`] keeping the last messages in memory.
+pub struct RingBufferDump {
+    capacity: usize,
+    messages: Mutex<VecDeque<DumpedMessage>>,
+}
+
+impl RingBufferDump {
+    /// Create a ring buffer which remembers up to `capacity` messages, forgetting the oldest.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// The messages recorded, from oldest to newest.
+    pub fn messages(&self) -> Vec<DumpedMessage> {
+        self.messages.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl MessageDump for RingBufferDump {
+    fn record(&self, message: &DumpedMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() == self.capacity {
+            messages.pop_front();
+        }
+        messages.push_back(message.clone());
+    }
+}
+
+/// A [`MessageDump`] writing every message to a file, one per line.
+pub struct FileDump {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl FileDump {
+    /// Open the file at the given path, creating it if needed, to append messages to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+}
+
+impl MessageDump for FileDump {
+    fn record(&self, message: &DumpedMessage) {
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", message) {
+            warn!("failed to write message to the dump file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(msg_id: i64) -> DumpedMessage {
+        DumpedMessage {
+            direction: Direction::Sent,
+            msg_id,
+            seq_no: 1,
+            constructor_id: manual_tl::MessageContainer::CONSTRUCTOR_ID,
+            body: vec![0xdc, 0xf8, 0xf1, 0x73],
+        }
+    }
+
+    #[test]
+    fn ring_buffer_forgets_oldest() {
+        let dump = RingBufferDump::new(2);
+        for msg_id in 1..=3 {
+            dump.record(&message(msg_id));
+        }
+        assert_eq!(dump.messages(), vec![message(2), message(3)]);
+    }
+
+    #[test]
+    fn display_includes_name_and_body() {
+        assert_eq!(
+            message(4).to_string(),
+            "sent msg_id=4 seq_no=1 msg_container (4 bytes): dc f8 f1 73"
+        );
+    }
+}
@@ -5,14 +5,20 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{Deserialization, DeserializeError, Mtp, RequestError};
+use super::{
+    BadMessageReason, Deserialization, DeserializeError, Direction, DumpedMessage, MessageDump,
+    Mtp, RequestError,
+};
 use crate::{manual_tl, MsgId};
 use getrandom::getrandom;
-use grammers_crypto::{decrypt_data_v2, encrypt_data_v2, AuthKey};
+use grammers_crypto::{
+    decrypt_data_v2, encrypt_data_v1, encrypt_data_v2, encrypt_data_v2_with_quick_ack, AuthKey,
+};
 use grammers_tl_types::{self as tl, Cursor, Deserializable, Identifiable, Serializable};
-use log::info;
+use log::{debug, info};
 use std::mem;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// How many future salts to fetch or have stored at a given time.
 ///
@@ -20,6 +26,17 @@ use std::time::{Instant, SystemTime, UNIX_EPOCH};
 /// code expects to fetch more salts when going from two to one.
 const NUM_FUTURE_SALTS: i32 = 64;
 
+/// How long a message sent can go without being acknowledged before asking the server about its
+/// state (and again, for as long as it's not acknowledged).
+const STATE_REQ_DELAY: Duration = Duration::from_secs(15);
+
+/// How many bytes of the messages sent which have not been acknowledged yet are kept, so that
+/// they can be re-sent as-is if the server did not receive them.
+///
+/// Past this, the bodies of the oldest messages are dropped, and they're sent again as new
+/// messages instead if needed.
+const MAX_UNACKED_BYTES: usize = 8 * 1024 * 1024;
+
 /// When switching to a different salt, how many seconds must have passed since its `valid_since`.
 ///
 /// Used to prevent small fluctuations in the system clock.
@@ -41,9 +58,28 @@ static UPDATE_IDS: [u32; 6] = [
 /// [`Mtp`]: struct.mtp.html
 /// [`Encrypted::build`]: fn.mtp.build.html
 pub struct Builder {
+    dump: Option<Arc<dyn MessageDump>>,
     time_offset: i32,
     first_salt: i64,
     compression_threshold: Option<usize>,
+    max_pending_acks: usize,
+    ack_delay: Duration,
+}
+
+/// A content-related message which was sent, kept until the server acknowledges it.
+struct SentMessage {
+    msg_id: i64,
+    seq_no: i32,
+    /// The body of the message, unless it was dropped to stay below [`MAX_UNACKED_BYTES`].
+    body: Option<Vec<u8>>,
+    /// When the message was last sent, or its state last asked about.
+    sent_at: Instant,
+    /// Whether the message should be re-sent with the next messages.
+    resend: bool,
+    /// Whether the message is in the buffer which has not been finalized yet.
+    in_buffer: bool,
+    /// The container in which the message was last sent, if any.
+    container_msg_id: Option<i64>,
 }
 
 /// An implementation of the [Mobile Transport Protocol] for ciphertext
@@ -51,6 +87,19 @@ pub struct Builder {
 ///
 /// [Mobile Transport Protocol]: https://core.telegram.or);
 pub struct Encrypted {
+    /// Content-related messages sent which the server has not acknowledged yet.
+    ///
+    /// Used to ask the server about their state if they go unacknowledged for too long, and to
+    /// re-send them with the same message identifier if the server did not receive them.
+    unacked: Vec<SentMessage>,
+
+    /// The size of the bodies kept in `unacked`.
+    unacked_bytes: usize,
+
+    /// The `msgs_state_req` sent which have not been answered yet, along with the messages
+    /// they asked about.
+    state_requests: Vec<(i64, Vec<i64>)>,
+
     /// The authorization key to use to encrypt payload.
     auth_key: AuthKey,
 
@@ -66,6 +115,11 @@ pub struct Encrypted {
     /// Used to accurately determine when salts become valid.
     start_salt_time: Option<(i32, Instant)>,
 
+    /// Whether a request for future salts has been sent and its response is still pending.
+    ///
+    /// Used to avoid asking for them more than once at a time.
+    salts_requested: bool,
+
     /// The secure, random identifier for this instance.
     client_id: i64,
 
@@ -84,6 +138,17 @@ pub struct Encrypted {
     /// [Content-related Message]: https://core.telegram.org/mtproto/description#content-related-message
     pending_ack: Vec<i64>,
 
+    /// When the oldest message in `pending_ack` was received, if any.
+    ack_since: Option<Instant>,
+
+    /// How many messages can be waiting to be acknowledged before doing so without waiting for
+    /// further requests to send.
+    max_pending_acks: usize,
+
+    /// How long messages can be waiting to be acknowledged before doing so without waiting for
+    /// further requests to send.
+    ack_delay: Duration,
+
     /// If present, the threshold in bytes at which a message will be
     /// considered large enough to attempt compressing it. Otherwise,
     /// outgoing messages will never be compressed.
@@ -95,6 +160,27 @@ pub struct Encrypted {
     /// Temporary updates that came in a response.
     updates: Vec<Vec<u8>>,
 
+    /// The `destroy_session` requests sent, along with the session they're destroying.
+    ///
+    /// Their response is not wrapped in a `rpc_result`, so this is needed to match them.
+    destroy_session_requests: Vec<(i64, MsgId)>,
+
+    /// The `destroy_auth_key` request sent, if any, whose response is not wrapped either.
+    destroy_auth_key_request: Option<MsgId>,
+
+    /// Messages in the buffer which the server should quickly acknowledge once it receives them.
+    quick_ack_requests: Vec<MsgId>,
+
+    /// The quick acknowledgement tokens the server may send, along with the messages they
+    /// acknowledge. Forgotten once the messages are acknowledged normally.
+    quick_acks: Vec<(u32, Vec<MsgId>)>,
+
+    /// Whether the last finalized buffer asked for a quick acknowledgement.
+    wants_quick_ack: bool,
+
+    /// Where to record every plaintext message sent and received, if anywhere.
+    dump: Option<Arc<dyn MessageDump>>,
+
     /// Buffer were requests are pushed to.
     buffer: Vec<u8>,
 
@@ -120,6 +206,21 @@ impl Builder {
         self
     }
 
+    /// Configures how many messages can be waiting to be acknowledged, and for how long, before
+    /// the acknowledgements are sent on their own rather than along with the next request.
+    pub fn ack_batching(mut self, max_pending_acks: usize, ack_delay: Duration) -> Self {
+        self.max_pending_acks = max_pending_acks;
+        self.ack_delay = ack_delay;
+        self
+    }
+
+    /// Enables the debug dump mode, in which every plaintext message sent and received is
+    /// recorded in the given [`MessageDump`].
+    pub fn dump(mut self, dump: Arc<dyn MessageDump>) -> Self {
+        self.dump = Some(dump);
+        self
+    }
+
     /// Finishes the builder and returns the `MTProto` instance with all
     /// the configuration changes applied.
     pub fn finish(self, auth_key: [u8; 256]) -> Encrypted {
@@ -132,6 +233,7 @@ impl Builder {
                 salt: self.first_salt,
             }],
             start_salt_time: None,
+            salts_requested: false,
             client_id: {
                 let mut buffer = [0u8; 8];
                 getrandom(&mut buffer).expect("failed to generate a secure client_id");
@@ -140,9 +242,21 @@ impl Builder {
             sequence: 0,
             last_msg_id: 0,
             pending_ack: vec![],
+            ack_since: None,
+            max_pending_acks: self.max_pending_acks,
+            ack_delay: self.ack_delay,
             compression_threshold: self.compression_threshold,
             rpc_results: Vec::new(),
             updates: Vec::new(),
+            destroy_session_requests: Vec::new(),
+            destroy_auth_key_request: None,
+            quick_ack_requests: Vec::new(),
+            quick_acks: Vec::new(),
+            wants_quick_ack: false,
+            unacked: Vec::new(),
+            unacked_bytes: 0,
+            state_requests: Vec::new(),
+            dump: self.dump,
             buffer: Vec::new(),
             msg_count: 0,
         }
@@ -153,8 +267,11 @@ impl Encrypted {
     /// Start building a new encrypted MTP.
     pub fn build() -> Builder {
         Builder {
+            dump: None,
             time_offset: 0,
             compression_threshold: crate::DEFAULT_COMPRESSION_THRESHOLD,
+            max_pending_acks: crate::DEFAULT_MAX_PENDING_ACKS,
+            ack_delay: crate::DEFAULT_ACK_DELAY,
             first_salt: 0,
         }
     }
@@ -164,15 +281,206 @@ impl Encrypted {
         self.auth_key.to_bytes()
     }
 
+    /// The difference, in seconds, between the server's clock and ours, as estimated from the
+    /// messages received from the server.
+    pub fn time_offset(&self) -> i32 {
+        self.time_offset
+    }
+
+    /// Changes the compression threshold for outgoing messages, as with
+    /// [`Builder::compression_threshold`]. Messages already pushed are not affected.
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Changes how acknowledgements are batched, as with [`Builder::ack_batching`].
+    pub fn set_ack_batching(&mut self, max_pending_acks: usize, ack_delay: Duration) {
+        self.max_pending_acks = max_pending_acks;
+        self.ack_delay = ack_delay;
+    }
+
+    /// Enables or disables the debug dump mode, as with [`Builder::dump`].
+    pub fn set_dump(&mut self, dump: Option<Arc<dyn MessageDump>>) {
+        self.dump = dump;
+    }
+
+    /// Pushes an `auth.bindTempAuthKey` request into the internal buffer, binding the
+    /// (temporary) authorization key used by this instance to the given permanent key until
+    /// `expires_at`. If the buffer is full, returns `None`.
+    ///
+    /// The request is built here because it must contain its own message ID, encrypted with the
+    /// permanent key. Both the message ID and the body of the request are returned.
+    pub fn push_bind_temp_auth_key(
+        &mut self,
+        perm_auth_key: [u8; 256],
+        expires_at: i32,
+    ) -> Option<(MsgId, Vec<u8>)> {
+        self.push_service_messages();
+
+        if self.msg_count == manual_tl::MessageContainer::MAXIMUM_LENGTH {
+            return None;
+        }
+
+        let perm_auth_key = AuthKey::from_bytes(perm_auth_key);
+        let perm_auth_key_id = i64::from_le_bytes(perm_auth_key.key_id());
+        let (random, nonce) = {
+            let mut buffer = [0; 16 + 8];
+            getrandom(&mut buffer).expect("failed to generate a secure nonce");
+            let mut nonce = [0; 8];
+            nonce.copy_from_slice(&buffer[16..]);
+            (buffer, i64::from_le_bytes(nonce))
+        };
+
+        let msg_id = self.get_new_msg_id();
+        let inner = tl::enums::BindAuthKeyInner::Inner(tl::types::BindAuthKeyInner {
+            nonce,
+            temp_auth_key_id: i64::from_le_bytes(self.auth_key.key_id()),
+            perm_auth_key_id,
+            temp_session_id: self.client_id,
+            expires_at,
+        })
+        .to_bytes();
+
+        // The message is serialized as usual, but with random data instead of the salt and
+        // session ID, and using the same message ID as the request that contains it.
+        let mut message = Vec::with_capacity(HEADER_LEN + manual_tl::Message::SIZE_OVERHEAD);
+        message.extend_from_slice(&random[..16]);
+        msg_id.serialize(&mut message);
+        0i32.serialize(&mut message);
+        (inner.len() as i32).serialize(&mut message);
+        message.extend_from_slice(&inner);
+
+        let body = tl::functions::auth::BindTempAuthKey {
+            perm_auth_key_id,
+            nonce,
+            expires_at,
+            encrypted_message: encrypt_data_v1(&message, &perm_auth_key),
+        }
+        .to_bytes();
+
+        let new_size = self.buffer.len() + body.len() + manual_tl::Message::SIZE_OVERHEAD;
+        if new_size >= manual_tl::MessageContainer::MAXIMUM_SIZE {
+            return None;
+        }
+
+        Some((self.serialize_msg_with_id(msg_id, &body, true), body))
+    }
+
+    /// Pushes the messages that need to be sent along with the requests, such as
+    /// acknowledgements, into the internal buffer.
+    fn push_service_messages(&mut self) {
+        if self.buffer.is_empty() {
+            // First push, reserve enough space for `finalize`.
+            self.buffer.resize(HEADER_LEN + CONTAINER_HEADER_LEN, 0);
+        }
+
+        // If we need to acknowledge messages, this notification goes in with the rest of requests
+        // so that we can also include it. It has priority over user requests because these should
+        // be sent out as soon as possible.
+        if !self.pending_ack.is_empty() {
+            // TODO avoid to_bytes here, serialize it in-place
+            let body = tl::enums::MsgsAck::Ack(tl::types::MsgsAck {
+                msg_ids: mem::take(&mut self.pending_ack),
+            })
+            .to_bytes();
+            self.ack_since = None;
+            self.serialize_msg(&body, false);
+        }
+
+        // Re-send the messages the server did not receive as-is, and ask about those which have
+        // gone unacknowledged for too long.
+        let now = Instant::now();
+        let mut unknown_state = Vec::new();
+        for msg in self.unacked.iter_mut() {
+            if msg.resend {
+                // Messages are only re-sent as-is while their body is kept.
+                let body = msg.body.as_ref().unwrap();
+                let new_size = self.buffer.len() + body.len() + manual_tl::Message::SIZE_OVERHEAD;
+                if new_size >= manual_tl::MessageContainer::MAXIMUM_SIZE
+                    || self.msg_count + 1 >= manual_tl::MessageContainer::MAXIMUM_LENGTH
+                {
+                    // Wait for the next container.
+                    continue;
+                }
+                msg.msg_id.serialize(&mut self.buffer);
+                msg.seq_no.serialize(&mut self.buffer);
+                (body.len() as i32).serialize(&mut self.buffer);
+                self.buffer.extend_from_slice(body);
+                self.msg_count += 1;
+                msg.resend = false;
+                msg.in_buffer = true;
+                msg.sent_at = now;
+            } else if now >= msg.sent_at + STATE_REQ_DELAY {
+                unknown_state.push(msg.msg_id);
+                msg.sent_at = now;
+            }
+        }
+        if !unknown_state.is_empty() {
+            info!(
+                "asking for the state of {} unacknowledged messages",
+                unknown_state.len()
+            );
+            let body = tl::enums::MsgsStateReq::Req(tl::types::MsgsStateReq {
+                msg_ids: unknown_state.clone(),
+            })
+            .to_bytes();
+            let MsgId(req_msg_id) = self.serialize_msg(&body, false);
+            self.state_requests.push((req_msg_id, unknown_state));
+        }
+
+        // Check to see if the next salt can be used already. If it can, drop the current one and,
+        // if the next salt is the last one, fetch more.
+        if let Some((start_secs, start_instant)) = self.start_salt_time {
+            let next = self.salts.len().checked_sub(2).map(|i| &self.salts[i]);
+            if let Some(salt) = next {
+                let now = start_secs + start_instant.elapsed().as_secs() as i32;
+                if now >= salt.valid_since + SALT_USE_DELAY {
+                    self.salts.pop();
+                    if self.salts.len() == 1 && !self.salts_requested {
+                        info!("only one future salt remaining; asking for more salts");
+                        let body = tl::functions::GetFutureSalts {
+                            num: NUM_FUTURE_SALTS,
+                        }
+                        .to_bytes();
+                        self.serialize_msg(&body, true);
+                        self.salts_requested = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pushes a request for future salts into the internal buffer, unless one is pending already.
+    ///
+    /// Returns `true` if the request was pushed or was already pending.
+    fn request_future_salts(&mut self) -> bool {
+        if !self.salts_requested {
+            self.salts_requested = self
+                .push(
+                    &tl::functions::GetFutureSalts {
+                        num: NUM_FUTURE_SALTS,
+                    }
+                    .to_bytes(),
+                )
+                .is_some();
+        }
+        self.salts_requested
+    }
+
     /// Correct our time offset based on a known valid message ID.
     fn correct_time_offset(&mut self, msg_id: i64) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("system time is before epoch")
-            .as_secs() as i32;
+        self.time_offset = time_offset_of(msg_id);
+    }
 
-        let correct = (msg_id >> 32) as i32;
-        self.time_offset = correct - now;
+    /// Correct our time offset based on a message ID received from the server, but only if it
+    /// moves the offset forward.
+    ///
+    /// Messages may arrive late (for example, when they're re-sent), in which case the time in
+    /// their ID is outdated, and correcting the offset back to it would make the server reject
+    /// our messages for being too old. An offset which got too far ahead is instead corrected
+    /// when the server rejects our messages for being too new.
+    fn advance_time_offset(&mut self, msg_id: i64) {
+        self.time_offset = self.time_offset.max(time_offset_of(msg_id));
     }
 
     /// Generates a new unique message ID based on the current
@@ -207,18 +515,45 @@ impl Encrypted {
 
     fn serialize_msg(&mut self, body: &[u8], content_related: bool) -> MsgId {
         let msg_id = self.get_new_msg_id();
+        self.serialize_msg_with_id(msg_id, body, content_related)
+    }
 
+    /// `serialize_msg`, but with a message ID which was already generated.
+    fn serialize_msg_with_id(&mut self, msg_id: i64, body: &[u8], content_related: bool) -> MsgId {
+        let seq_no = self.get_seq_no(content_related);
         msg_id.serialize(&mut self.buffer);
-        self.get_seq_no(content_related).serialize(&mut self.buffer);
+        seq_no.serialize(&mut self.buffer);
         (body.len() as i32).serialize(&mut self.buffer);
         self.buffer.extend_from_slice(body);
 
+        if content_related {
+            self.unacked.push(SentMessage {
+                msg_id,
+                seq_no,
+                body: Some(body.to_vec()),
+                sent_at: Instant::now(),
+                resend: false,
+                in_buffer: true,
+                container_msg_id: None,
+            });
+            self.unacked_bytes += body.len();
+            self.trim_unacked();
+        }
+
         self.msg_count += 1;
         MsgId(msg_id)
     }
 
     /// `finalize`, but without encryption.
     fn finalize_plain(&mut self) -> Vec<u8> {
+        if self.msg_count == 0
+            && self
+                .flush_deadline()
+                .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            self.push_service_messages();
+        }
+
         if self.msg_count == 0 {
             return Vec::new();
         }
@@ -240,12 +575,15 @@ impl Encrypted {
             self.buffer[0..tmp.len()].copy_from_slice(&tmp)
         }
 
+        let mut container_msg_id = None;
         if self.msg_count != 1 {
             // Give the container its message ID and sequence number.
             let mut tmp = Vec::with_capacity(CONTAINER_HEADER_LEN);
 
             // Manually `serialize_msg` because the container body was already written.
-            self.get_new_msg_id().serialize(&mut tmp);
+            let msg_id = self.get_new_msg_id();
+            container_msg_id = Some(msg_id);
+            msg_id.serialize(&mut tmp);
             self.get_seq_no(false).serialize(&mut tmp);
 
             // + 8 because it has to include the constructor ID and length (4 bytes each).
@@ -257,13 +595,62 @@ impl Encrypted {
             self.buffer[HEADER_LEN..HEADER_LEN + CONTAINER_HEADER_LEN].copy_from_slice(&tmp);
         }
 
+        // Remember the container of each message, in case the server complains about it.
+        for msg in self.unacked.iter_mut().filter(|msg| msg.in_buffer) {
+            msg.in_buffer = false;
+            msg.container_msg_id = container_msg_id;
+        }
+
         self.msg_count = 0;
+        self.dump_sent(&self.buffer[HEADER_LEN..]);
         mem::take(&mut self.buffer)
     }
 
+    /// Record the plaintext messages about to be sent, including those inside a container.
+    fn dump_sent(&self, buffer: &[u8]) {
+        let dump = match &self.dump {
+            Some(dump) => dump,
+            None => return,
+        };
+        let message = match manual_tl::Message::from_bytes(buffer) {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+        dump.record(&DumpedMessage::new(Direction::Sent, &message));
+        if message.constructor_id() == Ok(manual_tl::MessageContainer::CONSTRUCTOR_ID) {
+            if let Ok(container) = manual_tl::MessageContainer::from_bytes(&message.body) {
+                for message in container.messages.iter() {
+                    dump.record(&DumpedMessage::new(Direction::Sent, message));
+                }
+            }
+        }
+    }
+
+    /// When the messages waiting to be acknowledged should be sent, if any.
+    fn ack_deadline(&self) -> Option<Instant> {
+        let since = self.ack_since?;
+        if self.pending_ack.len() >= self.max_pending_acks {
+            Some(since)
+        } else {
+            Some(since + self.ack_delay)
+        }
+    }
+
+    /// Remember that the message has to be acknowledged.
+    fn ack(&mut self, msg_id: i64) {
+        if self.pending_ack.is_empty() {
+            self.ack_since = Some(Instant::now());
+        }
+        self.pending_ack.push(msg_id);
+    }
+
     fn process_message(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
+        if let Some(dump) = &self.dump {
+            dump.record(&DumpedMessage::new(Direction::Received, &message));
+        }
+
         if message.requires_ack() {
-            self.pending_ack.push(message.msg_id);
+            self.ack(message.msg_id);
         }
 
         // Handle all the possible Service Messages:
@@ -304,6 +691,12 @@ impl Encrypted {
             // Request to Destroy Session
             tl::types::DestroySessionOk::CONSTRUCTOR_ID
             | tl::types::DestroySessionNone::CONSTRUCTOR_ID => self.handle_destroy_session(message),
+            // Request to Destroy Authorization Key
+            tl::types::DestroyAuthKeyOk::CONSTRUCTOR_ID
+            | tl::types::DestroyAuthKeyNone::CONSTRUCTOR_ID
+            | tl::types::DestroyAuthKeyFail::CONSTRUCTOR_ID => {
+                self.handle_destroy_auth_key(message)
+            }
             // New Session Creation Notification
             tl::types::NewSessionCreated::CONSTRUCTOR_ID => {
                 self.handle_new_session_created(message)
@@ -407,6 +800,7 @@ impl Encrypted {
         let inner_constructor = rpc_result.inner_constructor();
         let manual_tl::RpcResult { req_msg_id, result } = rpc_result;
         let msg_id = MsgId(req_msg_id);
+        self.forget_sent(req_msg_id);
 
         // Any error during a RPC result will be given to the user,
         // which means this method itself is doing its job `Ok`.
@@ -429,17 +823,20 @@ impl Encrypted {
             )),
 
             // Cancellation of an RPC Query
-            tl::types::RpcAnswerUnknown::CONSTRUCTOR_ID => {
-                // The `msg_id` corresponds to the `rpc_drop_answer` request.
-            }
-            tl::types::RpcAnswerDroppedRunning::CONSTRUCTOR_ID => {
-                // We will receive two `rpc_result`, one with the `msg_id` of
-                // `rpc_drop_answer` request and other for the original RPC.
-            }
-            tl::types::RpcAnswerDropped::CONSTRUCTOR_ID => {
-                // "the RPC response was removed from the server's outgoing
-                // queue, and its msg_id, seq_no, and length in bytes are
-                // transmitted to the client."
+            //
+            // The `msg_id` corresponds to the `rpc_drop_answer` request, which is answered like
+            // any other so that whoever sent it can forget about it.
+            //
+            // With `rpc_answer_dropped_running`, we will receive two `rpc_result`, one with the
+            // `msg_id` of `rpc_drop_answer` request and other for the original RPC.
+            //
+            // With `rpc_answer_dropped`, "the RPC response was removed from the server's
+            // outgoing queue, and its msg_id, seq_no, and length in bytes are transmitted to
+            // the client."
+            tl::types::RpcAnswerUnknown::CONSTRUCTOR_ID
+            | tl::types::RpcAnswerDroppedRunning::CONSTRUCTOR_ID
+            | tl::types::RpcAnswerDropped::CONSTRUCTOR_ID => {
+                self.rpc_results.push((msg_id, Ok(result)));
             }
 
             // Response to an RPC query
@@ -516,9 +913,12 @@ impl Encrypted {
     /// transmits a stand-alone acknowledgment.
     ///
     /// [Acknowledgment of Receipt]: https://core.telegram.org/mtproto/service_messages_about_messages#acknowledgment-of-receipt
-    fn handle_ack(&self, message: manual_tl::Message) -> Result<(), DeserializeError> {
+    fn handle_ack(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
         // TODO notify about this somehow
-        let _ack = tl::enums::MsgsAck::from_bytes(&message.body)?;
+        let tl::enums::MsgsAck::Ack(ack) = tl::enums::MsgsAck::from_bytes(&message.body)?;
+        for msg_id in ack.msg_ids {
+            self.forget_sent(msg_id);
+        }
         Ok(())
     }
 
@@ -596,10 +996,7 @@ impl Encrypted {
         let bad_msg = match bad_msg {
             tl::enums::BadMsgNotification::Notification(x) => x,
             tl::enums::BadMsgNotification::BadServerSalt(x) => {
-                self.rpc_results.push((
-                    MsgId(x.bad_msg_id),
-                    Err(RequestError::BadMessage { code: x.error_code }),
-                ));
+                self.fail_bad_message(x.bad_msg_id, x.error_code);
 
                 self.salts.clear();
                 self.salts.push(tl::types::FutureSalt {
@@ -609,15 +1006,9 @@ impl Encrypted {
                 });
 
                 // Try enqueuing a request to get future salts, in order to prevent this from happening for longer.
-                if self
-                    .push(
-                        &tl::functions::GetFutureSalts {
-                            num: NUM_FUTURE_SALTS,
-                        }
-                        .to_bytes(),
-                    )
-                    .is_some()
-                {
+                // Any request pending was likely sent with the bad salt too, and won't be resent.
+                self.salts_requested = false;
+                if self.request_future_salts() {
                     info!("got bad salt; asking for more salts");
                 } else {
                     info!("got bad salt, but cannot ask for future salts because buffer is full");
@@ -627,12 +1018,7 @@ impl Encrypted {
             }
         };
 
-        self.rpc_results.push((
-            MsgId(bad_msg.bad_msg_id),
-            Err(RequestError::BadMessage {
-                code: bad_msg.error_code,
-            }),
-        ));
+        self.fail_bad_message(bad_msg.bad_msg_id, bad_msg.error_code);
         match bad_msg.error_code {
             16 => {
                 // Sent `msg_id` was too low (our `time_offset` is wrong).
@@ -660,6 +1046,34 @@ impl Encrypted {
         Ok(())
     }
 
+    /// Report every message affected by a bad message notification as failed, so that they're
+    /// sent again as new messages.
+    ///
+    /// If the message is a container, none of the messages it contained were processed either.
+    fn fail_bad_message(&mut self, bad_msg_id: i64, code: i32) {
+        let mut affected = self
+            .unacked
+            .iter()
+            .filter(|msg| msg.container_msg_id == Some(bad_msg_id))
+            .map(|msg| msg.msg_id)
+            .collect::<Vec<_>>();
+        if affected.is_empty() {
+            affected.push(bad_msg_id);
+        }
+
+        info!(
+            "message {} ignored by the server ({}); {} messages affected",
+            bad_msg_id,
+            BadMessageReason::from(code),
+            affected.len()
+        );
+        for msg_id in affected {
+            self.forget_sent(msg_id);
+            self.rpc_results
+                .push((MsgId(msg_id), Err(RequestError::BadMessage { code })));
+        }
+    }
+
     /// **[Request for Message Status Information]**
     ///
     /// If either party has not received information on the status of its
@@ -712,11 +1126,103 @@ impl Encrypted {
     /// valid, the message is to be wrapped in `msg_copy`).
     ///
     /// [Informational Message regarding Status of Messages]: https://core.telegram.org/mtproto/service_messages_about_messages#informational-message-regarding-status-of-messages
-    fn handle_state_info(&mut self, _message: manual_tl::Message) -> Result<(), DeserializeError> {
-        // TODO implement
+    fn handle_state_info(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
+        let tl::enums::MsgsStateInfo::Info(state_info) =
+            tl::enums::MsgsStateInfo::from_bytes(&message.body)?;
+
+        match self
+            .state_requests
+            .iter()
+            .position(|(req_msg_id, _)| *req_msg_id == state_info.req_msg_id)
+        {
+            Some(i) => {
+                let (_, msg_ids) = self.state_requests.remove(i);
+                for (msg_id, state) in msg_ids.into_iter().zip(state_info.info) {
+                    self.handle_msg_state(msg_id, state);
+                }
+            }
+            None => info!(
+                "got state info for unknown request {}",
+                state_info.req_msg_id
+            ),
+        }
         Ok(())
     }
 
+    /// Act on the state of a message sent, as reported by the server.
+    fn handle_msg_state(&mut self, msg_id: i64, state: u8) {
+        match state & 7 {
+            // Too old to be re-sent as-is, so it has to be sent again as a new message.
+            1 if self.forget_sent(msg_id) => {
+                info!("server forgot about message {}; sending it again", msg_id);
+                self.rpc_results
+                    .push((MsgId(msg_id), Err(RequestError::BadMessage { code: 20 })));
+            }
+            2 | 3 if self.resend_sent(msg_id) => {
+                info!("server did not receive message {}; re-sending it", msg_id);
+            }
+            4 => {
+                self.forget_sent(msg_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Stop keeping track of a message sent, once the server is known to have received it.
+    ///
+    /// Returns `true` if the message was being tracked.
+    fn forget_sent(&mut self, msg_id: i64) -> bool {
+        self.quick_acks.retain_mut(|(_, msg_ids)| {
+            msg_ids.retain(|id| id.0 != msg_id);
+            !msg_ids.is_empty()
+        });
+        match self.unacked.iter().position(|msg| msg.msg_id == msg_id) {
+            Some(i) => {
+                let msg = self.unacked.remove(i);
+                self.unacked_bytes -= msg.body.map_or(0, |body| body.len());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-send a message sent with the same message identifier, or as a new message if its body
+    /// was dropped (in which case it's reported as failed, so that it's sent again).
+    ///
+    /// Returns `false` if the message is unknown.
+    fn resend_sent(&mut self, msg_id: i64) -> bool {
+        match self.unacked.iter_mut().find(|msg| msg.msg_id == msg_id) {
+            Some(msg) if msg.body.is_some() => {
+                msg.resend = true;
+                true
+            }
+            Some(_) => {
+                self.forget_sent(msg_id);
+                self.rpc_results
+                    .push((MsgId(msg_id), Err(RequestError::BadMessage { code: 20 })));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop the bodies of the oldest messages sent until the rest fit in [`MAX_UNACKED_BYTES`].
+    ///
+    /// Messages waiting to be re-sent keep their body, as it's about to be used.
+    fn trim_unacked(&mut self) {
+        for msg in self.unacked.iter_mut() {
+            if self.unacked_bytes <= MAX_UNACKED_BYTES {
+                break;
+            }
+            if msg.resend {
+                continue;
+            }
+            if let Some(body) = msg.body.take() {
+                self.unacked_bytes -= body.len();
+            }
+        }
+    }
+
     /// **[Voluntary Communication of Status of Messages]**
     ///
     /// Either party may voluntarily inform the other party of the status of
@@ -734,8 +1240,13 @@ impl Encrypted {
     /// This message does not require an acknowledgment.
     ///
     /// [Voluntary Communication of Status of Messages]: https://core.telegram.org/mtproto/service_messages_about_messages#voluntary-communication-of-status-of-messages
-    fn handle_msg_all(&mut self, _message: manual_tl::Message) -> Result<(), DeserializeError> {
-        // TODO implement
+    fn handle_msg_all(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
+        let tl::enums::MsgsAllInfo::Info(all_info) =
+            tl::enums::MsgsAllInfo::from_bytes(&message.body)?;
+
+        for (msg_id, state) in all_info.msg_ids.into_iter().zip(all_info.info) {
+            self.handle_msg_state(msg_id, state);
+        }
         Ok(())
     }
 
@@ -771,10 +1282,11 @@ impl Encrypted {
         let msg_detailed = tl::enums::MsgDetailedInfo::from_bytes(&message.body)?;
         match msg_detailed {
             tl::enums::MsgDetailedInfo::Info(x) => {
-                self.pending_ack.push(x.answer_msg_id);
+                self.forget_sent(x.msg_id);
+                self.ack(x.answer_msg_id);
             }
             tl::enums::MsgDetailedInfo::MsgNewDetailedInfo(x) => {
-                self.pending_ack.push(x.answer_msg_id);
+                self.ack(x.answer_msg_id);
             }
         }
         Ok(())
@@ -808,9 +1320,18 @@ impl Encrypted {
     ///
     /// [Explicit Request to Re-Send Answers]: https://core.telegram.org/mtproto/service_messages_about_messages#explicit-request-to-re-send-answers
     /// [Explicit Request to Re-Send Messages]: https://core.telegram.org/mtproto/service_messages_about_messages#explicit-request-to-re-send-messages
-    fn handle_msg_resend(&self, _message: manual_tl::Message) -> Result<(), DeserializeError> {
-        // TODO implement
+    fn handle_msg_resend(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
         // `msg_resend_ans_req` seems to never occur (it was even missing from `mtproto.tl`)
+        let msg_ids = match tl::enums::MsgResendReq::from_bytes(&message.body)? {
+            tl::enums::MsgResendReq::Req(x) => x.msg_ids,
+            tl::enums::MsgResendReq::MsgResendAnsReq(_) => return Ok(()),
+        };
+
+        for msg_id in msg_ids {
+            if !self.resend_sent(msg_id) {
+                info!("cannot re-send message {} because it's unknown", msg_id);
+            }
+        }
         Ok(())
     }
 
@@ -840,10 +1361,12 @@ impl Encrypted {
         let tl::enums::FutureSalts::Salts(salts) =
             tl::enums::FutureSalts::from_bytes(&message.body)?;
 
+        self.forget_sent(salts.req_msg_id);
         self.rpc_results
             .push((MsgId(salts.req_msg_id), Ok(message.body)));
 
         self.start_salt_time = Some((salts.now, Instant::now()));
+        self.salts_requested = false;
         self.salts = salts.salts.0;
         self.salts.sort_by_key(|salt| -salt.valid_since);
         info!("got {} future salts", self.salts.len());
@@ -888,6 +1411,7 @@ impl Encrypted {
     /// [Deferred Connection Closure + PING]: https://core.telegram.org/mtproto/service_messages#deferred-connection-closure-ping
     fn handle_pong(&mut self, message: manual_tl::Message) -> Result<(), DeserializeError> {
         let tl::enums::Pong::Pong(pong) = tl::enums::Pong::from_bytes(&message.body)?;
+        self.forget_sent(pong.msg_id);
 
         self.rpc_results
             .push((MsgId(pong.msg_id), Ok(message.body)));
@@ -908,11 +1432,75 @@ impl Encrypted {
     /// ```
     ///
     /// [Request to Destroy Session]: https://core.telegram.org/mtproto/service_messages#request-to-destroy-session
-    fn handle_destroy_session(&self, _message: manual_tl::Message) -> Result<(), DeserializeError> {
-        // TODO implement
+    fn handle_destroy_session(
+        &mut self,
+        message: manual_tl::Message,
+    ) -> Result<(), DeserializeError> {
+        let session_id = match tl::enums::DestroySessionRes::from_bytes(&message.body)? {
+            tl::enums::DestroySessionRes::DestroySessionOk(x) => x.session_id,
+            tl::enums::DestroySessionRes::DestroySessionNone(x) => x.session_id,
+        };
+
+        match self
+            .destroy_session_requests
+            .iter()
+            .position(|(id, _)| *id == session_id)
+        {
+            Some(i) => {
+                let (_, msg_id) = self.destroy_session_requests.remove(i);
+                self.forget_sent(msg_id.0);
+                self.rpc_results.push((msg_id, Ok(message.body)));
+            }
+            None => info!(
+                "got destroy session result for unknown session {}",
+                session_id
+            ),
+        }
         Ok(())
     }
 
+    /// **Request to Destroy Authorization Key**
+    ///
+    /// Used by the client to ask the server to forget the authorization key used by the
+    /// current connection, after which it can no longer be used.
+    ///
+    /// ```tl
+    /// destroy_auth_key#d1435160 = DestroyAuthKeyRes;
+    /// destroy_auth_key_ok#f660e1d4 = DestroyAuthKeyRes;
+    /// destroy_auth_key_none#0a9f2259 = DestroyAuthKeyRes;
+    /// destroy_auth_key_fail#ea109b13 = DestroyAuthKeyRes;
+    /// ```
+    fn handle_destroy_auth_key(
+        &mut self,
+        message: manual_tl::Message,
+    ) -> Result<(), DeserializeError> {
+        match self.destroy_auth_key_request.take() {
+            Some(msg_id) => {
+                self.forget_sent(msg_id.0);
+                self.rpc_results.push((msg_id, Ok(message.body)));
+            }
+            None => info!("got destroy auth key result but no such request was sent"),
+        }
+        Ok(())
+    }
+
+    /// Remember the requests whose response is not wrapped in a `rpc_result`, so that it can be
+    /// matched with them once it arrives.
+    fn track_unwrapped_request(&mut self, request: &[u8], msg_id: MsgId) {
+        match u32::from_le_bytes([request[0], request[1], request[2], request[3]]) {
+            tl::functions::DestroySession::CONSTRUCTOR_ID if request.len() >= 12 => {
+                let mut session_id = [0; 8];
+                session_id.copy_from_slice(&request[4..12]);
+                self.destroy_session_requests
+                    .push((i64::from_le_bytes(session_id), msg_id));
+            }
+            tl::functions::DestroyAuthKey::CONSTRUCTOR_ID => {
+                self.destroy_auth_key_request = Some(msg_id);
+            }
+            _ => {}
+        }
+    }
+
     /// **[New Session Creation Notification]**
     ///
     /// The server notifies the client that a new session (from the server's
@@ -1136,41 +1724,7 @@ impl Mtp for Encrypted {
     fn push(&mut self, request: &[u8]) -> Option<MsgId> {
         // TODO rather than taking in bytes, take requests, serialize them in place, and if too large drop the last part of the buffer
 
-        if self.buffer.is_empty() {
-            // First push, reserve enough space for `finalize`.
-            self.buffer.resize(HEADER_LEN + CONTAINER_HEADER_LEN, 0);
-        }
-
-        // If we need to acknowledge messages, this notification goes in with the rest of requests
-        // so that we can also include it. It has priority over user requests because these should
-        // be sent out as soon as possible.
-        if !self.pending_ack.is_empty() {
-            // TODO avoid to_bytes here, serialize it in-place
-            let body = tl::enums::MsgsAck::Ack(tl::types::MsgsAck {
-                msg_ids: mem::take(&mut self.pending_ack),
-            })
-            .to_bytes();
-            self.serialize_msg(&body, false);
-        }
-
-        // Check to see if the next salt can be used already. If it can, drop the current one and,
-        // if the next salt is the last one, fetch more.
-        if let Some((start_secs, start_instant)) = self.start_salt_time {
-            if let Some(salt) = self.salts.get(self.salts.len() - 2) {
-                let now = start_secs + start_instant.elapsed().as_secs() as i32;
-                if now >= salt.valid_since + SALT_USE_DELAY {
-                    self.salts.pop();
-                    if self.salts.len() == 1 {
-                        info!("only one future salt remaining; asking for more salts");
-                        let body = tl::functions::GetFutureSalts {
-                            num: NUM_FUTURE_SALTS,
-                        }
-                        .to_bytes();
-                        self.serialize_msg(&body, true);
-                    }
-                }
-            }
-        }
+        self.push_service_messages();
 
         // Serialize `MAXIMUM_LENGTH` requests at most.
         if self.msg_count == manual_tl::MessageContainer::MAXIMUM_LENGTH {
@@ -1207,13 +1761,29 @@ impl Mtp for Encrypted {
         }
 
         // This request still fits in the container, so give it a message ID.
-        Some(self.serialize_msg(body, true))
+        let msg_id = self.serialize_msg(body, true);
+        self.track_unwrapped_request(request, msg_id);
+        Some(msg_id)
+    }
+
+    fn request_quick_ack(&mut self, msg_id: MsgId) {
+        self.quick_ack_requests.push(msg_id);
+    }
+
+    fn wants_quick_ack(&self) -> bool {
+        self.wants_quick_ack
     }
 
     fn finalize(&mut self) -> Vec<u8> {
         let buffer = self.finalize_plain();
+        self.wants_quick_ack = !buffer.is_empty() && !self.quick_ack_requests.is_empty();
         if buffer.is_empty() {
             buffer
+        } else if self.wants_quick_ack {
+            let (ciphertext, token) = encrypt_data_v2_with_quick_ack(&buffer, &self.auth_key);
+            self.quick_acks
+                .push((token, mem::take(&mut self.quick_ack_requests)));
+            ciphertext
         } else {
             encrypt_data_v2(&buffer, &self.auth_key)
         }
@@ -1221,9 +1791,26 @@ impl Mtp for Encrypted {
 
     /// Processes an encrypted response from the server.
     fn deserialize(&mut self, payload: &[u8]) -> Result<Deserialization, DeserializeError> {
+        // Quick acknowledgements are sent on their own, with the token that was expected.
+        if payload.len() == 4 {
+            let token = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            if let Some(i) = self.quick_acks.iter().position(|(t, _)| *t == token) {
+                let (_, msg_ids) = self.quick_acks.remove(i);
+                debug!("got quick acknowledgement for {:?}", msg_ids);
+                return Ok(Deserialization {
+                    rpc_results: Vec::new(),
+                    updates: Vec::new(),
+                    quick_acks: msg_ids,
+                });
+            }
+        }
+
         crate::utils::check_message_buffer(payload)?;
 
-        let plaintext = decrypt_data_v2(payload, &self.auth_key)?;
+        // The encrypted data is made of 16-byte blocks after the key identifier and message key,
+        // so anything beyond that is random padding added by the transport.
+        let padding = payload.len().saturating_sub(24) % 16;
+        let plaintext = decrypt_data_v2(&payload[..payload.len() - padding], &self.auth_key)?;
         let mut buffer = Cursor::from_slice(&plaintext[..]);
 
         let _salt = i64::deserialize(&mut buffer)?;
@@ -1232,7 +1819,17 @@ impl Mtp for Encrypted {
             panic!("wrong session id");
         }
 
-        self.process_message(manual_tl::Message::deserialize(&mut buffer)?)?;
+        let message = manual_tl::Message::deserialize(&mut buffer)?;
+        // Server message IDs carry the server's time, so our clock offset can be kept up to date
+        // with every message instead of waiting for the server to reject one of ours.
+        self.advance_time_offset(message.msg_id);
+        self.process_message(message)?;
+
+        // The first salt is only valid for a while, so ask for future salts as soon as the
+        // server can be reached, rather than waiting for it to complain about a bad salt.
+        if self.start_salt_time.is_none() && !self.request_future_salts() {
+            info!("cannot ask for future salts yet because buffer is full");
+        }
 
         // For simplicity, and to avoid passing too much stuff around (RPC results, updates),
         // the processing result is stored in self. After processing is done, that temporary
@@ -1240,8 +1837,36 @@ impl Mtp for Encrypted {
         Ok(Deserialization {
             rpc_results: mem::take(&mut self.rpc_results),
             updates: mem::take(&mut self.updates),
+            quick_acks: Vec::new(),
         })
     }
+
+    fn flush_deadline(&self) -> Option<Instant> {
+        let ack = self.ack_deadline();
+        let unacked = self
+            .unacked
+            .iter()
+            .map(|msg| {
+                if msg.resend {
+                    msg.sent_at
+                } else {
+                    msg.sent_at + STATE_REQ_DELAY
+                }
+            })
+            .min();
+        ack.into_iter().chain(unacked).min()
+    }
+}
+
+/// The difference, in seconds, between the time in a message ID and our clock.
+fn time_offset_of(msg_id: i64) -> i32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before epoch")
+        .as_secs() as i32;
+
+    let correct = (msg_id >> 32) as i32;
+    correct - now
 }
 
 #[cfg(test)]
@@ -1292,6 +1917,22 @@ mod tests {
         ensure_buffer_is_message(&buffer[MESSAGE_PREFIX_LEN..], REQUEST, 1);
     }
 
+    #[test]
+    fn ensure_bind_temp_auth_key_uses_own_msg_id() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+
+        let (msg_id, body) = mtproto.push_bind_temp_auth_key([1; 256], 0).unwrap();
+        let buffer = mtproto.finalize_plain();
+        let buffer = &buffer[MESSAGE_PREFIX_LEN..];
+
+        assert_eq!(&buffer[0..8], msg_id.0.to_le_bytes());
+        assert_eq!(
+            &body[0..4],
+            tl::functions::auth::BindTempAuthKey::CONSTRUCTOR_ID.to_le_bytes()
+        );
+        assert_eq!(&buffer[16..], &body[..]);
+    }
+
     #[test]
     fn ensure_correct_single_serialization() {
         let mut mtproto = Encrypted::build().finish(auth_key());
@@ -1421,4 +2062,329 @@ mod tests {
             assert!(buffer.windows(4).any(|w| w == GZIP_PACKED_HEADER));
         }
     }
+
+    #[test]
+    fn ensure_acks_are_flushed_when_due() {
+        let mut mtproto = Encrypted::build()
+            .ack_batching(2, Duration::from_secs(60))
+            .finish(auth_key());
+        assert_eq!(mtproto.ack_deadline(), None);
+
+        // Not due yet, so nothing is sent without requests.
+        mtproto.ack(4);
+        let since = mtproto.ack_since.unwrap();
+        assert_eq!(
+            mtproto.ack_deadline(),
+            Some(since + Duration::from_secs(60))
+        );
+        assert!(mtproto.finalize_plain().is_empty());
+
+        // Enough are pending, so they're sent right away.
+        mtproto.ack(8);
+        assert_eq!(mtproto.ack_deadline(), Some(since));
+        let buffer = mtproto.finalize();
+        assert!(!buffer.is_empty());
+        assert_eq!(mtproto.ack_deadline(), None);
+        assert!(mtproto.pending_ack.is_empty());
+    }
+
+    #[test]
+    fn ensure_destroy_session_result_is_matched() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        let msg_id = mtproto
+            .push(&tl::functions::DestroySession { session_id: 123 }.to_bytes())
+            .unwrap();
+
+        let body = tl::enums::DestroySessionRes::DestroySessionOk(tl::types::DestroySessionOk {
+            session_id: 123,
+        })
+        .to_bytes();
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 4,
+                seq_no: 1,
+                body: body.clone(),
+            })
+            .unwrap();
+
+        assert_eq!(mtproto.rpc_results, vec![(msg_id, Ok(body))]);
+        assert!(mtproto.destroy_session_requests.is_empty());
+    }
+
+    #[test]
+    fn ensure_late_messages_dont_move_time_offset_back() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let msg_id = |seconds: i64| (seconds << 32) | 1;
+        let mut mtproto = Encrypted::build().finish(auth_key());
+
+        mtproto.advance_time_offset(msg_id(now + 100));
+        assert!((99..=100).contains(&mtproto.time_offset()));
+
+        mtproto.advance_time_offset(msg_id(now - 100));
+        assert!((99..=100).contains(&mtproto.time_offset()));
+
+        // The server rejecting our messages is trusted, even if the offset goes back.
+        mtproto.correct_time_offset(msg_id(now - 100));
+        assert!((-101..=-100).contains(&mtproto.time_offset()));
+    }
+
+    #[test]
+    fn ensure_drop_answer_result_is_matched() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        let msg_id = mtproto
+            .push(&tl::functions::RpcDropAnswer { req_msg_id: 123 }.to_bytes())
+            .unwrap();
+
+        let result = tl::enums::RpcDropAnswer::RpcAnswerDropped(tl::types::RpcAnswerDropped {
+            msg_id: 123,
+            seq_no: 1,
+            bytes: 8,
+        })
+        .to_bytes();
+        let mut body = Vec::new();
+        manual_tl::RpcResult::CONSTRUCTOR_ID.serialize(&mut body);
+        msg_id.0.serialize(&mut body);
+        body.extend_from_slice(&result);
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 4,
+                seq_no: 1,
+                body,
+            })
+            .unwrap();
+
+        assert_eq!(mtproto.rpc_results, vec![(msg_id, Ok(result))]);
+    }
+
+    #[test]
+    fn ensure_unacknowledged_messages_are_resent() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        let msg_id = mtproto.push(REQUEST).unwrap();
+        mtproto.finalize_plain();
+
+        // Nothing to do until the message goes unacknowledged for long enough.
+        assert!(mtproto.flush_deadline().unwrap() > Instant::now());
+        mtproto.unacked[0].sent_at -= STATE_REQ_DELAY;
+        assert!(mtproto.flush_deadline().unwrap() <= Instant::now());
+
+        let buffer = mtproto.finalize_plain();
+        let state_req = tl::enums::MsgsStateReq::Req(tl::types::MsgsStateReq {
+            msg_ids: vec![msg_id.0],
+        })
+        .to_bytes();
+        assert_eq!(&buffer[MESSAGE_PREFIX_LEN + 16..], &state_req[..]);
+
+        // The server did not receive it, so it's sent again with the same message identifier.
+        let req_msg_id = mtproto.state_requests[0].0;
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 4,
+                seq_no: 0,
+                body: tl::enums::MsgsStateInfo::Info(tl::types::MsgsStateInfo {
+                    req_msg_id,
+                    info: vec![2],
+                })
+                .to_bytes(),
+            })
+            .unwrap();
+        assert!(mtproto.flush_deadline().unwrap() <= Instant::now());
+
+        let buffer = mtproto.finalize_plain();
+        let buffer = &buffer[MESSAGE_PREFIX_LEN..];
+        assert_eq!(&buffer[0..8], msg_id.0.to_le_bytes());
+        ensure_buffer_is_message(buffer, REQUEST, 1);
+
+        // Once acknowledged, it's forgotten.
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 8,
+                seq_no: 0,
+                body: tl::enums::MsgsAck::Ack(tl::types::MsgsAck {
+                    msg_ids: vec![msg_id.0],
+                })
+                .to_bytes(),
+            })
+            .unwrap();
+        assert!(mtproto.unacked.is_empty());
+        assert_eq!(mtproto.flush_deadline(), None);
+    }
+
+    #[test]
+    fn ensure_unacknowledged_bodies_are_bounded() {
+        let mut mtproto = Encrypted::build()
+            .compression_threshold(None)
+            .finish(auth_key());
+        let request = vec![0; 256 * 1024];
+        let msg_ids = (0..MAX_UNACKED_BYTES / request.len() + 2)
+            .map(|_| {
+                let msg_id = mtproto.push(&request).unwrap();
+                mtproto.finalize_plain();
+                msg_id
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(mtproto.unacked.len(), msg_ids.len());
+        assert!(mtproto.unacked_bytes <= MAX_UNACKED_BYTES);
+        assert!(mtproto.unacked[0].body.is_none());
+        assert!(mtproto.unacked[msg_ids.len() - 1].body.is_some());
+
+        // Messages whose body was dropped are sent again as new messages.
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 4,
+                seq_no: 0,
+                body: tl::enums::MsgResendReq::Req(tl::types::MsgResendReq {
+                    msg_ids: vec![msg_ids[0].0, msg_ids[msg_ids.len() - 1].0],
+                })
+                .to_bytes(),
+            })
+            .unwrap();
+        assert_eq!(
+            mtproto.rpc_results,
+            vec![(msg_ids[0], Err(RequestError::BadMessage { code: 20 }))]
+        );
+        assert_eq!(mtproto.unacked.len(), msg_ids.len() - 1);
+        assert!(mtproto.unacked.last().unwrap().resend);
+    }
+
+    #[test]
+    fn ensure_bad_container_fails_its_messages() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        let msg_a = mtproto.push(REQUEST).unwrap();
+        let msg_b = mtproto.push(REQUEST_B).unwrap();
+        let buffer = mtproto.finalize_plain();
+        let mut container_msg_id = [0; 8];
+        container_msg_id.copy_from_slice(&buffer[MESSAGE_PREFIX_LEN..MESSAGE_PREFIX_LEN + 8]);
+
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 4,
+                seq_no: 0,
+                body: tl::enums::BadMsgNotification::Notification(tl::types::BadMsgNotification {
+                    bad_msg_id: i64::from_le_bytes(container_msg_id),
+                    bad_msg_seqno: 0,
+                    error_code: 16,
+                })
+                .to_bytes(),
+            })
+            .unwrap();
+
+        let bad_message = Err(RequestError::BadMessage { code: 16 });
+        assert_eq!(
+            mtproto.rpc_results,
+            vec![(msg_a, bad_message.clone()), (msg_b, bad_message)]
+        );
+        assert!(mtproto.unacked.is_empty());
+        assert_eq!(
+            mtproto.rpc_results[0]
+                .1
+                .as_ref()
+                .unwrap_err()
+                .bad_message_reason(),
+            Some(BadMessageReason::MsgIdTooLow)
+        );
+    }
+
+    #[test]
+    fn ensure_quick_acks_are_matched() {
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        let msg_id = mtproto.push(REQUEST).unwrap();
+        mtproto.request_quick_ack(msg_id);
+        mtproto.finalize();
+        assert!(mtproto.wants_quick_ack());
+        let token = mtproto.quick_acks[0].0;
+
+        // Unknown tokens are still reported as transport errors.
+        assert_eq!(
+            mtproto.deserialize(&(-404i32).to_le_bytes()).err(),
+            Some(DeserializeError::TransportError { code: -404 })
+        );
+
+        let result = mtproto.deserialize(&token.to_le_bytes()).unwrap();
+        assert_eq!(result.quick_acks, vec![msg_id]);
+        assert!(mtproto.quick_acks.is_empty());
+
+        mtproto.push(REQUEST).unwrap();
+        mtproto.finalize();
+        assert!(!mtproto.wants_quick_ack());
+    }
+
+    #[test]
+    fn ensure_messages_are_dumped() {
+        let dump = Arc::new(crate::mtp::RingBufferDump::new(10));
+        let mut mtproto = Encrypted::build().dump(dump.clone()).finish(auth_key());
+        let msg_a = mtproto.push(REQUEST).unwrap();
+        let msg_b = mtproto.push(REQUEST_B).unwrap();
+        mtproto.finalize();
+
+        mtproto
+            .process_message(manual_tl::Message {
+                msg_id: 5,
+                seq_no: 0,
+                body: tl::enums::MsgsAck::Ack(tl::types::MsgsAck {
+                    msg_ids: vec![msg_a.0],
+                })
+                .to_bytes(),
+            })
+            .unwrap();
+
+        let messages = dump.messages();
+        let summary = messages
+            .iter()
+            .map(|m| (m.direction, m.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Direction::Sent, "msg_container"),
+                (Direction::Sent, "(unknown)"),
+                (Direction::Sent, "(unknown)"),
+                (Direction::Received, "msgs_ack"),
+            ]
+        );
+        assert_eq!(messages[1].msg_id, msg_a.0);
+        assert_eq!(messages[2].msg_id, msg_b.0);
+        assert_eq!(messages[2].body, REQUEST_B);
+    }
+
+    fn future_salt(valid_since: i32, salt: i64) -> tl::types::FutureSalt {
+        tl::types::FutureSalt {
+            valid_since,
+            valid_until: valid_since + 3600,
+            salt,
+        }
+    }
+
+    #[test]
+    fn ensure_salts_rotate_and_are_fetched_once() {
+        let get_future_salts = tl::functions::GetFutureSalts {
+            num: NUM_FUTURE_SALTS,
+        }
+        .to_bytes();
+
+        let mut mtproto = Encrypted::build().finish(auth_key());
+        mtproto.start_salt_time = Some((10_000, Instant::now()));
+        mtproto.salts = vec![future_salt(5_000, 2), future_salt(0, 1)];
+
+        // The next salt is valid by now, so it's used, and being the last one, more are fetched.
+        mtproto.push(REQUEST);
+        let buffer = mtproto.finalize_plain();
+        assert_eq!(&buffer[0..8], 2i64.to_le_bytes());
+        assert!(buffer
+            .windows(get_future_salts.len())
+            .any(|w| w == get_future_salts));
+        assert!(mtproto.salts_requested);
+
+        // A single salt remaining doesn't cause more requests while those are pending.
+        mtproto.salts.push(future_salt(0, 1));
+        mtproto.push(REQUEST);
+        let buffer = mtproto.finalize_plain();
+        assert_eq!(&buffer[0..8], 2i64.to_le_bytes());
+        assert!(!buffer
+            .windows(get_future_salts.len())
+            .any(|w| w == get_future_salts));
+    }
 }
@@ -16,15 +16,18 @@
 //! key).
 //!
 //! [Mobile Transport Protocol]: https://core.telegram.org/mtproto/description
+mod dump;
 mod encrypted;
 mod plain;
 
 use crate::MsgId;
+pub use dump::{Direction, DumpedMessage, FileDump, MessageDump, RingBufferDump};
 pub use encrypted::Encrypted;
 use grammers_crypto as crypto;
 use grammers_tl_types as tl;
 pub use plain::Plain;
 use std::fmt;
+use std::time::Instant;
 
 /// Results from the deserialization of a response.
 pub struct Deserialization {
@@ -32,6 +35,8 @@ pub struct Deserialization {
     pub rpc_results: Vec<(MsgId, Result<Vec<u8>, RequestError>)>,
     /// Updates that came in the response.
     pub updates: Vec<Vec<u8>>,
+    /// Messages which the server quickly acknowledged to have received.
+    pub quick_acks: Vec<MsgId>,
 }
 
 /// The error type for the deserialization of server messages.
@@ -164,26 +169,27 @@ impl fmt::Display for RpcError {
 
 impl From<tl::types::RpcError> for RpcError {
     fn from(error: tl::types::RpcError) -> Self {
-        // Extract the numeric value in the error, if any
-        if let Some(value) = error
-            .error_message
-            .split(|c: char| !c.is_digit(10))
-            .find(|s| !s.is_empty())
-        {
-            let mut to_remove = String::with_capacity(1 + value.len());
-            to_remove.push('_');
-            to_remove.push_str(value);
+        // Extract the numeric value in the error, if any. It takes up an entire word, so that
+        // digits which are part of the name (as in `2FA_CONFIRM_WAIT_X`) are left alone.
+        let mut words = error.error_message.split('_').collect::<Vec<_>>();
+        let value = words.iter().position(|word| {
+            !word.is_empty()
+                && word.bytes().all(|c| c.is_ascii_digit())
+                && word.parse::<u32>().is_ok()
+        });
+
+        if let Some(index) = value {
+            let value = words.remove(index).parse().ok();
             Self {
                 code: error.error_code,
-                name: error.error_message.replace(&to_remove, ""),
-                // Safe to unwrap, matched on digits
-                value: Some(value.parse().unwrap()),
+                name: words.join("_"),
+                value,
                 caused_by: None,
             }
         } else {
             Self {
                 code: error.error_code,
-                name: error.error_message.clone(),
+                name: error.error_message,
                 value: None,
                 caused_by: None,
             }
@@ -192,11 +198,30 @@ impl From<tl::types::RpcError> for RpcError {
 }
 
 impl RpcError {
+    /// A numerical value similar to HTTP status codes.
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// The ASCII error name, with the additional value removed (`FLOOD_WAIT` for
+    /// `FLOOD_WAIT_31`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The additional value contained in the error, if any (`31` for `FLOOD_WAIT_31`).
+    pub fn value(&self) -> Option<u32> {
+        self.value
+    }
+
     /// Matches on the name of the RPC error (case-sensitive).
     ///
     /// Useful in `match` arm guards. A single trailing or leading asterisk (`'*'`) is allowed,
     /// and will instead check if the error name starts (or ends with) the input parameter.
     ///
+    /// Names can also be written the way they are documented, with `X` in place of the
+    /// additional value (as in `FLOOD_WAIT_X`), in which case the error must contain a value.
+    ///
     /// # Examples
     ///
     /// ```
@@ -210,6 +235,17 @@ impl RpcError {
     /// }
     /// ```
     pub fn is(&self, rpc_error: &str) -> bool {
+        if rpc_error.contains("_X") && self.value.is_some() {
+            let name = rpc_error
+                .split('_')
+                .filter(|word| *word != "X")
+                .collect::<Vec<_>>()
+                .join("_");
+            if name != rpc_error {
+                return self.is(&name);
+            }
+        }
+
         if let Some(rpc_error) = rpc_error.strip_suffix('*') {
             self.name.starts_with(rpc_error)
         } else if let Some(rpc_error) = rpc_error.strip_prefix('*') {
@@ -253,25 +289,119 @@ impl fmt::Display for RequestError {
         match self {
             Self::RpcError(error) => write!(f, "request error: {}", error),
             Self::Dropped => write!(f, "request error: request dropped"),
-            Self::BadMessage { code } => write!(f, "request error: bad message (code {}, {})", code, match code {
-                16 => "msg_id too low",
-                17 => "msg_id too high",
-                18 => "incorrect two lower order msg_id bits; this is a bug",
-                19 => "container msg_id is the same as msg_id of a previously received message; this is a bug",
-                20 => "message too old",
-                32 => "msg_seqno too low",
-                33 => "msg_seqno too high",
-                34 => "an even msg_seqno expected; this may be a bug",
-                35 => "odd msg_seqno expected; this may be a bug",
-                48 => "incorrect server salt",
-                64 => "invalid container; this is likely a bug",
-                _ => "unknown explanation; please report this issue",
-            }),
+            Self::BadMessage { code } => write!(
+                f,
+                "request error: bad message (code {}, {})",
+                code,
+                BadMessageReason::from(*code)
+            ),
             Self::Deserialize(error) => write!(f, "request error: {}", error),
         }
     }
 }
 
+impl RequestError {
+    /// The reason why the server ignored the message containing the request, if this error is a
+    /// [`RequestError::BadMessage`].
+    pub fn bad_message_reason(&self) -> Option<BadMessageReason> {
+        match self {
+            Self::BadMessage { code } => Some(BadMessageReason::from(*code)),
+            _ => None,
+        }
+    }
+}
+
+/// The reason why the server ignored a message, as reported by a [bad message notification].
+///
+/// [bad message notification]: https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BadMessageReason {
+    /// The `msg_id` was too low, because the local time is wrong.
+    MsgIdTooLow,
+    /// The `msg_id` was too high, because the local time is wrong.
+    MsgIdTooHigh,
+    /// The two lower order bits of the `msg_id` were incorrect.
+    InvalidMsgIdBits,
+    /// The `msg_id` of a container was the same as the one of a message received before.
+    DuplicateContainerMsgId,
+    /// The message was too old to verify whether it was received before.
+    MessageTooOld,
+    /// The `msg_seqno` was too low.
+    SeqNoTooLow,
+    /// The `msg_seqno` was too high.
+    SeqNoTooHigh,
+    /// An even `msg_seqno` was expected.
+    EvenSeqNoExpected,
+    /// An odd `msg_seqno` was expected.
+    OddSeqNoExpected,
+    /// The server salt was incorrect.
+    BadServerSalt,
+    /// The container was invalid.
+    InvalidContainer,
+    /// A reason not known by this library, with its error code.
+    Unknown(i32),
+}
+
+impl BadMessageReason {
+    /// The error code of the reason, as sent by the server.
+    pub fn code(&self) -> i32 {
+        match *self {
+            Self::MsgIdTooLow => 16,
+            Self::MsgIdTooHigh => 17,
+            Self::InvalidMsgIdBits => 18,
+            Self::DuplicateContainerMsgId => 19,
+            Self::MessageTooOld => 20,
+            Self::SeqNoTooLow => 32,
+            Self::SeqNoTooHigh => 33,
+            Self::EvenSeqNoExpected => 34,
+            Self::OddSeqNoExpected => 35,
+            Self::BadServerSalt => 48,
+            Self::InvalidContainer => 64,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<i32> for BadMessageReason {
+    fn from(code: i32) -> Self {
+        match code {
+            16 => Self::MsgIdTooLow,
+            17 => Self::MsgIdTooHigh,
+            18 => Self::InvalidMsgIdBits,
+            19 => Self::DuplicateContainerMsgId,
+            20 => Self::MessageTooOld,
+            32 => Self::SeqNoTooLow,
+            33 => Self::SeqNoTooHigh,
+            34 => Self::EvenSeqNoExpected,
+            35 => Self::OddSeqNoExpected,
+            48 => Self::BadServerSalt,
+            64 => Self::InvalidContainer,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for BadMessageReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MsgIdTooLow => "msg_id too low",
+            Self::MsgIdTooHigh => "msg_id too high",
+            Self::InvalidMsgIdBits => "incorrect two lower order msg_id bits; this is a bug",
+            Self::DuplicateContainerMsgId => {
+                "container msg_id is the same as msg_id of a previously received message; this is a bug"
+            }
+            Self::MessageTooOld => "message too old",
+            Self::SeqNoTooLow => "msg_seqno too low",
+            Self::SeqNoTooHigh => "msg_seqno too high",
+            Self::EvenSeqNoExpected => "an even msg_seqno expected; this may be a bug",
+            Self::OddSeqNoExpected => "odd msg_seqno expected; this may be a bug",
+            Self::BadServerSalt => "incorrect server salt",
+            Self::InvalidContainer => "invalid container; this is likely a bug",
+            Self::Unknown(_) => "unknown explanation; please report this issue",
+        })
+    }
+}
+
 impl From<DeserializeError> for RequestError {
     fn from(error: DeserializeError) -> Self {
         Self::Deserialize(error)
@@ -311,6 +441,19 @@ pub trait Mtp {
     /// payload is below that mark, it's safe to call.
     fn push(&mut self, request: &[u8]) -> Option<MsgId>;
 
+    /// Asks the server to quickly acknowledge the given message, which must have been pushed
+    /// but not finalized yet, as soon as it receives it. The acknowledgement will be reported in
+    /// [`Deserialization::quick_acks`].
+    ///
+    /// Implementations which do not support quick acknowledgements ignore this.
+    fn request_quick_ack(&mut self, _msg_id: MsgId) {}
+
+    /// Whether the data returned by the last call to `finalize` should be sent asking the server
+    /// for a quick acknowledgement.
+    fn wants_quick_ack(&self) -> bool {
+        false
+    }
+
     /// Finalizes the internal buffer of requests.
     ///
     /// Note that even if there are no requests to serialize, the protocol may
@@ -319,6 +462,13 @@ pub trait Mtp {
 
     /// Deserializes a single incoming message payload into zero or more responses.
     fn deserialize(&mut self, payload: &[u8]) -> Result<Deserialization, DeserializeError>;
+
+    /// When the service messages waiting to be sent (such as acknowledgements or messages to
+    /// be re-sent) should be sent, even if there are no requests to send them with, if any.
+    /// Calling `finalize` after this instant will include them.
+    fn flush_deadline(&self) -> Option<Instant> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -365,5 +515,52 @@ mod tests {
                 caused_by: None,
             }
         );
+
+        assert_eq!(
+            RpcError::from(tl::types::RpcError {
+                error_code: 420,
+                error_message: "2FA_CONFIRM_WAIT_604800".into(),
+            }),
+            RpcError {
+                code: 420,
+                name: "2FA_CONFIRM_WAIT".into(),
+                value: Some(604800),
+                caused_by: None,
+            }
+        );
+
+        assert_eq!(
+            RpcError::from(tl::types::RpcError {
+                error_code: 400,
+                error_message: "VALUE_99999999999_INVALID".into(),
+            }),
+            RpcError {
+                code: 400,
+                name: "VALUE_99999999999_INVALID".into(),
+                value: None,
+                caused_by: None,
+            }
+        );
+    }
+
+    #[test]
+    fn check_rpc_error_matching() {
+        let error = RpcError::from(tl::types::RpcError {
+            error_code: 303,
+            error_message: "FILE_MIGRATE_4".into(),
+        });
+        assert_eq!(error.code(), 303);
+        assert_eq!(error.name(), "FILE_MIGRATE");
+        assert_eq!(error.value(), Some(4));
+        assert!(error.is("FILE_MIGRATE"));
+        assert!(error.is("FILE_MIGRATE_X"));
+        assert!(error.is("*_MIGRATE_X"));
+        assert!(!error.is("PHONE_MIGRATE_X"));
+
+        let error = RpcError::from(tl::types::RpcError {
+            error_code: 400,
+            error_message: "CHAT_INVALID".into(),
+        });
+        assert!(!error.is("CHAT_INVALID_X"));
     }
 }
@@ -110,6 +110,7 @@ impl Mtp for Plain {
         Ok(Deserialization {
             rpc_results: vec![(MsgId(0), Ok(payload[20..20 + len as usize].into()))],
             updates: Vec::new(),
+            quick_acks: Vec::new(),
         })
     }
 }
@@ -5,7 +5,7 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{Error, Transport};
+use super::{Error, Tagged, Transport};
 use bytes::{BufMut, BytesMut};
 
 /// The lightest MTProto transport protocol available. This is an
@@ -42,10 +42,8 @@ impl Abridged {
     pub fn new() -> Self {
         Self { init: false }
     }
-}
 
-impl Transport for Abridged {
-    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+    fn pack_with_flags(&mut self, input: &[u8], output: &mut BytesMut, flags: u8) {
         assert_eq!(input.len() % 4, 0);
 
         if !self.init {
@@ -55,14 +53,25 @@ impl Transport for Abridged {
 
         let len = input.len() / 4;
         if len < 127 {
-            output.put_u8(len as u8);
+            output.put_u8(len as u8 | flags);
             output.put(input);
         } else {
-            output.put_u8(0x7f);
+            output.put_u8(0x7f | flags);
             output.put_uint_le(len as _, 3);
             output.put(input);
         }
     }
+}
+
+impl Transport for Abridged {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        self.pack_with_flags(input, output, 0);
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack_with_flags(input, output, 0x80);
+        true
+    }
 
     fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
         if input.is_empty() {
@@ -74,6 +83,16 @@ impl Transport for Abridged {
         let len = if len < 127 {
             header_len = 1;
             len as u32
+        } else if len > 127 {
+            // Quick acknowledgements are sent on their own, in big-endian.
+            if input.len() < 4 {
+                return Err(Error::MissingBytes);
+            }
+
+            let mut token = [0; 4];
+            token.copy_from_slice(&input[..4]);
+            output.put_u32_le(u32::from_be_bytes(token));
+            return Ok(4);
         } else {
             if input.len() < 4 {
                 return Err(Error::MissingBytes);
@@ -95,6 +114,13 @@ impl Transport for Abridged {
     }
 }
 
+impl Tagged for Abridged {
+    fn init_tag(&mut self) -> [u8; 4] {
+        self.init = true;
+        [0xef, 0xef, 0xef, 0xef]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,6 +181,24 @@ mod tests {
         assert_eq!(input, unpacked);
     }
 
+    #[test]
+    fn pack_quick_ack() {
+        let (mut transport, input, mut output) = setup_pack(128);
+        assert!(transport.pack_quick_ack(&input, &mut output));
+        assert_eq!(&output[..2], &[0xef, 0x80 | 32]);
+    }
+
+    #[test]
+    fn unpack_quick_ack() {
+        let mut transport = Abridged::new();
+        let mut unpacked = BytesMut::new();
+        assert_eq!(
+            transport.unpack(&[0x81, 0x02, 0x03, 0x04, 0x01], &mut unpacked),
+            Ok(4)
+        );
+        assert_eq!(&unpacked[..], &[0x04, 0x03, 0x02, 0x81]);
+    }
+
     #[test]
     fn unpack_large() {
         let (mut transport, input, mut packed) = setup_pack(1024);
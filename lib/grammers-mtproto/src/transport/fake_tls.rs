@@ -0,0 +1,372 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Error, Transport};
+use bytes::{BufMut, BytesMut};
+use grammers_crypto::constant_time_eq;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RECORD_HANDSHAKE: u8 = 0x16;
+const RECORD_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const RECORD_APPLICATION_DATA: u8 = 0x17;
+const RECORD_HEADER_LEN: usize = 5;
+const MAX_RECORD_LEN: usize = 16 * 1024;
+
+/// The length of the `ClientHello` sent by browsers, which the handshake mimics.
+const CLIENT_HELLO_LEN: usize = 517;
+/// Where the random value is located in both the `ClientHello` and the `ServerHello`.
+const RANDOM_RANGE: std::ops::Range<usize> = 11..43;
+
+/// A wrapper around other transports which disguises the connection as a TLS 1.3 session, as
+/// needed by [MTProxy] servers whose secret starts with `ee`.
+///
+/// * Overhead: 5 bytes for every 16KiB of data, plus 6 bytes once.
+/// * Minimum envelope length: 5 bytes.
+/// * Maximum envelope length: 5 bytes for every 16KiB of data.
+///
+/// The data is sent as TLS application data records, without any actual encryption (the inner
+/// transport should be the [`Obfuscated`] transport, which takes care of that). Before using
+/// it, the handshake must have been completed with [`FakeTlsHandshake`].
+///
+/// [MTProxy]: https://core.telegram.org/mtproto/mtproto-transports#mtproxy
+/// [`Obfuscated`]: super::Obfuscated
+pub struct FakeTls<T: Transport> {
+    inner: T,
+    init: bool,
+    /// The data read from complete records but not used by the inner transport yet.
+    payload: BytesMut,
+}
+
+impl<T: Transport> FakeTls<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            init: false,
+            payload: BytesMut::new(),
+        }
+    }
+
+    /// Pack with the inner transport using `pack`, and split what it wrote into records.
+    fn pack_with<F>(&mut self, output: &mut BytesMut, pack: F) -> bool
+    where
+        F: FnOnce(&mut T, &mut BytesMut) -> bool,
+    {
+        if !self.init {
+            put_record_header(output, RECORD_CHANGE_CIPHER_SPEC, 1);
+            output.put_u8(1);
+            self.init = true;
+        }
+
+        let mut packed = BytesMut::new();
+        let result = pack(&mut self.inner, &mut packed);
+        for chunk in packed.chunks(MAX_RECORD_LEN) {
+            put_record_header(output, RECORD_APPLICATION_DATA, chunk.len());
+            output.put(chunk);
+        }
+        result
+    }
+}
+
+fn put_record_header(output: &mut BytesMut, kind: u8, len: usize) {
+    output.put_u8(kind);
+    output.put_u16(0x0303);
+    output.put_u16(len as u16);
+}
+
+impl<T: Transport> Transport for FakeTls<T> {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        self.pack_with(output, |inner, packed| {
+            inner.pack(input, packed);
+            false
+        });
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack_with(output, |inner, packed| inner.pack_quick_ack(input, packed))
+    }
+
+    fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
+        // Take the payload out of every complete record. The inner transport may need several
+        // of them to form a single packet, or find several packets in one.
+        let mut used = 0;
+        while input.len() - used >= RECORD_HEADER_LEN {
+            let header = &input[used..used + RECORD_HEADER_LEN];
+            let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+            if input.len() - used - RECORD_HEADER_LEN < len {
+                break;
+            }
+            let body = &input[used + RECORD_HEADER_LEN..used + RECORD_HEADER_LEN + len];
+            match header[0] {
+                RECORD_APPLICATION_DATA => self.payload.put(body),
+                RECORD_CHANGE_CIPHER_SPEC => {}
+                got => return Err(Error::BadRecord { got }),
+            }
+            used += RECORD_HEADER_LEN + len;
+        }
+
+        match self.inner.unpack(&self.payload, output) {
+            Ok(n) => {
+                let _ = self.payload.split_to(n);
+                Ok(used)
+            }
+            // Even if there's no packet yet, the records which were read must not be read again.
+            Err(Error::MissingBytes) if used != 0 => Ok(used),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The handshake which must be performed before using the [`FakeTls`] transport.
+///
+/// It proves to the proxy that the client knows the secret, and lets the client verify that the
+/// proxy knows it too.
+pub struct FakeTlsHandshake {
+    secret: [u8; 16],
+    client_random: [u8; 32],
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    let mut result = [0; 32];
+    result.copy_from_slice(&mac.finalize().into_bytes());
+    result
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("failed to generate secure random bytes");
+    bytes
+}
+
+/// A reserved GREASE value, which servers must ignore, like browsers use.
+fn grease() -> u16 {
+    let n = random_bytes::<1>()[0] & 0xf0;
+    u16::from_be_bytes([n | 0x0a, n | 0x0a])
+}
+
+/// Append the extension with the given type and data to the buffer.
+fn put_extension(output: &mut BytesMut, kind: u16, data: &[u8]) {
+    output.put_u16(kind);
+    output.put_u16(data.len() as u16);
+    output.put(data);
+}
+
+/// Build a `ClientHello` resembling the one sent by browsers, with the random value zeroed.
+fn client_hello(domain: &str) -> BytesMut {
+    let mut extensions = BytesMut::new();
+    put_extension(&mut extensions, grease(), &[]);
+
+    let mut server_name = BytesMut::new();
+    server_name.put_u16((domain.len() + 3) as u16);
+    server_name.put_u8(0);
+    server_name.put_u16(domain.len() as u16);
+    server_name.put(domain.as_bytes());
+    put_extension(&mut extensions, 0x0000, &server_name);
+
+    put_extension(&mut extensions, 0x0017, &[]);
+    put_extension(&mut extensions, 0xff01, &[0]);
+    let mut groups = BytesMut::new();
+    groups.put_u16(8);
+    groups.put_u16(grease());
+    groups.put(&[0x00, 0x1d, 0x00, 0x17, 0x00, 0x18][..]);
+    put_extension(&mut extensions, 0x000a, &groups);
+    put_extension(&mut extensions, 0x000b, &[1, 0]);
+    put_extension(&mut extensions, 0x0023, &[]);
+    put_extension(&mut extensions, 0x0010, b"\x00\x0c\x02h2\x08http/1.1");
+    put_extension(&mut extensions, 0x0005, &[1, 0, 0, 0, 0]);
+    put_extension(
+        &mut extensions,
+        0x000d,
+        &[
+            0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01,
+            0x08, 0x06, 0x06, 0x01,
+        ],
+    );
+    put_extension(&mut extensions, 0x0012, &[]);
+
+    // The key is never used, but it should look like an X25519 public key.
+    let mut key = random_bytes::<32>();
+    key[31] &= 0x7f;
+    let mut key_share = BytesMut::new();
+    key_share.put_u16(0x29);
+    key_share.put_u16(grease());
+    key_share.put(&[0x00, 0x01, 0x00, 0x00, 0x1d, 0x00, 0x20][..]);
+    key_share.put(&key[..]);
+    put_extension(&mut extensions, 0x0033, &key_share);
+
+    put_extension(&mut extensions, 0x002d, &[1, 1]);
+    let mut versions = BytesMut::new();
+    versions.put_u8(10);
+    versions.put_u16(grease());
+    versions.put(&[0x03, 0x04, 0x03, 0x03, 0x03, 0x02, 0x03, 0x01][..]);
+    put_extension(&mut extensions, 0x002b, &versions);
+    put_extension(&mut extensions, 0x001b, &[2, 0, 2]);
+    put_extension(&mut extensions, grease(), &[0]);
+
+    let mut hello = BytesMut::with_capacity(CLIENT_HELLO_LEN);
+    put_record_header(&mut hello, RECORD_HANDSHAKE, 0);
+    hello[1..3].copy_from_slice(&[0x03, 0x01]);
+    hello.put_u32(0x0100_0000);
+    hello.put_u16(0x0303);
+    hello.put(&[0; 32][..]);
+    hello.put_u8(32);
+    hello.put(&random_bytes::<32>()[..]);
+    hello.put_u16(32);
+    hello.put_u16(grease());
+    hello.put(
+        &[
+            0x13, 0x01, 0x13, 0x02, 0x13, 0x03, 0xc0, 0x2b, 0xc0, 0x2f, 0xc0, 0x2c, 0xc0, 0x30,
+            0xcc, 0xa9, 0xcc, 0xa8, 0xc0, 0x13, 0xc0, 0x14, 0x00, 0x9c, 0x00, 0x9d, 0x00, 0x2f,
+            0x00, 0x35,
+        ][..],
+    );
+    hello.put_u16(0x0100);
+
+    // Pad it to the usual length, if the domain is not too long for that.
+    let len = hello.len() + 2 + extensions.len();
+    if len + 4 <= CLIENT_HELLO_LEN {
+        put_extension(
+            &mut extensions,
+            0x0015,
+            &vec![0; CLIENT_HELLO_LEN - len - 4],
+        );
+    }
+    hello.put_u16(extensions.len() as u16);
+    hello.put(&extensions[..]);
+
+    let record_len = (hello.len() - RECORD_HEADER_LEN) as u16;
+    hello[3..5].copy_from_slice(&record_len.to_be_bytes());
+    let handshake_len = (hello.len() - RECORD_HEADER_LEN - 4) as u32;
+    hello[6..9].copy_from_slice(&handshake_len.to_be_bytes()[1..]);
+    hello
+}
+
+impl FakeTlsHandshake {
+    /// Start the handshake with the given secret (without the `ee` prefix), for a proxy which
+    /// disguises itself as the given domain.
+    ///
+    /// Returns the handshake, and the `ClientHello` which should be sent to the proxy.
+    pub fn new(secret: [u8; 16], domain: &str) -> (Self, Vec<u8>) {
+        let mut hello = client_hello(domain);
+
+        let mut random = hmac_sha256(&secret, &[&hello]);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        for (r, t) in random[28..].iter_mut().zip(timestamp.to_le_bytes().iter()) {
+            *r ^= t;
+        }
+        hello[RANDOM_RANGE].copy_from_slice(&random);
+
+        (
+            Self {
+                secret,
+                client_random: random,
+            },
+            hello.to_vec(),
+        )
+    }
+
+    /// The number of bytes that are still needed to have the complete response of the proxy
+    /// (its `ServerHello`, followed by a change cipher spec and an application data record),
+    /// or zero if it's complete.
+    pub fn missing_bytes(response: &[u8]) -> usize {
+        let mut offset = 0;
+        for _ in 0..3 {
+            if response.len() < offset + RECORD_HEADER_LEN {
+                return offset + RECORD_HEADER_LEN - response.len();
+            }
+            let len = u16::from_be_bytes([response[offset + 3], response[offset + 4]]) as usize;
+            offset += RECORD_HEADER_LEN + len;
+        }
+        offset.saturating_sub(response.len())
+    }
+
+    /// Check that the response of the proxy was generated by someone who knows the secret.
+    pub fn verify(&self, response: &[u8]) -> bool {
+        if response.len() < RANDOM_RANGE.end || response[0] != RECORD_HANDSHAKE {
+            return false;
+        }
+        let mut zeroed = response.to_vec();
+        zeroed[RANDOM_RANGE].copy_from_slice(&[0; 32]);
+        let expected = hmac_sha256(&self.secret, &[&self.client_random, &zeroed]);
+        constant_time_eq(&response[RANDOM_RANGE], &expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Intermediate;
+
+    #[test]
+    fn client_hello_length() {
+        let (_, hello) = FakeTlsHandshake::new([1; 16], "example.com");
+        assert_eq!(hello.len(), CLIENT_HELLO_LEN);
+        assert_eq!(&hello[..3], &[0x16, 0x03, 0x01]);
+        assert!(hello.windows(11).any(|w| w == b"example.com"));
+    }
+
+    #[test]
+    fn verify_response() {
+        let secret = [1; 16];
+        let (handshake, _) = FakeTlsHandshake::new(secret, "example.com");
+
+        let mut response = vec![0x16, 0x03, 0x03, 0x00, 0x40];
+        response.extend((0..0x40).map(|x| x as u8));
+        response.extend(&[0x14, 0x03, 0x03, 0x00, 0x01, 0x01]);
+        response.extend(&[0x17, 0x03, 0x03, 0x00, 0x02, 0xaa, 0xbb]);
+        assert_eq!(FakeTlsHandshake::missing_bytes(&response[..4]), 1);
+        assert_eq!(FakeTlsHandshake::missing_bytes(&response[..70]), 4);
+        assert_eq!(FakeTlsHandshake::missing_bytes(&response), 0);
+
+        response[RANDOM_RANGE].copy_from_slice(&[0; 32]);
+        let random = hmac_sha256(&secret, &[&handshake.client_random, &response]);
+        response[RANDOM_RANGE].copy_from_slice(&random);
+        assert!(handshake.verify(&response));
+        response[50] ^= 1;
+        assert!(!handshake.verify(&response));
+    }
+
+    #[test]
+    fn pack_and_unpack_records() {
+        let mut transport = FakeTls::new(Intermediate::new());
+        let mut output = BytesMut::new();
+        transport.pack(&[1, 2, 3, 4], &mut output);
+        assert_eq!(
+            &output[..],
+            &[
+                0x14, 0x03, 0x03, 0x00, 0x01, 0x01, 0x17, 0x03, 0x03, 0x00, 0x0c, 0xee, 0xee, 0xee,
+                0xee, 4, 0, 0, 0, 1, 2, 3, 4
+            ]
+        );
+
+        // A packet split in two records, followed by an incomplete one.
+        let input = [
+            0x17, 0x03, 0x03, 0x00, 0x02, 4, 0, 0x17, 0x03, 0x03, 0x00, 0x06, 0, 0, 5, 6, 7, 8,
+            0x17, 0x03,
+        ];
+        let mut unpacked = BytesMut::new();
+        assert_eq!(transport.unpack(&input, &mut unpacked), Ok(18));
+        assert_eq!(&unpacked[..], &[5, 6, 7, 8]);
+        unpacked.clear();
+        assert_eq!(
+            transport.unpack(&input[18..], &mut unpacked),
+            Err(Error::MissingBytes)
+        );
+        assert_eq!(
+            transport.unpack(&[0x16, 0x03, 0x03, 0x00, 0x00], &mut unpacked),
+            Err(Error::BadRecord { got: 0x16 })
+        );
+    }
+}
@@ -73,7 +73,8 @@ impl Transport for Full {
 
         // payload len
         let len = needle.get_u32_le() as usize;
-        if len < 12 {
+        // The payload is always padded to 4 bytes, so a length which isn't is a corrupted one.
+        if len < 12 || len & 3 != 0 {
             return Err(Error::BadLen { got: len as u32 });
         }
 
@@ -187,6 +188,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unpack_bad_len() {
+        let (_expected_output, mut transport, mut input, mut output) = setup_unpack(128);
+        input[0] += 2;
+        assert_eq!(
+            transport.unpack(&input, &mut output),
+            Err(Error::BadLen { got: 142 })
+        );
+    }
+
     #[test]
     fn unpack_normal() {
         let (expected_output, mut transport, input, mut output) = setup_unpack(128);
@@ -5,7 +5,7 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
-use super::{Error, Transport};
+use super::{Error, Tagged, Transport, QUICK_ACK_FLAG};
 use bytes::{Buf, BufMut, BytesMut};
 
 /// A light MTProto transport protocol available that guarantees data padded
@@ -33,10 +33,8 @@ impl Intermediate {
     pub fn new() -> Self {
         Self { init: false }
     }
-}
 
-impl Transport for Intermediate {
-    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+    fn pack_with_flags(&mut self, input: &[u8], output: &mut BytesMut, flags: u32) {
         assert_eq!(input.len() % 4, 0);
 
         if !self.init {
@@ -44,9 +42,20 @@ impl Transport for Intermediate {
             self.init = true;
         }
 
-        output.put_u32_le(input.len() as _);
+        output.put_u32_le(input.len() as u32 | flags);
         output.put(input);
     }
+}
+
+impl Transport for Intermediate {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        self.pack_with_flags(input, output, 0);
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack_with_flags(input, output, QUICK_ACK_FLAG);
+        true
+    }
 
     fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
         if input.len() < 4 {
@@ -54,7 +63,14 @@ impl Transport for Intermediate {
         }
         let needle = &mut &input[..];
 
-        let len = needle.get_u32_le() as usize;
+        let len = needle.get_u32_le();
+        if len & QUICK_ACK_FLAG != 0 {
+            // Quick acknowledgements are sent in place of the length, without any payload.
+            output.put_u32_le(len);
+            return Ok(4);
+        }
+
+        let len = len as usize;
         if needle.len() < len {
             return Err(Error::MissingBytes);
         }
@@ -64,6 +80,13 @@ impl Transport for Intermediate {
     }
 }
 
+impl Tagged for Intermediate {
+    fn init_tag(&mut self) -> [u8; 4] {
+        self.init = true;
+        [0xee, 0xee, 0xee, 0xee]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +130,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pack_quick_ack() {
+        let (mut transport, input, mut output) = setup_pack(128);
+        assert!(transport.pack_quick_ack(&input, &mut output));
+        assert_eq!(&output[..8], &[0xee, 0xee, 0xee, 0xee, 128, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn unpack_quick_ack() {
+        let mut transport = Intermediate::new();
+        let mut unpacked = BytesMut::new();
+        assert_eq!(
+            transport.unpack(&[0x04, 0x03, 0x02, 0x81, 0x01], &mut unpacked),
+            Ok(4)
+        );
+        assert_eq!(&unpacked[..], &[0x04, 0x03, 0x02, 0x81]);
+    }
+
     #[test]
     fn unpack_normal() {
         let (mut transport, input, mut packed) = setup_pack(128);
@@ -12,16 +12,26 @@
 //!
 //! [MTProto transports]: https://core.telegram.org/mtproto#mtproto-transport
 mod abridged;
+mod fake_tls;
 mod full;
 mod intermediate;
+mod obfuscated;
+mod padded_intermediate;
 
 pub use abridged::Abridged;
+pub use fake_tls::{FakeTls, FakeTlsHandshake};
 pub use full::Full;
 pub use intermediate::Intermediate;
+pub use obfuscated::Obfuscated;
+pub use padded_intermediate::PaddedIntermediate;
 use std::fmt;
 
 use bytes::BytesMut;
 
+/// Set in the length of a packet to ask for a quick acknowledgement, and in the token that the
+/// server sends back as the acknowledgement.
+const QUICK_ACK_FLAG: u32 = 0x8000_0000;
+
 /// The error type reported by the different transports when something is wrong.
 ///
 /// Certain transports will only produce certain variants of this error.
@@ -40,6 +50,9 @@ pub enum Error {
 
     /// The checksum of the packet does not match its expected value.
     BadCrc { expected: u32, got: u32 },
+
+    /// A TLS record of an unexpected type was received by the fake-TLS transport.
+    BadRecord { got: u8 },
 }
 
 impl std::error::Error for Error {}
@@ -56,6 +69,7 @@ impl fmt::Display for Error {
             Error::BadCrc { expected, got } => {
                 write!(f, "bad crc (expected {}, got {})", expected, got)
             }
+            Error::BadRecord { got } => write!(f, "bad tls record (got type {})", got),
         }
     }
 }
@@ -69,10 +83,50 @@ pub trait Transport {
     /// Panics if `input.len()` is not divisible by 4.
     fn pack(&mut self, input: &[u8], output: &mut BytesMut);
 
+    /// Packs and writes `input` into `output` like [`Transport::pack`], but also asks the server
+    /// to send a [quick acknowledgement] as soon as it receives the packet.
+    ///
+    /// Returns `false`, and packs `input` as usual, if the transport does not support them.
+    ///
+    /// [quick acknowledgement]: https://core.telegram.org/mtproto#quick-ack
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack(input, output);
+        false
+    }
+
     /// Unpacks the content from `input` into `output`.
     ///
     /// Previous contents in `output` are not cleared before this operation.
     ///
+    /// Quick acknowledgements are unpacked as their 4-byte token, in little-endian, which always
+    /// has its most significant bit set.
+    ///
     /// If successful, returns how many bytes of `input` were used.
+    ///
+    /// Transports which wrap others may keep some of the input in their own buffers, so it
+    /// can be successful even if no bytes were used. It should be called again (even with an
+    /// empty `input`) until `MissingBytes` is returned.
     fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error>;
 }
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        (**self).pack(input, output)
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        (**self).pack_quick_ack(input, output)
+    }
+
+    fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
+        (**self).unpack(input, output)
+    }
+}
+
+/// Transports which are identified by a tag at the start of the connection, and thus can be
+/// wrapped by the [`Obfuscated`] transport, which sends the tag on their behalf.
+pub trait Tagged {
+    /// Return the tag of the transport, after which the transport considers it sent, so that
+    /// it's not sent again when packing the first message.
+    fn init_tag(&mut self) -> [u8; 4];
+}
@@ -0,0 +1,206 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Error, Tagged, Transport};
+use bytes::{BufMut, BytesMut};
+use grammers_crypto::aes::Ctr256;
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+
+/// The values the first 4 bytes of the header may not take, so that the connection can't be
+/// confused with a different protocol (or a different transport).
+const FORBIDDEN_PREFIXES: [[u8; 4]; 7] = [
+    *b"HEAD",
+    *b"POST",
+    *b"GET ",
+    *b"OPTI",
+    [0x16, 0x03, 0x01, 0x02],
+    [0xdd, 0xdd, 0xdd, 0xdd],
+    [0xee, 0xee, 0xee, 0xee],
+];
+
+/// A wrapper around other transports which encrypts everything sent over the connection, so that
+/// it's hard to tell the connection is being used by Telegram. This is an implementation of the
+/// [obfuscated transport].
+///
+/// * Overhead: 64 bytes, only once.
+/// * Minimum envelope length: the same as the inner transport.
+/// * Maximum envelope length: the same as the inner transport.
+///
+/// The connection starts with a random header which determines the keys used to encrypt the data
+/// in both directions. If a secret is given (as is the case when connecting through an
+/// [MTProxy]), it's mixed into the keys.
+///
+/// [obfuscated transport]: https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation
+/// [MTProxy]: https://core.telegram.org/mtproto/mtproto-transports#mtproxy
+pub struct Obfuscated<T: Transport + Tagged> {
+    inner: T,
+    header: Option<[u8; 64]>,
+    encryptor: Ctr256,
+    decryptor: Ctr256,
+    /// Input which has been decrypted but not used by the inner transport yet.
+    decrypted: BytesMut,
+}
+
+fn init_cipher(key_iv: &[u8], secret: Option<&[u8; 16]>) -> Ctr256 {
+    let mut key: [u8; 32] = key_iv[..32].try_into().unwrap();
+    let iv: [u8; 16] = key_iv[32..].try_into().unwrap();
+    if let Some(secret) = secret {
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        hasher.update(secret);
+        key.copy_from_slice(&hasher.finalize());
+    }
+    Ctr256::new(&key, &iv)
+}
+
+fn random_header() -> [u8; 64] {
+    let mut header = [0; 64];
+    loop {
+        getrandom::getrandom(&mut header).expect("failed to generate a secure header");
+        let prefix: [u8; 4] = header[..4].try_into().unwrap();
+        if header[0] != 0xef
+            && !FORBIDDEN_PREFIXES.contains(&prefix)
+            && header[4..8] != [0, 0, 0, 0]
+        {
+            return header;
+        }
+    }
+}
+
+impl<T: Transport + Tagged> Obfuscated<T> {
+    /// Wrap the transport, to be used when connecting to the given datacenter.
+    ///
+    /// The secret must be given when connecting through an MTProxy, without the `dd` or `ee`
+    /// prefixes (nor the domain name).
+    pub fn new(mut inner: T, secret: Option<[u8; 16]>, dc_id: i16) -> Self {
+        let mut header = random_header();
+        header[56..60].copy_from_slice(&inner.init_tag());
+        header[60..62].copy_from_slice(&dc_id.to_le_bytes());
+
+        let mut reversed = header[8..56].to_vec();
+        reversed.reverse();
+        let mut encryptor = init_cipher(&header[8..56], secret.as_ref());
+        let decryptor = init_cipher(&reversed, secret.as_ref());
+
+        // Only the end of the header is sent encrypted, but it's still encrypted as a whole
+        // because the state of the encryptor must have gone past it.
+        let mut encrypted = header;
+        encryptor.apply(&mut encrypted);
+        header[56..].copy_from_slice(&encrypted[56..]);
+
+        Self {
+            inner,
+            header: Some(header),
+            encryptor,
+            decryptor,
+            decrypted: BytesMut::new(),
+        }
+    }
+
+    /// Pack with the inner transport using `pack`, and encrypt what it wrote.
+    fn pack_with<F>(&mut self, output: &mut BytesMut, pack: F) -> bool
+    where
+        F: FnOnce(&mut T, &mut BytesMut) -> bool,
+    {
+        if let Some(header) = self.header.take() {
+            output.put(&header[..]);
+        }
+
+        let start = output.len();
+        let result = pack(&mut self.inner, output);
+        self.encryptor.apply(&mut output[start..]);
+        result
+    }
+}
+
+impl<T: Transport + Tagged> Transport for Obfuscated<T> {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        self.pack_with(output, |inner, output| {
+            inner.pack(input, output);
+            false
+        });
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack_with(output, |inner, output| inner.pack_quick_ack(input, output))
+    }
+
+    fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
+        // Every byte of input maps to a single decrypted byte, and the input is only advanced by
+        // as much as the inner transport used, so the start of the input always corresponds to
+        // the bytes that were already decrypted. Only the rest needs decrypting.
+        let start = self.decrypted.len();
+        if input.len() > start {
+            self.decrypted.put(&input[start..]);
+            self.decryptor.apply(&mut self.decrypted[start..]);
+        }
+
+        let n = self.inner.unpack(&self.decrypted, output)?;
+        let _ = self.decrypted.split_to(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Intermediate;
+
+    /// Returns the transport used by the server on the other side of the connection, given the
+    /// header sent by the client.
+    fn server_side(header: &[u8], secret: Option<&[u8; 16]>) -> (Ctr256, Ctr256) {
+        let mut reversed = header[8..56].to_vec();
+        reversed.reverse();
+        let decryptor = init_cipher(&header[8..56], secret);
+        let encryptor = init_cipher(&reversed, secret);
+        (encryptor, decryptor)
+    }
+
+    #[test]
+    fn header_contents() {
+        let secret = [7; 16];
+        let mut transport = Obfuscated::new(Intermediate::new(), Some(secret), -2);
+        let mut output = BytesMut::new();
+        transport.pack(&[1, 2, 3, 4], &mut output);
+        assert_eq!(output.len(), 64 + 4 + 4);
+
+        let (_, mut decryptor) = server_side(&output[..64], Some(&secret));
+        let mut decrypted = output.to_vec();
+        decryptor.apply(&mut decrypted);
+        assert_eq!(&decrypted[56..60], &[0xee, 0xee, 0xee, 0xee]);
+        assert_eq!(&decrypted[60..62], &(-2i16).to_le_bytes());
+        assert_eq!(&decrypted[64..], &[4, 0, 0, 0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unpack_in_pieces() {
+        let mut transport = Obfuscated::new(Intermediate::new(), None, 2);
+        let mut output = BytesMut::new();
+        transport.pack(&[], &mut output);
+
+        let (mut encryptor, _) = server_side(&output[..64], None);
+        let mut response = vec![4, 0, 0, 0, 1, 2, 3, 4, 4, 0, 0, 0, 5, 6, 7, 8];
+        encryptor.apply(&mut response);
+
+        // Feed the data as the sender would, only advancing it by what was used.
+        let mut input = BytesMut::from(&response[..6]);
+        let mut unpacked = BytesMut::new();
+        assert_eq!(
+            transport.unpack(&input, &mut unpacked),
+            Err(Error::MissingBytes)
+        );
+        input.put(&response[6..]);
+        assert_eq!(transport.unpack(&input, &mut unpacked), Ok(8));
+        assert_eq!(&unpacked[..], &[1, 2, 3, 4]);
+        let _ = input.split_to(8);
+
+        unpacked.clear();
+        assert_eq!(transport.unpack(&input, &mut unpacked), Ok(8));
+        assert_eq!(&unpacked[..], &[5, 6, 7, 8]);
+    }
+}
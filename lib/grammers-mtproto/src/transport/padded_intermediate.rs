@@ -0,0 +1,168 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{Error, Tagged, Transport, QUICK_ACK_FLAG};
+use bytes::{Buf, BufMut, BytesMut};
+
+/// A variant of the intermediate transport which adds up to 15 bytes of random padding to every
+/// packet, so that their lengths don't reveal as much about their contents. This is an
+/// implementation of the [padded intermediate transport].
+///
+/// * Overhead: small.
+/// * Minimum envelope length: 4 bytes.
+/// * Maximum envelope length: 19 bytes.
+///
+/// It serializes the input payload as follows:
+///
+/// ```text
+/// +----+----...----+-...-+
+/// | len|  payload  | pad |
+/// +----+----...----+-...-+
+///  ^^^^ 4 bytes     ^^^^^ 0 to 15 bytes
+/// ```
+///
+/// It is meant to be used with the obfuscated transport, and it's required by proxies whose
+/// secret starts with `dd`.
+///
+/// Only the padding which makes the length of the packets received not a multiple of 4 can be
+/// removed when unpacking them, so the rest is left for the MTP to ignore.
+///
+/// [padded intermediate transport]: https://core.telegram.org/mtproto/mtproto-transports#padded-intermediate
+pub struct PaddedIntermediate {
+    init: bool,
+}
+
+impl Default for PaddedIntermediate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaddedIntermediate {
+    pub fn new() -> Self {
+        Self { init: false }
+    }
+
+    fn pack_with_flags(&mut self, input: &[u8], output: &mut BytesMut, flags: u32) {
+        assert_eq!(input.len() % 4, 0);
+
+        if !self.init {
+            output.put_u32_le(0xdd_dd_dd_dd);
+            self.init = true;
+        }
+
+        let mut padding = [0; 16];
+        getrandom::getrandom(&mut padding).expect("failed to generate a secure padding");
+        let padding = &padding[..(padding[0] % 16) as usize];
+
+        output.put_u32_le((input.len() + padding.len()) as u32 | flags);
+        output.put(input);
+        output.put(padding);
+    }
+}
+
+impl Transport for PaddedIntermediate {
+    fn pack(&mut self, input: &[u8], output: &mut BytesMut) {
+        self.pack_with_flags(input, output, 0);
+    }
+
+    fn pack_quick_ack(&mut self, input: &[u8], output: &mut BytesMut) -> bool {
+        self.pack_with_flags(input, output, QUICK_ACK_FLAG);
+        true
+    }
+
+    fn unpack(&mut self, input: &[u8], output: &mut BytesMut) -> Result<usize, Error> {
+        if input.len() < 4 {
+            return Err(Error::MissingBytes);
+        }
+        let needle = &mut &input[..];
+
+        let len = needle.get_u32_le();
+        if len & QUICK_ACK_FLAG != 0 {
+            // Quick acknowledgements are sent in place of the length, without any payload.
+            output.put_u32_le(len);
+            return Ok(4);
+        }
+
+        let len = len as usize;
+        if needle.len() < len {
+            return Err(Error::MissingBytes);
+        }
+        // Messages are always padded to 4 bytes, so anything beyond that is random padding.
+        output.put(&needle[..len - len % 4]);
+
+        Ok(len + 4)
+    }
+}
+
+impl Tagged for PaddedIntermediate {
+    fn init_tag(&mut self) -> [u8; 4] {
+        self.init = true;
+        [0xdd, 0xdd, 0xdd, 0xdd]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a new padded intermediate transport, `n` bytes of input data for it, and an empty
+    /// output buffer.
+    fn setup_pack(n: u32) -> (PaddedIntermediate, Vec<u8>, BytesMut) {
+        let input = (0..n).map(|x| (x & 0xff) as u8).collect();
+        (PaddedIntermediate::new(), input, BytesMut::new())
+    }
+
+    #[test]
+    fn pack_normal() {
+        let (mut transport, input, mut output) = setup_pack(128);
+        transport.pack(&input, &mut output);
+        assert_eq!(&output[..4], &[0xdd, 0xdd, 0xdd, 0xdd]);
+        let len = u32::from_le_bytes([output[4], output[5], output[6], output[7]]) as usize;
+        assert!((128..144).contains(&len));
+        assert_eq!(output.len(), 8 + len);
+        assert_eq!(&output[8..8 + 128], &input[..]);
+    }
+
+    #[test]
+    fn unpack_small() {
+        let mut transport = PaddedIntermediate::new();
+        let input = [1];
+        let mut output = BytesMut::new();
+        assert_eq!(
+            transport.unpack(&input, &mut output),
+            Err(Error::MissingBytes)
+        );
+    }
+
+    #[test]
+    fn unpack_normal() {
+        let (mut transport, input, mut packed) = setup_pack(128);
+        let mut unpacked = BytesMut::new();
+        transport.pack(&input, &mut packed);
+        let used = transport.unpack(&packed[4..], &mut unpacked).unwrap();
+        assert_eq!(used, packed.len() - 4);
+        assert_eq!(&unpacked[..128], &input[..]);
+        assert_eq!(unpacked.len() % 4, 0);
+    }
+
+    #[test]
+    fn unpack_padded() {
+        let mut transport = PaddedIntermediate::new();
+        let mut packed = BytesMut::new();
+        packed.put_u32_le(8 + 6);
+        packed.put(&[1, 2, 3, 4, 5, 6, 7, 8][..]);
+        packed.put(&[0xff; 6][..]);
+
+        let mut unpacked = BytesMut::new();
+        assert_eq!(transport.unpack(&packed, &mut unpacked), Ok(18));
+        assert_eq!(
+            &unpacked[..],
+            &[1, 2, 3, 4, 5, 6, 7, 8, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Establishing the underlying connections over which the data is exchanged.
+use crate::Proxy;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// A boxed future, as returned by the [`Connector`] trait.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A byte stream over which the sender exchanges data with the server.
+///
+/// The stream only needs to deliver the bytes in order. Splitting them into packets (and
+/// encrypting them) is done by the sender's [`Transport`] and MTP.
+///
+/// This is implemented for every type that can be read from and written to asynchronously, such
+/// as TCP streams, Unix sockets, or in-memory pipes.
+///
+/// [`Transport`]: grammers_mtproto::transport::Transport
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+/// Establishes the [`Connection`]s used by the sender, so that something other than a direct TCP
+/// connection can be used (such as tunnels, or harnesses for tests).
+///
+/// The [`Proxy`] implements this trait too.
+pub trait Connector: Send + Sync {
+    /// Open a new connection to the server at the given address.
+    ///
+    /// The address is the one of the datacenter the sender wants to reach. Connectors which
+    /// always lead to the same place are free to ignore it.
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Box<dyn Connection>>>;
+}
+
+/// Resolves host names into the addresses the connections are made to.
+///
+/// Proxies are usually given by their host name, which is resolved using the system's DNS by
+/// default. A custom resolver can be used instead to control exactly where the sockets go.
+///
+/// A map of host names to addresses implements this trait too. Host names not in the map are
+/// resolved using the system's DNS.
+pub trait Resolver: Send + Sync {
+    /// Resolve the host name (without the port) into the addresses it points to, in the order
+    /// they should be tried.
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>>;
+}
+
+/// The [`Resolver`] that uses the system's DNS.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>> {
+        Box::pin(async move {
+            Ok(tokio::net::lookup_host((host, 0))
+                .await?
+                .map(|addr| addr.ip())
+                .collect())
+        })
+    }
+}
+
+impl Resolver for HashMap<String, IpAddr> {
+    fn resolve<'a>(&'a self, host: &'a str) -> BoxFuture<'a, io::Result<Vec<IpAddr>>> {
+        match self.get(host) {
+            Some(ip) => {
+                let ip = *ip;
+                Box::pin(async move { Ok(vec![ip]) })
+            }
+            None => SystemResolver.resolve(host),
+        }
+    }
+}
+
+/// Options applied to the TCP sockets before connecting them.
+///
+/// These options are also a [`Connector`] that connects directly to the server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Whether to disable Nagle's algorithm (`TCP_NODELAY`), so that small packets are sent
+    /// right away instead of being grouped together.
+    pub nodelay: bool,
+    /// Whether to enable TCP keepalive, and after how long without activity the probes are sent
+    /// (which is also the interval between them).
+    pub keepalive: Option<Duration>,
+    /// The local address to connect from, which is useful on servers with multiple addresses.
+    pub local_addr: Option<IpAddr>,
+    /// The name of the network interface to bind the socket to (`SO_BINDTODEVICE`), which is
+    /// only supported on Linux and Android.
+    pub interface: Option<String>,
+    /// How long to wait for a connection to be established before giving up.
+    pub connect_timeout: Option<Duration>,
+}
+
+impl SocketOptions {
+    /// Create a socket with these options and connect it to the address.
+    pub async fn connect_tcp(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        if let Some(time) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new()
+                .with_time(time)
+                .with_interval(time);
+            socket2::SockRef::from(&socket).set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(interface) = &self.interface {
+            bind_device(&socket, interface)?;
+        }
+        if let Some(ip) = self.local_addr {
+            socket.bind(SocketAddr::new(ip, 0))?;
+        }
+
+        let stream = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, socket.connect(addr))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connection timed out"))??,
+            None => socket.connect(addr).await?,
+        };
+        stream.set_nodelay(self.nodelay)?;
+        Ok(stream)
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+fn bind_device(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+    socket2::SockRef::from(socket).bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux")))]
+fn bind_device(_socket: &TcpSocket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface is not supported on this platform",
+    ))
+}
+
+impl Connector for SocketOptions {
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let stream = self.connect_tcp(addr).await?;
+            Ok(Box::new(stream) as Box<dyn Connection>)
+        })
+    }
+}
+
+/// A [`Proxy`] whose host name is resolved using a custom [`Resolver`], and which is connected
+/// to with custom [`SocketOptions`].
+pub struct ProxyConnector<'a> {
+    pub proxy: &'a Proxy,
+    pub resolver: &'a dyn Resolver,
+    pub socket_options: &'a SocketOptions,
+}
+
+impl Connector for Proxy {
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let stream = self
+                .tunnel(addr, &SystemResolver, &SocketOptions::default())
+                .await?;
+            Ok(Box::new(stream) as Box<dyn Connection>)
+        })
+    }
+}
+
+impl Connector for ProxyConnector<'_> {
+    fn connect(&self, addr: SocketAddr) -> BoxFuture<'_, io::Result<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let stream = self
+                .proxy
+                .tunnel(addr, self.resolver, self.socket_options)
+                .await?;
+            Ok(Box::new(stream) as Box<dyn Connection>)
+        })
+    }
+}
+
+/// Split an address given as `host:port` (with IPv6 addresses between brackets).
+fn split_host_port(addr: &str) -> io::Result<(&str, u16)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid host and port");
+    let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    Ok((host, port.parse().map_err(|_| invalid())?))
+}
+
+/// Connect to the address given as `host:port`, trying every address the host resolves to.
+pub(crate) async fn connect_host(
+    addr: &str,
+    resolver: &dyn Resolver,
+    socket_options: &SocketOptions,
+) -> io::Result<TcpStream> {
+    let (host, port) = split_host_port(addr)?;
+    let ips = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => resolver.resolve(host).await?,
+    };
+
+    let mut last_error = None;
+    for ip in ips {
+        match socket_options.connect_tcp(SocketAddr::new(ip, port)).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "host did not resolve")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn split_addresses() {
+        assert_eq!(
+            split_host_port("proxy.example.com:1080").unwrap(),
+            ("proxy.example.com", 1080)
+        );
+        assert_eq!(split_host_port("[::1]:443").unwrap(), ("::1", 443));
+        assert!(split_host_port("proxy.example.com").is_err());
+        assert!(split_host_port("proxy.example.com:http").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_static_hosts() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let mut hosts = HashMap::new();
+        hosts.insert("proxy.internal".to_string(), ip);
+        assert_eq!(hosts.resolve("proxy.internal").await.unwrap(), vec![ip]);
+    }
+
+    #[tokio::test]
+    async fn connect_with_socket_options() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(30)),
+            local_addr: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            interface: None,
+            connect_timeout: Some(Duration::from_secs(5)),
+        };
+
+        let stream = options
+            .connect_tcp(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert_eq!(stream.local_addr().unwrap().ip(), Ipv4Addr::LOCALHOST);
+    }
+}
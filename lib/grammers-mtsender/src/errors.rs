@@ -9,6 +9,7 @@ use grammers_mtproto::{authentication, mtp, transport};
 use grammers_tl_types as tl;
 use std::fmt;
 use std::io;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum ReadError {
@@ -68,6 +69,19 @@ pub enum InvocationError {
 
     /// The error occured while reading the response.
     Read(ReadError),
+
+    /// Too many requests were made in a short period of time (or a slow mode is active), so
+    /// the request was rejected, and it may only be retried after the given delay.
+    ///
+    /// This is what `FLOOD_WAIT_X` errors (or any other with code 420) become when the client
+    /// is configured to sleep on short flood waits, but the delay was too long to do so.
+    /// Otherwise, they're returned as [`InvocationError::Rpc`], which keeps the name of the error.
+    FloodWait(Duration),
+
+    /// The result of the request did not arrive within the given time, so it was given up on.
+    ///
+    /// The request may have still been executed by the server.
+    Timeout(Duration),
 }
 
 impl std::error::Error for InvocationError {}
@@ -78,6 +92,16 @@ impl fmt::Display for InvocationError {
             Self::Rpc(err) => write!(f, "request error: {}", err),
             Self::Dropped => write!(f, "request error: dropped (cancelled)"),
             Self::Read(err) => write!(f, "request error: {}", err),
+            Self::FloodWait(delay) => {
+                write!(
+                    f,
+                    "request error: flood wait of {} seconds",
+                    delay.as_secs()
+                )
+            }
+            Self::Timeout(timeout) => {
+                write!(f, "request error: timed out after {:?}", timeout)
+            }
         }
     }
 }
@@ -105,6 +129,7 @@ impl InvocationError {
     ///
     /// Useful in `match` arm guards. A single trailing or leading asterisk (`'*'`) is allowed,
     /// and will instead check if the error name starts (or ends with) the input parameter.
+    /// Documented names such as `FLOOD_WAIT_X` are also accepted, as with [`mtp::RpcError::is`].
     ///
     /// If the error is not a RPC error, returns `false`.
     ///
@@ -127,6 +152,21 @@ impl InvocationError {
             _ => false,
         }
     }
+
+    /// The delay after which the request may be retried, if it failed because too many requests
+    /// were made (or a slow mode is active).
+    ///
+    /// This is the case for [`InvocationError::FloodWait`], and for RPC errors with code 420
+    /// which carry the delay, such as `FLOOD_WAIT_X` or `SLOWMODE_WAIT_X`.
+    pub fn flood_wait(&self) -> Option<Duration> {
+        match self {
+            Self::FloodWait(delay) => Some(*delay),
+            Self::Rpc(rpc) if rpc.code == 420 => {
+                rpc.value.map(|secs| Duration::from_secs(secs as u64))
+            }
+            _ => None,
+        }
+    }
 }
 
 /// This error occurs when the process to generate an authorization key fails.
@@ -5,20 +5,27 @@
 // <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+mod connector;
 mod errors;
+mod proxy;
 
 use bytes::{Buf, BytesMut};
+pub use connector::{
+    BoxFuture, Connection, Connector, ProxyConnector, Resolver, SocketOptions, SystemResolver,
+};
 pub use errors::{AuthorizationError, InvocationError, ReadError};
-use grammers_mtproto::mtp::{self, Mtp};
+use grammers_mtproto::mtp::{self, BadMessageReason, Mtp};
 use grammers_mtproto::transport::{self, Transport};
 use grammers_mtproto::{authentication, MsgId};
 use grammers_tl_types::{self as tl, Deserializable, RemoteCall};
 use log::{debug, info, trace, warn};
+pub use proxy::{ParseProxyError, Proxy};
 use std::io;
 use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
 use std::time::SystemTime;
 use tl::Serializable;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
@@ -36,17 +43,18 @@ use tokio::time::{sleep_until, Duration, Instant};
 /// kilobytes to the maximum data size.
 const MAXIMUM_DATA: usize = (1024 * 1024) + (8 * 1024);
 
-/// Every how often are pings sent?
-const PING_DELAY: Duration = Duration::from_secs(60);
+/// Every how often are pings sent, unless changed with [`Sender::set_keepalive`]?
+pub const PING_DELAY: Duration = Duration::from_secs(60);
 
-/// After how many seconds should the server close the connection when we send a ping?
+/// After how long should the server close the connection when we send a ping, unless changed
+/// with [`Sender::set_keepalive`]?
 ///
 /// What this value essentially means is that we have `NO_PING_DISCONNECT - PING_DELAY` seconds
 /// to keep sending pings, or the server will close the connection.
 ///
 /// Pings ensure the connection is kept active, and the delayed disconnect ensures the messages
 /// are getting through consistently enough.
-const NO_PING_DISCONNECT: i32 = 75;
+pub const NO_PING_DISCONNECT: Duration = Duration::from_secs(75);
 
 /// Generate a "random" ping ID.
 pub(crate) fn generate_random_id() -> i64 {
@@ -68,7 +76,8 @@ pub(crate) fn generate_random_id() -> i64 {
 
 // Manages enqueuing requests, matching them to their response, and IO.
 pub struct Sender<T: Transport, M: Mtp> {
-    stream: TcpStream,
+    reader: ReadHalf<Box<dyn Connection>>,
+    writer: WriteHalf<Box<dyn Connection>>,
     transport: T,
     mtp: M,
     mtp_buffer: BytesMut,
@@ -79,17 +88,105 @@ pub struct Sender<T: Transport, M: Mtp> {
     request_tx: mpsc::UnboundedSender<Request>,
     request_rx: mpsc::UnboundedReceiver<Request>,
     next_ping: Instant,
+    ping_delay: Duration,
+    ping_disconnect: Duration,
 
     // Transport-level buffers and positions
     read_buffer: BytesMut,
     write_buffer: BytesMut,
     write_index: usize,
+
+    // Bytes sent and received through the connection since they were last taken.
+    bytes_sent: usize,
+    bytes_received: usize,
+
+    // Requests ignored by the server since they were last taken.
+    bad_messages: Vec<BadMessage>,
+}
+
+/// A request which the server ignored because the message containing it was invalid, so it was
+/// sent again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BadMessage {
+    /// The name of the function requested, such as `messages.sendMessage`.
+    pub request: &'static str,
+    /// Why the server ignored the message.
+    pub reason: BadMessageReason,
 }
 
 struct Request {
     body: Vec<u8>,
     state: RequestState,
-    result: oneshot::Sender<Result<Vec<u8>, InvocationError>>,
+    // `None` for the requests made by the sender itself, whose result nobody waits for.
+    result: Option<oneshot::Sender<Result<Vec<u8>, InvocationError>>>,
+    // Told the message identifier of the request for as long as it's pending, if any.
+    handle: Option<RequestHandle>,
+    // The request which must be executed by the server before this one, if any.
+    after: Option<RequestHandle>,
+    // Notified when the server quickly acknowledges the request, if it should be asked to.
+    quick_ack: Option<oneshot::Sender<()>>,
+    // The span in which the request was made, which is told its message identifier.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Request {
+    fn new(
+        body: Vec<u8>,
+        state: RequestState,
+        result: Option<oneshot::Sender<Result<Vec<u8>, InvocationError>>>,
+    ) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: if result.is_some() {
+                tracing::Span::current()
+            } else {
+                tracing::Span::none()
+            },
+            body,
+            state,
+            result,
+            handle: None,
+            quick_ack: None,
+            after: None,
+        }
+    }
+
+    /// The body to serialize, wrapped in `invokeAfterMsg` if the request it must be executed
+    /// after is still pending.
+    fn wrapped_body(&self) -> Option<Vec<u8>> {
+        let msg_id = self.after.as_ref()?.msg_id()?;
+        let mut body = Vec::with_capacity(12 + self.body.len());
+        <tl::functions::InvokeAfterMsg<tl::functions::Ping> as tl::Identifiable>::CONSTRUCTOR_ID
+            .serialize(&mut body);
+        msg_id.serialize(&mut body);
+        body.extend_from_slice(&self.body);
+        Some(body)
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        // Requests made after this one no longer need to wait for it.
+        if let Some(handle) = &self.handle {
+            handle.0.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Refers to a request made through [`Enqueuer::enqueue_after`], so that other requests can be
+/// executed by the server only after it.
+#[derive(Clone, Debug, Default)]
+pub struct RequestHandle(Arc<AtomicI64>);
+
+impl RequestHandle {
+    /// The message identifier of the request, if it has been serialized and is still pending.
+    fn msg_id(&self) -> Option<i64> {
+        match self.0.load(Ordering::SeqCst) {
+            0 => None,
+            msg_id => Some(msg_id),
+        }
+    }
 }
 
 enum RequestState {
@@ -105,6 +202,52 @@ impl Enqueuer {
     pub fn enqueue<R: RemoteCall>(
         &self,
         request: &R,
+    ) -> oneshot::Receiver<Result<Vec<u8>, InvocationError>> {
+        self.enqueue_request(request, None, None, None)
+    }
+
+    /// Like [`Enqueuer::enqueue`], but if the given request is still pending when this one is
+    /// sent, the server is told to execute it only after that one (with `invokeAfterMsg`),
+    /// without having to wait for its result first.
+    ///
+    /// The returned handle can be used to order further requests after this one.
+    pub fn enqueue_after<R: RemoteCall>(
+        &self,
+        request: &R,
+        after: Option<&RequestHandle>,
+    ) -> (
+        RequestHandle,
+        oneshot::Receiver<Result<Vec<u8>, InvocationError>>,
+    ) {
+        let handle = RequestHandle::default();
+        let rx = self.enqueue_request(request, Some(handle.clone()), after.cloned(), None);
+        (handle, rx)
+    }
+
+    /// Like [`Enqueuer::enqueue`], but the server is also asked to quickly acknowledge the
+    /// request as soon as it receives it, which is notified through the first receiver returned,
+    /// long before the result arrives for requests which take a while to execute.
+    ///
+    /// Not all transports support quick acknowledgements, in which case the notification will
+    /// never be sent.
+    pub fn enqueue_with_quick_ack<R: RemoteCall>(
+        &self,
+        request: &R,
+    ) -> (
+        oneshot::Receiver<()>,
+        oneshot::Receiver<Result<Vec<u8>, InvocationError>>,
+    ) {
+        let (tx, quick_ack) = oneshot::channel();
+        let rx = self.enqueue_request(request, None, None, Some(tx));
+        (quick_ack, rx)
+    }
+
+    fn enqueue_request<R: RemoteCall>(
+        &self,
+        request: &R,
+        handle: Option<RequestHandle>,
+        after: Option<RequestHandle>,
+        quick_ack: Option<oneshot::Sender<()>>,
     ) -> oneshot::Receiver<Result<Vec<u8>, InvocationError>> {
         // TODO we probably want a bound here (to not enqueue more than N at once)
         let body = request.to_bytes();
@@ -116,30 +259,59 @@ impl Enqueuer {
         );
 
         let (tx, rx) = oneshot::channel();
-        if let Err(err) = self.0.send(Request {
-            body,
-            state: RequestState::NotSerialized,
-            result: tx,
-        }) {
-            err.0.result.send(Err(InvocationError::Dropped)).unwrap();
+        let mut request = Request::new(body, RequestState::NotSerialized, Some(tx));
+        request.handle = handle;
+        request.after = after;
+        request.quick_ack = quick_ack;
+        if let Err(mut err) = self.0.send(request) {
+            if let Some(result) = err.0.result.take() {
+                drop(result.send(Err(InvocationError::Dropped)));
+            }
         }
         rx
     }
 }
 
+/// Connect to the address, using the connector if one is given (such as a proxy).
+async fn connect_stream<A: ToSocketAddrs>(
+    addr: A,
+    connector: Option<&dyn Connector>,
+) -> io::Result<Box<dyn Connection>> {
+    match connector {
+        Some(connector) => {
+            let target = tokio::net::lookup_host(addr).await?.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve")
+            })?;
+            info!("connecting to {}...", target);
+            connector.connect(target).await
+        }
+        None => {
+            info!("connecting...");
+            Ok(Box::new(TcpStream::connect(addr).await?))
+        }
+    }
+}
+
 impl<T: Transport, M: Mtp> Sender<T, M> {
     async fn connect<A: ToSocketAddrs>(
         transport: T,
         mtp: M,
         addr: A,
+        connector: Option<&dyn Connector>,
     ) -> Result<(Self, Enqueuer), io::Error> {
-        info!("connecting...");
-        let stream = TcpStream::connect(addr).await?;
+        let stream = connect_stream(addr, connector).await?;
+        Ok(Self::from_connection(transport, mtp, stream))
+    }
+
+    /// Build the sender over an already-established connection.
+    fn from_connection(transport: T, mtp: M, stream: Box<dyn Connection>) -> (Self, Enqueuer) {
+        let (reader, writer) = tokio::io::split(stream);
         let (tx, rx) = mpsc::unbounded_channel();
 
-        Ok((
+        (
             Self {
-                stream,
+                reader,
+                writer,
                 transport,
                 mtp,
                 mtp_buffer: BytesMut::with_capacity(MAXIMUM_DATA),
@@ -148,13 +320,19 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
                 request_tx: tx.clone(),
                 request_rx: rx,
                 next_ping: Instant::now() + PING_DELAY,
+                ping_delay: PING_DELAY,
+                ping_disconnect: NO_PING_DISCONNECT,
 
                 read_buffer: BytesMut::with_capacity(MAXIMUM_DATA),
                 write_buffer: BytesMut::with_capacity(MAXIMUM_DATA),
                 write_index: 0,
+
+                bytes_sent: 0,
+                bytes_received: 0,
+                bad_messages: Vec::new(),
             },
             Enqueuer(tx),
-        ))
+        )
     }
 
     pub async fn invoke<R: RemoteCall>(&mut self, request: &R) -> Result<Vec<u8>, InvocationError> {
@@ -180,14 +358,56 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
         );
 
         let (tx, rx) = oneshot::channel();
-        self.requests.push(Request {
-            body,
-            state: RequestState::NotSerialized,
-            result: tx,
-        });
+        self.requests
+            .push(Request::new(body, RequestState::NotSerialized, Some(tx)));
         rx
     }
 
+    /// Enqueue a request made by the sender itself, whose result is not needed.
+    fn enqueue_internal(&mut self, body: Vec<u8>) {
+        self.requests
+            .push(Request::new(body, RequestState::NotSerialized, None));
+    }
+
+    /// Take every request which has been enqueued so far, so that all of those made at the same
+    /// time can be sent together in the same container, rather than one per step.
+    fn receive_enqueued(&mut self) {
+        while let Ok(request) = self.request_rx.try_recv() {
+            self.requests.push(request);
+        }
+    }
+
+    /// Forget about the requests whose result is no longer awaited by anyone (for example,
+    /// because the future waiting for it was dropped after a timeout).
+    ///
+    /// The server is asked to drop the answer of those which were already sent, so that it
+    /// doesn't have to keep them around until they're acknowledged.
+    fn cancel_abandoned(&mut self) {
+        let mut dropped = Vec::new();
+        self.requests.retain(|req| {
+            if !req.result.as_ref().is_some_and(|result| result.is_closed()) {
+                return true;
+            }
+            match req.state {
+                RequestState::NotSerialized => {}
+                RequestState::Serialized(msg_id) | RequestState::Sent(msg_id) => {
+                    dropped.push(msg_id)
+                }
+            }
+            false
+        });
+
+        for msg_id in dropped {
+            debug!("dropping answer to abandoned request {:?}", msg_id);
+            self.enqueue_internal(
+                tl::functions::RpcDropAnswer {
+                    req_msg_id: msg_id.value(),
+                }
+                .to_bytes(),
+            );
+        }
+    }
+
     async fn step_until_receive(
         &mut self,
         mut rx: oneshot::Receiver<Result<Vec<u8>, InvocationError>>,
@@ -204,18 +424,31 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
         }
     }
 
+    /// Gracefully close the connection, writing any data which is still pending first.
+    ///
+    /// Further attempts to step the network will fail.
+    pub async fn disconnect(&mut self) -> io::Result<()> {
+        self.writer
+            .write_all(&self.write_buffer[self.write_index..])
+            .await?;
+        self.write_index = self.write_buffer.len();
+        self.writer.shutdown().await
+    }
+
     /// Step network events, writing and reading at the same time.
     ///
     /// Updates received during this step, if any, are returned.
     pub async fn step(&mut self) -> Result<Vec<tl::enums::Updates>, ReadError> {
+        self.receive_enqueued();
+        self.cancel_abandoned();
         self.try_fill_write();
 
         // TODO probably want to properly set the request state on disconnect (read fail)
 
         let write_len = self.write_buffer.len() - self.write_index;
 
-        let (mut reader, mut writer) = self.stream.split();
         if self.write_buffer.is_empty() {
+            let flush_deadline = self.mtp.flush_deadline().map(Instant::from_std);
             // TODO this always has to read the header of the packet and then the rest (2 or more calls)
             // it would be better to always perform calls in a circular buffer to have as much data from
             // the network as possible at all times, not just reading what's needed
@@ -228,13 +461,16 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
                     self.requests.push(request.unwrap());
                     Ok(Vec::new())
                 },
-                n = reader.read_buf(&mut self.read_buffer) => {
+                n = self.reader.read_buf(&mut self.read_buffer) => {
                     self.on_net_read(n?)
                 },
                 _ = sleep_until(self.next_ping) => {
                     self.on_ping_timeout();
                     Ok(Vec::new())
                 }
+                // The service messages are sent on the next step.
+                _ = sleep_until(flush_deadline.unwrap_or(self.next_ping)),
+                    if flush_deadline.is_some() => Ok(Vec::new()),
             )
         } else {
             trace!(
@@ -246,10 +482,10 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
                     self.requests.push(request.unwrap());
                     Ok(Vec::new())
                 },
-                n = reader.read_buf(&mut self.read_buffer) => {
+                n = self.reader.read_buf(&mut self.read_buffer) => {
                     self.on_net_read(n?)
                 }
-                n = writer.write(&self.write_buffer[self.write_index..]) => {
+                n = self.writer.write(&self.write_buffer[self.write_index..]) => {
                     self.on_net_write(n?);
                     Ok(Vec::new())
                 }
@@ -278,24 +514,48 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
             .collect::<Vec<_>>();
 
         // TODO add a test to make sure we don't send empty data
-        if requests.is_empty() {
+        let flush_due = self
+            .mtp
+            .flush_deadline()
+            .is_some_and(|deadline| Instant::from_std(deadline) <= Instant::now());
+        if requests.is_empty() && !flush_due {
             return;
         }
 
         // TODO make mtp itself use BytesMut to avoid copies
         let mut msg_ids = Vec::new();
         for request in requests.iter() {
-            if let Some(msg_id) = self.mtp.push(&request.body) {
+            // The request it goes after, if any, was enqueued first, so it's already been given
+            // its message identifier by now.
+            let pushed = match request.wrapped_body() {
+                Some(body) => self.mtp.push(&body),
+                None => self.mtp.push(&request.body),
+            };
+            if let Some(msg_id) = pushed {
+                if let Some(handle) = &request.handle {
+                    handle.0.store(msg_id.value(), Ordering::SeqCst);
+                }
+                if request.quick_ack.is_some() {
+                    self.mtp.request_quick_ack(msg_id);
+                }
                 msg_ids.push(msg_id);
             } else {
                 break;
             }
         }
         let temp_vec = self.mtp.finalize();
+        if temp_vec.is_empty() {
+            return;
+        }
         self.mtp_buffer = temp_vec[..].into();
         self.write_buffer.clear();
-        self.transport
-            .pack(&self.mtp_buffer, &mut self.write_buffer);
+        if self.mtp.wants_quick_ack() {
+            self.transport
+                .pack_quick_ack(&self.mtp_buffer, &mut self.write_buffer);
+        } else {
+            self.transport
+                .pack(&self.mtp_buffer, &mut self.write_buffer);
+        }
 
         // NOTE: we have to use the FILTERED requests, not the saved ones.
         // The key to finding this was printing the old and new state (but took ~2h to find).
@@ -309,6 +569,18 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
                 assert!(req.body.len() >= 4);
                 let req_id =
                     u32::from_le_bytes([req.body[0], req.body[1], req.body[2], req.body[3]]);
+                #[cfg(feature = "tracing")]
+                {
+                    req.span.record("msg_id", msg_id.value());
+                    req.span.in_scope(|| {
+                        tracing::debug!(
+                            method = tl::name_for_id(req_id),
+                            msg_id = msg_id.value(),
+                            "serialized request"
+                        )
+                    });
+                }
+                #[cfg(not(feature = "tracing"))]
                 debug!(
                     "serialized request {:x} ({}) with {:?}",
                     req_id,
@@ -331,6 +603,7 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
         }
 
         trace!("read {} bytes from the network", n);
+        self.bytes_received += n;
 
         trace!(
             "trying to unpack buffer of {} bytes...",
@@ -339,8 +612,11 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
 
         // TODO the buffer might have multiple transport packets, what should happen with the
         // updates successfully read if subsequent packets fail to be deserialized properly?
+        //
+        // Some transports buffer data on their own, so they're asked to unpack even when the read
+        // buffer is empty, until they report that they're missing bytes.
         let mut updates = Vec::new();
-        while !self.read_buffer.is_empty() {
+        loop {
             self.mtp_buffer.clear();
             match self
                 .transport
@@ -348,7 +624,9 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
             {
                 Ok(n) => {
                     self.read_buffer.advance(n);
-                    self.process_mtp_buffer(&mut updates)?;
+                    if !self.mtp_buffer.is_empty() {
+                        self.process_mtp_buffer(&mut updates)?;
+                    }
                 }
                 Err(transport::Error::MissingBytes) => break,
                 Err(err) => return Err(err.into()),
@@ -361,6 +639,7 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
     /// Handle `n` more written bytes being ready to process by the transport.
     fn on_net_write(&mut self, n: usize) {
         self.write_index += n;
+        self.bytes_sent += n;
         trace!(
             "written {} bytes to the network ({}/{})",
             n,
@@ -389,16 +668,14 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
     fn on_ping_timeout(&mut self) {
         let ping_id = generate_random_id();
         debug!("enqueueing keepalive ping {}", ping_id);
-        drop(
-            self.enqueue_body(
-                tl::functions::PingDelayDisconnect {
-                    ping_id,
-                    disconnect_delay: NO_PING_DISCONNECT,
-                }
-                .to_bytes(),
-            ),
+        self.enqueue_internal(
+            tl::functions::PingDelayDisconnect {
+                ping_id,
+                disconnect_delay: self.ping_disconnect.as_secs() as i32,
+            }
+            .to_bytes(),
         );
-        self.next_ping = Instant::now() + PING_DELAY;
+        self.next_ping = Instant::now() + self.ping_delay;
     }
 
     /// Process the `mtp_buffer` contents and dispatch the results and errors.
@@ -422,6 +699,17 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
             }
         }));
 
+        for msg_id in result.quick_acks {
+            let request = self.requests.iter_mut().find(|req| match req.state {
+                RequestState::Serialized(sid) | RequestState::Sent(sid) => sid == msg_id,
+                RequestState::NotSerialized => false,
+            });
+            if let Some(quick_ack) = request.and_then(|req| req.quick_ack.take()) {
+                debug!("request {:?} was quickly acknowledged", msg_id);
+                let _ = quick_ack.send(());
+            }
+        }
+
         for (msg_id, ret) in result.rpc_results {
             let mut found = false;
             for i in (0..self.requests.len()).rev() {
@@ -462,16 +750,25 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
                                 );
                                 Err(InvocationError::Read(error.into()))
                             }
-                            Err(err @ mtp::RequestError::BadMessage { .. }) => {
+                            Err(err @ mtp::RequestError::BadMessage { code }) => {
                                 // TODO add a test to make sure we resend the request
                                 info!("{}; re-sending request {:?}", err, msg_id);
                                 req.state = RequestState::NotSerialized;
+                                let x = req.body.as_slice();
+                                self.bad_messages.push(BadMessage {
+                                    request: tl::name_for_id(u32::from_le_bytes([
+                                        x[0], x[1], x[2], x[3],
+                                    ])),
+                                    reason: BadMessageReason::from(code),
+                                });
                                 break;
                             }
                         };
 
-                        let req = self.requests.remove(i);
-                        drop(req.result.send(result));
+                        let mut req = self.requests.remove(i);
+                        if let Some(tx) = req.result.take() {
+                            drop(tx.send(result));
+                        }
                         break;
                     }
                     _ => {}
@@ -487,57 +784,314 @@ impl<T: Transport, M: Mtp> Sender<T, M> {
     }
 }
 
+impl<T: Transport, M: Mtp> Sender<T, M> {
+    /// Move the requests which have not been answered yet, including those enqueued but not
+    /// received yet, to a different sender, so that they're sent through its connection instead.
+    ///
+    /// Requests which were already sent will be sent again by the other sender.
+    pub fn transfer_requests<N: Mtp>(&mut self, other: &mut Sender<T, N>) {
+        self.receive_enqueued();
+        other
+            .requests
+            .extend(self.requests.drain(..).map(|mut request| {
+                request.state = RequestState::NotSerialized;
+                request
+            }));
+    }
+
+    /// Change every how often pings are sent to keep the connection alive, and after how long
+    /// without pings the server should close the connection.
+    ///
+    /// The disconnect delay should be greater than the ping delay, or the connection will be
+    /// closed before the next ping is sent.
+    pub fn set_keepalive(&mut self, ping_delay: Duration, disconnect_delay: Duration) {
+        self.next_ping = Instant::now() + ping_delay;
+        self.ping_delay = ping_delay;
+        self.ping_disconnect = disconnect_delay;
+    }
+
+    /// Return the requests which the server ignored, and were sent again, since the last time
+    /// this method was called.
+    pub fn take_bad_messages(&mut self) -> Vec<BadMessage> {
+        std::mem::take(&mut self.bad_messages)
+    }
+
+    /// Return how many bytes were sent and received through the connection (in that order)
+    /// since the last time this method was called.
+    pub fn take_traffic(&mut self) -> (usize, usize) {
+        let traffic = (self.bytes_sent, self.bytes_received);
+        self.bytes_sent = 0;
+        self.bytes_received = 0;
+        traffic
+    }
+
+    /// Replace the MTP used to encrypt and decrypt the messages, keeping the same connection.
+    fn with_mtp<N: Mtp>(self, mtp: N) -> Sender<T, N> {
+        Sender {
+            reader: self.reader,
+            writer: self.writer,
+            transport: self.transport,
+            mtp,
+            mtp_buffer: self.mtp_buffer,
+            requests: self.requests,
+            request_tx: self.request_tx,
+            request_rx: self.request_rx,
+            next_ping: Instant::now() + self.ping_delay,
+            ping_delay: self.ping_delay,
+            ping_disconnect: self.ping_disconnect,
+            read_buffer: self.read_buffer,
+            write_buffer: self.write_buffer,
+            write_index: self.write_index,
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            bad_messages: self.bad_messages,
+        }
+    }
+}
+
+impl<T: Transport> Sender<T, mtp::Plain> {
+    /// Generate a new authorization key, which will be temporary if `temp` contains the
+    /// datacenter ID and the number of seconds after which it expires.
+    ///
+    /// The server's RSA key is looked up in `rsa_keys` before the built-in keys.
+    async fn generate_auth_key(
+        &mut self,
+        temp: Option<(i32, i32)>,
+        rsa_keys: &[authentication::RsaKey],
+    ) -> Result<authentication::Finished, AuthorizationError> {
+        info!("generating new authorization key...");
+        let (request, data) = authentication::step1()?;
+        debug!("gen auth key: sending step 1");
+        let response = self.send(request).await?;
+        debug!("gen auth key: starting step 2");
+        let (request, data) = match temp {
+            None => authentication::step2_with_keys(data, &response, rsa_keys)?,
+            Some((dc_id, expires_in)) => {
+                authentication::step2_temp_with_keys(data, &response, dc_id, expires_in, rsa_keys)?
+            }
+        };
+        debug!("gen auth key: sending step 2");
+        let response = self.send(request).await?;
+        debug!("gen auth key: starting step 3");
+        let (request, data) = authentication::step3(data, &response)?;
+        debug!("gen auth key: sending step 3");
+        let response = self.send(request).await?;
+        debug!("gen auth key: completing generation");
+        let finished = authentication::create_key(data, &response)?;
+        info!("authorization key generated successfully");
+        Ok(finished)
+    }
+
+    /// Generate a new authorization key and switch to encrypted messages using it.
+    async fn into_encrypted(
+        mut self,
+        temp: Option<(i32, i32)>,
+        rsa_keys: &[authentication::RsaKey],
+    ) -> Result<Sender<T, mtp::Encrypted>, AuthorizationError> {
+        let authentication::Finished {
+            auth_key,
+            time_offset,
+            first_salt,
+        } = self.generate_auth_key(temp, rsa_keys).await?;
+
+        Ok(self.with_mtp(
+            mtp::Encrypted::build()
+                .time_offset(time_offset)
+                .first_salt(first_salt)
+                .finish(auth_key),
+        ))
+    }
+}
+
 impl<T: Transport> Sender<T, mtp::Encrypted> {
     pub fn auth_key(&self) -> [u8; 256] {
         self.mtp.auth_key()
     }
+
+    /// The difference, in seconds, between the server's clock and ours.
+    pub fn time_offset(&self) -> i32 {
+        self.mtp.time_offset()
+    }
+
+    /// Change the size in bytes from which requests are compressed with gzip before being
+    /// sent, if doing so makes them smaller, or disable compression if `None`.
+    ///
+    /// By default, [`grammers_mtproto::DEFAULT_COMPRESSION_THRESHOLD`] is used. Responses are
+    /// always decompressed as needed.
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.mtp.set_compression_threshold(threshold);
+    }
+
+    /// Change how many received messages can be waiting to be acknowledged, and for how long,
+    /// before the acknowledgements are sent on their own rather than along with the next request.
+    ///
+    /// By default, [`grammers_mtproto::DEFAULT_MAX_PENDING_ACKS`] and
+    /// [`grammers_mtproto::DEFAULT_ACK_DELAY`] are used.
+    pub fn set_ack_batching(&mut self, max_pending_acks: usize, ack_delay: Duration) {
+        self.mtp.set_ack_batching(max_pending_acks, ack_delay);
+    }
+
+    /// Record every plaintext message sent and received in the given dump, or stop doing so if
+    /// `None`. This is meant for debugging, and disabled by default.
+    pub fn set_message_dump(&mut self, dump: Option<Arc<dyn mtp::MessageDump>>) {
+        self.mtp.set_dump(dump);
+    }
+
+    /// Bind the temporary authorization key in use to the given permanent key until
+    /// `expires_at`.
+    ///
+    /// Must be called before any other request is sent, because the request needs to be built
+    /// when its message ID is known.
+    async fn bind_temp_auth_key(
+        &mut self,
+        perm_auth_key: [u8; 256],
+        expires_at: i32,
+    ) -> Result<(), InvocationError> {
+        assert!(self.write_buffer.is_empty());
+        let (msg_id, body) = self
+            .mtp
+            .push_bind_temp_auth_key(perm_auth_key, expires_at)
+            .expect("bind request should fit in an empty buffer");
+
+        let temp_vec = self.mtp.finalize();
+        self.mtp_buffer = temp_vec[..].into();
+        self.transport
+            .pack(&self.mtp_buffer, &mut self.write_buffer);
+
+        debug!(
+            "serialized request to bind temporary auth key with {:?}",
+            msg_id
+        );
+        let (tx, rx) = oneshot::channel();
+        self.requests.push(Request::new(
+            body,
+            RequestState::Serialized(msg_id),
+            Some(tx),
+        ));
+
+        let response = self.step_until_receive(rx).await?;
+        match bool::from_bytes(&response)? {
+            true => Ok(()),
+            false => Err(InvocationError::Rpc(mtp::RpcError {
+                code: 400,
+                name: "ENCRYPTED_MESSAGE_INVALID".to_string(),
+                value: None,
+                caused_by: Some(
+                    <tl::functions::auth::BindTempAuthKey as tl::Identifiable>::CONSTRUCTOR_ID,
+                ),
+            })),
+        }
+    }
 }
 
 pub async fn connect<T: Transport, A: ToSocketAddrs>(
     transport: T,
     addr: A,
 ) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer), AuthorizationError> {
-    let (mut sender, enqueuer) = Sender::connect(transport, mtp::Plain::new(), addr).await?;
-
-    info!("generating new authorization key...");
-    let (request, data) = authentication::step1()?;
-    debug!("gen auth key: sending step 1");
-    let response = sender.send(request).await?;
-    debug!("gen auth key: starting step 2");
-    let (request, data) = authentication::step2(data, &response)?;
-    debug!("gen auth key: sending step 2");
-    let response = sender.send(request).await?;
-    debug!("gen auth key: starting step 3");
-    let (request, data) = authentication::step3(data, &response)?;
-    debug!("gen auth key: sending step 3");
-    let response = sender.send(request).await?;
-    debug!("gen auth key: completing generation");
-    let authentication::Finished {
-        auth_key,
-        time_offset,
-        first_salt,
-    } = authentication::create_key(data, &response)?;
-    info!("authorization key generated successfully");
-
-    Ok((
-        Sender {
-            stream: sender.stream,
-            transport: sender.transport,
-            mtp: mtp::Encrypted::build()
-                .time_offset(time_offset)
-                .first_salt(first_salt)
-                .finish(auth_key),
-            mtp_buffer: sender.mtp_buffer,
-            requests: sender.requests,
-            request_tx: sender.request_tx,
-            request_rx: sender.request_rx,
-            next_ping: Instant::now() + PING_DELAY,
-            read_buffer: sender.read_buffer,
-            write_buffer: sender.write_buffer,
-            write_index: sender.write_index,
-        },
-        enqueuer,
-    ))
+    connect_impl(transport, addr, None, &[]).await
+}
+
+/// Like [`connect`], but the connection is made through the given proxy (or any other
+/// [`Connector`]), and the given RSA keys are trusted along with the built-in keys of the
+/// official servers.
+pub async fn connect_via_proxy<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    connector: &dyn Connector,
+    rsa_keys: &[authentication::RsaKey],
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer), AuthorizationError> {
+    connect_impl(transport, addr, Some(connector), rsa_keys).await
+}
+
+async fn connect_impl<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    connector: Option<&dyn Connector>,
+    rsa_keys: &[authentication::RsaKey],
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer), AuthorizationError> {
+    let (sender, enqueuer) = Sender::connect(transport, mtp::Plain::new(), addr, connector).await?;
+    Ok((sender.into_encrypted(None, rsa_keys).await?, enqueuer))
+}
+
+/// Connect using a new temporary authorization key, bound to a permanent key, which provides
+/// [perfect forward secrecy].
+///
+/// If the permanent key is not given, a new one is generated first. The temporary key is only
+/// valid for the datacenter `dc_id`, and expires after `expires_in` seconds, so a new connection
+/// must be made before then.
+///
+/// The permanent key is returned along with the sender, and should be saved for future
+/// connections, unlike the temporary key.
+///
+/// [perfect forward secrecy]: https://core.telegram.org/api/pfs
+pub async fn connect_with_temp_auth<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    perm_auth_key: Option<[u8; 256]>,
+    dc_id: i32,
+    expires_in: i32,
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer, [u8; 256]), AuthorizationError> {
+    connect_with_temp_auth_impl(transport, addr, None, &[], perm_auth_key, dc_id, expires_in).await
+}
+
+/// Like [`connect_with_temp_auth`], but the connection is made through the given proxy (or any
+/// other [`Connector`]), and the given RSA keys are trusted along with the built-in keys of the
+/// official servers.
+pub async fn connect_with_temp_auth_via_proxy<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    connector: &dyn Connector,
+    rsa_keys: &[authentication::RsaKey],
+    perm_auth_key: Option<[u8; 256]>,
+    dc_id: i32,
+    expires_in: i32,
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer, [u8; 256]), AuthorizationError> {
+    connect_with_temp_auth_impl(
+        transport,
+        addr,
+        Some(connector),
+        rsa_keys,
+        perm_auth_key,
+        dc_id,
+        expires_in,
+    )
+    .await
+}
+
+async fn connect_with_temp_auth_impl<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    connector: Option<&dyn Connector>,
+    rsa_keys: &[authentication::RsaKey],
+    perm_auth_key: Option<[u8; 256]>,
+    dc_id: i32,
+    expires_in: i32,
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer, [u8; 256]), AuthorizationError> {
+    let (mut sender, enqueuer) =
+        Sender::connect(transport, mtp::Plain::new(), addr, connector).await?;
+
+    let perm_auth_key = match perm_auth_key {
+        Some(auth_key) => auth_key,
+        None => sender.generate_auth_key(None, rsa_keys).await?.auth_key,
+    };
+
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("system time is before epoch")
+        .as_secs() as i32;
+
+    let mut sender = sender
+        .into_encrypted(Some((dc_id, expires_in)), rsa_keys)
+        .await?;
+
+    // The expiration date must be in the server's time, but the time offset is small enough
+    // compared to the lifetime of the key that it doesn't matter.
+    info!("binding temporary authorization key");
+    sender
+        .bind_temp_auth_key(perm_auth_key, started + expires_in)
+        .await?;
+
+    Ok((sender, enqueuer, perm_auth_key))
 }
 
 pub async fn connect_with_auth<T: Transport, A: ToSocketAddrs>(
@@ -545,5 +1099,77 @@ pub async fn connect_with_auth<T: Transport, A: ToSocketAddrs>(
     addr: A,
     auth_key: [u8; 256],
 ) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer), io::Error> {
-    Ok(Sender::connect(transport, mtp::Encrypted::build().finish(auth_key), addr).await?)
+    Sender::connect(
+        transport,
+        mtp::Encrypted::build().finish(auth_key),
+        addr,
+        None,
+    )
+    .await
+}
+
+/// Like [`connect_with_auth`], but the connection is made through the given proxy (or any other
+/// [`Connector`]).
+pub async fn connect_with_auth_via_proxy<T: Transport, A: ToSocketAddrs>(
+    transport: T,
+    addr: A,
+    connector: &dyn Connector,
+    auth_key: [u8; 256],
+) -> Result<(Sender<T, mtp::Encrypted>, Enqueuer), io::Error> {
+    Sender::connect(
+        transport,
+        mtp::Encrypted::build().finish(auth_key),
+        addr,
+        Some(connector),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_mtproto::transport::Full;
+
+    /// A plain message as the server would send it, containing the given body.
+    fn plain_message(body: &[u8]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        0i64.serialize(&mut buffer);
+        1i64.serialize(&mut buffer);
+        (body.len() as i32).serialize(&mut buffer);
+        buffer.extend_from_slice(body);
+        buffer
+    }
+
+    #[test]
+    fn abandoned_request_is_forgotten_after_drop_answer() {
+        let (stream, _server) = tokio::io::duplex(MAXIMUM_DATA);
+        let (mut sender, enqueuer) =
+            Sender::from_connection(Full::new(), mtp::Plain::new(), Box::new(stream));
+
+        let rx = enqueuer.enqueue(&tl::functions::Ping { ping_id: 1 });
+        sender.receive_enqueued();
+        sender.try_fill_write();
+        sender.on_net_write(sender.write_buffer.len());
+        drop(rx);
+
+        sender.cancel_abandoned();
+        assert_eq!(sender.requests.len(), 1);
+        assert_eq!(
+            sender.requests[0].body,
+            tl::functions::RpcDropAnswer { req_msg_id: 0 }.to_bytes()
+        );
+        sender.try_fill_write();
+        sender.on_net_write(sender.write_buffer.len());
+
+        let answer = tl::enums::RpcDropAnswer::RpcAnswerDropped(tl::types::RpcAnswerDropped {
+            msg_id: 0,
+            seq_no: 1,
+            bytes: 8,
+        })
+        .to_bytes();
+        sender.mtp_buffer = plain_message(&answer)[..].into();
+        sender.process_mtp_buffer(&mut Vec::new()).unwrap();
+
+        assert!(sender.requests.is_empty());
+    }
 }
@@ -25,6 +25,7 @@ fn main() -> std::io::Result<()> {
         dataCenter flags:# id:int ipv4:flags.0?int ipv6:flags.1?int128 port:int auth:flags.2?bytes = DataCenter;
         user id:int dc:int bot:Bool = User;
         channelState channel_id:int pts:int = ChannelState;
+        channelStatePacked channel_id:int pts:int chat:bytes = ChannelState;
         updateState pts:int qts:int date:int seq:int channels:Vector<ChannelState> = UpdateState;
         session flags:# dcs:Vector<DataCenter> user:flags.0?User state:flags.1?UpdateState = Session;
         "#,
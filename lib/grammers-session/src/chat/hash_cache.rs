@@ -10,18 +10,65 @@ use grammers_tl_types as tl;
 use std::collections::HashMap;
 
 /// In-memory chat cache, mapping peers to their respective access hashes.
+///
+/// Peers are also indexed by their username and phone number, when known, so that they can be
+/// found without having to resolve them through the API again.
 pub struct ChatHashCache {
     // As far as I've observed, user, chat and channel IDs cannot collide,
     // but it will be an interesting moment if they ever do.
     hash_map: HashMap<i32, (i64, PackedType)>,
+    usernames: Index,
+    phones: Index,
     self_id: Option<i32>,
     self_bot: bool,
 }
 
+/// Secondary index from a key (such as an username) to the peer ID, which also remembers the
+/// key of each peer, so that it can be removed once the peer stops using it.
+#[derive(Default)]
+struct Index {
+    by_key: HashMap<String, i32>,
+    by_id: HashMap<i32, String>,
+}
+
+impl Index {
+    fn set(&mut self, id: i32, key: Option<String>) {
+        if let Some(old) = self.by_id.remove(&id) {
+            if self.by_key.get(&old) == Some(&id) {
+                self.by_key.remove(&old);
+            }
+        }
+        if let Some(key) = key.filter(|key| !key.is_empty()) {
+            self.by_key.insert(key.clone(), id);
+            self.by_id.insert(id, key);
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<i32> {
+        self.by_key.get(key).copied()
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(id) = self.by_key.remove(key) {
+            self.by_id.remove(&id);
+        }
+    }
+}
+
+fn normalize_username(username: &str) -> String {
+    username.trim_start_matches('@').to_ascii_lowercase()
+}
+
+fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(char::is_ascii_digit).collect()
+}
+
 impl ChatHashCache {
     pub fn new(self_user: Option<(i32, bool)>) -> Self {
         Self {
             hash_map: HashMap::new(),
+            usernames: Index::default(),
+            phones: Index::default(),
             self_id: self_user.map(|user| user.0),
             self_bot: self_user.map(|user| user.1).unwrap_or(false),
         }
@@ -53,9 +100,62 @@ impl ChatHashCache {
         })
     }
 
+    /// Remember the access hash of a chat which was packed before, such as one from the
+    /// [`crate::UpdateState::channel_chats`]. Chats without an access hash are ignored.
+    pub fn insert(&mut self, chat: PackedChat) {
+        if let Some(hash) = chat.access_hash {
+            self.hash_map.insert(chat.id, (hash, chat.ty));
+        }
+    }
+
+    /// Find the peer which currently owns the given username, if it's known.
+    ///
+    /// The search is case-insensitive, and the leading `@` may be omitted.
+    pub fn get_by_username(&self, username: &str) -> Option<PackedChat> {
+        self.usernames
+            .get(&normalize_username(username))
+            .and_then(|id| self.get(id))
+    }
+
+    /// Forget which peer owns the given username, for example because it turned out to be
+    /// stale. The access hash of the peer is kept.
+    pub fn forget_username(&mut self, username: &str) {
+        self.usernames.remove(&normalize_username(username));
+    }
+
+    /// Find the user with the given phone number, if it's known.
+    ///
+    /// Any character other than the digits, such as the leading `+` or spaces, is ignored.
+    pub fn get_by_phone(&self, phone: &str) -> Option<PackedChat> {
+        self.phones
+            .get(&normalize_phone(phone))
+            .and_then(|id| self.get(id))
+    }
+
     pub fn extend(&mut self, users: &[tl::enums::User], chats: &[tl::enums::Chat]) {
         // See https://core.telegram.org/api/min for "issues" with "min constructors".
         use tl::enums::{Chat as C, User as U};
+
+        // Min constructors may lack the username and phone, so they can't be used to update them.
+        for user in users {
+            if let U::User(u) = user {
+                if !u.min && u.access_hash.is_some() {
+                    self.usernames
+                        .set(u.id, u.username.as_deref().map(normalize_username));
+                    self.phones
+                        .set(u.id, u.phone.as_deref().map(normalize_phone));
+                }
+            }
+        }
+        for chat in chats {
+            if let C::Channel(c) = chat {
+                if !c.min && c.access_hash.is_some() {
+                    self.usernames
+                        .set(c.id, c.username.as_deref().map(normalize_username));
+                }
+            }
+        }
+
         self.hash_map
             .extend(users.iter().flat_map(|user| match user {
                 U::Empty(_) => None,
@@ -100,3 +200,87 @@ impl ChatHashCache {
             }));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, username: Option<&str>, phone: Option<&str>, min: bool) -> tl::enums::User {
+        tl::types::User {
+            is_self: false,
+            contact: false,
+            mutual_contact: false,
+            deleted: false,
+            bot: false,
+            bot_chat_history: false,
+            bot_nochats: false,
+            verified: false,
+            restricted: false,
+            min,
+            bot_inline_geo: false,
+            support: false,
+            scam: false,
+            apply_min_photo: false,
+            fake: false,
+            id,
+            access_hash: Some(id as i64 * 10),
+            first_name: None,
+            last_name: None,
+            username: username.map(String::from),
+            phone: phone.map(String::from),
+            photo: None,
+            status: None,
+            bot_info_version: None,
+            restriction_reason: None,
+            bot_inline_placeholder: None,
+            lang_code: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn find_by_username_and_phone() {
+        let mut cache = ChatHashCache::new(None);
+        cache.extend(&[user(1, Some("Alice"), Some("+34 600"), false)], &[]);
+
+        let found = cache.get_by_username("@alice").unwrap();
+        assert_eq!(found.id, 1);
+        assert_eq!(found.access_hash, Some(10));
+        assert_eq!(cache.get_by_phone("34600").map(|c| c.id), Some(1));
+        assert!(cache.get_by_username("bob").is_none());
+    }
+
+    #[test]
+    fn username_changes_replace_old_entries() {
+        let mut cache = ChatHashCache::new(None);
+        cache.extend(&[user(1, Some("alice"), None, false)], &[]);
+        cache.extend(&[user(1, Some("alicia"), None, false)], &[]);
+        assert!(cache.get_by_username("alice").is_none());
+        assert_eq!(cache.get_by_username("alicia").map(|c| c.id), Some(1));
+
+        // Another user taking the username over.
+        cache.extend(&[user(2, Some("alicia"), None, false)], &[]);
+        assert_eq!(cache.get_by_username("alicia").map(|c| c.id), Some(2));
+
+        // The previous owner dropping it must not remove the new owner's entry.
+        cache.extend(&[user(1, None, None, false)], &[]);
+        assert_eq!(cache.get_by_username("alicia").map(|c| c.id), Some(2));
+    }
+
+    #[test]
+    fn min_users_do_not_change_index() {
+        let mut cache = ChatHashCache::new(None);
+        cache.extend(&[user(1, Some("alice"), None, false)], &[]);
+        cache.extend(&[user(1, None, None, true)], &[]);
+        assert_eq!(cache.get_by_username("alice").map(|c| c.id), Some(1));
+    }
+
+    #[test]
+    fn forget_username_keeps_hash() {
+        let mut cache = ChatHashCache::new(None);
+        cache.extend(&[user(1, Some("alice"), None, false)], &[]);
+        cache.forget_username("@Alice");
+        assert!(cache.get_by_username("alice").is_none());
+        assert_eq!(cache.get(1).and_then(|c| c.access_hash), Some(10));
+    }
+}
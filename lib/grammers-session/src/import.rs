@@ -0,0 +1,170 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversion of the sessions used by other libraries, so that the accounts logged in with them
+//! don't need to sign in again.
+use crate::{Error, Session};
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+#[cfg(feature = "sqlite")]
+use std::{io, net::SocketAddr, path::Path};
+
+/// Addresses of the production datacenters, where the index is the datacenter ID, for the
+/// sessions which don't store one.
+const DC_ADDRESSES: [(Ipv4Addr, u16); 6] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 0),
+    (Ipv4Addr::new(149, 154, 175, 53), 443),
+    (Ipv4Addr::new(149, 154, 167, 51), 443),
+    (Ipv4Addr::new(149, 154, 175, 100), 443),
+    (Ipv4Addr::new(149, 154, 167, 92), 443),
+    (Ipv4Addr::new(91, 108, 56, 190), 443),
+];
+
+/// Like [`DC_ADDRESSES`], but for the test datacenters, for the sessions created in test mode.
+const TEST_DC_ADDRESSES: [(Ipv4Addr, u16); 4] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 0),
+    (Ipv4Addr::new(149, 154, 175, 10), 443),
+    (Ipv4Addr::new(149, 154, 167, 40), 443),
+    (Ipv4Addr::new(149, 154, 175, 117), 443),
+];
+
+impl Session {
+    /// Import a session string exported by Pyrogram (with `export_session_string`).
+    ///
+    /// Both the format used since Pyrogram 2.0 and the older ones are supported. Pyrogram
+    /// session strings don't contain the address of the datacenter, so the production address is
+    /// assumed, unless the session was created in test mode, in which case the address of the
+    /// test datacenter is used instead. Such sessions only work when connecting to the test
+    /// datacenters (with `InitParams::test_dc` in `grammers-client`).
+    pub fn from_pyrogram_string(string: &str) -> Result<Self, Error> {
+        let data = base64::decode_config(string.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+            .map_err(|_| Error::MalformedData)?;
+
+        // (dc_id, test_mode, auth_key, user_id, is_bot)
+        let (dc_id, test_mode, auth, user_id, bot) = match data.len() {
+            // dc_id: u8, api_id: u32, test_mode: bool, auth_key, user_id: u64, is_bot: bool
+            271 => (
+                data[0],
+                data[5] != 0,
+                &data[6..262],
+                u64::from_be_bytes(data[262..270].try_into().unwrap()),
+                data[270] != 0,
+            ),
+            // dc_id: u8, test_mode: bool, auth_key, user_id: u64, is_bot: bool
+            267 => (
+                data[0],
+                data[1] != 0,
+                &data[2..258],
+                u64::from_be_bytes(data[258..266].try_into().unwrap()),
+                data[266] != 0,
+            ),
+            // dc_id: u8, test_mode: bool, auth_key, user_id: u32, is_bot: bool
+            263 => (
+                data[0],
+                data[1] != 0,
+                &data[2..258],
+                u32::from_be_bytes(data[258..262].try_into().unwrap()) as u64,
+                data[262] != 0,
+            ),
+            _ => return Err(Error::MalformedData),
+        };
+
+        let dc_id = dc_id as i32;
+        let addresses = if test_mode {
+            &TEST_DC_ADDRESSES[..]
+        } else {
+            &DC_ADDRESSES[..]
+        };
+        let addr = addresses
+            .get(dc_id as usize)
+            .filter(|_| dc_id != 0)
+            .ok_or(Error::MalformedData)?;
+
+        let session = Self::new();
+        session.insert_dc(dc_id, (*addr).into(), auth.try_into().unwrap());
+        // User identifiers which don't fit are not supported by the current layer.
+        if let Ok(user_id) = user_id.try_into() {
+            session.set_user(user_id, dc_id, bot);
+        }
+        Ok(session)
+    }
+
+    /// Import a SQLite session file created by Telethon (the `.session` files).
+    ///
+    /// Only the authorization key is imported, and not the cached entities or update state.
+    /// Like [`Session::from_string_session`], the logged-in user is not known.
+    #[cfg(feature = "sqlite")]
+    pub fn from_telethon_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let to_io = |e: rusqlite::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let conn =
+            rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(to_io)?;
+
+        let (dc_id, address, port, auth): (i32, String, u16, Option<Vec<u8>>) = conn
+            .query_row(
+                "SELECT dc_id, server_address, port, auth_key FROM sessions",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(to_io)?;
+
+        let invalid = |what| io::Error::new(io::ErrorKind::InvalidData, what);
+        let ip = address
+            .parse()
+            .map_err(|_| invalid("invalid server address"))?;
+        let auth = auth
+            .and_then(|auth| auth.try_into().ok())
+            .ok_or_else(|| invalid("missing or invalid authorization key"))?;
+
+        let session = Self::new();
+        session.insert_dc(dc_id, SocketAddr::new(ip, port), auth);
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pyrogram_string(dc_id: u8, test_mode: bool) -> String {
+        let mut data = vec![dc_id];
+        data.extend(&12345u32.to_be_bytes());
+        data.push(test_mode as u8);
+        data.extend(&[7; 256]);
+        data.extend(&42u64.to_be_bytes());
+        data.push(0);
+        base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// The address the session stores for the datacenter, as seen in its Telethon export.
+    fn imported_address(string: &str) -> String {
+        let session = Session::from_pyrogram_string(string).unwrap();
+        assert_eq!(session.dc_auth_key(2), Some([7; 256]));
+        let exported = session.to_string_session().unwrap();
+        let data = base64::decode_config(&exported[1..], base64::URL_SAFE).unwrap();
+        Ipv4Addr::new(data[1], data[2], data[3], data[4]).to_string()
+    }
+
+    #[test]
+    fn import_pyrogram_production_session() {
+        assert_eq!(
+            imported_address(&pyrogram_string(2, false)),
+            "149.154.167.51"
+        );
+    }
+
+    #[test]
+    fn import_pyrogram_test_session() {
+        assert_eq!(
+            imported_address(&pyrogram_string(2, true)),
+            "149.154.167.40"
+        );
+        assert!(Session::from_pyrogram_string(&pyrogram_string(4, true)).is_err());
+    }
+}
@@ -7,7 +7,10 @@
 // except according to those terms.
 mod chat;
 mod generated;
+mod import;
 mod message_box;
+pub mod storage;
+mod string_session;
 
 pub use chat::{ChatHashCache, PackedChat, PackedType};
 pub use generated::types::User;
@@ -17,22 +20,56 @@ use grammers_tl_types::deserialize::Error as DeserializeError;
 pub use message_box::MessageBox;
 use std::collections::HashMap;
 use std::fmt;
-use std::fs::{File, OpenOptions};
-use std::io::{self, Read, Seek, Write};
+use std::io;
 use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::path::Path;
 use std::sync::Mutex;
+#[cfg(feature = "encryption")]
+pub use storage::EncryptedStorage;
+#[cfg(feature = "sqlite")]
+pub use storage::SqliteStorage;
+pub use storage::{AsyncSessionStorage, FileStorage, MemoryStorage, ProfileStore, SessionStorage};
 
 // Needed for auto-generated definitions.
 use grammers_tl_types::{deserialize, serialize, Deserializable, Identifiable, Serializable};
 
+/// The state of the updates, persisted so that clients can resume where they left off.
 #[derive(Debug)]
 pub struct UpdateState {
+    /// The account-wide persistent timestamp.
     pub pts: i32,
+    /// The persistent timestamp for secret chats.
     pub qts: i32,
+    /// The date of the last update.
     pub date: i32,
+    /// The sequence number of the last update container.
     pub seq: i32,
+    /// The persistent timestamp of each channel, keyed by the channel identifier.
     pub channels: HashMap<i32, i32>,
+    // The packed channels from `channels` whose access hash is known, keyed by the channel
+    // identifier. Without the access hash, the difference of a channel can't be fetched, so
+    // persisting them allows catching up on all channels after a restart.
+    channel_chats: HashMap<i32, PackedChat>,
+}
+
+impl UpdateState {
+    /// Create a new update state, where the access hash of the channels is not known.
+    pub fn new(pts: i32, qts: i32, date: i32, seq: i32, channels: HashMap<i32, i32>) -> Self {
+        Self {
+            pts,
+            qts,
+            date,
+            seq,
+            channels,
+            channel_chats: HashMap::new(),
+        }
+    }
+
+    /// The packed channels from `channels` whose access hash is known, keyed by the channel
+    /// identifier.
+    pub fn channel_chats(&self) -> &HashMap<i32, PackedChat> {
+        &self.channel_chats
+    }
 }
 
 pub struct Session {
@@ -54,20 +91,24 @@ impl Session {
     /// creating one if it doesn't exist
     pub fn load_file_or_create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         let path = path.as_ref();
-        if !path.exists() {
-            File::create(path)?;
-            let session = Session::new();
-            session.save_to_file(path)?;
-            Ok(session)
-        } else {
-            Self::load_file(path)
+        match storage::read_file(path)? {
+            Some(data) => {
+                Self::load(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            None => {
+                let session = Session::new();
+                session.save_to_file(path)?;
+                Ok(session)
+            }
         }
     }
 
     /// Load a previous session instance from a file.
+    ///
+    /// If the file was left corrupted by an interrupted save, the previous copy is used instead.
     pub fn load_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut data = Vec::new();
-        File::open(path.as_ref())?.read_to_end(&mut data)?;
+        let data = storage::read_file(path.as_ref())?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "session file not found"))?;
 
         Self::load(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
@@ -111,6 +152,20 @@ impl Session {
             .next()
     }
 
+    /// Returns the identifier of the last datacenter with an authorization key, if any.
+    ///
+    /// Useful to know where to connect when the logged-in user is not known, as is the case
+    /// with sessions imported from other libraries.
+    pub fn authorized_dc(&self) -> Option<i32> {
+        self.session
+            .lock()
+            .unwrap()
+            .dcs
+            .iter()
+            .rev()
+            .find_map(|enums::DataCenter::Center(dc)| dc.auth.as_ref().map(|_| dc.id))
+    }
+
     pub fn insert_dc(&self, id: i32, addr: SocketAddr, auth: [u8; 256]) {
         let mut session = self.session.lock().unwrap();
         if let Some(pos) = session
@@ -152,6 +207,19 @@ impl Session {
             .map(|enums::User::User(user)| user.clone())
     }
 
+    /// Forget the logged-in user, the update state, and the authorization key of every
+    /// datacenter (but not their addresses), as needed after signing out.
+    pub fn clear_authorization(&self) {
+        let mut session = self.session.lock().unwrap();
+        session.user = None;
+        session.state = None;
+        session
+            .dcs
+            .iter_mut()
+            .for_each(|enums::DataCenter::Center(dc)| dc.auth = None);
+    }
+
+    /// Returns the stored update state, if any.
     pub fn get_state(&self) -> Option<UpdateState> {
         let session = self.session.lock().unwrap();
         let enums::UpdateState::State(state) = session.state.as_ref()?;
@@ -163,12 +231,24 @@ impl Session {
             channels: state
                 .channels
                 .iter()
-                .map(|enums::ChannelState::State(s)| (s.channel_id, s.pts))
+                .map(|channel| (channel.channel_id(), channel.pts()))
+                .collect(),
+            channel_chats: state
+                .channels
+                .iter()
+                .filter_map(|channel| match channel {
+                    enums::ChannelState::State(_) => None,
+                    enums::ChannelState::Packed(s) => PackedChat::from_bytes(&s.chat)
+                        .ok()
+                        .map(|chat| (s.channel_id, chat)),
+                })
                 .collect(),
         })
     }
 
+    /// Replaces the stored update state.
     pub fn set_state(&self, state: UpdateState) {
+        let channel_chats = &state.channel_chats;
         self.session.lock().unwrap().state = Some(
             types::UpdateState {
                 pts: state.pts,
@@ -178,7 +258,15 @@ impl Session {
                 channels: state
                     .channels
                     .into_iter()
-                    .map(|(channel_id, pts)| types::ChannelState { channel_id, pts }.into())
+                    .map(|(channel_id, pts)| match channel_chats.get(&channel_id) {
+                        Some(chat) => types::ChannelStatePacked {
+                            channel_id,
+                            pts,
+                            chat: chat.to_bytes(),
+                        }
+                        .into(),
+                        None => types::ChannelState { channel_id, pts }.into(),
+                    })
                     .collect(),
             }
             .into(),
@@ -189,13 +277,53 @@ impl Session {
         enums::Session::Session(self.session.lock().unwrap().clone()).to_bytes()
     }
 
+    /// Export only what's needed to use the authorization in a different process: the
+    /// datacenter of the logged-in user (including its authorization key), and the user itself.
+    ///
+    /// The update state and the addresses of other datacenters are left out. The result can be
+    /// turned into a new session with [`Session::import_auth`].
+    ///
+    /// Returns `None` if there is no authorization key for the datacenter of the user (or for
+    /// any datacenter, if no user is signed in).
+    pub fn export_auth(&self) -> Option<Vec<u8>> {
+        let session = self.session.lock().unwrap();
+        let user_dc = session.user.as_ref().map(|enums::User::User(user)| user.dc);
+        let dc = session
+            .dcs
+            .iter()
+            .rev()
+            .find(|enums::DataCenter::Center(dc)| {
+                dc.auth.is_some() && user_dc.map(|id| id == dc.id).unwrap_or(true)
+            })?;
+
+        Some(
+            enums::Session::Session(types::Session {
+                dcs: vec![dc.clone()],
+                user: session.user.clone(),
+                state: None,
+            })
+            .to_bytes(),
+        )
+    }
+
+    /// Import the authorization exported with [`Session::export_auth`] into a new session.
+    ///
+    /// Any other data, such as the update state, starts empty, and is filled as the session is
+    /// used.
+    pub fn import_auth(data: &[u8]) -> Result<Self, Error> {
+        let session = Self::load(data)?;
+        if session.authorized_dc().is_none() {
+            return Err(Error::MalformedData);
+        }
+        session.session.lock().unwrap().state = None;
+        Ok(session)
+    }
+
     /// Saves the session to a file.
+    ///
+    /// The file is replaced atomically, and the previous copy is kept with the `.bak` extension.
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let mut file = OpenOptions::new().write(true).open(path.as_ref())?;
-        file.seek(io::SeekFrom::Start(0))?;
-        file.set_len(0)?;
-        file.write_all(&self.save())?;
-        file.sync_data()
+        storage::write_file(path.as_ref(), &self.save())
     }
 }
 
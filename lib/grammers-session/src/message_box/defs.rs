@@ -6,7 +6,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 use grammers_tl_types as tl;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::{Duration, Instant};
 
 /// Telegram sends `seq` equal to `0` when "it doesn't matter", so we use that value too.
@@ -28,6 +28,9 @@ pub(super) const POSSIBLE_GAP_TIMEOUT: Duration = Duration::from_millis(500);
 /// Documentation recommends 15 minutes without updates (https://core.telegram.org/api/updates).
 pub(super) const NO_UPDATES_TIMEOUT: Duration = Duration::from_secs(15 * 60);
 
+/// How many of the most recent messages are remembered in order to filter duplicates.
+pub(super) const SEEN_MESSAGES_LIMIT: usize = 1000;
+
 /// A [`MessageBox`] entry key.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Entry {
@@ -75,6 +78,10 @@ pub struct MessageBox {
     /// Temporarily stores which entries should have their update deadline reset.
     /// Stored in the message box in order to reuse the allocation.
     pub(super) reset_deadlines_for: HashSet<Entry>,
+
+    /// The most recent messages which were returned, used to filter out those which arrive more
+    /// than once (for example, through both the socket and getting difference).
+    pub(super) seen_messages: SeenMessages,
 }
 
 /// Represents the information needed to correctly handle a specific `tl::enums::Update`.
@@ -110,6 +117,14 @@ pub(super) struct PossibleGap {
     pub(super) updates: Vec<tl::enums::Update>,
 }
 
+/// Remembers up to [`SEEN_MESSAGES_LIMIT`] messages, identified by their channel (or `0` if they
+/// don't belong to a channel) and their message identifier, forgetting the oldest ones first.
+#[derive(Debug, Default)]
+pub(super) struct SeenMessages {
+    pub(super) set: HashSet<(i32, i32)>,
+    pub(super) order: VecDeque<(i32, i32)>,
+}
+
 pub struct Gap;
 
 #[derive(PartialEq)]
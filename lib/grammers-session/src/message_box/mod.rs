@@ -30,7 +30,9 @@ use crate::message_box::defs::PossibleGap;
 use crate::UpdateState;
 pub(crate) use defs::Entry;
 pub use defs::{Gap, MessageBox};
-use defs::{PtsInfo, ResetDeadline, State, NO_SEQ, POSSIBLE_GAP_TIMEOUT};
+use defs::{
+    PtsInfo, ResetDeadline, SeenMessages, State, NO_SEQ, POSSIBLE_GAP_TIMEOUT, SEEN_MESSAGES_LIMIT,
+};
 use grammers_tl_types as tl;
 use log::{debug, info, trace, warn};
 use std::cmp::Ordering;
@@ -56,10 +58,14 @@ impl MessageBox {
             possible_gaps: HashMap::new(),
             getting_diff_for: HashSet::new(),
             reset_deadlines_for: HashSet::new(),
+            seen_messages: SeenMessages::default(),
         }
     }
 
     /// Create a [`MessageBox`] from a previously known update state.
+    ///
+    /// The account difference will need to be fetched right away, in order to catch up on the
+    /// updates which occurred since the state was saved.
     pub fn load(state: UpdateState) -> Self {
         let deadline = next_updates_deadline();
         let mut map = HashMap::with_capacity(2 + state.channels.len());
@@ -90,15 +96,17 @@ impl MessageBox {
             seq: state.seq,
             next_deadline: Some(Entry::AccountWide),
             possible_gaps: HashMap::new(),
-            getting_diff_for: HashSet::new(),
+            getting_diff_for: vec![Entry::AccountWide].into_iter().collect(),
             reset_deadlines_for: HashSet::new(),
+            seen_messages: SeenMessages::default(),
         }
     }
 
     /// Return the current state in a format that sessions understand.
     ///
-    /// This should be used for persisting the state.
-    pub fn session_state(&self) -> UpdateState {
+    /// This should be used for persisting the state. The known access hashes of the channels
+    /// are included too, so that their difference can be fetched after loading it again.
+    pub fn session_state(&self, chat_hashes: &ChatHashCache) -> UpdateState {
         UpdateState {
             pts: self
                 .map
@@ -120,6 +128,14 @@ impl MessageBox {
                     _ => None,
                 })
                 .collect(),
+            channel_chats: self
+                .map
+                .keys()
+                .filter_map(|entry| match entry {
+                    Entry::Channel(id) => chat_hashes.get(*id).map(|chat| (*id, chat)),
+                    _ => None,
+                })
+                .collect(),
         }
     }
 
@@ -319,6 +335,8 @@ impl MessageBox {
         chat_hashes: &mut ChatHashCache,
         result: &mut Vec<tl::enums::Update>,
     ) -> Result<(Vec<tl::enums::User>, Vec<tl::enums::Chat>), Gap> {
+        let start = result.len();
+
         // Top level, when handling received `updates` and `updatesCombined`.
         // `updatesCombined` groups all the fields we care about, which is why we use it.
         let tl::types::UpdatesCombined {
@@ -405,6 +423,7 @@ impl MessageBox {
             }
         }
 
+        self.retain_unseen(result, start);
         Ok((users, chats))
     }
 
@@ -640,6 +659,7 @@ impl MessageBox {
                 })),
         );
 
+        self.retain_unseen(&mut updates, 0);
         (updates, users, chats)
     }
 }
@@ -647,17 +667,22 @@ impl MessageBox {
 /// Getting and applying channel difference.
 impl MessageBox {
     /// Return the request that needs to be made to get a channel's difference, if any.
+    ///
+    /// If several channels need their difference fetched, the one with the lowest identifier is
+    /// picked first, so that the order in which they're caught up on is deterministic.
     pub fn get_channel_difference(
         &mut self,
         chat_hashes: &ChatHashCache,
     ) -> Option<tl::functions::updates::GetChannelDifference> {
-        let (entry, id) = self
+        let id = self
             .getting_diff_for
             .iter()
-            .find_map(|&entry| match entry {
-                Entry::Channel(id) => Some((entry, id)),
+            .filter_map(|&entry| match entry {
+                Entry::Channel(id) => Some(id),
                 _ => None,
-            })?;
+            })
+            .min()?;
+        let entry = Entry::Channel(id);
 
         if let Some(packed) = chat_hashes.get(id) {
             let channel = tl::types::InputChannel {
@@ -787,8 +812,219 @@ impl MessageBox {
                 chat_hashes.extend(&users, &chats);
                 self.reset_channel_deadline(channel_id, timeout);
 
+                self.retain_unseen(&mut updates, 0);
                 (updates, users, chats)
             }
         }
     }
 }
+
+/// Filtering of messages which were already returned.
+impl MessageBox {
+    /// Remove the new messages starting at `start` which were already returned before.
+    ///
+    /// The same message can arrive more than once, for example, if it's received through the
+    /// socket right before getting difference (which returns it again).
+    fn retain_unseen(&mut self, updates: &mut Vec<tl::enums::Update>, start: usize) {
+        let seen = &mut self.seen_messages;
+        let mut index = 0;
+        updates.retain(|update| {
+            index += 1;
+            if index <= start {
+                return true;
+            }
+            match message_key(update) {
+                Some(key) if !seen.insert(key) => {
+                    debug!("skipping duplicate message {:?}", key);
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+}
+
+impl SeenMessages {
+    /// Remember the message, returning `false` if it had already been seen.
+    fn insert(&mut self, key: (i32, i32)) -> bool {
+        if !self.set.insert(key) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > SEEN_MESSAGES_LIMIT {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Identify the new message contained in the update, if any, by its channel and message ID.
+fn message_key(update: &tl::enums::Update) -> Option<(i32, i32)> {
+    let message = match update {
+        tl::enums::Update::NewMessage(u) => &u.message,
+        tl::enums::Update::NewChannelMessage(u) => &u.message,
+        _ => return None,
+    };
+    let (id, peer) = match message {
+        tl::enums::Message::Message(m) => (m.id, &m.peer_id),
+        tl::enums::Message::Service(m) => (m.id, &m.peer_id),
+        tl::enums::Message::Empty(_) => return None,
+    };
+    let channel_id = match peer {
+        tl::enums::Peer::Channel(c) => c.channel_id,
+        _ => 0,
+    };
+    Some((channel_id, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i32) -> tl::enums::Message {
+        tl::types::MessageService {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: false,
+            legacy: false,
+            id,
+            from_id: None,
+            peer_id: tl::types::PeerUser { user_id: 1 }.into(),
+            reply_to: None,
+            date: 0,
+            action: tl::enums::MessageAction::Empty,
+            ttl_period: None,
+        }
+        .into()
+    }
+
+    fn live_update(id: i32, pts: i32) -> tl::enums::Updates {
+        tl::types::Updates {
+            updates: vec![tl::types::UpdateNewMessage {
+                message: message(id),
+                pts,
+                pts_count: 1,
+            }
+            .into()],
+            users: Vec::new(),
+            chats: Vec::new(),
+            date: 0,
+            seq: NO_SEQ,
+        }
+        .into()
+    }
+
+    fn difference(ids: &[i32], pts: i32) -> tl::enums::updates::Difference {
+        tl::types::updates::Difference {
+            new_messages: ids.iter().copied().map(message).collect(),
+            new_encrypted_messages: Vec::new(),
+            other_updates: Vec::new(),
+            chats: Vec::new(),
+            users: Vec::new(),
+            state: tl::types::updates::State {
+                pts,
+                qts: 0,
+                date: 0,
+                seq: 0,
+                unread_count: 0,
+            }
+            .into(),
+        }
+        .into()
+    }
+
+    fn message_box() -> MessageBox {
+        let mut message_box = MessageBox::new();
+        message_box.set_state(
+            tl::types::updates::State {
+                pts: 1,
+                qts: 0,
+                date: 0,
+                seq: 0,
+                unread_count: 0,
+            }
+            .into(),
+        );
+        message_box
+    }
+
+    fn ids(updates: &[tl::enums::Update]) -> Vec<i32> {
+        updates
+            .iter()
+            .filter_map(message_key)
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    #[test]
+    fn skip_live_messages_returned_again_by_difference() {
+        let mut message_box = message_box();
+        let mut chat_hashes = ChatHashCache::new(None);
+
+        let mut result = Vec::new();
+        assert!(message_box
+            .process_updates(live_update(10, 2), &mut chat_hashes, &mut result)
+            .is_ok());
+        assert_eq!(ids(&result), vec![10]);
+
+        message_box.begin_get_diff(Entry::AccountWide);
+        let (updates, _, _) =
+            message_box.apply_difference(difference(&[10, 11], 3), &mut chat_hashes);
+        assert_eq!(ids(&updates), vec![11]);
+    }
+
+    #[test]
+    fn skip_difference_messages_received_again_live() {
+        let mut message_box = message_box();
+        let mut chat_hashes = ChatHashCache::new(None);
+
+        message_box.begin_get_diff(Entry::AccountWide);
+        let (updates, _, _) = message_box.apply_difference(difference(&[10], 1), &mut chat_hashes);
+        assert_eq!(ids(&updates), vec![10]);
+
+        let mut result = Vec::new();
+        assert!(message_box
+            .process_updates(live_update(10, 2), &mut chat_hashes, &mut result)
+            .is_ok());
+        assert!(result.is_empty());
+
+        assert!(message_box
+            .process_updates(live_update(11, 3), &mut chat_hashes, &mut result)
+            .is_ok());
+        assert_eq!(ids(&result), vec![11]);
+    }
+
+    #[test]
+    fn skip_duplicates_within_the_same_difference() {
+        let mut message_box = message_box();
+        let mut chat_hashes = ChatHashCache::new(None);
+
+        message_box.begin_get_diff(Entry::AccountWide);
+        let (updates, _, _) =
+            message_box.apply_difference(difference(&[10, 10, 11], 3), &mut chat_hashes);
+        assert_eq!(ids(&updates), vec![10, 11]);
+    }
+
+    #[test]
+    fn forget_the_oldest_seen_messages() {
+        let mut seen = SeenMessages::default();
+        for id in 0..SEEN_MESSAGES_LIMIT as i32 {
+            assert!(seen.insert((0, id)));
+        }
+        assert!(!seen.insert((0, 0)));
+
+        // One more message pushes the oldest one out.
+        assert!(seen.insert((0, SEEN_MESSAGES_LIMIT as i32)));
+        assert_eq!(seen.order.len(), SEEN_MESSAGES_LIMIT);
+        assert_eq!(seen.set.len(), SEEN_MESSAGES_LIMIT);
+        assert!(seen.insert((0, 0)));
+        assert!(!seen.insert((0, SEEN_MESSAGES_LIMIT as i32)));
+
+        // Channels are told apart from each other and from private messages.
+        assert!(seen.insert((1, SEEN_MESSAGES_LIMIT as i32)));
+    }
+}
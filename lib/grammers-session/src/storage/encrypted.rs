@@ -0,0 +1,202 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::SessionStorage;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::io;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Identifies the data as an encrypted session, followed by the version of the format.
+const MAGIC: &[u8; 4] = b"GRSE";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// A derived key, wiped from memory once dropped.
+type Key32 = Zeroizing<[u8; 32]>;
+
+/// Session storage which encrypts the data with a passphrase before handing it to another storage.
+///
+/// The key is derived from the passphrase using Argon2id, and the session is sealed with
+/// ChaCha20-Poly1305, so that the data saved by the inner storage (for example, a file that ends
+/// up in a backup) does not reveal the authorization key to anyone who doesn't know the passphrase.
+///
+/// Loading data which was encrypted with a different passphrase, or which has been tampered with,
+/// fails with an error of kind [`io::ErrorKind::InvalidData`].
+pub struct EncryptedStorage<S: SessionStorage> {
+    inner: S,
+    passphrase: Zeroizing<Vec<u8>>,
+    /// The last salt used and the key derived from it, as deriving the key is slow on purpose.
+    key: Mutex<Option<([u8; SALT_LEN], Key32)>>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<S: SessionStorage> EncryptedStorage<S> {
+    /// Wrap the given storage, encrypting the data it stores with the passphrase.
+    pub fn new<P: AsRef<[u8]>>(inner: S, passphrase: P) -> Self {
+        Self {
+            inner,
+            passphrase: Zeroizing::new(passphrase.as_ref().to_vec()),
+            key: Mutex::new(None),
+        }
+    }
+
+    /// Return the inner storage, which holds the encrypted data.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn derive_key(&self, salt: &[u8; SALT_LEN]) -> io::Result<Key32> {
+        let mut cached = self.key.lock().unwrap();
+        if let Some((cached_salt, key)) = cached.as_ref() {
+            if cached_salt == salt {
+                return Ok(key.clone());
+            }
+        }
+
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::default()
+            .hash_password_into(&self.passphrase, salt, key.as_mut())
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        *cached = Some((*salt, key.clone()));
+        Ok(key)
+    }
+
+    fn random_salt(&self) -> io::Result<[u8; SALT_LEN]> {
+        if let Some((salt, _)) = self.key.lock().unwrap().as_ref() {
+            return Ok(*salt);
+        }
+        let mut salt = [0; SALT_LEN];
+        getrandom::getrandom(&mut salt).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(salt)
+    }
+}
+
+impl<S: SessionStorage> SessionStorage for EncryptedStorage<S> {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        let data = match self.inner.load()? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+            return Err(invalid_data("the session is not encrypted"));
+        }
+        if data[MAGIC.len()] != VERSION {
+            return Err(invalid_data("unsupported encrypted session version"));
+        }
+
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(&header[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN]);
+        let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+        let key = self.derive_key(&salt)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map(Some)
+            .map_err(|_| invalid_data("wrong passphrase or corrupted session"))
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        let salt = self.random_salt()?;
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| io::Error::other(e.to_string()))?;
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+
+        let key = self.derive_key(&salt)?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: data,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| io::Error::other("failed to encrypt the session"))?;
+
+        header.extend_from_slice(&ciphertext);
+        self.inner.save(&header)
+    }
+
+    fn delete(&self) -> io::Result<()> {
+        self.inner.delete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStorage;
+
+    #[test]
+    fn roundtrip_and_wrong_passphrase() {
+        let storage = EncryptedStorage::new(MemoryStorage::new(), "correct horse");
+        storage.save(b"auth key").unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"auth key"[..]));
+
+        let inner = storage.into_inner();
+        assert!(!inner
+            .load()
+            .unwrap()
+            .unwrap()
+            .windows(8)
+            .any(|w| w == b"auth key"));
+
+        let storage = EncryptedStorage::new(inner, "battery staple");
+        let err = storage.load().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupted_ciphertext() {
+        let storage = EncryptedStorage::new(MemoryStorage::new(), "correct horse");
+        storage.save(b"auth key").unwrap();
+        let sealed = storage.inner.load().unwrap().unwrap();
+
+        // Flipping a bit of the salt, the nonce or the ciphertext must be detected.
+        for &pos in [
+            MAGIC.len() + 1,
+            HEADER_LEN - 1,
+            HEADER_LEN,
+            sealed.len() - 1,
+        ]
+        .iter()
+        {
+            let mut data = sealed.clone();
+            data[pos] ^= 1;
+            storage.inner.save(&data).unwrap();
+            let err = storage.load().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "at {}", pos);
+        }
+
+        storage.inner.save(&sealed[..HEADER_LEN - 1]).unwrap();
+        assert_eq!(
+            storage.load().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
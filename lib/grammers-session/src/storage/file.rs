@@ -0,0 +1,266 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::SessionStorage;
+use log::warn;
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies the file as a checksummed session. Files without it are read as-is, because that's
+/// how sessions were saved before.
+const MAGIC: &[u8; 4] = b"GRSF";
+/// The magic, followed by the CRC-32 of the data and its length.
+const HEADER_LEN: usize = 12;
+
+/// Session storage backed by a file in the filesystem.
+///
+/// The session is first written to a temporary file, and only replaces the previous one once
+/// it's fully on disk, so that a crash midway can't leave a half-written session behind. The
+/// previous copy is kept next to it with the `.bak` extension, and used if the current file is
+/// missing or fails its checksum.
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+/// CRC-32 (as used by zlib), which is enough to detect torn writes.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(extension);
+    name.into()
+}
+
+fn read_checked(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !data.starts_with(MAGIC) {
+        return Ok(Some(data));
+    }
+
+    let valid = data.len() >= HEADER_LEN && {
+        let crc = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        let len = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        data.len() - HEADER_LEN == len && crc32(&data[HEADER_LEN..]) == crc
+    };
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "session file is corrupted",
+        ));
+    }
+    data.drain(..HEADER_LEN);
+    Ok(Some(data))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Read the session data saved by [`write_file`], falling back to the previous copy if the
+/// current one is missing or corrupted.
+pub(crate) fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let error = match read_checked(path) {
+        Ok(Some(data)) => return Ok(Some(data)),
+        Ok(None) => None,
+        Err(e) => Some(e),
+    };
+
+    match read_checked(&with_extension(path, "bak")) {
+        Ok(Some(data)) => {
+            warn!(
+                "session file {} is missing or corrupted; using the previous copy",
+                path.display()
+            );
+            Ok(Some(data))
+        }
+        Ok(None) | Err(_) => match error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Atomically replace the session data in the file, keeping the previous copy as a backup.
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = with_extension(path, "tmp");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&crc32(data).to_le_bytes())?;
+    file.write_all(&(data.len() as u32).to_le_bytes())?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    match fs::rename(path, with_extension(path, "bak")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::rename(&tmp, path)?;
+
+    // The renames themselves are only durable once the directory is synced. This isn't possible
+    // on every platform, and the backup covers for it anyway, so failing to do so is not fatal.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if let Ok(dir) = fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+impl FileStorage {
+    /// Create a new storage which will use the file at the given path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SessionStorage for FileStorage {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        read_file(&self.path)
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        write_file(&self.path, data)
+    }
+
+    fn delete(&self) -> io::Result<()> {
+        remove_if_exists(&self.path)?;
+        remove_if_exists(&with_extension(&self.path, "bak"))?;
+        remove_if_exists(&with_extension(&self.path, "tmp"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn recover_from_torn_write() {
+        let path = std::env::temp_dir().join(format!("grammers-{}.session", std::process::id()));
+        let storage = FileStorage::new(&path);
+
+        storage.save(b"first").unwrap();
+        storage.save(b"second").unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"second"[..]));
+
+        let mut torn = fs::read(&path).unwrap();
+        torn.truncate(torn.len() - 2);
+        fs::write(&path, torn).unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"first"[..]));
+
+        storage.delete().unwrap();
+        assert_eq!(storage.load().unwrap(), None);
+    }
+
+    fn temp_storage(name: &str) -> (PathBuf, FileStorage) {
+        let path =
+            std::env::temp_dir().join(format!("grammers-{}-{}.session", std::process::id(), name));
+        let storage = FileStorage::new(&path);
+        storage.delete().unwrap();
+        (path, storage)
+    }
+
+    #[test]
+    fn recover_from_missing_file() {
+        let (path, storage) = temp_storage("missing");
+        storage.save(b"first").unwrap();
+        storage.save(b"second").unwrap();
+
+        // A crash between both renames leaves only the backup behind.
+        fs::remove_file(&path).unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"first"[..]));
+
+        storage.delete().unwrap();
+    }
+
+    #[test]
+    fn ignore_stale_temporary_file() {
+        let (path, storage) = temp_storage("stale");
+        storage.save(b"saved").unwrap();
+
+        // A crash while writing leaves the temporary file behind, which must not be read.
+        fs::write(with_extension(&path, "tmp"), b"half-writ").unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"saved"[..]));
+
+        // And it's simply overwritten by the next save.
+        storage.save(b"next").unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"next"[..]));
+        assert!(!with_extension(&path, "tmp").exists());
+
+        storage.delete().unwrap();
+    }
+
+    #[test]
+    fn fail_when_all_copies_are_corrupted() {
+        let (path, storage) = temp_storage("corrupted");
+        storage.save(b"first").unwrap();
+        storage.save(b"second").unwrap();
+
+        for path in [path.clone(), with_extension(&path, "bak")].iter() {
+            let mut data = fs::read(path).unwrap();
+            *data.last_mut().unwrap() ^= 0xff;
+            fs::write(path, data).unwrap();
+        }
+        assert_eq!(
+            storage.load().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        storage.delete().unwrap();
+        assert_eq!(storage.load().unwrap(), None);
+    }
+
+    #[test]
+    fn read_unchecked_legacy_file() {
+        let (path, storage) = temp_storage("legacy");
+        fs::write(&path, b"legacy").unwrap();
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"legacy"[..]));
+
+        // The legacy file becomes the backup once saved with a checksum.
+        storage.save(b"checked").unwrap();
+        assert_eq!(fs::read(with_extension(&path, "bak")).unwrap(), b"legacy");
+        assert_eq!(storage.load().unwrap().as_deref(), Some(&b"checked"[..]));
+
+        storage.delete().unwrap();
+    }
+}
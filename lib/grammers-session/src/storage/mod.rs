@@ -0,0 +1,254 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Places where the serialized session can be persisted.
+#[cfg(feature = "encryption")]
+mod encrypted;
+mod file;
+mod profiles;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::Session;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedStorage;
+pub use file::FileStorage;
+pub(crate) use file::{read_file, write_file};
+pub use profiles::ProfileStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+/// A place where the serialized session data can be loaded from and saved to.
+///
+/// Implement this trait to persist sessions somewhere other than a file, such as a database.
+/// The data is opaque and should be stored as-is.
+pub trait SessionStorage: Send + Sync {
+    /// Load the previously-saved session data, or `None` if nothing was saved yet.
+    fn load(&self) -> io::Result<Option<Vec<u8>>>;
+
+    /// Save the session data, replacing any previously-saved data.
+    fn save(&self, data: &[u8]) -> io::Result<()>;
+
+    /// Delete the previously-saved session data, if any.
+    fn delete(&self) -> io::Result<()>;
+}
+
+/// An owned dynamically typed future, as returned by [`AsyncSessionStorage`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous version of [`SessionStorage`], for places which need to perform I/O that
+/// shouldn't block the runtime, such as databases or key-value stores reached over the network.
+///
+/// Every [`SessionStorage`] is also an [`AsyncSessionStorage`], and it runs its operations
+/// immediately when the future is polled.
+pub trait AsyncSessionStorage: Send + Sync {
+    /// Load the previously-saved session data, or `None` if nothing was saved yet.
+    fn load(&self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>>;
+
+    /// Save the session data, replacing any previously-saved data.
+    fn save<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Delete the previously-saved session data, if any.
+    fn delete(&self) -> BoxFuture<'_, io::Result<()>>;
+}
+
+impl<S: SessionStorage + ?Sized> AsyncSessionStorage for S {
+    fn load(&self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>> {
+        Box::pin(async move { SessionStorage::load(self) })
+    }
+
+    fn save<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move { SessionStorage::save(self, data) })
+    }
+
+    fn delete(&self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move { SessionStorage::delete(self) })
+    }
+}
+
+/// Session storage which only keeps the data in memory, lost once dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    data: Mutex<Option<Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Create a new, empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStorage for MemoryStorage {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.data.lock().unwrap().clone())
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        *self.data.lock().unwrap() = Some(data.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self) -> io::Result<()> {
+        *self.data.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+impl Session {
+    /// Load a previous session instance from the given storage, creating a new one if nothing
+    /// was saved in it yet.
+    pub fn load_storage<S: SessionStorage + ?Sized>(storage: &S) -> io::Result<Self> {
+        match storage.load()? {
+            Some(data) => {
+                Self::load(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Saves the session to the given storage.
+    pub fn save_to_storage<S: SessionStorage + ?Sized>(&self, storage: &S) -> io::Result<()> {
+        storage.save(&self.save())
+    }
+
+    /// Like [`Session::load_storage`], but from an [`AsyncSessionStorage`].
+    pub async fn load_async_storage<S: AsyncSessionStorage + ?Sized>(
+        storage: &S,
+    ) -> io::Result<Self> {
+        match AsyncSessionStorage::load(storage).await? {
+            Some(data) => {
+                Self::load(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Like [`Session::save_to_storage`], but to an [`AsyncSessionStorage`].
+    pub async fn save_to_async_storage<S: AsyncSessionStorage + ?Sized>(
+        &self,
+        storage: &S,
+    ) -> io::Result<()> {
+        let data = self.save();
+        AsyncSessionStorage::save(storage, &data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage() {
+        let storage = MemoryStorage::new();
+        assert_eq!(SessionStorage::load(&storage).unwrap(), None);
+        assert!(!Session::load_storage(&storage).unwrap().signed_in());
+
+        let session = Session::new();
+        session.set_user(1234, 2, false);
+        session.save_to_storage(&storage).unwrap();
+        assert_eq!(
+            SessionStorage::load(&storage).unwrap(),
+            Some(session.save())
+        );
+        assert_eq!(
+            Session::load_storage(&storage)
+                .unwrap()
+                .get_user()
+                .unwrap()
+                .id,
+            1234
+        );
+
+        SessionStorage::delete(&storage).unwrap();
+        assert_eq!(SessionStorage::load(&storage).unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let storage = MemoryStorage::new();
+        SessionStorage::save(&storage, b"not a session").unwrap();
+        let err = Session::load_storage(&storage).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// Storage which only completes its operations after yielding to the runtime.
+    struct YieldingStorage(MemoryStorage);
+
+    impl AsyncSessionStorage for YieldingStorage {
+        fn load(&self) -> BoxFuture<'_, io::Result<Option<Vec<u8>>>> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                SessionStorage::load(&self.0)
+            })
+        }
+
+        fn save<'a>(&'a self, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                SessionStorage::save(&self.0, data)
+            })
+        }
+
+        fn delete(&self) -> BoxFuture<'_, io::Result<()>> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                SessionStorage::delete(&self.0)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn async_storage() {
+        let storage = YieldingStorage(MemoryStorage::new());
+        assert!(!Session::load_async_storage(&storage)
+            .await
+            .unwrap()
+            .signed_in());
+
+        let session = Session::new();
+        session.set_user(1234, 2, true);
+        session.save_to_async_storage(&storage).await.unwrap();
+        let user = Session::load_async_storage(&storage)
+            .await
+            .unwrap()
+            .get_user()
+            .unwrap();
+        assert_eq!((user.id, user.dc, user.bot), (1234, 2, true));
+
+        AsyncSessionStorage::delete(&storage).await.unwrap();
+        assert_eq!(AsyncSessionStorage::load(&storage).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn sync_storage_is_async() {
+        let storage = MemoryStorage::new();
+        let session = Session::new();
+        session.set_user(1234, 2, false);
+        session.save_to_async_storage(&storage).await.unwrap();
+
+        // Both APIs see the same data.
+        assert_eq!(
+            Session::load_storage(&storage)
+                .unwrap()
+                .get_user()
+                .unwrap()
+                .id,
+            1234
+        );
+        assert_eq!(
+            AsyncSessionStorage::load(&storage).await.unwrap(),
+            Some(session.save())
+        );
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::{FileStorage, SessionStorage};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const EXTENSION: &str = "session";
+
+/// A directory holding the sessions of several accounts, each saved under a different name
+/// (its profile).
+///
+/// Every profile is stored in its own `<name>.session` file, so it can be used with any method
+/// that works with a [`FileStorage`].
+#[derive(Clone, Debug)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+/// Profile names end up as file names, so only a safe subset of characters is allowed.
+fn check_name(name: &str) -> io::Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid profile name: {:?}", name),
+        ))
+    }
+}
+
+impl ProfileStore {
+    /// Open the profiles in the given directory, creating it if it doesn't exist.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The names of all the profiles which have a saved session, in alphabetical order.
+    pub fn profiles(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                if check_name(name).is_ok() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The storage for the session of the profile with the given name.
+    ///
+    /// The profile is created once something is saved to it. Names may only contain ASCII
+    /// letters, digits, `-`, `_` and `.` (though not as the first character).
+    pub fn storage(&self, name: &str) -> io::Result<FileStorage> {
+        check_name(name)?;
+        Ok(FileStorage::new(
+            self.dir.join(format!("{}.{}", name, EXTENSION)),
+        ))
+    }
+
+    /// Delete the session saved in the profile with the given name, if any.
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        self.storage(name)?.delete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_names() {
+        for name in ["main", "work-2", "a_b.c"].iter() {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".hidden", "../up", "a/b", "a b", "ñ"].iter() {
+            assert!(check_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn save_list_and_remove() {
+        let dir = std::env::temp_dir().join(format!("grammers-{}-profiles", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = ProfileStore::open(&dir).unwrap();
+        assert!(store.profiles().unwrap().is_empty());
+
+        store.storage("work").unwrap().save(b"work").unwrap();
+        store.storage("main").unwrap().save(b"main").unwrap();
+        // Unrelated files (including the backups of the profiles) aren't listed.
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+        fs::write(dir.join(".hidden.session"), b"").unwrap();
+        store.storage("main").unwrap().save(b"main").unwrap();
+
+        assert_eq!(store.profiles().unwrap(), vec!["main", "work"]);
+        assert_eq!(
+            store.storage("work").unwrap().load().unwrap().as_deref(),
+            Some(&b"work"[..])
+        );
+        assert!(store.storage("../escape").is_err());
+
+        store.remove("work").unwrap();
+        store.remove("unknown").unwrap();
+        assert_eq!(store.profiles().unwrap(), vec!["main"]);
+        assert_eq!(store.storage("work").unwrap().load().unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use super::SessionStorage;
+use crate::{enums, types, Session};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::convert::TryFrom;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The statements needed to migrate the database schema from one version to the next, where the
+/// index is the version they migrate from.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE datacenter (
+        id INTEGER NOT NULL,
+        ipv4 INTEGER,
+        ipv6 BLOB,
+        port INTEGER NOT NULL,
+        auth BLOB
+    );
+    CREATE TABLE user (
+        id INTEGER NOT NULL,
+        dc INTEGER NOT NULL,
+        bot INTEGER NOT NULL
+    );
+    CREATE TABLE update_state (
+        pts INTEGER NOT NULL,
+        qts INTEGER NOT NULL,
+        date INTEGER NOT NULL,
+        seq INTEGER NOT NULL
+    );
+    CREATE TABLE channel_state (
+        channel_id INTEGER NOT NULL PRIMARY KEY,
+        pts INTEGER NOT NULL
+    );
+",
+    "
+    ALTER TABLE channel_state ADD COLUMN chat BLOB;
+",
+];
+
+/// Session storage backed by a SQLite database.
+///
+/// Unlike [`super::FileStorage`], each part of the session is stored in its own table, and the
+/// database can be safely accessed by other processes while in use. The schema is created (or
+/// migrated to the latest version) when the database is opened.
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+fn to_io(error: rusqlite::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+impl SqliteStorage {
+    /// Open the SQLite database at the given path, creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(to_io)?)
+    }
+
+    /// Open a new SQLite database which only lives in memory.
+    pub fn open_in_memory() -> io::Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(to_io)?)
+    }
+
+    fn from_connection(conn: Connection) -> io::Result<Self> {
+        // Write-ahead logging lets readers in other processes proceed while the session is saved.
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))
+            .map_err(to_io)?;
+        Self::migrate(&conn).map_err(to_io)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(rusqlite::Error::InvalidQuery);
+        }
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn load_session(conn: &Connection) -> rusqlite::Result<Option<types::Session>> {
+        let mut stmt =
+            conn.prepare("SELECT id, ipv4, ipv6, port, auth FROM datacenter ORDER BY rowid")?;
+        let dcs = stmt
+            .query_map([], |row| {
+                let ipv6: Option<Vec<u8>> = row.get(2)?;
+                Ok(types::DataCenter {
+                    id: row.get(0)?,
+                    ipv4: row.get(1)?,
+                    ipv6: ipv6.and_then(|ip| <[u8; 16]>::try_from(ip).ok()),
+                    port: row.get(3)?,
+                    auth: row.get(4)?,
+                }
+                .into())
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let user = conn
+            .query_row("SELECT id, dc, bot FROM user", [], |row| {
+                Ok(types::User {
+                    id: row.get(0)?,
+                    dc: row.get(1)?,
+                    bot: row.get(2)?,
+                }
+                .into())
+            })
+            .optional()?;
+
+        let mut stmt = conn.prepare("SELECT channel_id, pts, chat FROM channel_state")?;
+        let channels = stmt
+            .query_map([], |row| {
+                let channel_id = row.get(0)?;
+                let pts = row.get(1)?;
+                Ok(match row.get(2)? {
+                    Some(chat) => types::ChannelStatePacked {
+                        channel_id,
+                        pts,
+                        chat,
+                    }
+                    .into(),
+                    None => types::ChannelState { channel_id, pts }.into(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let state = conn
+            .query_row("SELECT pts, qts, date, seq FROM update_state", [], |row| {
+                Ok(types::UpdateState {
+                    pts: row.get(0)?,
+                    qts: row.get(1)?,
+                    date: row.get(2)?,
+                    seq: row.get(3)?,
+                    channels,
+                }
+                .into())
+            })
+            .optional()?;
+
+        if dcs.is_empty() && user.is_none() && state.is_none() {
+            Ok(None)
+        } else {
+            Ok(Some(types::Session { dcs, user, state }))
+        }
+    }
+
+    fn save_session(conn: &mut Connection, session: &types::Session) -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        Self::clear(&tx)?;
+
+        for enums::DataCenter::Center(dc) in session.dcs.iter() {
+            tx.execute(
+                "INSERT INTO datacenter (id, ipv4, ipv6, port, auth) VALUES (?, ?, ?, ?, ?)",
+                params![
+                    dc.id,
+                    dc.ipv4,
+                    dc.ipv6.as_ref().map(|ip| &ip[..]),
+                    dc.port,
+                    dc.auth
+                ],
+            )?;
+        }
+
+        if let Some(enums::User::User(user)) = &session.user {
+            tx.execute(
+                "INSERT INTO user (id, dc, bot) VALUES (?, ?, ?)",
+                params![user.id, user.dc, user.bot],
+            )?;
+        }
+
+        if let Some(enums::UpdateState::State(state)) = &session.state {
+            tx.execute(
+                "INSERT INTO update_state (pts, qts, date, seq) VALUES (?, ?, ?, ?)",
+                params![state.pts, state.qts, state.date, state.seq],
+            )?;
+            for channel in state.channels.iter() {
+                let chat = match channel {
+                    enums::ChannelState::State(_) => None,
+                    enums::ChannelState::Packed(s) => Some(&s.chat),
+                };
+                tx.execute(
+                    "INSERT INTO channel_state (channel_id, pts, chat) VALUES (?, ?, ?)",
+                    params![channel.channel_id(), channel.pts(), chat],
+                )?;
+            }
+        }
+
+        tx.commit()
+    }
+
+    fn clear(conn: &Connection) -> rusqlite::Result<()> {
+        conn.execute_batch(
+            "DELETE FROM datacenter;
+            DELETE FROM user;
+            DELETE FROM update_state;
+            DELETE FROM channel_state;",
+        )
+    }
+}
+
+impl SessionStorage for SqliteStorage {
+    fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(Self::load_session(&conn).map_err(to_io)?.map(|session| {
+            Session {
+                session: Mutex::new(session),
+            }
+            .save()
+        }))
+    }
+
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        let session =
+            Session::load(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let session = session.session.into_inner().unwrap();
+        let mut conn = self.conn.lock().unwrap();
+        Self::save_session(&mut conn, &session).map_err(to_io)
+    }
+
+    fn delete(&self) -> io::Result<()> {
+        Self::clear(&self.conn.lock().unwrap()).map_err(to_io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PackedChat, PackedType, UpdateState};
+    use std::collections::HashMap;
+
+    fn session() -> Session {
+        let session = Session::new();
+        session.insert_dc(2, "149.154.167.51:443".parse().unwrap(), [7; 256]);
+        session.insert_dc(4, "[2001:67c:4e8:f004::a]:443".parse().unwrap(), [9; 256]);
+        session.set_user(1234, 2, true);
+        session.set_state(UpdateState {
+            pts: 10,
+            qts: 20,
+            date: 30,
+            seq: 40,
+            channels: vec![(100, 5), (200, 6)].into_iter().collect(),
+            channel_chats: vec![(
+                100,
+                PackedChat {
+                    ty: PackedType::Megagroup,
+                    id: 100,
+                    access_hash: Some(555),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        });
+        session
+    }
+
+    #[test]
+    fn round_trip() {
+        let storage = SqliteStorage::open_in_memory().unwrap();
+        assert!(storage.load().unwrap().is_none());
+
+        session().save_to_storage(&storage).unwrap();
+        let loaded = Session::load_storage(&storage).unwrap();
+
+        assert_eq!(loaded.dc_auth_key(2), Some([7; 256]));
+        assert_eq!(loaded.dc_auth_key(4), Some([9; 256]));
+        let user = loaded.get_user().unwrap();
+        assert_eq!((user.id, user.dc, user.bot), (1234, 2, true));
+
+        let state = loaded.get_state().unwrap();
+        assert_eq!(
+            (state.pts, state.qts, state.date, state.seq),
+            (10, 20, 30, 40)
+        );
+        assert_eq!(state.channels, session().get_state().unwrap().channels);
+        assert_eq!(state.channel_chats().len(), 1);
+        assert_eq!(state.channel_chats()[&100].access_hash, Some(555));
+
+        // Saving again replaces the previous session rather than adding to it.
+        let other = Session::new();
+        other.set_user(5678, 4, false);
+        other.save_to_storage(&storage).unwrap();
+        let loaded = Session::load_storage(&storage).unwrap();
+        assert_eq!(loaded.get_user().unwrap().id, 5678);
+        assert!(loaded.dc_auth_key(2).is_none());
+        assert!(loaded.get_state().is_none());
+
+        storage.delete().unwrap();
+        assert!(storage.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn migrate_old_schema() {
+        let path = std::env::temp_dir().join(format!("grammers-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(&format!("{} PRAGMA user_version = 1;", MIGRATIONS[0]))
+                .unwrap();
+            conn.execute_batch(
+                "INSERT INTO update_state (pts, qts, date, seq) VALUES (1, 2, 3, 4);
+                INSERT INTO channel_state (channel_id, pts) VALUES (100, 5);",
+            )
+            .unwrap();
+        }
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        let state = Session::load_storage(&storage)
+            .unwrap()
+            .get_state()
+            .unwrap();
+        let channels: HashMap<_, _> = vec![(100, 5)].into_iter().collect();
+        assert_eq!(state.channels, channels);
+        assert!(state.channel_chats().is_empty());
+
+        session().save_to_storage(&storage).unwrap();
+        let state = Session::load_storage(&storage)
+            .unwrap()
+            .get_state()
+            .unwrap();
+        assert_eq!(state.channel_chats().len(), 1);
+        drop(storage);
+
+        // Databases written by a newer version must not be touched.
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("PRAGMA user_version = 100;")
+            .unwrap();
+        assert!(SqliteStorage::open(&path).is_err());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_extension("sqlite-wal"));
+        let _ = std::fs::remove_file(path.with_extension("sqlite-shm"));
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2020 - developers of the `grammers` project.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversion of sessions from and into the string format used by other libraries.
+use crate::{enums, Error, Session};
+use std::convert::{TryFrom, TryInto};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The version of the string session format used by Telethon and GramJS.
+const TELETHON_VERSION: char = '1';
+
+impl Session {
+    /// Export the authorization of the logged-in user as a string session compatible with
+    /// Telethon and GramJS, so it can be used with those libraries.
+    ///
+    /// The string contains the datacenter of the user, its address, and the authorization key,
+    /// so it must be kept secret, as it grants full access to the account.
+    ///
+    /// Returns `None` if there is no authorization key for the datacenter of the user (or for
+    /// any datacenter, if no user is signed in).
+    pub fn to_string_session(&self) -> Option<String> {
+        let session = self.session.lock().unwrap();
+        let user_dc = session.user.as_ref().map(|enums::User::User(user)| user.dc);
+
+        let dc = session
+            .dcs
+            .iter()
+            .map(|enums::DataCenter::Center(dc)| dc)
+            .filter(|dc| dc.auth.is_some())
+            .find(|dc| user_dc.map(|id| id == dc.id).unwrap_or(true))?;
+
+        let mut data = Vec::with_capacity(1 + 16 + 2 + 256);
+        data.push(dc.id as u8);
+        match (dc.ipv4, dc.ipv6) {
+            (Some(ipv4), _) => data.extend(&Ipv4Addr::from(ipv4.to_le_bytes()).octets()),
+            (None, Some(ipv6)) => data.extend(&Ipv6Addr::from(ipv6).octets()),
+            (None, None) => return None,
+        }
+        data.extend(&(dc.port as u16).to_be_bytes());
+        data.extend(dc.auth.as_ref()?);
+
+        let mut result = TELETHON_VERSION.to_string();
+        result.push_str(&base64::encode_config(&data, base64::URL_SAFE));
+        Some(result)
+    }
+
+    /// Import a string session created by Telethon or GramJS (or [`Session::to_string_session`]).
+    ///
+    /// Such sessions don't contain the logged-in user, so [`Session::signed_in`] will return
+    /// `false` until signing in again, but the authorization key can still be used.
+    pub fn from_string_session(string: &str) -> Result<Self, Error> {
+        let data = match string.strip_prefix(TELETHON_VERSION) {
+            Some(data) => {
+                base64::decode_config(data, base64::URL_SAFE).map_err(|_| Error::MalformedData)?
+            }
+            None => return Err(Error::UnsupportedVersion),
+        };
+
+        let ip_len = match data.len() {
+            275 => 16,
+            263 => 4,
+            _ => return Err(Error::MalformedData),
+        };
+        let ip: IpAddr = if ip_len == 4 {
+            Ipv4Addr::from(<[u8; 4]>::try_from(&data[1..5]).unwrap()).into()
+        } else {
+            Ipv6Addr::from(<[u8; 16]>::try_from(&data[1..17]).unwrap()).into()
+        };
+        let port = u16::from_be_bytes(data[1 + ip_len..3 + ip_len].try_into().unwrap());
+        let auth = data[3 + ip_len..].try_into().unwrap();
+
+        let session = Self::new();
+        session.insert_dc(data[0] as i32, SocketAddr::new(ip, port), auth);
+        Ok(session)
+    }
+}
@@ -111,7 +111,9 @@ pub trait Identifiable {
 /// Structures implementing this trait indicate that they are suitable for
 /// use to perform Remote Procedure Calls (RPC), and know what the type of
 /// the response will be.
-pub trait RemoteCall: Serializable {
+///
+/// The constructor identifier of the call tells which method is being invoked.
+pub trait RemoteCall: Serializable + Identifiable {
     /// The type of the "return" value coming from the other end of the
     /// connection.
     type Return: Deserializable;